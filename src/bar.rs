@@ -12,22 +12,273 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use gtk::glib;
 use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::rc::Rc;
 
-pub(crate) struct Bar {
-    left_modules: Vec<Box<dyn crate::module::Module>>,
-    center_modules: Vec<Box<dyn crate::module::Module>>,
-    right_modules: Vec<Box<dyn crate::module::Module>>,
+/// A module in a bar with its ID.
+struct BarModule {
+    id: String,
+    text_scale: Option<f64>,
+    priority: i32,
+    module: Box<dyn crate::module::Module>,
+}
+
+/// A module container that can be moved to the overflow menu.
+struct Collapsible {
+    container: gtk::Box,
+    /// The left, center, or right box.
+    parent: gtk::Box,
+    /// Position in the parent.
+    index: usize,
+    priority: i32,
+    /// Position in the bar. Later modules are moved first among the same priority.
+    order: usize,
+    /// The width in the bar while in the overflow menu.
+    overflowed: Cell<Option<i32>>,
+}
+
+/// Moves the modules that don't fit in the bar to the overflow menu.
+///
+/// GTK grows the window to fit the modules, so the width is compared with the monitor width
+/// instead of the allocation.
+struct Overflow {
+    width: i32,
+    boxes: Vec<gtk::Box>,
+    modules: Vec<Collapsible>,
+    button: gtk::Button,
+    list: gtk::Box,
+    pending: Cell<bool>,
+}
+
+impl Overflow {
+    /// Returns the natural width of the modules in the bar.
+    fn required_width(&self) -> i32 {
+        self.boxes.iter().map(|b| b.preferred_width().1).sum()
+    }
+
+    /// Moves the modules between the bar and the overflow menu.
+    fn relayout(&self) {
+        let mut order: Vec<&Collapsible> = self.modules.iter().collect();
+        order.sort_by_key(|m| (m.priority, std::cmp::Reverse(m.order)));
+        // Collapse from the lowest priority.
+        for m in order.iter() {
+            if self.required_width() <= self.width {
+                break;
+            }
+            if m.overflowed.get().is_none() {
+                m.overflowed.set(Some(m.container.preferred_width().1));
+                m.parent.remove(&m.container);
+                self.list.add(&m.container);
+                self.button.show();
+            }
+        }
+        // Restore from the highest priority.
+        for m in order.iter().rev() {
+            let width = match m.overflowed.get() {
+                Some(w) => w,
+                None => continue,
+            };
+            if self.required_width() + width > self.width {
+                break;
+            }
+            self.list.remove(&m.container);
+            m.parent.add(&m.container);
+            let position = self
+                .modules
+                .iter()
+                .filter(|o| o.parent == m.parent && o.index < m.index)
+                .filter(|o| o.overflowed.get().is_none())
+                .count();
+            m.parent.reorder_child(&m.container, position as i32);
+            m.overflowed.set(None);
+        }
+        self.button
+            .set_visible(self.modules.iter().any(|m| m.overflowed.get().is_some()));
+    }
+}
+
+/// Escapes a string to use it as a CSS identifier.
+fn css_escape_ident(s: &str) -> String {
+    let mut ret = String::new();
+    for (i, c) in s.chars().enumerate() {
+        if c.is_ascii_alphabetic() || c == '-' || c == '_' || (i > 0 && c.is_ascii_digit()) {
+            ret.push(c);
+        } else if c.is_ascii_digit() || c.is_ascii_whitespace() {
+            ret += &format!("\\{:x} ", c as u32);
+        } else {
+            ret.push('\\');
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+/// Adds a CSS provider to the widget with the name (e.g. a module ID) that scales its text.
+pub(crate) fn add_text_scale_css(container: &gtk::Box, id: &str, scale: f64) {
+    let css = format!(
+        "#{} {{ font-size: {:.0}%; }}",
+        css_escape_ident(id),
+        scale * 100.0
+    );
+    let provider = gtk::CssProvider::new();
+    if let Err(e) = provider.load_from_data(css.as_bytes()) {
+        eprintln!("Failed to set text_scale of {}: {}", id, e);
+        return;
+    }
+    // The provider of the widget is dropped with the widget, unlike the providers for the screen.
+    container
+        .style_context()
+        .add_provider(&provider, gtk::STYLE_PROVIDER_PRIORITY_APPLICATION);
+}
+
+/// Adds a CSS provider to the bar with the name that rounds its corners.
+fn add_corner_radius_css(container: &gtk::Box, id: &str, radius: i32) {
+    let css = format!(
+        "#{} {{ border-radius: {}px; }}",
+        css_escape_ident(id),
+        radius
+    );
+    let provider = gtk::CssProvider::new();
+    if let Err(e) = provider.load_from_data(css.as_bytes()) {
+        eprintln!("Failed to set corner_radius of {}: {}", id, e);
+        return;
+    }
+    container
+        .style_context()
+        .add_provider(&provider, gtk::STYLE_PROVIDER_PRIORITY_APPLICATION);
+}
+
+/// Reserves the area of the window at the top of the monitor so that other windows don't overlap
+/// it. The geometry of the window is in application pixels.
+fn set_strut(window: &gtk::gdk::Window, geom: &gtk::gdk::Rectangle, scale: i32) {
+    let top = ((geom.y + geom.height) * scale) as libc::c_ulong;
+    let start_x = (geom.x * scale) as libc::c_ulong;
+    let end_x = ((geom.x + geom.width) * scale - 1) as libc::c_ulong;
+    let cardinal = gtk::gdk::Atom::intern("CARDINAL");
+    let partial = [0, 0, top, 0, 0, 0, 0, 0, start_x, end_x, 0, 0];
+    gtk::gdk::property_change(
+        window,
+        &gtk::gdk::Atom::intern("_NET_WM_STRUT_PARTIAL"),
+        &cardinal,
+        32,
+        gtk::gdk::PropMode::Replace,
+        gtk::gdk::ChangeData::ULongs(&partial),
+    );
+    // For the window managers that don't support the partial strut.
+    gtk::gdk::property_change(
+        window,
+        &gtk::gdk::Atom::intern("_NET_WM_STRUT"),
+        &cardinal,
+        32,
+        gtk::gdk::PropMode::Replace,
+        gtk::gdk::ChangeData::ULongs(&partial[..4]),
+    );
+}
+
+/// Ends the keyboard navigation started by [`Bar::focus`].
+fn release_focus(win: &gtk::ApplicationWindow) {
+    win.style_context().remove_class("keyboard-navigation");
+    win.set_focus(None::<&gtk::Widget>);
+    if let Some(seat) = win.display().default_seat() {
+        seat.ungrab();
+    }
+}
+
+/// Moves the focus with the arrow keys and activates the focused widget with Enter or Space.
+fn handle_navigation_key(win: &gtk::ApplicationWindow, e: &gtk::gdk::EventKey) -> Inhibit {
+    use gtk::gdk::keys::constants as key;
+    if !win.style_context().has_class("keyboard-navigation") {
+        return Inhibit(false);
+    }
+    let k = e.keyval();
+    if k == key::Escape {
+        release_focus(win);
+    } else if k == key::Left || k == key::Right || k == key::Up || k == key::Down {
+        // The focus chain follows the layout, which mirrors in the right-to-left direction.
+        let backward = if win.direction() == gtk::TextDirection::Rtl {
+            k == key::Right || k == key::Up
+        } else {
+            k == key::Left || k == key::Up
+        };
+        win.child_focus(if backward {
+            gtk::DirectionType::TabBackward
+        } else {
+            gtk::DirectionType::TabForward
+        });
+    } else if k == key::Return || k == key::KP_Enter || k == key::space {
+        // Buttons click on activation.
+        if let Some(widget) = gtk::prelude::GtkWindowExt::focus(win) {
+            widget.activate();
+        }
+    } else {
+        return Inhibit(false);
+    }
+    Inhibit(true)
+}
+
+/// Returns the name that identifies the monitor across restarts.
+///
+/// GDK 3 has no connector name, but its X11 backend sets the RandR output name (e.g. "HDMI-1") as
+/// the model. The other backends set the product model, which is the same for identical monitors,
+/// so the position is added.
+fn monitor_state_name(monitor: &gtk::gdk::Monitor) -> String {
+    let model = monitor.model().map(|v| v.to_string()).unwrap_or_default();
+    if monitor.display().type_().name() == "GdkX11Display" {
+        return model;
+    }
+    let geom = monitor.geometry();
+    format!("{}@{},{}", model, geom.x, geom.y)
+}
+
+/// Returns the scale of the monitor from its physical DPI, in quarter steps.
+///
+/// The geometry is in application pixels, so this excludes the scale factor that GDK applied.
+fn dpi_scale(monitor: &gtk::gdk::Monitor) -> f64 {
+    let width_mm = monitor.width_mm();
+    if width_mm <= 0 {
+        return 1.0;
+    }
+    let dpi = monitor.geometry().width as f64 / (width_mm as f64 / 25.4);
+    ((dpi / 96.0 * 4.0).round() / 4.0).max(1.0)
+}
+
+pub struct Bar {
+    left_modules: Vec<BarModule>,
+    center_modules: Vec<BarModule>,
+    right_modules: Vec<BarModule>,
     name: String,
+    /// Name of the monitor that the module states are keyed by. See [`monitor_state_name`].
+    state_name: String,
+    /// Name of the config. Empty unless multiple configs are read from a directory.
+    config_name: String,
     x: i32,
     y: i32,
     width: i32,
     height: i32,
+    /// The scale factor of the monitor applied by GDK.
+    scale_factor: i32,
+    /// The scale applied by the bar on top of scale_factor.
+    scale: f64,
+    /// Space around the bar in the window.
+    margin: crate::config::MarginConfig,
+    /// Width of the bar. None to fill the monitor width.
+    bar_width: Option<i32>,
+    corner_radius: Option<i32>,
+    click_through: Cell<bool>,
+    /// The dock window created by build_ui.
+    window: RefCell<Option<gtk::ApplicationWindow>>,
 }
 
 impl Bar {
-    pub(crate) fn new(
+    /// Creates the modules of a bar for the monitor.
+    ///
+    /// `config_name` distinguishes the bars of multiple configs on the same monitor. It can be
+    /// empty.
+    pub fn new(
+        config_name: &str,
         config: &crate::config::MonitorConfig,
         module_factories: &HashMap<String, Box<dyn crate::module::ModuleFactory>>,
         monitor: &gtk::gdk::Monitor,
@@ -36,46 +287,268 @@ impl Bar {
         let center_modules = Bar::init_modules(&config.center_modules, module_factories, monitor);
         let right_modules = Bar::init_modules(&config.right_modules, module_factories, monitor);
         let geom = monitor.geometry();
+        let scale = config.scale.unwrap_or_else(|| {
+            if config.auto_scale {
+                dpi_scale(monitor)
+            } else {
+                1.0
+            }
+        });
         return Bar {
             left_modules,
             center_modules,
             right_modules,
             name: monitor.model().map(|v| v.to_string()).unwrap_or("".to_string()),
+            state_name: monitor_state_name(monitor),
+            config_name: config_name.to_owned(),
             x: geom.x,
             y: geom.y,
             width: geom.width,
-            height: config.height.unwrap_or(30),
+            height: (config.height.unwrap_or(30) as f64 * scale).round() as i32,
+            scale_factor: monitor.scale_factor(),
+            scale,
+            margin: config.margin,
+            bar_width: config.width,
+            corner_radius: config.corner_radius,
+            click_through: Cell::new(config.click_through),
+            window: RefCell::new(None),
         };
     }
 
-    pub(crate) fn build_ui(&self, app: &gtk::Application) {
+    /// Shows the bar in a new dock window.
+    pub fn build_ui(&self, app: &gtk::Application) {
         let win = gtk::ApplicationWindow::builder()
             .application(app)
             .type_hint(gtk::gdk::WindowTypeHint::Dock)
             .build();
+        let window_height = self.height + self.margin.top + self.margin.bottom;
         win.move_(self.x, self.y);
-        win.resize(self.width, self.height);
+        win.resize(self.width, window_height);
         win.set_widget_name(&format!("root-{}", self.name));
         win.style_context().add_class("root");
+        if !self.config_name.is_empty() {
+            // Allows the CSS to style the bars of each config (e.g. ".config-work .bar").
+            win.style_context().add_class(&format!("config-{}", self.config_name));
+        }
+
+        if self.is_floating() {
+            // The window is transparent around the bar.
+            win.style_context().add_class("floating");
+            if let Some(visual) = win.screen().and_then(|s| s.rgba_visual()) {
+                win.set_visual(Some(&visual));
+            }
+            win.set_app_paintable(true);
+        }
+
+        win.add(&self.widget());
+        win.connect_key_press_event(handle_navigation_key);
+        win.connect_focus_out_event(|win, _| {
+            release_focus(win);
+            Inhibit(false)
+        });
+
+        win.show_all();
+        if let Some(window) = win.window() {
+            let geom = gtk::gdk::Rectangle {
+                x: self.x,
+                y: self.y,
+                width: self.width,
+                height: window_height,
+            };
+            set_strut(&window, &geom, self.scale_factor);
+        }
+        self.window.replace(Some(win));
+        self.set_click_through(self.click_through.get());
+    }
+
+    /// Takes the keyboard focus for the keyboard navigation.
+    ///
+    /// The arrow keys move the focus between the focusable widgets (e.g. buttons) in the order of
+    /// the layout, and Enter or Space activates the focused one. Escape or a focus change ends it.
+    pub fn focus(&self) {
+        let win = match *self.window.borrow() {
+            Some(ref win) => win.clone(),
+            None => return,
+        };
+        // Dock windows don't get the focus from the window managers.
+        win.style_context().add_class("keyboard-navigation");
+        win.present();
+        if let (Some(window), Some(seat)) = (win.window(), win.display().default_seat()) {
+            let status = seat.grab(
+                &window,
+                gtk::gdk::SeatCapabilities::KEYBOARD,
+                true,
+                None,
+                None,
+                None,
+            );
+            if status != gtk::gdk::GrabStatus::Success {
+                eprintln!("Cannot grab the keyboard: {:?}", status);
+            }
+        }
+        win.child_focus(gtk::DirectionType::TabForward);
+    }
+
+    /// Returns the monitor model of the bar (e.g. "HDMI-1").
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
+    /// Returns true if the mouse events pass through the bar.
+    pub fn click_through(&self) -> bool {
+        self.click_through.get()
+    }
+
+    /// Lets the mouse events pass through the window created by build_ui with an empty input
+    /// shape.
+    pub fn set_click_through(&self, enabled: bool) {
+        self.click_through.set(enabled);
+        if let Some(ref win) = *self.window.borrow() {
+            if enabled {
+                win.input_shape_combine_region(Some(&gtk::cairo::Region::create()));
+            } else {
+                win.input_shape_combine_region(None);
+            }
+        }
+    }
+
+    /// Returns true if the bar doesn't fill the window.
+    fn is_floating(&self) -> bool {
+        let m = &self.margin;
+        m.top != 0
+            || m.bottom != 0
+            || m.left != 0
+            || m.right != 0
+            || self.bar_width.is_some()
+            || self.corner_radius.is_some()
+    }
+
+    /// Closes the window created by build_ui.
+    pub fn close(&self) {
+        if let Some(win) = self.window.take() {
+            win.close();
+        }
+    }
+
+    /// Builds the bar content.
+    ///
+    /// This is for embedding the bar in another window. Call this only once because the modules
+    /// are built for each call.
+    pub fn widget(&self) -> gtk::Box {
         let win_box = gtk::Box::new(gtk::Orientation::Horizontal, 0);
         win_box.set_widget_name(&format!("bar-{}", self.name));
         win_box.style_context().add_class("bar");
-        win_box.pack_start(&Bar::init_box("left-modules", &self.left_modules), false, false, 0);
-        win_box.set_center_widget(Some(&Bar::init_box("center-modules", &self.center_modules)));
-        win_box.pack_end(&Bar::init_box("right-modules", &self.right_modules), false, false, 0);
-        win.add(&win_box);
+        if self.scale_factor > 1 || self.scale > 1.0 {
+            win_box.style_context().add_class("hidpi");
+        }
+        if self.scale != 1.0 {
+            add_text_scale_css(&win_box, &format!("bar-{}", self.name), self.scale);
+        }
+        win_box.set_margin_top(self.margin.top);
+        win_box.set_margin_bottom(self.margin.bottom);
+        // The margins are on the screen, and they don't mirror.
+        if crate::module_base::locale::is_rtl() {
+            win_box.set_margin_start(self.margin.right);
+            win_box.set_margin_end(self.margin.left);
+        } else {
+            win_box.set_margin_start(self.margin.left);
+            win_box.set_margin_end(self.margin.right);
+        }
+        let width = self
+            .bar_width
+            .unwrap_or(self.width - self.margin.left - self.margin.right);
+        if let Some(bar_width) = self.bar_width {
+            win_box.set_size_request(bar_width, -1);
+            win_box.set_halign(gtk::Align::Center);
+        }
+        if let Some(radius) = self.corner_radius {
+            add_corner_radius_css(&win_box, &format!("bar-{}", self.name), radius);
+        }
+        let mut occurrences = HashMap::new();
+        let mut collapsibles = vec![];
+        let left = self.init_box("left-modules", &self.left_modules, &mut occurrences, &mut collapsibles);
+        let center = self.init_box("center-modules", &self.center_modules, &mut occurrences, &mut collapsibles);
+        let right = self.init_box("right-modules", &self.right_modules, &mut occurrences, &mut collapsibles);
+        win_box.pack_start(&left, false, false, 0);
+        win_box.set_center_widget(Some(&center));
+        win_box.pack_end(&right, false, false, 0);
 
-        win.show_all();
+        let button = gtk::Button::with_label(if crate::module_base::locale::is_rtl() {
+            "«"
+        } else {
+            "»"
+        });
+        button.set_relief(gtk::ReliefStyle::None);
+        button.style_context().add_class("overflow");
+        button.set_no_show_all(true);
+        right.add(&button);
+        let popover = gtk::Popover::new(Some(&button));
+        let list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        list.show();
+        popover.add(&list);
+        button.connect_clicked(move |_| popover.popup());
+
+        let overflow = Rc::new(Overflow {
+            width,
+            boxes: vec![left, center, right],
+            modules: collapsibles,
+            button,
+            list,
+            pending: Cell::new(false),
+        });
+        // Relayout after the allocation because moving the modules changes the allocation.
+        win_box.connect_size_allocate(move |_, _| {
+            if overflow.pending.replace(true) {
+                return;
+            }
+            let overflow = overflow.clone();
+            glib::idle_add_local(move || {
+                overflow.pending.set(false);
+                overflow.relayout();
+                Continue(false)
+            });
+        });
+        win_box
     }
 
-    fn init_box(class: &str, modules: &Vec<Box<dyn crate::module::Module>>) -> gtk::Box {
+    /// Creates a box with the modules. `occurrences` counts the module IDs on this bar. The module
+    /// containers are added to `collapsibles`.
+    fn init_box(
+        &self,
+        class: &str,
+        modules: &Vec<BarModule>,
+        occurrences: &mut HashMap<String, usize>,
+        collapsibles: &mut Vec<Collapsible>,
+    ) -> gtk::Box {
         let b = gtk::Box::new(gtk::Orientation::Horizontal, 0);
         b.style_context().add_class(class);
-        for ref module in modules {
+        for (index, module) in modules.iter().enumerate() {
             let container = gtk::Box::new(gtk::Orientation::Horizontal, 0);
-            module.build_ui(&container);
+            container.set_widget_name(&module.id);
+            crate::module_base::register_module(&module.id, &container);
+            let occurrence = occurrences.entry(module.id.clone()).or_insert(0);
+            // Modules in different configs have different states.
+            let state_prefix = if self.config_name.is_empty() {
+                self.state_name.clone()
+            } else {
+                format!("{}:{}", self.config_name, self.state_name)
+            };
+            crate::module_base::state::register_instance(&container, &state_prefix, *occurrence);
+            *occurrence += 1;
+            if let Some(scale) = module.text_scale {
+                add_text_scale_css(&container, &module.id, scale);
+            }
+            module.module.build_ui(&container);
+            crate::module_base::restore_pin(&container);
             b.pack_start(&container, false, false, 0);
+            collapsibles.push(Collapsible {
+                container,
+                parent: b.clone(),
+                index,
+                priority: module.priority,
+                order: collapsibles.len(),
+                overflowed: Cell::new(None),
+            });
         }
         b
     }
@@ -84,15 +557,26 @@ impl Bar {
         configs: &Vec<crate::config::ModuleConfig>,
         module_factories: &HashMap<String, Box<dyn crate::module::ModuleFactory>>,
         monitor: &gtk::gdk::Monitor,
-    ) -> Vec<Box<dyn crate::module::Module>> {
+    ) -> Vec<BarModule> {
         let mut modules = vec![];
         for ref config in configs {
-            modules.push(
-                module_factories
-                    .get(config.name.as_str())
-                    .expect("Failed to find a module")
-                    .create(&config.config, monitor),
-            );
+            if !config.enabled {
+                continue;
+            }
+            let module =
+                match crate::containers::create_module(config, module_factories, monitor) {
+                    Ok(module) => module,
+                    Err(e) => {
+                        crate::error::report(&e);
+                        Box::new(crate::error::ErrorModule::new(&config.name, e))
+                    }
+                };
+            modules.push(BarModule {
+                id: config.id.clone().unwrap_or_else(|| config.name.clone()),
+                text_scale: config.text_scale,
+                priority: config.priority,
+                module,
+            });
         }
         modules
     }
@@ -0,0 +1,335 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bluetooth state from BlueZ over D-Bus.
+
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{gio, glib};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+const BLUEZ: &str = "org.bluez";
+
+#[derive(Clone, Debug)]
+pub struct AdapterState {
+    /// D-Bus object path (e.g. "/org/bluez/hci0").
+    pub path: String,
+    pub powered: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceState {
+    /// D-Bus object path (e.g. "/org/bluez/hci0/dev_00_11_22_33_44_55").
+    pub path: String,
+    /// The alias of the device. This is the name unless the user renamed it.
+    pub name: String,
+    pub address: String,
+    pub paired: bool,
+    pub connected: bool,
+    /// Battery percentage if the device reports it.
+    pub battery: Option<u8>,
+}
+
+#[derive(Clone, Debug, Default, glib::GBoxed)]
+#[gboxed(type_name = "BluetoothAdapter")]
+pub struct Adapter(Option<AdapterState>);
+
+#[derive(Clone, Debug, Default, glib::GBoxed)]
+#[gboxed(type_name = "BluetoothDevices")]
+pub struct Devices(BTreeMap<String, DeviceState>);
+
+gtk::glib::wrapper! {
+    pub struct BluetoothState(ObjectSubclass<imp::BluetoothState>);
+}
+
+thread_local! {
+    static SHARED: RefCell<Option<BluetoothState>> = RefCell::new(None);
+}
+
+impl BluetoothState {
+    pub fn new() -> Self {
+        glib::Object::new(&[]).expect("Failed to create a BluetoothState")
+    }
+
+    /// Returns the state shared in the application. It's created on the first call.
+    pub fn shared() -> Self {
+        SHARED.with(|s| {
+            s.borrow_mut()
+                .get_or_insert_with(BluetoothState::new)
+                .clone()
+        })
+    }
+
+    /// Returns the device with the address (e.g. "00:11:22:33:44:55").
+    pub fn find_by_address(&self, address: &str) -> Option<DeviceState> {
+        self.devices()
+            .into_iter()
+            .map(|(_, d)| d)
+            .find(|d| d.address.eq_ignore_ascii_case(address))
+    }
+
+    /// Returns the first adapter. None if there's no adapter.
+    pub fn adapter(&self) -> Option<AdapterState> {
+        self.property("adapter")
+            .unwrap()
+            .get::<Adapter>()
+            .unwrap()
+            .0
+    }
+
+    /// Returns the devices keyed by the object path.
+    pub fn devices(&self) -> BTreeMap<String, DeviceState> {
+        self.property("devices")
+            .unwrap()
+            .get::<Devices>()
+            .unwrap()
+            .0
+    }
+
+    /// Reloads the state from BlueZ.
+    pub fn refresh(&self) {
+        let self_ = imp::BluetoothState::from_instance(self);
+        if let Some(ref poller) = *self_.poller.borrow() {
+            poller.refresh();
+        }
+    }
+
+    /// Turns on or off the adapter.
+    pub fn set_powered(&self, powered: bool) {
+        if let Some(adapter) = self.adapter() {
+            self.call(
+                &adapter.path,
+                "org.freedesktop.DBus.Properties",
+                "Set",
+                Some(&("org.bluez.Adapter1", "Powered", powered.to_variant()).to_variant()),
+            );
+        }
+    }
+
+    /// Connects to the device.
+    pub fn connect_device(&self, path: &str) {
+        self.call(path, "org.bluez.Device1", "Connect", None);
+    }
+
+    /// Disconnects the device.
+    pub fn disconnect_device(&self, path: &str) {
+        self.call(path, "org.bluez.Device1", "Disconnect", None);
+    }
+
+    /// Calls a BlueZ method asynchronously. Errors are logged.
+    fn call(&self, path: &str, interface: &str, method: &str, params: Option<&glib::Variant>) {
+        let self_ = imp::BluetoothState::from_instance(self);
+        let conn = match *self_.connection.borrow() {
+            Some(ref conn) => conn.clone(),
+            None => return,
+        };
+        let description = format!("{} {}", method, path);
+        conn.call(
+            Some(BLUEZ),
+            path,
+            interface,
+            method,
+            params,
+            None,
+            gio::DBusCallFlags::NONE,
+            // Connecting to a device can take long.
+            30000,
+            None::<&gio::Cancellable>,
+            glib::clone!(@weak self as obj => move |result| {
+                if let Err(e) = result {
+                    eprintln!("Bluetooth: {} failed: {}", description, e);
+                }
+                obj.refresh();
+            }),
+        );
+    }
+}
+
+/// Returns the value of the property in a{sv}.
+fn lookup(props: &glib::Variant, name: &str) -> Option<glib::Variant> {
+    (0..props.n_children())
+        .map(|i| props.child_value(i))
+        .find(|e| e.child_value(0).str() == Some(name))?
+        .child_value(1)
+        .as_variant()
+}
+
+/// Reads the adapter and the devices with ObjectManager.GetManagedObjects.
+fn fetch() -> Result<(Adapter, Devices), String> {
+    let conn = gio::bus_get_sync(gio::BusType::System, None::<&gio::Cancellable>)
+        .map_err(|e| e.to_string())?;
+    let reply = conn
+        .call_sync(
+            Some(BLUEZ),
+            "/",
+            "org.freedesktop.DBus.ObjectManager",
+            "GetManagedObjects",
+            None,
+            None,
+            gio::DBusCallFlags::NONE,
+            5000,
+            None::<&gio::Cancellable>,
+        )
+        .map_err(|e| e.to_string())?;
+    // a{oa{sa{sv}}}
+    let objects = reply.child_value(0);
+    let mut adapter = None;
+    let mut devices = BTreeMap::new();
+    for i in 0..objects.n_children() {
+        let object = objects.child_value(i);
+        let path = object.child_value(0).str().unwrap_or_default().to_owned();
+        let interfaces = object.child_value(1);
+        let interface = |name: &str| lookup_interface(&interfaces, name);
+        if let Some(props) = interface("org.bluez.Adapter1") {
+            if adapter.is_none() {
+                adapter = Some(AdapterState {
+                    path: path.clone(),
+                    powered: lookup(&props, "Powered")
+                        .and_then(|v| v.get())
+                        .unwrap_or(false),
+                });
+            }
+        }
+        if let Some(props) = interface("org.bluez.Device1") {
+            let string = |name| {
+                lookup(&props, name)
+                    .and_then(|v| v.get::<String>())
+                    .unwrap_or_default()
+            };
+            let flag = |name| lookup(&props, name).and_then(|v| v.get()).unwrap_or(false);
+            let battery = interface("org.bluez.Battery1")
+                .and_then(|b| lookup(&b, "Percentage"))
+                .and_then(|v| v.get::<u8>());
+            devices.insert(
+                path.clone(),
+                DeviceState {
+                    path,
+                    name: string("Alias"),
+                    address: string("Address"),
+                    paired: flag("Paired"),
+                    connected: flag("Connected"),
+                    battery,
+                },
+            );
+        }
+    }
+    Ok((Adapter(adapter), Devices(devices)))
+}
+
+/// Returns the properties of the interface in a{sa{sv}}.
+fn lookup_interface(interfaces: &glib::Variant, name: &str) -> Option<glib::Variant> {
+    (0..interfaces.n_children())
+        .map(|i| interfaces.child_value(i))
+        .find(|e| e.child_value(0).str() == Some(name))
+        .map(|e| e.child_value(1))
+}
+
+mod imp {
+    use super::{Adapter, Devices};
+    use crate::module_base::{poll_in_worker, PollHandle};
+    use glib::{ParamFlags, ParamSpec};
+    use gtk::prelude::*;
+    use gtk::subclass::prelude::*;
+    use gtk::{gio, glib};
+    use once_cell::sync::Lazy;
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    pub struct BluetoothState {
+        pub(crate) connection: RefCell<Option<gio::DBusConnection>>,
+        pub(crate) poller: RefCell<Option<PollHandle>>,
+        pub(crate) adapter: RefCell<Adapter>,
+        pub(crate) devices: RefCell<Devices>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for BluetoothState {
+        const NAME: &'static str = "BluetoothState";
+        type Type = super::BluetoothState;
+        type ParentType = glib::Object;
+    }
+
+    impl ObjectImpl for BluetoothState {
+        fn properties() -> &'static [ParamSpec] {
+            static PROPERTIES: Lazy<Vec<ParamSpec>> =
+                Lazy::new(|| vec![ADAPTER.clone(), DEVICES.clone()]);
+            PROPERTIES.as_ref()
+        }
+
+        fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            match pspec.name() {
+                "adapter" => self.adapter.borrow().to_value(),
+                "devices" => self.devices.borrow().to_value(),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn constructed(&self, obj: &Self::Type) {
+            self.parent_constructed(obj);
+            self.poller.replace(Some(poll_in_worker(
+                Duration::from_secs(60),
+                super::fetch,
+                glib::clone!(@weak obj => @default-return Continue(false), move |result| {
+                    match result {
+                        Ok((adapter, devices)) => {
+                            let self_ = BluetoothState::from_instance(&obj);
+                            self_.adapter.replace(adapter);
+                            self_.devices.replace(devices);
+                            obj.notify_by_pspec(&ADAPTER);
+                            obj.notify_by_pspec(&DEVICES);
+                        }
+                        Err(e) => eprintln!("Failed to read the Bluetooth state: {}", e),
+                    }
+                    Continue(true)
+                }),
+            )));
+
+            // Reload on any change (e.g. a device is connected, the adapter is turned off).
+            match gio::bus_get_sync(gio::BusType::System, None::<&gio::Cancellable>) {
+                Ok(conn) => {
+                    conn.signal_subscribe(
+                        Some(super::BLUEZ),
+                        None,
+                        None,
+                        None,
+                        None,
+                        gio::DBusSignalFlags::NONE,
+                        glib::clone!(@weak obj => move |_, _, _, _, _, _| obj.refresh()),
+                    );
+                    self.connection.replace(Some(conn));
+                }
+                Err(e) => eprintln!("Failed to connect to the system bus: {}", e),
+            }
+        }
+    }
+
+    lazy_static! {
+        static ref ADAPTER: ParamSpec = ParamSpec::new_boxed(
+            "adapter",
+            "adapter",
+            "adapter",
+            Adapter::static_type(),
+            ParamFlags::READABLE,
+        );
+        static ref DEVICES: ParamSpec = ParamSpec::new_boxed(
+            "devices",
+            "devices",
+            "devices",
+            Devices::static_type(),
+            ParamFlags::READABLE,
+        );
+    }
+}
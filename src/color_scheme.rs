@@ -0,0 +1,118 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Following the system dark mode.
+//!
+//! The desktop's color scheme is read from the settings portal (org.freedesktop.appearance
+//! color-scheme) and applied to gtk-application-prefer-dark-theme, so that the GTK theme colors
+//! (@theme_bg_color and @theme_fg_color in the CSS) switch at runtime.
+
+use crate::config::ColorScheme;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SETTINGS: &str = "org.freedesktop.portal.Settings";
+const NAMESPACE: &str = "org.freedesktop.appearance";
+const KEY: &str = "color-scheme";
+
+/// Applies the color scheme of the portal. 1 is dark, 2 is light, and 0 is no preference. With no
+/// preference, `initial` (the setting before jiji changes it) is restored.
+fn apply_portal_value(value: &glib::Variant, initial: bool) {
+    // Settings.Read wraps the value in another variant.
+    let mut value = value.clone();
+    while let Some(inner) = value.as_variant() {
+        value = inner;
+    }
+    let dark = match value.get::<u32>() {
+        Some(1) => true,
+        Some(2) => false,
+        Some(_) => initial,
+        None => return,
+    };
+    set_prefer_dark(dark);
+}
+
+/// Returns true if the error means that no settings portal is running.
+fn is_portal_missing(e: &glib::Error) -> bool {
+    [
+        gio::DBusError::ServiceUnknown,
+        gio::DBusError::NameHasNoOwner,
+        gio::DBusError::UnknownMethod,
+        gio::DBusError::UnknownInterface,
+        gio::DBusError::UnknownObject,
+    ]
+    .iter()
+    .any(|code| e.matches(*code))
+}
+
+fn set_prefer_dark(dark: bool) {
+    if let Some(settings) = gtk::Settings::default() {
+        settings.set_gtk_application_prefer_dark_theme(dark);
+    }
+}
+
+/// Sets up the color scheme. With "system", the bars follow the changes of the desktop's color
+/// scheme.
+pub(crate) fn setup(scheme: ColorScheme) {
+    match scheme {
+        ColorScheme::Dark => return set_prefer_dark(true),
+        ColorScheme::Light => return set_prefer_dark(false),
+        ColorScheme::System => {}
+    }
+    let initial = gtk::Settings::default()
+        .map(|s| s.is_gtk_application_prefer_dark_theme())
+        .unwrap_or(false);
+    let conn = match gio::bus_get_sync(gio::BusType::Session, None::<&gio::Cancellable>) {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("Failed to connect to the session bus: {}", e);
+            return;
+        }
+    };
+    conn.signal_subscribe(
+        Some(PORTAL),
+        Some(SETTINGS),
+        Some("SettingChanged"),
+        Some(PORTAL_PATH),
+        None,
+        gio::DBusSignalFlags::NONE,
+        move |_, _, _, _, _, params| {
+            // (namespace, key, value)
+            if params.child_value(0).str() == Some(NAMESPACE)
+                && params.child_value(1).str() == Some(KEY)
+            {
+                apply_portal_value(&params.child_value(2), initial);
+            }
+        },
+    );
+    conn.call(
+        Some(PORTAL),
+        PORTAL_PATH,
+        SETTINGS,
+        "Read",
+        Some(&(NAMESPACE, KEY).to_variant()),
+        None,
+        gio::DBusCallFlags::NONE,
+        5000,
+        None::<&gio::Cancellable>,
+        move |result| match result {
+            Ok(reply) => apply_portal_value(&reply.child_value(0), initial),
+            // Without the portal, the GTK theme and settings.ini decide.
+            Err(e) if is_portal_missing(&e) => {}
+            Err(e) => eprintln!("Failed to read the color scheme from the portal: {}", e),
+        },
+    );
+}
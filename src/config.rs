@@ -12,6 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub(crate) mod import;
+pub(crate) mod init;
+pub(crate) mod lint;
+pub(crate) mod schema;
+
+use crate::error::Error;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -19,8 +26,8 @@ use std::collections::HashMap;
 ///
 /// A plugin provides modules. For example, "i3" plugin may provide a workspace switcher module and
 /// a window title module.
-#[derive(Serialize, Deserialize)]
-pub(crate) struct PluginConfig {
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+pub struct PluginConfig {
     /// Name of the plugin.
     pub(crate) name: String,
 
@@ -29,22 +36,66 @@ pub(crate) struct PluginConfig {
     pub(crate) config: serde_json::Value,
 }
 
+fn default_enabled() -> bool {
+    true
+}
+
 /// Configuration for a module.
 ///
 /// Module is one component shown in a bar.
-#[derive(Serialize, Deserialize)]
-pub(crate) struct ModuleConfig {
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+pub struct ModuleConfig {
     /// Name of the module.
     pub(crate) name: String,
 
+    /// ID of the module. Defaults to the module name.
+    ///
+    /// This is used as the widget name of the module's container (e.g. "#clock" in CSS), and to
+    /// specify the module in IPC commands.
+    #[serde(default)]
+    pub(crate) id: Option<String>,
+
+    /// Scale of the text in the module (e.g. 1.5 for 150%). Unlike a theme change, this enlarges
+    /// only this module.
+    #[serde(default)]
+    pub(crate) text_scale: Option<f64>,
+
+    /// Priority to keep the module in the bar. When the modules don't fit in the bar, the modules
+    /// with lower priorities are moved to the overflow menu ("»") first. Defaults to 0.
+    #[serde(default)]
+    pub(crate) priority: i32,
+
+    /// Whether to show the module. Setting false disables the module without removing its
+    /// config.
+    #[serde(default = "default_enabled")]
+    pub(crate) enabled: bool,
+
+    /// Update interval in seconds, at least 1. This overrides `interval` in the module-specific
+    /// config of the polling modules. The other modules ignore it.
+    #[serde(default)]
+    pub(crate) interval: Option<u64>,
+
     /// Configuration of the module. The schema depends on the module.
     #[serde(default)]
     pub(crate) config: serde_json::Value,
 }
 
+/// Space around the bar in pixels.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Default)]
+pub(crate) struct MarginConfig {
+    #[serde(default)]
+    pub(crate) top: i32,
+    #[serde(default)]
+    pub(crate) bottom: i32,
+    #[serde(default)]
+    pub(crate) left: i32,
+    #[serde(default)]
+    pub(crate) right: i32,
+}
+
 /// Configuration for a monitor.
-#[derive(Serialize, Deserialize, Default)]
-pub(crate) struct MonitorConfig {
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub struct MonitorConfig {
     /// Hide the bar for this monitor.
     #[serde(default)]
     pub(crate) hidden: bool,
@@ -53,6 +104,37 @@ pub(crate) struct MonitorConfig {
     #[serde(default)]
     pub(crate) height: Option<i32>,
 
+    /// Scale of the bar on this monitor. The height and the text size are multiplied by this.
+    ///
+    /// GDK already scales the monitors with a scale factor (e.g. GDK_SCALE=2). This is for the
+    /// setups that GDK doesn't scale, like mixed DPI monitors on X11.
+    #[serde(default)]
+    pub(crate) scale: Option<f64>,
+
+    /// Derive the scale from the physical DPI of the monitor if scale is not set. 96 DPI is 1,
+    /// and the monitor's scale factor is taken into account.
+    #[serde(default)]
+    pub(crate) auto_scale: bool,
+
+    /// Space around the bar. With margins, the bar floats in a transparent window, and the space
+    /// is reserved for the bar too.
+    #[serde(default)]
+    pub(crate) margin: MarginConfig,
+
+    /// Width of the bar. Defaults to the monitor width without the left and right margins. A
+    /// narrower bar is centered.
+    #[serde(default)]
+    pub(crate) width: Option<i32>,
+
+    /// Radius of the bar's corners in pixels. This makes the bar float like the margins.
+    #[serde(default)]
+    pub(crate) corner_radius: Option<i32>,
+
+    /// Let the mouse events pass through the bar to the windows behind it. This is for bars that
+    /// only show information. Switched at runtime with `jiji msg click-through`.
+    #[serde(default)]
+    pub(crate) click_through: bool,
+
     /// Modules on the left side.
     #[serde(default)]
     pub(crate) left_modules: Vec<ModuleConfig>,
@@ -66,9 +148,180 @@ pub(crate) struct MonitorConfig {
     pub(crate) right_modules: Vec<ModuleConfig>,
 }
 
+/// The backend to resolve icons.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum IconBackend {
+    /// Emoji characters.
+    Emoji,
+    /// Nerd Font glyphs. This requires a Nerd Font in the CSS.
+    NerdFont,
+    /// The GTK icon theme.
+    Gtk,
+}
+
+impl Default for IconBackend {
+    fn default() -> Self {
+        IconBackend::Emoji
+    }
+}
+
+/// Configuration for icons.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub(crate) struct IconConfig {
+    /// The backend to resolve icons. "emoji" (default), "nerd-font", or "gtk".
+    #[serde(default)]
+    pub(crate) backend: IconBackend,
+
+    /// Overrides for the icons. The key is a semantic icon name (e.g. "volume-high"), and the
+    /// value is a text, or an icon name with the "gtk" backend.
+    #[serde(default)]
+    pub(crate) overrides: HashMap<String, String>,
+}
+
+/// The clock style of the default time formats.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum HourCycle {
+    /// 24-hour clock (e.g. "13:00").
+    H24,
+    /// 12-hour clock (e.g. "01:00 PM").
+    H12,
+}
+
+impl Default for HourCycle {
+    fn default() -> Self {
+        HourCycle::H24
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl Default for TemperatureUnit {
+    fn default() -> Self {
+        TemperatureUnit::Celsius
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ByteUnit {
+    /// Powers of 1024 (e.g. "KiB").
+    Binary,
+    /// Powers of 1000 (e.g. "kB").
+    Si,
+}
+
+impl Default for ByteUnit {
+    fn default() -> Self {
+        ByteUnit::Binary
+    }
+}
+
+/// Direction of the bar layout and the text.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum TextDirection {
+    /// The direction of the locale (LC_MESSAGES).
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+impl Default for TextDirection {
+    fn default() -> Self {
+        TextDirection::Auto
+    }
+}
+
+/// Locale and unit settings shared by all modules.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub(crate) struct LocaleConfig {
+    /// "h24" (default) or "h12". Used by the default time formats of the modules.
+    #[serde(default)]
+    pub(crate) hour_cycle: HourCycle,
+
+    /// The first day of the week in the calendars. 0 is Sunday and 1 is Monday. Defaults to the
+    /// one of the LC_TIME locale.
+    #[serde(default)]
+    pub(crate) first_day_of_week: Option<u32>,
+
+    /// "celsius" (default) or "fahrenheit". The thresholds in the module configs stay in Celsius.
+    #[serde(default)]
+    pub(crate) temperature_unit: TemperatureUnit,
+
+    /// "binary" (default) or "si".
+    #[serde(default)]
+    pub(crate) byte_unit: ByteUnit,
+
+    /// "auto" (default), "ltr", or "rtl". In the right-to-left direction, the module groups
+    /// mirror: the left modules are at the right end and the right modules are at the left end.
+    #[serde(default)]
+    pub(crate) text_direction: TextDirection,
+}
+
+/// A built-in theme loaded on top of the default CSS.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Theme {
+    /// Only the default CSS. The colors follow the GTK theme.
+    Default,
+    Dark,
+    Light,
+    HighContrast,
+    /// No backgrounds or borders.
+    Minimal,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::Default
+    }
+}
+
+/// Whether the GTK theme uses the dark variant.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ColorScheme {
+    /// Follow the desktop's color scheme through the settings portal.
+    System,
+    Dark,
+    Light,
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        ColorScheme::System
+    }
+}
+
+/// A named set of overrides that can be switched at runtime.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub(crate) struct ProfileConfig {
+    /// Monitor configurations that replace the ones in the base config.
+    #[serde(default)]
+    pub(crate) monitors: HashMap<String, MonitorConfig>,
+
+    /// Replaces the base default_monitor. The monitors in the base monitors config still use
+    /// their own configs.
+    #[serde(default)]
+    pub(crate) default_monitor: Option<MonitorConfig>,
+
+    /// The path to the GTK CSS file loaded on top of css_path while the profile is active.
+    ///
+    /// If the path is relative, it'll be relative from the XDG_CONFIG_DIR.
+    #[serde(default)]
+    pub(crate) css_path: String,
+}
+
 /// Configuration for the application.
-#[derive(Serialize, Deserialize)]
-pub(crate) struct Config {
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub struct Config {
     /// Disable loading the default CSS.
     #[serde(default)]
     pub(crate) disable_default_css: bool,
@@ -79,6 +332,33 @@ pub(crate) struct Config {
     #[serde(default)]
     pub(crate) css_path: String,
 
+    /// Built-in theme. "default", "dark", "light", "high-contrast", or "minimal". The CSS in
+    /// css_path is applied on top of it. Ignored if disable_default_css is set.
+    #[serde(default)]
+    pub(crate) theme: Theme,
+
+    /// "system" (default), "dark", or "light". Switches the dark variant of the GTK theme, which
+    /// the default CSS and the "default" theme follow.
+    #[serde(default)]
+    pub(crate) color_scheme: ColorScheme,
+
+    /// Same as the "high-contrast" theme. Kept for the configs before the themes.
+    #[serde(default)]
+    pub(crate) high_contrast: bool,
+
+    /// Disable animations in all modules (e.g. the battery charging animation and the flashing
+    /// urgent workspaces) and in GTK widgets.
+    #[serde(default)]
+    pub(crate) reduced_motion: bool,
+
+    /// Icon configuration shared by all modules.
+    #[serde(default)]
+    pub(crate) icons: IconConfig,
+
+    /// Locale and unit settings shared by all modules.
+    #[serde(default)]
+    pub(crate) locale: LocaleConfig,
+
     /// Plugin configurations. Only plugins configured here are activated.
     #[serde(default)]
     pub(crate) plugins: Vec<PluginConfig>,
@@ -93,26 +373,194 @@ pub(crate) struct Config {
     /// monitors config, this config is used.
     #[serde(default)]
     pub(crate) default_monitor: MonitorConfig,
+
+    /// Profiles keyed by the name (e.g. "work"). A profile overrides the monitor configs and the
+    /// CSS, and is switched with `jiji msg profile <name>` or the "profile-switcher" module.
+    #[serde(default)]
+    pub(crate) profiles: HashMap<String, ProfileConfig>,
+
+    /// The profile active at the first startup. Defaults to none, the base config. After a switch,
+    /// the last active profile is restored at startup.
+    #[serde(default)]
+    pub(crate) profile: Option<String>,
+}
+
+impl Config {
+    /// Parses a config in the same schema as config.json.
+    pub fn from_json(value: serde_json::Value) -> Result<Config, String> {
+        serde_json::from_value(value).map_err(|e| format!("Failed to parse the config: {}", e))
+    }
+
+    /// Returns the plugin configs.
+    pub fn plugins(&self) -> &Vec<PluginConfig> {
+        &self.plugins
+    }
+
+    /// Activates the plugin with the config.
+    pub fn add_plugin(&mut self, name: &str, config: serde_json::Value) -> &mut Self {
+        self.plugins.push(PluginConfig {
+            name: name.to_owned(),
+            config,
+        });
+        self
+    }
+
+    /// Sets the config for the monitor model (e.g. "HDMI-1").
+    pub fn set_monitor(&mut self, model: &str, monitor: MonitorConfig) -> &mut Self {
+        self.monitors.insert(model.to_owned(), monitor);
+        self
+    }
+
+    /// Sets the config for the monitors that don't have their own config.
+    pub fn set_default_monitor(&mut self, monitor: MonitorConfig) -> &mut Self {
+        self.default_monitor = monitor;
+        self
+    }
+}
+
+impl MonitorConfig {
+    /// Adds a module on the left side.
+    pub fn add_left(&mut self, module: ModuleConfig) -> &mut Self {
+        self.left_modules.push(module);
+        self
+    }
+
+    /// Adds a module at the center.
+    pub fn add_center(&mut self, module: ModuleConfig) -> &mut Self {
+        self.center_modules.push(module);
+        self
+    }
+
+    /// Adds a module on the right side.
+    pub fn add_right(&mut self, module: ModuleConfig) -> &mut Self {
+        self.right_modules.push(module);
+        self
+    }
+}
+
+impl ModuleConfig {
+    /// Creates a config for the module with the module-specific config.
+    pub fn new(name: &str, config: serde_json::Value) -> ModuleConfig {
+        ModuleConfig {
+            name: name.to_owned(),
+            id: None,
+            text_scale: None,
+            priority: 0,
+            enabled: default_enabled(),
+            interval: None,
+            config,
+        }
+    }
+
+    /// Sets the module ID.
+    pub fn with_id(mut self, id: &str) -> ModuleConfig {
+        self.id = Some(id.to_owned());
+        self
+    }
 }
 
 /// Reads the config file.
 ///
 /// The config file is based on the XDG Base Directory Specification. See [`crate::config::Config`]
-/// for the config schema.
-pub(crate) fn read_config() -> Config {
-    let xdg_dirs =
-        xdg::BaseDirectories::with_prefix("jiji").expect("Failed to read the config dir");
-    let config_path = xdg_dirs.find_config_file("config.json");
-    if let Some(pth) = config_path {
-        let config_str = std::fs::read_to_string(pth).expect("Failed to read the config.json");
-        serde_json::from_str(&config_str).expect("Failed to parse the config.json")
-    } else {
-        serde_json::from_str("{}").expect("Failed to create the default config")
+/// for the config schema. The default config is returned if there's no config file.
+pub fn read_config() -> Result<Config, Error> {
+    let xdg_dirs = xdg::BaseDirectories::with_prefix("jiji")
+        .map_err(|e| Error::file(std::path::Path::new("$XDG_CONFIG_HOME/jiji"), e))?;
+    match xdg_dirs.find_config_file("config.json") {
+        Some(p) => parse_config_file(&p),
+        None => Ok(default_config()),
+    }
+}
+
+/// Returns the config used when there's no config file.
+pub(crate) fn default_config() -> Config {
+    serde_json::from_str("{}").expect("Failed to create the default config")
+}
+
+fn parse_config_file(p: &std::path::Path) -> Result<Config, Error> {
+    let config_str = std::fs::read_to_string(p).map_err(|e| Error::file(p, e))?;
+    serde_json::from_str(&config_str).map_err(|e| Error::file(p, e))
+}
+
+/// Reads the configs specified by `--config`.
+///
+/// If `path` is a directory, all `*.json` files in it are read as named configs (e.g. "work" for
+/// "work.json"), sorted by the name. Each config has its own plugins and bars. If `path` is a
+/// file, it's read as the only config. If `path` is None, the default config file is read. The
+/// name is empty unless the configs are read from a directory.
+///
+/// The configs that cannot be read are skipped and returned as the errors.
+pub(crate) fn read_configs(path: Option<&std::path::Path>) -> (Vec<(String, Config)>, Vec<Error>) {
+    let path = match path {
+        Some(p) => p,
+        None => {
+            return match read_config() {
+                Ok(config) => (vec![(String::new(), config)], vec![]),
+                Err(e) => (vec![], vec![e]),
+            }
+        }
+    };
+    if !path.is_dir() {
+        return match parse_config_file(path) {
+            Ok(config) => (vec![(String::new(), config)], vec![]),
+            Err(e) => (vec![], vec![e]),
+        };
+    }
+    let mut paths: Vec<std::path::PathBuf> = match std::fs::read_dir(path) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().map_or(false, |e| e == "json"))
+            .collect(),
+        Err(e) => return (vec![], vec![Error::file(path, e)]),
+    };
+    paths.sort();
+    if paths.is_empty() {
+        return (vec![], vec![Error::file(path, "no *.json config")]);
+    }
+    let mut configs = vec![];
+    let mut errors = vec![];
+    for p in paths {
+        let name = p
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match parse_config_file(&p) {
+            Ok(config) => configs.push((name, config)),
+            Err(e) => errors.push(e),
+        }
+    }
+    (configs, errors)
+}
+
+/// Finds the MonitorConfig for the monitor with the overrides of the profile.
+///
+/// The profile's config for the monitor is used first, then the base config for the monitor, the
+/// profile's default_monitor, and the base default_monitor.
+pub(crate) fn find_profile_monitor_config<'a>(
+    config: &'a Config,
+    profile: Option<&str>,
+    monitor: &gtk::gdk::Monitor,
+) -> &'a MonitorConfig {
+    let profile = profile.and_then(|p| config.profiles.get(p));
+    if let (Some(profile), Some(model)) = (profile, monitor.model()) {
+        if let Some(mc) = profile.monitors.get(model.as_str()) {
+            return mc;
+        }
+    }
+    if monitor
+        .model()
+        .map_or(false, |m| config.monitors.contains_key(m.as_str()))
+    {
+        return find_monitor_config(config, monitor);
     }
+    profile
+        .and_then(|p| p.default_monitor.as_ref())
+        .unwrap_or(&config.default_monitor)
 }
 
 /// Finds the MonitorConfig for the monitor.
-pub(crate) fn find_monitor_config<'a>(
+pub fn find_monitor_config<'a>(
     config: &'a Config,
     monitor: &gtk::gdk::Monitor,
 ) -> &'a MonitorConfig {
@@ -0,0 +1,729 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Converting Waybar and Polybar configs.
+//!
+//! `jiji import --from waybar|polybar <path>` prints a jiji config converted from the common
+//! subset: the bar height, the module lists, the formats that have jiji equivalents, and custom
+//! script modules as buttons. What can't be converted is reported to stderr.
+
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A converted config with the notes about what couldn't be converted.
+#[derive(Default)]
+struct Importer {
+    plugins: BTreeSet<&'static str>,
+    notes: Vec<String>,
+}
+
+impl Importer {
+    fn note(&mut self, note: String) {
+        self.notes.push(note);
+    }
+
+    /// Returns the config of a module in the plugin.
+    fn module(&mut self, plugin: &'static str, name: &str, config: Value) -> Value {
+        self.plugins.insert(plugin);
+        if config.as_object().map_or(true, |o| o.is_empty()) {
+            json!({ "name": name })
+        } else {
+            json!({ "name": name, "config": config })
+        }
+    }
+
+    /// Returns the jiji config with the plugins of the converted modules.
+    fn config(&self, monitors: Map<String, Value>, default_monitor: Option<Value>) -> Value {
+        let plugins: Vec<Value> = self.plugins.iter().map(|p| json!({ "name": p })).collect();
+        let mut config = json!({
+            "plugins": plugins,
+            "default_monitor": default_monitor.unwrap_or_else(|| json!({})),
+        });
+        if !monitors.is_empty() {
+            config["monitors"] = Value::Object(monitors);
+        }
+        config
+    }
+}
+
+/// Replaces the placeholders in a format. None if the format has other placeholders.
+fn convert_format(format: &str, names: &[(&str, &str)]) -> Option<String> {
+    let mut ret = format.to_owned();
+    for (from, to) in names {
+        ret = ret.replace(&format!("{{{}}}", from), &format!("{{{}}}", to));
+    }
+    let known: Vec<String> = names.iter().map(|(_, to)| format!("{{{}}}", to)).collect();
+    let mut rest = ret.clone();
+    for k in &known {
+        rest = rest.replace(k, "");
+    }
+    if rest.contains('{') {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+/// Sets the converted format in the module config, or notes that it's not supported.
+fn set_format(
+    im: &mut Importer,
+    config: &mut Map<String, Value>,
+    id: &str,
+    format: Option<String>,
+    names: &[(&str, &str)],
+) {
+    if let Some(format) = format {
+        match convert_format(&format, names) {
+            Some(f) => {
+                config.insert("format".to_owned(), f.into());
+            }
+            None => im.note(format!("{}: format \"{}\" is not supported", id, format)),
+        }
+    }
+}
+
+/// Removes "{icon}" from a format. Returns the format and whether it had the icon.
+fn take_icon(format: &str) -> (String, bool) {
+    if format.contains("{icon}") {
+        (format.replace("{icon}", "").trim().to_owned(), true)
+    } else {
+        (format.to_owned(), false)
+    }
+}
+
+/// Converts a module. `kind` is the Waybar module name without the "#id" suffix, and `id` is the
+/// full name. Polybar modules are converted to Waybar's kinds first.
+fn convert_module(
+    im: &mut Importer,
+    kind: &str,
+    id: &str,
+    settings: &Map<String, Value>,
+) -> Option<Value> {
+    let string = |key: &str| {
+        settings
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_owned())
+    };
+    let mut config = Map::new();
+    let mut module = match kind {
+        "sway/workspaces" | "i3/workspaces" => im.module("i3", "i3-workspaces", Value::Null),
+        "clock" => {
+            // Waybar's "{:%H:%M}" is a strftime format in a placeholder.
+            if let Some(format) = string("format") {
+                match format.strip_prefix("{:").and_then(|f| f.strip_suffix('}')) {
+                    Some(f) if !f.contains('{') => {
+                        config.insert("format".to_owned(), f.into());
+                    }
+                    _ => im.note(format!("{}: format \"{}\" is not supported", id, format)),
+                }
+            }
+            im.module("clock", "clock", Value::Object(config))
+        }
+        "pulseaudio" | "wireplumber" => {
+            let format = string("format").map(|f| {
+                let (f, icon) = take_icon(&f);
+                if icon {
+                    config.insert("show_icon".to_owned(), true.into());
+                }
+                f
+            });
+            set_format(im, &mut config, id, format, &[("volume", "volume")]);
+            im.module(
+                "pulseaudio",
+                "pulseaudio-default-sink-volume-toggle",
+                Value::Object(config),
+            )
+        }
+        "battery" => {
+            // The battery module always shows the icon.
+            set_format(
+                im,
+                &mut config,
+                id,
+                string("format").map(|f| take_icon(&f).0),
+                &[("capacity", "capacity")],
+            );
+            if let Some(bat) = string("bat") {
+                config.insert("battery".to_owned(), bat.into());
+            }
+            im.module("battery", "battery", Value::Object(config))
+        }
+        "cpu" => im.module("sysinfo", "cpu", Value::Null),
+        "temperature" => {
+            set_format(
+                im,
+                &mut config,
+                id,
+                string("format").map(|f| {
+                    f.replace("{temperatureC}°C", "{temp}{unit}")
+                        .replace("{temperatureF}°F", "{temp}{unit}")
+                }),
+                &[("temperatureC", "temp"), ("unit", "unit")],
+            );
+            im.module("sysinfo", "temperature", Value::Object(config))
+        }
+        "network" => {
+            set_format(
+                im,
+                &mut config,
+                id,
+                string("format-wifi").or_else(|| string("format")),
+                &[("essid", "ssid"), ("signalStrength", "quality")],
+            );
+            if let Some(interface) = string("interface") {
+                config.insert("interface".to_owned(), interface.into());
+            }
+            im.module("wifi", "wifi", Value::Object(config))
+        }
+        "disk" => {
+            if let Some(path) = string("path") {
+                config.insert("path".to_owned(), path.into());
+            }
+            set_format(
+                im,
+                &mut config,
+                id,
+                string("format"),
+                &[
+                    ("free", "free"),
+                    ("used", "used"),
+                    ("total", "total"),
+                    ("percentage_used", "percentage"),
+                ],
+            );
+            im.module("disk", "disk", Value::Object(config))
+        }
+        "bluetooth" => im.module("bluetooth", "bluetooth", Value::Null),
+        "custom/text" => {
+            let text = string("format").unwrap_or_default();
+            im.module("text", "text", json!({ "text": text }))
+        }
+        k if k.starts_with("custom/") => {
+            let text = string("format")
+                .filter(|f| !f.contains('{'))
+                .unwrap_or_else(|| k["custom/".len()..].to_owned());
+            let command = string("on-click").unwrap_or_else(|| "true".to_owned());
+            let mut config = json!({ "text": text, "command": command });
+            if let Some(exec) = string("exec") {
+                if string("return-type").as_deref() == Some("json") {
+                    im.note(format!(
+                        "{}: the JSON output of \"{}\" is shown as is",
+                        id, exec
+                    ));
+                }
+                config["label_command"] = exec.into();
+            }
+            im.module("button", "button", config)
+        }
+        _ => {
+            im.note(format!("{}: no equivalent module", id));
+            return None;
+        }
+    };
+    if let Some(interval) = settings.get("interval").and_then(|v| v.as_u64()) {
+        module["interval"] = interval.into();
+    }
+    if id != module["name"].as_str().unwrap_or_default() {
+        module["id"] = id.replace('/', "-").replace('#', "-").into();
+    }
+    Some(module)
+}
+
+/// Removes the comments and the trailing commas of Waybar's JSONC.
+fn strip_jsonc(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut ret = String::new();
+    let mut i = 0;
+    let mut in_string = false;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if in_string {
+            ret.push(c);
+            if c == '\\' {
+                if let Some(n) = next {
+                    ret.push(n);
+                }
+                i += 1;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+            ret.push(c);
+        } else if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                i += 1;
+            }
+            i += 1;
+        } else if c == ',' {
+            let rest = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if rest != Some(&'}') && rest != Some(&']') {
+                ret.push(c);
+            }
+        } else {
+            ret.push(c);
+        }
+        i += 1;
+    }
+    ret
+}
+
+/// Converts a Waybar bar to a jiji monitor config.
+fn waybar_bar(im: &mut Importer, bar: &Map<String, Value>) -> Value {
+    let mut monitor = Map::new();
+    if let Some(height) = bar.get("height").and_then(|v| v.as_i64()) {
+        monitor.insert("height".to_owned(), height.into());
+    }
+    if let Some(width) = bar.get("width").and_then(|v| v.as_i64()) {
+        monitor.insert("width".to_owned(), width.into());
+    }
+    let mut margin = Map::new();
+    for side in ["top", "bottom", "left", "right"].iter() {
+        if let Some(m) = bar
+            .get(&format!("margin-{}", side))
+            .and_then(|v| v.as_i64())
+        {
+            margin.insert(side.to_string(), m.into());
+        }
+    }
+    if bar.contains_key("margin") {
+        im.note(
+            "margin is not supported. Use margin-top, margin-bottom, margin-left, and margin-right"
+                .to_owned(),
+        );
+    }
+    if !margin.is_empty() {
+        monitor.insert("margin".to_owned(), Value::Object(margin));
+    }
+    if let Some(position) = bar.get("position").and_then(|v| v.as_str()) {
+        if position != "top" {
+            im.note(format!("position \"{}\" is not supported", position));
+        }
+    }
+    for (from, to) in [
+        ("modules-left", "left_modules"),
+        ("modules-center", "center_modules"),
+        ("modules-right", "right_modules"),
+    ]
+    .iter()
+    {
+        let names = bar
+            .get(*from)
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default();
+        let modules: Vec<Value> = names
+            .iter()
+            .filter_map(|n| n.as_str())
+            .filter_map(|id| {
+                let kind = id.split('#').next().unwrap_or(id);
+                let settings = bar
+                    .get(id)
+                    .and_then(|v| v.as_object())
+                    .cloned()
+                    .unwrap_or_default();
+                convert_module(im, kind, id, &settings)
+            })
+            .collect();
+        monitor.insert(to.to_string(), modules.into());
+    }
+    Value::Object(monitor)
+}
+
+fn import_waybar(im: &mut Importer, text: &str) -> Result<Value, String> {
+    let parsed: Value = serde_json::from_str(&strip_jsonc(text))
+        .map_err(|e| format!("Failed to parse the Waybar config: {}", e))?;
+    let bars: Vec<Map<String, Value>> = match parsed {
+        Value::Array(bars) => bars
+            .into_iter()
+            .filter_map(|b| b.as_object().cloned())
+            .collect(),
+        Value::Object(bar) => vec![bar],
+        _ => return Err("The Waybar config is not an object or an array".to_owned()),
+    };
+    let mut monitors = Map::new();
+    let mut default_monitor = None;
+    for bar in &bars {
+        let monitor = waybar_bar(im, bar);
+        let outputs: Vec<String> = match bar.get("output") {
+            Some(Value::String(o)) => vec![o.clone()],
+            Some(Value::Array(os)) => os
+                .iter()
+                .filter_map(|o| o.as_str().map(|s| s.to_owned()))
+                .collect(),
+            _ => vec![],
+        };
+        if outputs.is_empty() {
+            if default_monitor.is_some() {
+                im.note("only the first bar without \"output\" is used".to_owned());
+            } else {
+                default_monitor = Some(monitor);
+            }
+            continue;
+        }
+        for output in outputs {
+            if output.starts_with('!') {
+                im.note(format!("output \"{}\" is not supported", output));
+                continue;
+            }
+            monitors.insert(output, monitor.clone());
+        }
+    }
+    Ok(im.config(monitors, default_monitor))
+}
+
+/// Parses Polybar's INI config into sections. References like "${colors.fg}" are kept as is.
+fn parse_ini(text: &str) -> BTreeMap<String, BTreeMap<String, String>> {
+    let mut sections: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = name.trim().to_owned();
+            sections.entry(current.clone()).or_default();
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            sections
+                .entry(current.clone())
+                .or_default()
+                .insert(key.trim().to_owned(), value.to_owned());
+        }
+    }
+    sections
+}
+
+/// Returns the Waybar kind and settings equivalent to a Polybar module.
+fn polybar_module(module: &BTreeMap<String, String>) -> Option<(&'static str, Map<String, Value>)> {
+    let get = |key: &str| module.get(key).cloned();
+    let mut settings = Map::new();
+    let mut set = |key: &str, value: Option<String>| {
+        if let Some(value) = value {
+            settings.insert(key.to_owned(), value.into());
+        }
+    };
+    let kind = match module.get("type").map(|t| t.as_str()) {
+        Some("internal/i3") | Some("internal/xworkspaces") => "i3/workspaces",
+        Some("internal/date") => {
+            // Polybar's formats are strftime formats.
+            let date = [get("date"), get("time")]
+                .iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(" ");
+            if !date.is_empty() {
+                set("format", Some(format!("{{:{}}}", date)));
+            }
+            "clock"
+        }
+        Some("internal/pulseaudio") => "pulseaudio",
+        Some("internal/battery") => {
+            set("bat", get("battery"));
+            "battery"
+        }
+        Some("internal/cpu") => "cpu",
+        Some("internal/temperature") => "temperature",
+        Some("internal/network") => {
+            set("interface", get("interface"));
+            "network"
+        }
+        Some("internal/fs") => {
+            set("path", get("mount-0"));
+            "disk"
+        }
+        Some("custom/text") => {
+            set("format", get("content").or_else(|| get("format")));
+            "custom/text"
+        }
+        Some("custom/script") => {
+            set("exec", get("exec"));
+            set("on-click", get("click-left"));
+            "custom/script"
+        }
+        _ => return None,
+    };
+    if let Some(interval) = module.get("interval").and_then(|i| i.parse::<u64>().ok()) {
+        settings.insert("interval".to_owned(), interval.into());
+    }
+    Some((kind, settings))
+}
+
+fn import_polybar(im: &mut Importer, text: &str) -> Result<Value, String> {
+    let sections = parse_ini(text);
+    let mut monitors = Map::new();
+    let mut default_monitor = None;
+    for (name, bar) in sections.iter() {
+        let bar_name = match name.strip_prefix("bar/") {
+            Some(n) => n,
+            None => continue,
+        };
+        let mut monitor = Map::new();
+        if let Some(height) = bar.get("height") {
+            match height.parse::<i64>() {
+                Ok(h) => {
+                    monitor.insert("height".to_owned(), h.into());
+                }
+                Err(_) => im.note(format!(
+                    "bar/{}: height \"{}\" is not supported",
+                    bar_name, height
+                )),
+            }
+        }
+        if bar.get("bottom").map(|b| b == "true").unwrap_or(false) {
+            im.note(format!("bar/{}: bottom is not supported", bar_name));
+        }
+        if let Some(radius) = bar.get("radius").and_then(|r| r.parse::<f64>().ok()) {
+            monitor.insert("corner_radius".to_owned(), (radius.round() as i64).into());
+        }
+        for (from, to) in [
+            ("modules-left", "left_modules"),
+            ("modules-center", "center_modules"),
+            ("modules-right", "right_modules"),
+        ]
+        .iter()
+        {
+            let ids = bar.get(*from).cloned().unwrap_or_default();
+            let modules: Vec<Value> = ids
+                .split_whitespace()
+                .filter_map(|id| {
+                    let module = match sections.get(&format!("module/{}", id)) {
+                        Some(m) => m,
+                        None => {
+                            im.note(format!("{}: no [module/{}] section", id, id));
+                            return None;
+                        }
+                    };
+                    match polybar_module(module) {
+                        Some((kind, settings)) => convert_module(im, kind, id, &settings),
+                        None => {
+                            let kind = module.get("type").cloned().unwrap_or_default();
+                            im.note(format!("{}: no equivalent module for {}", id, kind));
+                            None
+                        }
+                    }
+                })
+                .collect();
+            monitor.insert(to.to_string(), modules.into());
+        }
+        let monitor = Value::Object(monitor);
+        match bar.get("monitor") {
+            Some(output) if !output.starts_with("${") => {
+                monitors.insert(output.clone(), monitor);
+            }
+            _ if default_monitor.is_none() => default_monitor = Some(monitor),
+            _ => im.note(format!(
+                "bar/{}: only the first bar without a monitor is used",
+                bar_name
+            )),
+        }
+    }
+    if monitors.is_empty() && default_monitor.is_none() {
+        return Err("No [bar/...] section in the Polybar config".to_owned());
+    }
+    Ok(im.config(monitors, default_monitor))
+}
+
+/// Converts a config as a subcommand. Returns the exit code.
+///
+/// The config is printed to stdout and what couldn't be converted to stderr.
+pub(crate) fn run_cli(args: &[String]) -> i32 {
+    let (from, path) = match args {
+        [flag, from, path] if flag == "--from" => (from.as_str(), path),
+        _ => {
+            eprintln!("Usage: jiji import --from waybar|polybar <path>");
+            return 2;
+        }
+    };
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            return 1;
+        }
+    };
+    let mut im = Importer::default();
+    let result = match from {
+        "waybar" => import_waybar(&mut im, &text),
+        "polybar" => import_polybar(&mut im, &text),
+        _ => Err(format!("Unknown config type: {}", from)),
+    };
+    let config =
+        match result.and_then(|c| serde_json::to_string_pretty(&c).map_err(|e| e.to_string())) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        };
+    println!("{}", config);
+    for note in im.notes.iter() {
+        eprintln!("Not converted: {}", note);
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_jsonc_removes_comments_and_trailing_commas() {
+        let text = r#"{
+  // A comment
+  "a": "http://x/*y*/", /* A block
+  comment */ "b": ["\"//", 1,],
+}"#;
+        let parsed: Value = serde_json::from_str(&strip_jsonc(text)).unwrap();
+        assert_eq!(parsed, json!({ "a": "http://x/*y*/", "b": ["\"//", 1] }));
+    }
+
+    #[test]
+    fn parse_ini_sections() {
+        let text = "; comment\nkey = top\n[bar/a]\n# comment\nheight = \"30\"\n\
+                    font = ${colors.fg}\n[ module/b ]\ntype=internal/cpu\n";
+        let sections = parse_ini(text);
+        assert_eq!(sections[""]["key"], "top");
+        assert_eq!(sections["bar/a"]["height"], "30");
+        assert_eq!(sections["bar/a"]["font"], "${colors.fg}");
+        assert_eq!(sections["module/b"]["type"], "internal/cpu");
+        assert_eq!(sections.len(), 3);
+    }
+
+    #[test]
+    fn waybar() {
+        let mut im = Importer::default();
+        let config = import_waybar(&mut im, include_str!("testdata/waybar.jsonc")).unwrap();
+        assert_eq!(
+            config,
+            json!({
+                "plugins": [
+                    { "name": "button" },
+                    { "name": "clock" },
+                    { "name": "i3" },
+                    { "name": "pulseaudio" },
+                    { "name": "sysinfo" },
+                ],
+                "default_monitor": {
+                    "height": 24,
+                    "left_modules": [{ "name": "cpu" }],
+                    "center_modules": [],
+                    "right_modules": [],
+                },
+                "monitors": {
+                    "DP-1": {
+                        "height": 30,
+                        "margin": { "top": 4 },
+                        "left_modules": [{ "name": "i3-workspaces", "id": "sway-workspaces" }],
+                        "center_modules": [{ "name": "clock", "config": { "format": "%H:%M" } }],
+                        "right_modules": [
+                            {
+                                "name": "pulseaudio-default-sink-volume-toggle",
+                                "id": "pulseaudio",
+                                "config": { "format": "{volume}%", "show_icon": true },
+                            },
+                            {
+                                "name": "button",
+                                "id": "custom-weather-home",
+                                "interval": 600,
+                                "config": {
+                                    "text": "weather",
+                                    "command": "xdg-open https://example.com/",
+                                    "label_command": "weather.sh",
+                                },
+                            },
+                        ],
+                    },
+                },
+            })
+        );
+        assert_eq!(im.notes, vec!["mpd: no equivalent module".to_owned()]);
+    }
+
+    #[test]
+    fn polybar() {
+        let mut im = Importer::default();
+        let config = import_polybar(&mut im, include_str!("testdata/polybar.ini")).unwrap();
+        assert_eq!(
+            config,
+            json!({
+                "plugins": [
+                    { "name": "button" },
+                    { "name": "clock" },
+                    { "name": "i3" },
+                    { "name": "sysinfo" },
+                ],
+                "default_monitor": {
+                    "left_modules": [],
+                    "center_modules": [{ "name": "cpu" }],
+                    "right_modules": [],
+                },
+                "monitors": {
+                    "HDMI-1": {
+                        "height": 28,
+                        "corner_radius": 6,
+                        "left_modules": [{ "name": "i3-workspaces", "id": "i3" }],
+                        "center_modules": [],
+                        "right_modules": [
+                            {
+                                "name": "clock",
+                                "id": "date",
+                                "interval": 5,
+                                "config": { "format": "%Y-%m-%d %H:%M" },
+                            },
+                            {
+                                "name": "button",
+                                "id": "script",
+                                "config": {
+                                    "text": "script",
+                                    "command": "notify-send hi",
+                                    "label_command": "~/bin/status.sh",
+                                },
+                            },
+                        ],
+                    },
+                },
+            })
+        );
+        assert_eq!(
+            im.notes,
+            vec![
+                "missing: no [module/missing] section".to_owned(),
+                "bar/other: height \"3%\" is not supported".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn polybar_without_bars() {
+        let mut im = Importer::default();
+        assert!(import_polybar(&mut im, "[module/a]\ntype = internal/cpu\n").is_err());
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A starter config generator.
+//!
+//! `jiji init` looks at the environment and writes a config.json with the modules that work on
+//! this machine: workspaces on the left, the clock at the center, and the volume and the battery
+//! on the right.
+
+use serde_json::json;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Returns true if i3 or sway is running.
+fn has_i3() -> bool {
+    if std::env::var_os("I3SOCK").is_some() || std::env::var_os("SWAYSOCK").is_some() {
+        return true;
+    }
+    Command::new("i3")
+        .arg("--get-socketpath")
+        .stderr(Stdio::null())
+        .output()
+        .map(|o| o.status.success() && !o.stdout.is_empty())
+        .unwrap_or(false)
+}
+
+/// Returns true if a PulseAudio server (including pipewire-pulse) is running.
+fn has_pulseaudio() -> bool {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        if Path::new(&dir).join("pulse/native").exists() {
+            return true;
+        }
+    }
+    Command::new("pactl")
+        .arg("info")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+fn has_battery() -> bool {
+    std::fs::read_dir("/sys/class/power_supply")
+        .map(|d| {
+            d.flatten()
+                .any(|e| e.file_name().to_string_lossy().starts_with("BAT"))
+        })
+        .unwrap_or(false)
+}
+
+/// Returns the models of the connected monitors and the model of the primary monitor.
+fn monitors() -> (Vec<String>, Option<String>) {
+    if gtk::init().is_err() {
+        return (vec![], None);
+    }
+    let display = match gtk::gdk::Display::default() {
+        Some(display) => display,
+        None => return (vec![], None),
+    };
+    let models = (0..display.n_monitors())
+        .filter_map(|i| display.monitor(i)?.model())
+        .map(|m| m.to_string())
+        .collect();
+    let primary = display
+        .primary_monitor()
+        .and_then(|m| m.model())
+        .map(|m| m.to_string());
+    (models, primary)
+}
+
+fn module(name: &str) -> serde_json::Value {
+    json!({ "name": name })
+}
+
+/// Returns a starter config for this machine.
+pub(crate) fn generate() -> serde_json::Value {
+    let mut plugins = vec![json!({ "name": "clock" })];
+    let mut left = vec![];
+    let mut right = vec![];
+    if cfg!(feature = "i3") && has_i3() {
+        plugins.push(json!({ "name": "i3" }));
+        left.push(module("i3-workspaces"));
+    }
+    if cfg!(feature = "pulseaudio") && has_pulseaudio() {
+        plugins.push(json!({ "name": "pulseaudio" }));
+        right.push(module("pulseaudio-default-sink-volume-toggle"));
+    }
+    if has_battery() {
+        plugins.push(json!({ "name": "battery" }));
+        right.push(module("battery"));
+    }
+    let center = vec![module("clock")];
+    let mut config = json!({
+        "plugins": plugins,
+        "default_monitor": {
+            "left_modules": left,
+            "center_modules": center,
+            "right_modules": right,
+        },
+    });
+    // With multiple monitors, the secondary monitors get the workspaces and the clock only.
+    let (models, primary) = monitors();
+    if let (true, Some(primary)) = (models.len() > 1, primary) {
+        let monitors: serde_json::Map<String, serde_json::Value> = models
+            .into_iter()
+            .filter(|m| *m != primary)
+            .map(|m| {
+                (
+                    m,
+                    json!({
+                        "left_modules": left,
+                        "center_modules": center,
+                    }),
+                )
+            })
+            .collect();
+        config["monitors"] = serde_json::Value::Object(monitors);
+    }
+    config
+}
+
+/// Writes a starter config as a subcommand. Returns the exit code.
+///
+/// An existing config is not overwritten unless "--force" is given. "--stdout" prints the config
+/// instead.
+pub(crate) fn run_cli(args: &[String]) -> i32 {
+    let config = match serde_json::to_string_pretty(&generate()) {
+        Ok(config) => config + "\n",
+        Err(e) => {
+            eprintln!("Failed to generate the config: {}", e);
+            return 1;
+        }
+    };
+    if args.iter().any(|a| a == "--stdout") {
+        print!("{}", config);
+        return 0;
+    }
+    let xdg_dirs = match xdg::BaseDirectories::with_prefix("jiji") {
+        Ok(dirs) => dirs,
+        Err(e) => {
+            eprintln!("Failed to read the config dir: {}", e);
+            return 1;
+        }
+    };
+    let path = match xdg_dirs.place_config_file("config.json") {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Failed to create the config dir: {}", e);
+            return 1;
+        }
+    };
+    if path.exists() && !args.iter().any(|a| a == "--force") {
+        eprintln!(
+            "{} already exists. Use --force to overwrite it",
+            path.display()
+        );
+        return 1;
+    }
+    if let Err(e) = std::fs::write(&path, config) {
+        eprintln!("Failed to write {}: {}", path.display(), e);
+        return 1;
+    }
+    println!("Wrote {}", path.display());
+    0
+}
@@ -0,0 +1,147 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lint rules for common mistakes in the config.
+//!
+//! The config-only rules are in this module. Plugins can add rules that need the runtime state
+//! (e.g. the existing audio devices) with [`register`].
+
+use super::{Config, ModuleConfig};
+use gtk::prelude::*;
+use std::cell::RefCell;
+
+thread_local! {
+    static RULES: RefCell<Vec<Box<dyn Fn() -> Vec<String>>>> = RefCell::new(vec![]);
+}
+
+/// Registers a lint rule that returns the warning messages.
+pub(crate) fn register<F: Fn() -> Vec<String> + 'static>(rule: F) {
+    RULES.with(|r| r.borrow_mut().push(Box::new(rule)));
+}
+
+fn all_modules(config: &Config) -> impl Iterator<Item = &ModuleConfig> {
+    config
+        .monitors
+        .values()
+        .chain(std::iter::once(&config.default_monitor))
+        .chain(
+            config
+                .profiles
+                .values()
+                .flat_map(|p| p.monitors.values().chain(p.default_monitor.iter())),
+        )
+        .flat_map(|m| {
+            m.left_modules
+                .iter()
+                .chain(m.center_modules.iter())
+                .chain(m.right_modules.iter())
+        })
+}
+
+/// Checks the modules that are not provided by the configured plugins.
+///
+/// `module_names` are the modules provided by the configured plugins.
+fn check_modules(config: &Config, module_names: &[String]) -> Vec<String> {
+    let plugin_names: Vec<&str> = config.plugins.iter().map(|p| p.name.as_str()).collect();
+    let mut ret = vec![];
+    for p in &plugin_names {
+        // The plugins compiled out are reported when the plugins are loaded.
+        if crate::module::missing_feature(p).is_none() && !crate::module::plugin_exists(p) {
+            ret.push(format!("plugin \"{}\" doesn't exist", p));
+        }
+    }
+    // The modules in the containers too.
+    let mut modules: Vec<ModuleConfig> = all_modules(config).cloned().collect();
+    let mut i = 0;
+    while i < modules.len() {
+        let children = crate::containers::children(&modules[i]);
+        modules.extend(children);
+        i += 1;
+    }
+    for m in modules.iter() {
+        if module_names.contains(&m.name) || crate::containers::is_container(&m.name) {
+            continue;
+        }
+        // Most modules are named after their plugin (e.g. "i3-workspaces" in "i3").
+        let hint = match crate::module::plugin_names()
+            .into_iter()
+            .find(|p| m.name == *p || m.name.starts_with(&format!("{}-", p)))
+        {
+            Some(p) if plugin_names.contains(&p) => {
+                format!(". Plugin \"{}\" doesn't have this module", p)
+            }
+            Some(p) => format!(". Add {{\"name\": \"{}\"}} to \"plugins\"", p),
+            None => String::new(),
+        };
+        ret.push(format!(
+            "module \"{}\" is not provided by the plugins {:?}{}",
+            m.name, plugin_names, hint
+        ));
+    }
+    ret
+}
+
+/// Checks the monitor configs that don't match with any connected monitor.
+fn check_monitors(config: &Config, models: &[String]) -> Vec<String> {
+    config
+        .monitors
+        .keys()
+        .filter(|k| !models.contains(k))
+        .map(|k| {
+            format!(
+                "monitor config \"{}\" doesn't match any connected monitor. Connected monitors: {:?}",
+                k, models
+            )
+        })
+        .collect()
+}
+
+/// Checks the startup profile that doesn't exist.
+fn check_profile(config: &Config) -> Vec<String> {
+    match config.profile {
+        Some(ref p) if !config.profiles.contains_key(p) => {
+            let mut names: Vec<&String> = config.profiles.keys().collect();
+            names.sort();
+            vec![format!(
+                "profile \"{}\" doesn't exist. Profiles: {:?}",
+                p, names
+            )]
+        }
+        _ => vec![],
+    }
+}
+
+/// Runs all lint rules and returns the warning messages.
+pub(crate) fn run(config: &Config, module_names: &[String]) -> Vec<String> {
+    let models: Vec<String> = gtk::gdk::Display::default()
+        .map(|d| {
+            (0..d.n_monitors())
+                .filter_map(|i| d.monitor(i)?.model())
+                .map(|m| m.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut ret = check_modules(config, module_names);
+    ret.extend(check_monitors(config, &models));
+    ret.extend(check_profile(config));
+    RULES.with(|r| {
+        for rule in r.borrow().iter() {
+            ret.extend(rule());
+        }
+    });
+    // The same module on multiple bars registers the same rule.
+    ret.sort();
+    ret.dedup();
+    ret
+}
@@ -0,0 +1,77 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JSON Schema of config.json for editors.
+//!
+//! `jiji schema` prints the schema. The schema of the module-specific config is chosen by the
+//! module name, so that editors can complete and validate the module configs too.
+
+use super::Config;
+use schemars::gen::SchemaSettings;
+use serde_json::json;
+
+/// Returns the JSON Schema of config.json with the module-specific configs of all plugins.
+pub(crate) fn generate() -> serde_json::Value {
+    let mut root = serde_json::to_value(schemars::schema_for!(Config)).unwrap_or_default();
+    // Plugins can have config types with the same name (e.g. "Provider"). Inlining them avoids
+    // the collisions in the definitions.
+    let mut gen = SchemaSettings::draft07()
+        .with(|s| s.inline_subschemas = true)
+        .into_generator();
+    let mut factories: Vec<_> = crate::module::make_all_module_factories()
+        .into_iter()
+        .collect();
+    factories.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut variants: Vec<serde_json::Value> = factories
+        .iter()
+        .map(|(name, factory)| {
+            json!({
+                "properties": {
+                    "name": {"const": name},
+                    "config": factory.config_schema(&mut gen),
+                },
+            })
+        })
+        .collect();
+    for (name, schema) in crate::containers::config_schemas(&mut gen) {
+        variants.push(json!({
+            "properties": {
+                "name": {"const": name},
+                "config": schema,
+            },
+        }));
+    }
+    let module = &mut root["definitions"]["ModuleConfig"];
+    let base = module.take();
+    *module = json!({"allOf": [base, {"oneOf": variants}]});
+
+    let mut plugins = crate::module::plugin_names();
+    plugins.sort();
+    root["definitions"]["PluginConfig"]["properties"]["name"]["enum"] = json!(plugins);
+    root
+}
+
+/// Prints the schema as a subcommand. Returns the exit code.
+pub(crate) fn run_cli() -> i32 {
+    match serde_json::to_string_pretty(&generate()) {
+        Ok(s) => {
+            println!("{}", s);
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to print the schema: {}", e);
+            1
+        }
+    }
+}
@@ -0,0 +1,351 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Modules that contain other modules.
+//!
+//! The containers (e.g. "carousel" and "reveal-on-hover") are built into the bar instead of
+//! plugins, because they create the child modules with the module factories of the config. The
+//! child modules are configured in the same way as the modules in a bar.
+
+use crate::config::ModuleConfig;
+use crate::error::Error;
+use crate::module::{Module, ModuleFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Names of the containers.
+const CONTAINERS: &[&str] = &["carousel", "reveal-on-hover"];
+
+/// Returns true if the module is a built-in container.
+pub(crate) fn is_container(name: &str) -> bool {
+    CONTAINERS.contains(&name)
+}
+
+/// Creates the module for the config. An error in a child module fails the container.
+pub(crate) fn create_module(
+    config: &ModuleConfig,
+    module_factories: &HashMap<String, Box<dyn ModuleFactory>>,
+    monitor: &gtk::gdk::Monitor,
+) -> Result<Box<dyn Module>, Error> {
+    let name = config.name.as_str();
+    let declares_interval = match name {
+        "carousel" => true,
+        "reveal-on-hover" => false,
+        _ => module_factories
+            .get(name)
+            .map_or(false, |f| crate::module_base::declares_interval(f.as_ref())),
+    };
+    if config.interval.is_some() && !declares_interval {
+        eprintln!(
+            "Module {} doesn't have an update interval. Ignoring \"interval\"",
+            name
+        );
+    }
+    let json_config = crate::module_base::apply_interval(
+        &config.config,
+        config.interval.filter(|_| declares_interval),
+    )
+    .map_err(|e| Error::module_config(name, e))?;
+    match name {
+        "carousel" => {
+            let config: CarouselConfig =
+                serde_json::from_value(json_config).map_err(|e| Error::module_config(name, e))?;
+            Ok(Box::new(Carousel {
+                children: create_children(&config.modules, module_factories, monitor)?,
+                config,
+            }))
+        }
+        "reveal-on-hover" => {
+            let config: RevealConfig =
+                serde_json::from_value(json_config).map_err(|e| Error::module_config(name, e))?;
+            let mut children = create_children(
+                &[config.compact.clone(), config.expanded.clone()],
+                module_factories,
+                monitor,
+            )?;
+            let (expanded, compact) = match (children.pop(), children.pop()) {
+                (Some(expanded), Some(compact)) => (expanded, compact),
+                _ => {
+                    return Err(Error::module_config(
+                        name,
+                        "the compact and expanded modules must be enabled",
+                    ))
+                }
+            };
+            Ok(Box::new(Reveal {
+                config,
+                compact,
+                expanded,
+            }))
+        }
+        _ => match module_factories.get(name) {
+            Some(factory) => factory.create(&json_config, monitor),
+            None => Err(Error::UnknownModule(name.to_owned())),
+        },
+    }
+}
+
+/// Returns the child module configs of a container. Empty for other modules.
+pub(crate) fn children(config: &ModuleConfig) -> Vec<ModuleConfig> {
+    match config.name.as_str() {
+        "carousel" => serde_json::from_value::<CarouselConfig>(config.config.clone())
+            .map(|c| c.modules)
+            .unwrap_or_default(),
+        "reveal-on-hover" => serde_json::from_value::<RevealConfig>(config.config.clone())
+            .map(|c| vec![c.compact, c.expanded])
+            .unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+/// Returns the JSON Schemas of the container configs by the name. The child module configs refer
+/// to the ModuleConfig definition.
+pub(crate) fn config_schemas(
+    gen: &mut schemars::gen::SchemaGenerator,
+) -> Vec<(&'static str, serde_json::Value)> {
+    let mut carousel =
+        serde_json::to_value(gen.subschema_for::<CarouselConfig>()).unwrap_or_default();
+    carousel["properties"]["modules"]["items"] =
+        serde_json::json!({"$ref": "#/definitions/ModuleConfig"});
+    let mut reveal = serde_json::to_value(gen.subschema_for::<RevealConfig>()).unwrap_or_default();
+    for child in ["compact", "expanded"].iter() {
+        reveal["properties"][*child] = serde_json::json!({"$ref": "#/definitions/ModuleConfig"});
+    }
+    vec![("carousel", carousel), ("reveal-on-hover", reveal)]
+}
+
+/// A child module with the module config.
+struct Child {
+    id: String,
+    text_scale: Option<f64>,
+    module: Box<dyn Module>,
+}
+
+fn create_children(
+    configs: &[ModuleConfig],
+    module_factories: &HashMap<String, Box<dyn ModuleFactory>>,
+    monitor: &gtk::gdk::Monitor,
+) -> Result<Vec<Child>, Error> {
+    configs
+        .iter()
+        .filter(|c| c.enabled)
+        .map(|c| {
+            Ok(Child {
+                id: c.id.clone().unwrap_or_else(|| c.name.clone()),
+                text_scale: c.text_scale,
+                module: create_module(c, module_factories, monitor)?,
+            })
+        })
+        .collect()
+}
+
+/// Builds the child module in a new container, in the same way as the modules in a bar.
+fn build_child(child: &Child) -> gtk::Box {
+    let container = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    container.set_widget_name(&child.id);
+    crate::module_base::register_module(&child.id, &container);
+    if let Some(scale) = child.text_scale {
+        crate::bar::add_text_scale_css(&container, &child.id, scale);
+    }
+    child.module.build_ui(&container);
+    container
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct CarouselConfig {
+    /// The modules to cycle through.
+    modules: Vec<ModuleConfig>,
+
+    /// Seconds to show each module. 0 cycles only on click and scroll.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Slide to the next module. Disabled with reduced_motion.
+    #[serde(default = "default_true")]
+    animate: bool,
+}
+
+/// Shows one of the child modules at a time. A click or scrolling down shows the next module, and
+/// scrolling up shows the previous one.
+struct Carousel {
+    config: CarouselConfig,
+    children: Vec<Child>,
+}
+
+/// Shows the module after (or before if `forward` is false) the visible one.
+fn cycle(stack: &gtk::Stack, forward: bool) {
+    let children = stack.children();
+    if children.is_empty() {
+        return;
+    }
+    let current = stack
+        .visible_child()
+        .and_then(|v| children.iter().position(|c| *c == v))
+        .unwrap_or(0);
+    let next = if forward {
+        (current + 1) % children.len()
+    } else {
+        (current + children.len() - 1) % children.len()
+    };
+    stack.set_visible_child(&children[next]);
+}
+
+impl Module for Carousel {
+    fn build_ui(&self, container: &gtk::Box) {
+        let event_box = gtk::EventBox::new();
+        let stack = gtk::Stack::new();
+        stack.style_context().add_class("carousel");
+        // Keeps the width of the visible module.
+        stack.set_hhomogeneous(false);
+        if self.config.animate && !crate::module_base::reduced_motion() {
+            stack.set_transition_type(gtk::StackTransitionType::SlideLeftRight);
+        }
+        for child in self.children.iter() {
+            stack.add(&build_child(child));
+        }
+        event_box.add(&stack);
+        container.add(&event_box);
+
+        event_box.connect_button_release_event(
+            glib::clone!(@weak stack => @default-return Inhibit(false), move |_, e| {
+                if e.button() == gtk::gdk::BUTTON_PRIMARY {
+                    cycle(&stack, true);
+                    return Inhibit(true);
+                }
+                Inhibit(false)
+            }),
+        );
+        event_box.add_events(gtk::gdk::EventMask::SCROLL_MASK);
+        event_box.connect_scroll_event(
+            glib::clone!(@weak stack => @default-return Inhibit(false), move |_, e| {
+                match e.direction() {
+                    gtk::gdk::ScrollDirection::Up => cycle(&stack, false),
+                    gtk::gdk::ScrollDirection::Down => cycle(&stack, true),
+                    _ => return Inhibit(false),
+                }
+                Inhibit(true)
+            }),
+        );
+        if self.config.interval > 0 {
+            glib::timeout_add_seconds_local(
+                self.config.interval as u32,
+                glib::clone!(@weak stack => @default-return Continue(false), move || {
+                    cycle(&stack, true);
+                    Continue(true)
+                }),
+            );
+        }
+    }
+}
+
+/// The side where the expanded module is revealed.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum Side {
+    Left,
+    Right,
+}
+
+impl Default for Side {
+    fn default() -> Self {
+        Side::Right
+    }
+}
+
+fn default_transition_duration() -> u32 {
+    250
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct RevealConfig {
+    /// The module always shown (e.g. a volume icon).
+    compact: ModuleConfig,
+
+    /// The module revealed while hovered (e.g. a volume slider).
+    expanded: ModuleConfig,
+
+    /// "left" or "right" (default) of the compact module. Mirrored in the right-to-left direction.
+    #[serde(default)]
+    side: Side,
+
+    /// Duration of the slide animation in milliseconds. No animation with reduced_motion.
+    #[serde(default = "default_transition_duration")]
+    transition_duration: u32,
+}
+
+/// Shows the compact module, and reveals the expanded module next to it while hovered.
+struct Reveal {
+    config: RevealConfig,
+    compact: Child,
+    expanded: Child,
+}
+
+impl Module for Reveal {
+    fn build_ui(&self, container: &gtk::Box) {
+        let event_box = gtk::EventBox::new();
+        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        hbox.style_context().add_class("reveal-on-hover");
+        let revealer = gtk::Revealer::new();
+        revealer.set_transition_type(match self.config.side {
+            Side::Left => gtk::RevealerTransitionType::SlideLeft,
+            Side::Right => gtk::RevealerTransitionType::SlideRight,
+        });
+        revealer.set_transition_duration(if crate::module_base::reduced_motion() {
+            0
+        } else {
+            self.config.transition_duration
+        });
+        revealer.add(&build_child(&self.expanded));
+        let compact = build_child(&self.compact);
+        if self.config.side == Side::Left {
+            hbox.add(&revealer);
+            hbox.add(&compact);
+        } else {
+            hbox.add(&compact);
+            hbox.add(&revealer);
+        }
+        event_box.add(&hbox);
+        container.add(&event_box);
+
+        event_box.add_events(
+            gtk::gdk::EventMask::ENTER_NOTIFY_MASK | gtk::gdk::EventMask::LEAVE_NOTIFY_MASK,
+        );
+        event_box.connect_enter_notify_event(
+            glib::clone!(@weak revealer => @default-return Inhibit(false), move |_, _| {
+                revealer.set_reveal_child(true);
+                Inhibit(false)
+            }),
+        );
+        event_box.connect_leave_notify_event(
+            glib::clone!(@weak revealer => @default-return Inhibit(false), move |_, e| {
+                // Moving the pointer into a child widget also leaves the event box.
+                if e.detail() != gtk::gdk::NotifyType::Inferior {
+                    revealer.set_reveal_child(false);
+                }
+                Inhibit(false)
+            }),
+        );
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A timeline of CSS class changes for debugging themes.
+//!
+//! `jiji msg css-timeline` opens a window that logs every CSS class added to or removed from the
+//! widgets in modules, with timestamps. This helps to debug theme rules that react to state
+//! classes like "workspace-urgent" or "battery-critical".
+
+use gtk::glib;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+thread_local! {
+    /// The log buffer of the open window.
+    static LOG: RefCell<Option<gtk::TextBuffer>> = RefCell::new(None);
+    /// Widgets that are already watched.
+    static WATCHED: RefCell<Vec<glib::WeakRef<gtk::Widget>>> = RefCell::new(vec![]);
+}
+
+fn classes(widget: &gtk::Widget) -> BTreeSet<String> {
+    widget
+        .style_context()
+        .list_classes()
+        .into_iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+fn log(line: &str) {
+    LOG.with(|l| {
+        if let Some(ref buffer) = *l.borrow() {
+            let time = glib::DateTime::new_now_local()
+                .and_then(|t| t.format("%H:%M:%S.%f"))
+                .map(|s| s.to_string())
+                .unwrap_or_default();
+            buffer.insert(&mut buffer.end_iter(), &format!("{} {}\n", time, line));
+        }
+    });
+}
+
+/// Watches the widget and its descendants, including the ones added later.
+fn watch(widget: &gtk::Widget, module_id: &str) {
+    let already = WATCHED.with(|w| {
+        let mut w = w.borrow_mut();
+        w.retain(|r| r.upgrade().is_some());
+        if w.iter().any(|r| r.upgrade().as_ref() == Some(widget)) {
+            true
+        } else {
+            w.push(widget.downgrade());
+            false
+        }
+    });
+    if already {
+        return;
+    }
+
+    let previous = RefCell::new(classes(widget));
+    let description = format!("{} {}", module_id, widget.type_().name());
+    widget
+        .style_context()
+        .connect_changed(glib::clone!(@weak widget => move |_| {
+            let current = classes(&widget);
+            let mut previous = previous.borrow_mut();
+            for c in current.difference(&previous) {
+                log(&format!("{}: +{}", description, c));
+            }
+            for c in previous.difference(&current) {
+                log(&format!("{}: -{}", description, c));
+            }
+            *previous = current;
+        }));
+
+    if let Some(container) = widget.downcast_ref::<gtk::Container>() {
+        for child in container.children() {
+            watch(&child, module_id);
+        }
+        let module_id = module_id.to_owned();
+        container.connect_add(move |_, child| watch(child, &module_id));
+    }
+}
+
+/// Opens the timeline window.
+pub(crate) fn open() {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title("jiji CSS class timeline");
+    window.set_default_size(600, 400);
+    let scrolled = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    let view = gtk::TextView::new();
+    view.set_editable(false);
+    view.set_monospace(true);
+    scrolled.add(&view);
+    window.add(&scrolled);
+
+    let buffer = view.buffer().expect("Failed to get the text buffer");
+    LOG.with(|l| l.replace(Some(buffer.clone())));
+    window.connect_destroy(move |_| {
+        LOG.with(|l| {
+            // Stop logging unless another window replaced the buffer.
+            if l.borrow().as_ref() == Some(&buffer) {
+                l.replace(None);
+            }
+        });
+    });
+
+    for (id, container) in crate::module_base::module_containers() {
+        watch(container.upcast_ref(), &id);
+    }
+    log("Watching CSS class changes");
+    window.show_all();
+}
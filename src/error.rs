@@ -0,0 +1,128 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Errors in the configs and the module creation.
+//!
+//! The errors are returned up to the bar or the application setup, which passes them to
+//! [`report`]. A module that cannot be created is replaced with an [`ErrorModule`], and a config
+//! that cannot be read is skipped, so that one mistake doesn't take down the other bars.
+
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::fmt;
+use std::path::Path;
+
+/// An error in a config or a module.
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// A config file or a CSS file cannot be read or parsed.
+    File { path: String, message: String },
+    /// No plugin provides the module.
+    UnknownModule(String),
+    /// The plugin is compiled out. `feature` is the cargo feature that provides it.
+    MissingFeature { plugin: String, feature: String },
+    /// The config of the module is invalid.
+    ModuleConfig { module: String, message: String },
+}
+
+impl Error {
+    pub(crate) fn file(path: &Path, message: impl fmt::Display) -> Error {
+        Error::File {
+            path: path.display().to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn module_config(module: &str, message: impl fmt::Display) -> Error {
+        Error::ModuleConfig {
+            module: module.to_owned(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::File { path, message } => write!(f, "{}: {}", path, message),
+            Error::UnknownModule(name) => {
+                write!(f, "module \"{}\" is not provided by the plugins", name)
+            }
+            Error::MissingFeature { plugin, feature } => write!(
+                f,
+                "plugin \"{}\" is not available because jiji is built without the \"{}\" feature",
+                plugin, feature
+            ),
+            Error::ModuleConfig { module, message } => {
+                write!(f, "invalid config of module \"{}\": {}", module, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+thread_local! {
+    static DIALOG: RefCell<Option<(gtk::MessageDialog, Vec<String>)>> = RefCell::new(None);
+}
+
+/// Logs the error and shows it in a dialog so that it's noticed without a terminal. The errors
+/// reported while the dialog is open are added to it.
+pub(crate) fn report(error: &Error) {
+    eprintln!("{}", error);
+    DIALOG.with(|d| {
+        let mut d = d.borrow_mut();
+        let (dialog, messages) = d.get_or_insert_with(|| {
+            let dialog = gtk::MessageDialog::new(
+                None::<&gtk::Window>,
+                gtk::DialogFlags::empty(),
+                gtk::MessageType::Error,
+                gtk::ButtonsType::Close,
+                "jiji: errors in the config",
+            );
+            dialog.connect_response(|dialog, _| {
+                dialog.close();
+                DIALOG.with(|d| d.replace(None));
+            });
+            (dialog, vec![])
+        });
+        messages.push(error.to_string());
+        dialog.set_secondary_text(Some(&messages.join("\n")));
+        dialog.show_all();
+    });
+}
+
+/// Shows the error in place of a module that cannot be created.
+pub(crate) struct ErrorModule {
+    name: String,
+    error: Error,
+}
+
+impl ErrorModule {
+    pub(crate) fn new(name: &str, error: Error) -> ErrorModule {
+        ErrorModule {
+            name: name.to_owned(),
+            error,
+        }
+    }
+}
+
+impl crate::module::Module for ErrorModule {
+    fn build_ui(&self, container: &gtk::Box) {
+        let label = gtk::Label::new(Some(&format!("⚠ {}", self.name)));
+        label.style_context().add_class("error");
+        label.set_tooltip_text(Some(&self.error.to_string()));
+        container.add(&label);
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks the keybindings in the i3 config.
+//!
+//! This reads `bindsym` and `bindcode` lines, including the ones in `mode` blocks, and reports:
+//!
+//! * duplicates: the same key is bound twice in a mode, and only the last one takes effect.
+//! * shadowed: a key in a mode is also bound in the default mode, so the default binding is not
+//!   available while in the mode.
+//!
+//! The `bindsym` lines in `bar` blocks are the mouse bindings of the bar, and they are skipped.
+//! Variables defined by `set` are expanded. `include` directives are not followed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A keybinding in the config.
+#[derive(Clone, Debug)]
+pub struct Binding {
+    pub mode: String,
+    /// The normalized key (e.g. "Mod4+Shift+q").
+    pub key: String,
+    pub command: String,
+    /// 1-based line number.
+    pub line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum Issue {
+    Duplicate { first: Binding, second: Binding },
+    Shadowed { default: Binding, mode: Binding },
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Issue::Duplicate { first, second } => write!(
+                f,
+                "duplicate {} in mode \"{}\": line {} ({}) is overridden by line {} ({})",
+                second.key, second.mode, first.line, first.command, second.line, second.command
+            ),
+            Issue::Shadowed { default, mode } => write!(
+                f,
+                "{} in mode \"{}\" (line {}) shadows the default binding on line {} ({})",
+                mode.key, mode.mode, mode.line, default.line, default.command
+            ),
+        }
+    }
+}
+
+/// Returns the path of the i3 config, searched in the same order as i3.
+pub fn config_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|h| h.join(".config")));
+    let mut candidates = vec![];
+    if let Some(ref h) = home {
+        candidates.push(h.join(".i3/config"));
+    }
+    if let Some(c) = config_home {
+        candidates.push(c.join("i3/config"));
+    }
+    candidates.push(PathBuf::from("/etc/xdg/i3/config"));
+    candidates.push(PathBuf::from("/etc/i3/config"));
+    candidates.into_iter().find(|p| p.is_file())
+}
+
+/// Normalizes a key combination so that the order and case of the modifiers don't matter.
+fn normalize_key(key: &str, flags: &[&str]) -> String {
+    let mut parts: Vec<&str> = key.split('+').collect();
+    let keysym = parts.pop().unwrap_or_default();
+    let mut mods: Vec<String> = parts.iter().map(|m| m.to_lowercase()).collect();
+    mods.sort();
+    mods.dedup();
+    let mut ret = String::new();
+    for flag in flags {
+        ret += flag;
+        ret += " ";
+    }
+    for m in mods {
+        ret += &m;
+        ret += "+";
+    }
+    ret + keysym
+}
+
+/// Parses the bindings in the config text.
+pub fn parse(text: &str) -> Vec<Binding> {
+    let mut vars: Vec<(String, String)> = vec![];
+    let mut bindings = vec![];
+    let mut mode = "default".to_owned();
+    // The depth of the blocks in a `bar` block, including the `bar` block itself.
+    let mut bar_depth = 0;
+    let mut logical = String::new();
+    let mut start_line = 0;
+    for (i, raw) in text.lines().enumerate() {
+        if logical.is_empty() {
+            start_line = i + 1;
+        }
+        // Join the continuation lines.
+        if let Some(l) = raw.strip_suffix('\\') {
+            logical += l;
+            continue;
+        }
+        logical += raw;
+        let line = std::mem::take(&mut logical);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if bar_depth > 0 {
+            if line.ends_with('{') {
+                bar_depth += 1;
+            } else if line.starts_with('}') {
+                bar_depth -= 1;
+            }
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("bar") if line.ends_with('{') => bar_depth = 1,
+            Some("set") => {
+                if let (Some(name), Some(_)) = (words.next(), words.clone().next()) {
+                    let value = words.collect::<Vec<_>>().join(" ");
+                    vars.push((name.to_owned(), value));
+                    // Expand longer names first so that "$mod" doesn't match "$mod2".
+                    vars.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+                }
+            }
+            Some("mode") if line.ends_with('{') => {
+                // e.g. mode "resize" {, mode --pango_markup "<b>move</b>" {
+                let rest = line["mode".len()..line.len() - 1].trim();
+                mode = match (rest.find('"'), rest.rfind('"')) {
+                    (Some(s), Some(e)) if s < e => rest[s + 1..e].to_owned(),
+                    _ => rest.split_whitespace().last().unwrap_or(rest).to_owned(),
+                };
+            }
+            Some("}") => mode = "default".to_owned(),
+            Some(kind @ "bindsym") | Some(kind @ "bindcode") => {
+                let mut expanded = line.to_owned();
+                for (name, value) in &vars {
+                    expanded = expanded.replace(name.as_str(), value);
+                }
+                let mut words = expanded.split_whitespace().skip(1);
+                let mut flags = vec![];
+                let key = loop {
+                    match words.next() {
+                        Some(w) if w.starts_with("--") => flags.push(w),
+                        Some(w) => break Some(w),
+                        None => break None,
+                    }
+                };
+                if let Some(key) = key {
+                    let key = if kind == "bindcode" {
+                        format!("code:{}", key)
+                    } else {
+                        key.to_owned()
+                    };
+                    bindings.push(Binding {
+                        mode: mode.clone(),
+                        key: normalize_key(&key, &flags),
+                        command: words.collect::<Vec<_>>().join(" "),
+                        line: start_line,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+    bindings
+}
+
+/// Finds the issues in the bindings.
+pub fn check(bindings: &[Binding]) -> Vec<Issue> {
+    let mut issues = vec![];
+    let mut seen: HashMap<(&str, &str), &Binding> = HashMap::new();
+    for b in bindings {
+        if let Some(first) = seen.insert((b.mode.as_str(), b.key.as_str()), b) {
+            issues.push(Issue::Duplicate {
+                first: first.clone(),
+                second: b.clone(),
+            });
+        }
+    }
+    for b in bindings.iter().filter(|b| b.mode != "default") {
+        if let Some(default) = seen.get(&("default", b.key.as_str())) {
+            issues.push(Issue::Shadowed {
+                default: (*default).clone(),
+                mode: b.clone(),
+            });
+        }
+    }
+    issues
+}
+
+/// Reads the config and returns the issues.
+pub fn check_file(path: &Path) -> Result<Vec<Issue>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    Ok(check(&parse(&text)))
+}
+
+/// Runs the check as a subcommand. Returns the exit code.
+pub fn run_cli(args: &[String]) -> i32 {
+    let path = match args.first().map(PathBuf::from).or_else(config_path) {
+        Some(p) => p,
+        None => {
+            eprintln!("Cannot find the i3 config");
+            return 2;
+        }
+    };
+    match check_file(&path) {
+        Ok(issues) if issues.is_empty() => {
+            println!("{}: no conflicts", path.display());
+            0
+        }
+        Ok(issues) => {
+            for issue in issues {
+                println!("{}: {}", path.display(), issue);
+            }
+            1
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
@@ -12,10 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod keybindings;
+
+use crate::module_base::supervisor::Health;
 use gtk::glib;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 
 #[derive(Clone, Debug)]
 pub struct WorkspaceState {
@@ -28,18 +32,116 @@ pub struct WorkspaceState {
 
 #[derive(Clone, Debug, Default, glib::GBoxed)]
 #[gboxed(type_name = "Workspaces")]
-pub struct Workspaces(HashMap<String, Vec<WorkspaceState>>);
+pub struct Workspaces(BTreeMap<String, Vec<WorkspaceState>>);
+
+#[derive(Clone, Debug)]
+pub struct OutputState {
+    pub name: String,
+    pub active: bool,
+    /// (x, y, width, height) of the output.
+    pub rect: (i32, i32, i32, i32),
+}
+
+#[derive(Clone, Debug, Default, glib::GBoxed)]
+#[gboxed(type_name = "Outputs")]
+pub struct Outputs(Vec<OutputState>);
+
+/// Window marks.
+#[derive(Clone, Debug, Default, glib::GBoxed)]
+#[gboxed(type_name = "Marks")]
+pub struct Marks(Vec<String>);
+
+/// The result of an i3 command.
+#[derive(Clone, Debug)]
+pub struct CommandOutcome {
+    pub command: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// The connection that runs the i3 commands.
+///
+/// [`I3State::new`] uses i3ipc's connection to i3. A mock can be injected with
+/// [`I3State::with_backend`] to drive the modules without i3.
+pub trait I3Backend {
+    /// Runs the commands separated by ";" and returns (success, error) of each command.
+    fn run_command(&mut self, command: &str) -> Result<Vec<(bool, Option<String>)>, String>;
+}
+
+impl I3Backend for i3ipc::I3Connection {
+    fn run_command(&mut self, command: &str) -> Result<Vec<(bool, Option<String>)>, String> {
+        let reply = i3ipc::I3Connection::run_command(self, command).map_err(|e| e.to_string())?;
+        Ok(reply
+            .outcomes
+            .into_iter()
+            .map(|o| (o.success, o.error))
+            .collect())
+    }
+}
 
 gtk::glib::wrapper! {
     pub struct I3State(ObjectSubclass<imp::I3State>);
 }
 
+thread_local! {
+    static SHARED: RefCell<Option<I3State>> = RefCell::new(None);
+}
+
 impl I3State {
     pub fn new() -> Self {
-        glib::Object::new(&[]).expect("Failed to create an I3State")
+        let obj: Self = glib::Object::new(&[]).expect("Failed to create an I3State");
+        imp::I3State::from_instance(&obj).start(&obj);
+        obj
+    }
+
+    /// Makes an I3State that runs the commands with the backend instead of connecting to i3.
+    ///
+    /// The state is updated only by [`I3State::push_workspaces`], [`I3State::push_outputs`], and
+    /// [`I3State::push_marks`].
+    pub fn with_backend(backend: Box<dyn I3Backend>) -> Self {
+        let obj: Self = glib::Object::new(&[]).expect("Failed to create an I3State");
+        let self_ = imp::I3State::from_instance(&obj);
+        self_.connection.replace(Some(backend));
+        self_.health.replace(Health::Ok);
+        obj
+    }
+
+    /// Replaces the state returned by [`I3State::shared`], e.g. with one made by
+    /// [`I3State::with_backend`]. This must be called before the modules are created.
+    pub fn set_shared(state: I3State) {
+        SHARED.with(|s| s.replace(Some(state)));
+    }
+
+    /// Replaces the workspaces as if i3 sent a workspace event.
+    pub fn push_workspaces(&self, workspaces: BTreeMap<String, Vec<WorkspaceState>>) {
+        imp::I3State::from_instance(self)
+            .apply(self, imp::Update::Workspaces(Workspaces(workspaces)));
+    }
+
+    /// Replaces the outputs as if i3 sent an output event.
+    pub fn push_outputs(&self, outputs: Vec<OutputState>) {
+        imp::I3State::from_instance(self).apply(self, imp::Update::Outputs(Outputs(outputs)));
+    }
+
+    /// Replaces the marks as if i3 sent a window event.
+    pub fn push_marks(&self, marks: Vec<String>) {
+        imp::I3State::from_instance(self).apply(self, imp::Update::Marks(Marks(marks)));
+    }
+
+    /// Returns the state shared in the application. It's created on the first call, so that i3 is
+    /// connected only when a module uses it.
+    pub fn shared() -> Self {
+        SHARED.with(|s| s.borrow_mut().get_or_insert_with(I3State::new).clone())
+    }
+
+    /// Returns the health of the connection to i3. The "backend-health" property is notified when
+    /// this changes.
+    pub fn backend_health(&self) -> Health {
+        imp::I3State::from_instance(self).health.borrow().clone()
     }
 
-    pub fn workspaces(&self) -> HashMap<String, Vec<WorkspaceState>> {
+    /// Returns the workspaces keyed by the output name. Each list is sorted by the number.
+    pub fn workspaces(&self) -> BTreeMap<String, Vec<WorkspaceState>> {
         self.property("workspaces")
             .unwrap()
             .get::<Workspaces>()
@@ -47,31 +149,117 @@ impl I3State {
             .0
     }
 
-    pub fn switch_workspace(&self, num: i32) {
+    pub fn outputs(&self) -> Vec<OutputState> {
+        self.property("outputs")
+            .unwrap()
+            .get::<Outputs>()
+            .unwrap()
+            .0
+    }
+
+    /// Returns the marks of all windows, sorted by name.
+    pub fn marks(&self) -> Vec<String> {
+        self.property("marks").unwrap().get::<Marks>().unwrap().0
+    }
+
+    /// Returns the i3 output name that has the geometry.
+    ///
+    /// GDK's monitor model doesn't always match with i3's output name. This can be used to find
+    /// the output from the GDK monitor geometry.
+    pub fn find_output_by_rect(&self, rect: (i32, i32, i32, i32)) -> Option<String> {
+        self.outputs()
+            .into_iter()
+            .find(|o| o.active && o.rect == rect)
+            .map(|o| o.name)
+    }
+
+    /// Returns the lowest workspace number that is not used on any output.
+    pub fn lowest_unused_workspace_num(&self) -> i32 {
+        let used: Vec<i32> = self
+            .workspaces()
+            .values()
+            .flat_map(|wss| wss.iter().map(|ws| ws.num))
+            .collect();
+        (1..).find(|n| !used.contains(n)).unwrap()
+    }
+
+    /// Runs the i3 commands in order and returns the outcome of each command.
+    ///
+    /// Each command is sent in its own message, so that a command with "," or ";" gets one
+    /// outcome. It fails if any part of it fails. Failed commands are logged. Err is returned only
+    /// if the commands cannot be sent to i3.
+    pub fn run_commands(&self, commands: &[&str]) -> Result<Vec<CommandOutcome>, String> {
         let self_ = imp::I3State::from_instance(self);
-        if let Some(ref mut connection) = self_.connection.borrow_mut().as_mut() {
-            connection
-                .run_command(&format!("workspace number {}", num))
-                .expect("Failed to switch workspaces");
+        let mut connection = self_.connection.borrow_mut();
+        let connection = connection
+            .as_mut()
+            .ok_or_else(|| "Not connected to i3".to_owned())?;
+        let mut outcomes = vec![];
+        for command in commands {
+            let reply = connection
+                .run_command(command)
+                .map_err(|e| format!("Failed to run i3 commands: {}", e))?;
+            let failure = reply.into_iter().find(|(success, _)| !success);
+            outcomes.push(CommandOutcome {
+                command: command.to_string(),
+                success: failure.is_none(),
+                error: failure.and_then(|(_, error)| error),
+            });
+        }
+        for o in outcomes.iter().filter(|o| !o.success) {
+            eprintln!(
+                "i3 command failed: {}: {}",
+                o.command,
+                o.error.as_deref().unwrap_or("unknown error")
+            );
+        }
+        Ok(outcomes)
+    }
+
+    /// Focuses the window with the mark.
+    pub fn focus_mark(&self, mark: &str) {
+        // con_mark takes a regular expression.
+        let command = format!(
+            "[con_mark=\"^{}$\"] focus",
+            regex::escape(mark).replace('"', "\\\"")
+        );
+        if let Err(e) = self.run_commands(&[&command]) {
+            eprintln!("{}", e);
+        }
+    }
+
+    pub fn switch_workspace(&self, num: i32) {
+        if let Err(e) = self.run_commands(&[&format!("workspace number {}", num)]) {
+            eprintln!("{}", e);
         }
     }
 }
 
 mod imp {
-    use super::{WorkspaceState, Workspaces};
+    use super::{I3Backend, Marks, OutputState, Outputs, WorkspaceState, Workspaces};
+    use crate::module_base::supervisor::{supervise, Health, Worker};
     use glib::{ParamFlags, ParamSpec};
     use gtk::glib;
     use gtk::prelude::*;
     use gtk::subclass::prelude::*;
     use once_cell::sync::Lazy;
     use std::cell::RefCell;
-    use std::collections::HashMap;
-    use std::thread;
+    use std::collections::BTreeMap;
 
-    #[derive(Debug, Default)]
+    #[derive(Default)]
     pub struct I3State {
-        pub(crate) connection: RefCell<Option<i3ipc::I3Connection>>,
+        pub(crate) connection: RefCell<Option<Box<dyn I3Backend>>>,
         pub(crate) workspaces: RefCell<Workspaces>,
+        pub(crate) outputs: RefCell<Outputs>,
+        pub(crate) marks: RefCell<Marks>,
+        pub(crate) health: RefCell<Health>,
+    }
+
+    /// Updates sent from the i3 listener thread.
+    pub(super) enum Update {
+        Workspaces(Workspaces),
+        Outputs(Outputs),
+        Marks(Marks),
     }
 
     #[glib::object_subclass]
@@ -83,56 +271,146 @@ mod imp {
 
     impl ObjectImpl for I3State {
         fn properties() -> &'static [ParamSpec] {
-            static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| vec![WORKSPACES.clone()]);
+            static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
+                vec![
+                    WORKSPACES.clone(),
+                    OUTPUTS.clone(),
+                    MARKS.clone(),
+                    BACKEND_HEALTH.clone(),
+                ]
+            });
             PROPERTIES.as_ref()
         }
 
         fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
             match pspec.name() {
                 "workspaces" => self.workspaces.borrow().to_value(),
+                "outputs" => self.outputs.borrow().to_value(),
+                "marks" => self.marks.borrow().to_value(),
+                "backend-health" => self.health.borrow().as_str().to_value(),
                 _ => unimplemented!(),
             }
         }
+    }
 
-        fn constructed(&self, obj: &Self::Type) {
-            self.parent_constructed(obj);
-            self.connection.replace(Some(
-                i3ipc::I3Connection::connect().expect("Failed to connect i3"),
-            ));
+    impl I3State {
+        /// Connects to i3 and starts the listener.
+        pub(super) fn start(&self, obj: &super::I3State) {
+            self.connection.replace(connect());
+            crate::module_base::shutdown::on_shutdown(glib::clone!(@weak obj => move || {
+                I3State::from_instance(&obj).connection.replace(None);
+            }));
 
             let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
             receiver.attach(
                 None,
-                glib::clone!(@weak obj => @default-return Continue(false), move |ws| {
-                    I3State::from_instance(&obj).workspaces.replace(ws);
-                    obj.notify_by_pspec(&WORKSPACES);
+                glib::clone!(@weak obj => @default-return Continue(false), move |update| {
+                    I3State::from_instance(&obj).apply(&obj, update);
                     Continue(true)
                 }),
             );
-            thread::spawn(glib::clone!(@strong sender => move || {
-                let mut connection = i3ipc::I3Connection::connect().expect("Failed to connect i3");
-                sender.send(get_workspaces(&mut connection)).expect("Failed to send new workspaces");
-
-                let mut listener = i3ipc::I3EventListener::connect().expect("Failed to connect i3");
-                listener.subscribe(&[i3ipc::Subscription::Workspace]).expect("Failed to subscribe to i3");
-                for event in listener.listen() {
-                    match event.expect("Failed to parse an i3 event") {
-                        i3ipc::event::Event::WorkspaceEvent(_) => {
-                            sender.send(get_workspaces(&mut connection)).expect("Failed to send new workspaces");
-                        },
-                        _ => unreachable!()
+            supervise(
+                "i3",
+                move |worker| listen(worker, &sender),
+                glib::clone!(@weak obj => move |health| {
+                    let self_ = I3State::from_instance(&obj);
+                    // The command connection is also closed if i3 restarted.
+                    let restarted = *self_.health.borrow() != Health::Starting;
+                    if *health == Health::Ok && (restarted || self_.connection.borrow().is_none()) {
+                        self_.connection.replace(connect());
                     }
+                    self_.health.replace(health.clone());
+                    obj.notify_by_pspec(&BACKEND_HEALTH);
+                }),
+            );
+        }
+
+        pub(super) fn apply(&self, obj: &super::I3State, update: Update) {
+            match update {
+                Update::Workspaces(ws) => {
+                    self.workspaces.replace(ws);
+                    obj.notify_by_pspec(&WORKSPACES);
                 }
-            }));
+                Update::Outputs(outputs) => {
+                    self.outputs.replace(outputs);
+                    obj.notify_by_pspec(&OUTPUTS);
+                }
+                Update::Marks(marks) => {
+                    self.marks.replace(marks);
+                    obj.notify_by_pspec(&MARKS);
+                }
+            }
         }
     }
 
-    fn get_workspaces(connection: &mut i3ipc::I3Connection) -> Workspaces {
+    fn connect() -> Option<Box<dyn I3Backend>> {
+        match i3ipc::I3Connection::connect() {
+            Ok(c) => Some(Box::new(c)),
+            Err(e) => {
+                eprintln!("Failed to connect i3: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Sends the i3 state and then its updates until i3 closes the connection. Returns Ok when the
+    /// main thread stops receiving the updates.
+    fn listen(worker: &Worker, sender: &glib::Sender<Update>) -> Result<(), String> {
+        let mut connection =
+            i3ipc::I3Connection::connect().map_err(|e| format!("Failed to connect i3: {}", e))?;
+        let mut marks = get_marks(&mut connection)?;
+        let updates = vec![
+            Update::Outputs(get_outputs(&mut connection)?),
+            Update::Workspaces(get_workspaces(&mut connection)?),
+            Update::Marks(Marks(marks.clone())),
+        ];
+        if updates.into_iter().any(|u| sender.send(u).is_err()) {
+            return Ok(());
+        }
+
+        let mut listener = i3ipc::I3EventListener::connect()
+            .map_err(|e| format!("Failed to connect i3: {}", e))?;
+        listener
+            .subscribe(&[
+                i3ipc::Subscription::Workspace,
+                i3ipc::Subscription::Output,
+                i3ipc::Subscription::Window,
+            ])
+            .map_err(|e| format!("Failed to subscribe to i3: {}", e))?;
+        worker.ready();
+        for event in listener.listen() {
+            let updates = match event.map_err(|e| format!("Failed to read an i3 event: {}", e))? {
+                i3ipc::event::Event::WorkspaceEvent(_) => {
+                    vec![Update::Workspaces(get_workspaces(&mut connection)?)]
+                }
+                i3ipc::event::Event::OutputEvent(_) => vec![
+                    Update::Outputs(get_outputs(&mut connection)?),
+                    Update::Workspaces(get_workspaces(&mut connection)?),
+                ],
+                i3ipc::event::Event::WindowEvent(_) => {
+                    // Window events are frequent (e.g. focus). Notify only when the marks change.
+                    let new_marks = get_marks(&mut connection)?;
+                    if new_marks == marks {
+                        continue;
+                    }
+                    marks = new_marks;
+                    vec![Update::Marks(Marks(marks.clone()))]
+                }
+                _ => continue,
+            };
+            if updates.into_iter().any(|u| sender.send(u).is_err()) {
+                return Ok(());
+            }
+        }
+        Err("i3 closed the connection".to_owned())
+    }
+
+    fn get_workspaces(connection: &mut i3ipc::I3Connection) -> Result<Workspaces, String> {
         let i3wses = connection
             .get_workspaces()
-            .expect("Failed to get workspaces")
+            .map_err(|e| format!("Failed to get workspaces: {}", e))?
             .workspaces;
-        let mut wses = HashMap::new();
+        let mut wses = BTreeMap::new();
         for ref i3ws in i3wses {
             if !wses.contains_key(i3ws.output.as_str()) {
                 wses.insert(i3ws.output.to_owned(), vec![]);
@@ -149,7 +427,33 @@ mod imp {
         for (_, ref mut wss) in &mut wses {
             wss.sort_by_key(|ref ws| ws.num);
         }
-        Workspaces(wses)
+        Ok(Workspaces(wses))
+    }
+
+    fn get_outputs(connection: &mut i3ipc::I3Connection) -> Result<Outputs, String> {
+        let outputs = connection
+            .get_outputs()
+            .map_err(|e| format!("Failed to get outputs: {}", e))?
+            .outputs;
+        Ok(Outputs(
+            outputs
+                .into_iter()
+                .map(|o| OutputState {
+                    name: o.name,
+                    active: o.active,
+                    rect: o.rect,
+                })
+                .collect(),
+        ))
+    }
+
+    fn get_marks(connection: &mut i3ipc::I3Connection) -> Result<Vec<String>, String> {
+        let mut marks = connection
+            .get_marks()
+            .map_err(|e| format!("Failed to get marks: {}", e))?
+            .marks;
+        marks.sort();
+        Ok(marks)
     }
 
     lazy_static! {
@@ -160,5 +464,26 @@ mod imp {
             Workspaces::static_type(),
             ParamFlags::READABLE,
         );
+        static ref OUTPUTS: ParamSpec = ParamSpec::new_boxed(
+            "outputs",
+            "outputs",
+            "outputs",
+            Outputs::static_type(),
+            ParamFlags::READABLE,
+        );
+        static ref MARKS: ParamSpec = ParamSpec::new_boxed(
+            "marks",
+            "marks",
+            "marks",
+            Marks::static_type(),
+            ParamFlags::READABLE,
+        );
+        static ref BACKEND_HEALTH: ParamSpec = ParamSpec::new_string(
+            "backend-health",
+            "backend-health",
+            "backend-health",
+            Some("starting"),
+            ParamFlags::READABLE,
+        );
     }
 }
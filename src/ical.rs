@@ -0,0 +1,141 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A minimal reader of iCalendar (.ics) files.
+//!
+//! Only the summary and the start time of VEVENTs are read. Recurrence rules are not expanded.
+//! This is enough for highlighting days and showing upcoming events. A directory of .ics files,
+//! such as a vdir used by khal and vdirsyncer, can be read as well.
+
+use std::path::Path;
+
+/// An event in a calendar.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub summary: String,
+    /// (year, month, day). The month is 1-based.
+    pub start_date: (i32, u32, u32),
+    /// (hour, minute, second). None for all-day events.
+    pub start_time: Option<(u32, u32, u32)>,
+    /// True if the start time is in UTC. Otherwise, it's in the local time.
+    pub utc: bool,
+}
+
+impl Event {
+    /// Returns the start time in UNIX time.
+    pub fn start_unix(&self) -> Option<i64> {
+        let tz = if self.utc {
+            gtk::glib::TimeZone::new_utc()
+        } else {
+            gtk::glib::TimeZone::new_local()
+        };
+        let (y, m, d) = self.start_date;
+        let (hh, mm, ss) = self.start_time.unwrap_or((0, 0, 0));
+        gtk::glib::DateTime::new(&tz, y, m as i32, d as i32, hh as i32, mm as i32, ss as f64)
+            .map(|t| t.to_unix())
+    }
+}
+
+/// Parses "YYYYMMDD" or "YYYYMMDDTHHMMSS[Z]".
+fn parse_date_time(s: &str) -> Option<((i32, u32, u32), Option<(u32, u32, u32)>, bool)> {
+    let s = s.trim();
+    let date = (
+        s.get(0..4)?.parse().ok()?,
+        s.get(4..6)?.parse().ok()?,
+        s.get(6..8)?.parse().ok()?,
+    );
+    let time = match s.get(8..9) {
+        Some("T") => Some((
+            s.get(9..11)?.parse().ok()?,
+            s.get(11..13)?.parse().ok()?,
+            s.get(13..15)?.parse().ok()?,
+        )),
+        _ => None,
+    };
+    Some((date, time, s.ends_with('Z')))
+}
+
+/// Parses the events in an iCalendar text.
+pub fn parse(text: &str) -> Vec<Event> {
+    // Unfold the continuation lines.
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        lines.push(line.to_owned());
+    }
+
+    let mut events = vec![];
+    let mut summary = None;
+    let mut start = None;
+    let mut in_event = false;
+    for line in lines {
+        let (key, value) = match line.find(':') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => continue,
+        };
+        // Drop parameters like "DTSTART;TZID=...".
+        let name = key.split(';').next().unwrap_or(key);
+        match (name, value) {
+            ("BEGIN", "VEVENT") => {
+                in_event = true;
+                summary = None;
+                start = None;
+            }
+            ("END", "VEVENT") => {
+                in_event = false;
+                if let Some((start_date, start_time, utc)) = start.take() {
+                    events.push(Event {
+                        summary: summary.take().unwrap_or_default(),
+                        start_date,
+                        start_time,
+                        utc,
+                    });
+                }
+            }
+            ("SUMMARY", v) if in_event => summary = Some(v.replace("\\,", ",")),
+            ("DTSTART", v) if in_event => start = parse_date_time(v),
+            _ => (),
+        }
+    }
+    events
+}
+
+/// Reads the events from .ics files. A path can be a file or a directory containing .ics files.
+pub fn read_events<P: AsRef<Path>>(paths: &[P]) -> Vec<Event> {
+    let mut events = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        if path.is_dir() {
+            if let Ok(entries) = std::fs::read_dir(path) {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let p = entry.path();
+                    if p.extension().map(|e| e == "ics").unwrap_or(false) {
+                        if let Ok(text) = std::fs::read_to_string(&p) {
+                            events.extend(parse(&text));
+                        }
+                    }
+                }
+            }
+        } else if let Ok(text) = std::fs::read_to_string(path) {
+            events.extend(parse(&text));
+        }
+    }
+    events
+}
@@ -0,0 +1,182 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A simple IPC over a Unix domain socket.
+//!
+//! The socket is placed at `$XDG_RUNTIME_DIR/jiji/jiji.sock`. A client sends one command per line
+//! as whitespace-separated words, and the server replies one line per command, either "ok ..." or
+//! "error ...". `jiji msg <command> [args...]` is a client for this.
+//!
+//! Commands are registered with [`register_command`] and run in the main thread.
+
+use gtk::glib;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+/// A command handler. It takes the words after the command name and the rest of the line.
+type Command = Box<dyn Fn(&[&str], &str) -> Result<String, String>>;
+
+thread_local! {
+    static COMMANDS: RefCell<HashMap<String, Command>> = RefCell::new(HashMap::new());
+}
+
+/// Registers an IPC command.
+///
+/// The handler takes the arguments after the command name, and returns the reply message.
+pub(crate) fn register_command<F>(name: &str, f: F)
+where
+    F: Fn(&[&str]) -> Result<String, String> + 'static,
+{
+    COMMANDS.with(|c| {
+        c.borrow_mut()
+            .insert(name.to_owned(), Box::new(move |args, _| f(args)))
+    });
+}
+
+/// Registers an IPC command that takes the rest of the line after the command name as is, e.g.
+/// for an argument with spaces.
+pub(crate) fn register_line_command<F>(name: &str, f: F)
+where
+    F: Fn(&str) -> Result<String, String> + 'static,
+{
+    COMMANDS.with(|c| {
+        c.borrow_mut()
+            .insert(name.to_owned(), Box::new(move |_, line| f(line)))
+    });
+}
+
+/// Splits the first whitespace-separated word from the line. Returns the word and the rest, both
+/// without the leading whitespace.
+pub(crate) fn next_word(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim_start()),
+        None => (line, ""),
+    }
+}
+
+fn dispatch(line: &str) -> String {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if words.is_empty() {
+        return "error empty command".to_owned();
+    }
+    let (_, rest) = next_word(line);
+    let result = COMMANDS.with(|c| match c.borrow().get(words[0]) {
+        Some(f) => f(&words[1..], rest),
+        None => Err(format!("unknown command: {}", words[0])),
+    });
+    match result {
+        Ok(s) if s.is_empty() => "ok".to_owned(),
+        Ok(s) => format!("ok {}", s),
+        Err(e) => format!("error {}", e),
+    }
+}
+
+/// Returns the path of the socket.
+pub(crate) fn socket_path() -> std::io::Result<PathBuf> {
+    xdg::BaseDirectories::with_prefix("jiji")?.place_runtime_file("jiji.sock")
+}
+
+/// Starts the IPC server.
+pub(crate) fn start() -> std::io::Result<()> {
+    let path = socket_path()?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            "Another jiji is running",
+        ));
+    }
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    crate::module_base::shutdown::on_shutdown(move || {
+        let _ = std::fs::remove_file(&path);
+    });
+
+    let (sender, receiver) =
+        glib::MainContext::channel::<(String, mpsc::Sender<String>)>(glib::PRIORITY_DEFAULT);
+    receiver.attach(None, |(line, reply)| {
+        let _ = reply.send(dispatch(&line));
+        glib::Continue(true)
+    });
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            let sender = sender.clone();
+            thread::spawn(move || handle_client(stream, sender));
+        }
+    });
+    Ok(())
+}
+
+fn handle_client(stream: UnixStream, sender: glib::Sender<(String, mpsc::Sender<String>)>) {
+    let mut writer = match stream.try_clone() {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => return,
+        };
+        let (reply_sender, reply_receiver) = mpsc::channel();
+        if sender.send((line, reply_sender)).is_err() {
+            return;
+        }
+        let reply = reply_receiver
+            .recv()
+            .unwrap_or_else(|_| "error no reply".to_owned());
+        if writeln!(writer, "{}", reply).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends a command to the running jiji and prints the reply. Returns the exit code.
+pub(crate) fn send(args: &[String]) -> i32 {
+    let result = socket_path()
+        .and_then(UnixStream::connect)
+        .and_then(|mut stream| {
+            writeln!(stream, "{}", args.join(" "))?;
+            let mut reply = String::new();
+            BufReader::new(stream).read_line(&mut reply)?;
+            Ok(reply)
+        });
+    match result {
+        Ok(reply) => {
+            let reply = reply.trim_end();
+            if let Some(msg) = reply.strip_prefix("error") {
+                eprintln!("{}", msg.trim_start());
+                1
+            } else {
+                let msg = reply.strip_prefix("ok").unwrap_or(reply).trim_start();
+                if !msg.is_empty() {
+                    println!("{}", msg);
+                }
+                0
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to jiji: {}", e);
+            1
+        }
+    }
+}
@@ -32,8 +32,10 @@
 //!
 //! What you need to do is:
 //!
-//! 1. Write a Module and a ModuleFactory.
-//! 2. Add them through the `plugins` (Rust) module's `PLUGINS`.
+//! 1. Write a Module and a ModuleFactory. Derive `JsonSchema` for the config so that `jiji schema`
+//!    can describe it.
+//! 2. Declare the file in the `plugins` (Rust) module, and register the plugin with
+//!    `register_plugin!` in the file.
 //! 3. Modify your `config.json` to instantiate the module in a bar.
 //!
 //! A very simple example is the `text` plugin. This takes a string to show as a config then shows
@@ -50,39 +52,83 @@
 //! that represents the i3wm workspace state and provides a way to operate on them. Since changes
 //! to GObject properties can trigger GTK widget changes, you can write a widget that reacts to
 //! other system's state changes easily.
+//!
+//! ## Embedding
+//!
+//! The `prelude` module is the public API with semver guarantees. It allows building a config in
+//! code and showing the bars inside another GTK application.
 
 #[macro_use]
 extern crate lazy_static;
 
+// For register_plugin!.
+#[doc(hidden)]
+pub use inventory;
+
 pub(crate) mod bar;
+pub(crate) mod bluetooth;
+pub(crate) mod color_scheme;
 pub(crate) mod config;
+pub(crate) mod containers;
+pub(crate) mod css_debug;
+pub(crate) mod error;
+#[cfg(feature = "i3")]
 pub(crate) mod i3;
+pub(crate) mod ical;
+pub(crate) mod ipc;
 pub(crate) mod module;
 pub(crate) mod module_base;
 pub(crate) mod plugins;
+pub mod prelude;
+pub(crate) mod profile;
+#[cfg(feature = "pulseaudio")]
 pub(crate) mod pulseaudio;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 use gtk::glib;
 use gtk::prelude::*;
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 /// Jiji holds the whole application data.
 ///
 /// In order to use glib's weak references for callbacks, we need to own the data somewhere. This
 /// struct is the root of such structures.
 struct Jiji {
+    /// Name of the config. Empty unless multiple configs are read from a directory.
+    name: String,
     config: config::Config,
     module_factories: HashMap<String, Box<dyn module::ModuleFactory>>,
     bars: Vec<bar::Bar>,
+    /// The CSS of the active profile.
+    profile_css: Option<gtk::CssProvider>,
+}
+
+/// Returns the path of a CSS file in the config. A relative path is relative from the
+/// XDG_CONFIG_DIR.
+fn css_file_path(path: &str) -> Result<PathBuf, error::Error> {
+    let mut p = PathBuf::from(path);
+    if p.is_relative() {
+        let xdg_dirs =
+            xdg::BaseDirectories::with_prefix("jiji").map_err(|e| error::Error::file(&p, e))?;
+        p = xdg_dirs.get_config_home().join(p);
+    }
+    Ok(p)
 }
 
 impl Jiji {
     /// Callback for new monitors.
     fn handle_monitor_added(&mut self, app: &gtk::Application, monitor: &gtk::gdk::Monitor) {
         let bar = bar::Bar::new(
-            config::find_monitor_config(&self.config, monitor),
+            &self.name,
+            config::find_profile_monitor_config(
+                &self.config,
+                profile::current().as_deref(),
+                monitor,
+            ),
             &self.module_factories,
             monitor,
         );
@@ -91,8 +137,11 @@ impl Jiji {
     }
 
     /// Sets up the CSS for the bars.
-    fn setup_css(&self, screen: &gtk::gdk::Screen) {
-        if !self.config.disable_default_css {
+    ///
+    /// The default CSS is loaded only if `load_default` is set, so that it's loaded once with
+    /// multiple configs.
+    fn setup_css(&self, screen: &gtk::gdk::Screen, load_default: bool) {
+        if load_default && !self.config.disable_default_css {
             let provider = gtk::CssProvider::new();
             provider
                 .load_from_data(include_bytes!("default_style.css"))
@@ -102,18 +151,38 @@ impl Jiji {
                 &provider,
                 gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
             );
+            let theme = if self.config.high_contrast {
+                config::Theme::HighContrast
+            } else {
+                self.config.theme
+            };
+            let css: Option<&[u8]> = match theme {
+                config::Theme::Default => None,
+                config::Theme::Dark => Some(include_bytes!("themes/dark.css")),
+                config::Theme::Light => Some(include_bytes!("themes/light.css")),
+                config::Theme::HighContrast => Some(include_bytes!("themes/high_contrast.css")),
+                config::Theme::Minimal => Some(include_bytes!("themes/minimal.css")),
+            };
+            if let Some(css) = css {
+                let provider = gtk::CssProvider::new();
+                provider.load_from_data(css).expect("Failed to load CSS");
+                gtk::StyleContext::add_provider_for_screen(
+                    screen,
+                    &provider,
+                    gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+                );
+            }
         }
         if !self.config.css_path.is_empty() {
-            let mut p = PathBuf::from(&self.config.css_path);
-            if p.is_relative() {
-                let xdg_dirs =
-                    xdg::BaseDirectories::with_prefix("jiji").expect("Failed to read the CSS");
-                p = xdg_dirs.get_config_home().join(p);
-            }
+            let p = match css_file_path(&self.config.css_path) {
+                Ok(p) => p,
+                Err(e) => return error::report(&e),
+            };
             let provider = gtk::CssProvider::new();
-            provider
-                .load_from_path(p.to_str().expect("Failed to read the CSS"))
-                .expect("Failed to load CSS");
+            if let Err(e) = provider.load_from_path(&p.to_string_lossy()) {
+                error::report(&error::Error::file(&p, e));
+                return;
+            }
             gtk::StyleContext::add_provider_for_screen(
                 screen,
                 &provider,
@@ -121,34 +190,282 @@ impl Jiji {
             );
         }
     }
+
+    /// Replaces the CSS of the previous profile with the one of the active profile.
+    fn setup_profile_css(&mut self, screen: &gtk::gdk::Screen) {
+        if let Some(provider) = self.profile_css.take() {
+            gtk::StyleContext::remove_provider_for_screen(screen, &provider);
+        }
+        let css_path = match profile::current().and_then(|p| self.config.profiles.get(&p)) {
+            Some(p) if !p.css_path.is_empty() => p.css_path.clone(),
+            _ => return,
+        };
+        let p = match css_file_path(&css_path) {
+            Ok(p) => p,
+            Err(e) => return error::report(&e),
+        };
+        let provider = gtk::CssProvider::new();
+        if let Err(e) = provider.load_from_path(&p.to_string_lossy()) {
+            error::report(&error::Error::file(&p, e));
+            return;
+        }
+        // Above the CSS in css_path.
+        gtk::StyleContext::add_provider_for_screen(
+            screen,
+            &provider,
+            gtk::STYLE_PROVIDER_PRIORITY_USER + 1,
+        );
+        self.profile_css = Some(provider);
+    }
+
+    /// Closes the bars on all monitors.
+    fn close_bars(&mut self) {
+        for bar in self.bars.drain(..) {
+            bar.close();
+        }
+    }
+
+    /// Rebuilds the bars on all monitors for the active profile.
+    fn switch_profile(&mut self, app: &gtk::Application, display: &gtk::gdk::Display) {
+        self.setup_profile_css(&display.default_screen());
+        self.close_bars();
+        for i in 0..display.n_monitors() {
+            let monitor = display.monitor(i).expect("Failed to get a monitor");
+            self.handle_monitor_added(app, &monitor);
+        }
+    }
 }
 
 /// Sets up the bars.
-fn handle_activate(app: &gtk::Application) {
-    let config = config::read_config();
-    let module_factories = module::make_module_factories(&config.plugins);
-    let mut jiji = Jiji {
-        config,
-        module_factories,
-        bars: vec![],
-    };
+///
+/// With multiple configs, the options that apply to the whole application (icons, locale,
+/// reduced_motion, color_scheme, theme, high_contrast, disable_default_css, and the startup
+/// profile) are taken from the first config.
+fn handle_activate(app: &gtk::Application, config_path: Option<&Path>) {
+    let (mut configs, errors) = config::read_configs(config_path);
+    for e in &errors {
+        error::report(e);
+    }
+    if configs.is_empty() {
+        // Show the default bar rather than nothing, so that the errors are noticed.
+        configs.push((String::new(), config::default_config()));
+    }
+    let first = &configs[0].1;
+    module_base::icon::set_config(first.icons.clone());
+    module_base::locale::set_config(first.locale.clone());
+    color_scheme::setup(first.color_scheme);
+    if first.reduced_motion {
+        module_base::set_reduced_motion(true);
+        if let Some(settings) = gtk::Settings::default() {
+            settings.set_gtk_enable_animations(false);
+        }
+    }
+    let mut jijis = vec![];
+    let mut lint_targets = vec![];
+    for (name, config) in configs {
+        let module_factories = module::make_module_factories(&config.plugins);
+        let module_names: Vec<String> = module_factories.keys().cloned().collect();
+        lint_targets.push((name.clone(), config.clone(), module_names));
+        jijis.push(Jiji {
+            name,
+            config,
+            module_factories,
+            bars: vec![],
+            profile_css: None,
+        });
+    }
+    let lint_targets = Rc::new(lint_targets);
     let display = gtk::gdk::Display::default().expect("Failed to get the default Display");
 
-    jiji.setup_css(&display.default_screen());
+    setup_ipc(lint_targets.clone());
+    for (i, jiji) in jijis.iter().enumerate() {
+        jiji.setup_css(&display.default_screen(), i == 0);
+    }
 
-    for i in 0..display.n_monitors() {
-        let monitor = display.monitor(i).expect("Failed to get a monitor");
-        jiji.handle_monitor_added(app, &monitor);
+    // The startup profile is taken from the first config.
+    let mut profile_names: Vec<String> = jijis
+        .iter()
+        .flat_map(|j| j.config.profiles.keys().cloned())
+        .collect();
+    profile_names.sort();
+    profile_names.dedup();
+    let jijis = Rc::new(RefCell::new(jijis));
+    profile::setup(
+        profile_names,
+        jijis.borrow()[0].config.profile.clone(),
+        glib::clone!(@weak app, @weak display, @weak jijis => move || {
+            for jiji in jijis.borrow_mut().iter_mut() {
+                jiji.switch_profile(&app, &display);
+            }
+        }),
+    );
+    for jiji in jijis.borrow_mut().iter_mut() {
+        jiji.switch_profile(app, &display);
     }
-    let jiji = RefCell::new(jiji);
-    display.connect_monitor_added(glib::clone!(@weak app => move |_, monitor| {
-        jiji.borrow_mut().handle_monitor_added(&app, monitor);
+    // focus [monitor]: starts the keyboard navigation on the bar of the monitor, or the first bar.
+    ipc::register_command(
+        "focus",
+        glib::clone!(@weak jijis => @default-return Err("jiji is shutting down".into()), move |args| {
+            let monitor = match args {
+                [] => None,
+                [monitor] => Some(*monitor),
+                _ => return Err("usage: focus [monitor]".into()),
+            };
+            let jijis = jijis.borrow();
+            let bar = jijis
+                .iter()
+                .flat_map(|j| j.bars.iter())
+                .find(|b| monitor.map_or(true, |m| b.name() == m))
+                .ok_or_else(|| format!("no bar on {}", monitor.unwrap_or("any monitor")))?;
+            bar.focus();
+            Ok("".into())
+        }),
+    );
+    // click-through <on|off|toggle> [monitor]
+    ipc::register_command(
+        "click-through",
+        glib::clone!(@weak jijis => @default-return Err("jiji is shutting down".into()), move |args| {
+            let (mode, monitor) = match args {
+                [mode] => (*mode, None),
+                [mode, monitor] => (*mode, Some(*monitor)),
+                _ => return Err("usage: click-through <on|off|toggle> [monitor]".into()),
+            };
+            let jijis = jijis.borrow();
+            let bars = jijis
+                .iter()
+                .flat_map(|j| j.bars.iter())
+                .filter(|b| monitor.map_or(true, |m| b.name() == m));
+            let mut found = false;
+            for bar in bars {
+                found = true;
+                bar.set_click_through(match mode {
+                    "on" => true,
+                    "off" => false,
+                    "toggle" => !bar.click_through(),
+                    _ => return Err(format!("unknown mode: {}", mode)),
+                });
+            }
+            if !found {
+                return Err(format!("no bar on {}", monitor.unwrap_or("any monitor")));
+            }
+            Ok("".into())
+        }),
+    );
+    // Close the bars first so that their struts go away before the backends disconnect.
+    for signal in [libc::SIGTERM, libc::SIGINT].iter() {
+        glib::unix_signal_add_local(
+            *signal,
+            glib::clone!(@weak app, @weak jijis => @default-return Continue(false), move || {
+                for jiji in jijis.borrow_mut().iter_mut() {
+                    jiji.close_bars();
+                }
+                module_base::shutdown::run_hooks();
+                app.quit();
+                Continue(false)
+            }),
+        );
+    }
+    display.connect_monitor_added(glib::clone!(@weak app, @strong jijis => move |_, monitor| {
+        for jiji in jijis.borrow_mut().iter_mut() {
+            jiji.handle_monitor_added(&app, monitor);
+        }
     }));
+
+    // Some lint rules need the runtime state (e.g. the audio devices), which is loaded
+    // asynchronously.
+    glib::timeout_add_seconds_local(5, move || {
+        for message in lint(&lint_targets) {
+            eprintln!("config: {}", message);
+        }
+        Continue(false)
+    });
+}
+
+/// Runs the config lint for the configs with their module names.
+fn lint(targets: &[(String, config::Config, Vec<String>)]) -> Vec<String> {
+    targets
+        .iter()
+        .flat_map(|(name, config, module_names)| {
+            config::lint::run(config, module_names)
+                .into_iter()
+                .map(move |m| {
+                    if name.is_empty() {
+                        m
+                    } else {
+                        format!("{}: {}", name, m)
+                    }
+                })
+        })
+        .collect()
+}
+
+/// Starts the IPC server and registers the built-in commands.
+fn setup_ipc(lint_targets: Rc<Vec<(String, config::Config, Vec<String>)>>) {
+    if let Err(e) = ipc::start() {
+        eprintln!("Failed to start the IPC server: {}", e);
+        return;
+    }
+    // action <module-id> <action> [string-param]: the param is the rest of the line, so that it
+    // can have spaces (e.g. a file path).
+    ipc::register_line_command("action", |line| {
+        let (id, rest) = ipc::next_word(line);
+        let (action, param) = ipc::next_word(rest);
+        match (id, action, param) {
+            ("", _, _) | (_, "", _) => Err("usage: action <module-id> <action> [param]".into()),
+            (id, action, "") => module_base::activate_module_action(id, action, None),
+            (id, action, param) => {
+                module_base::activate_module_action(id, action, Some(&param.to_variant()))
+            }
+        }
+        .map(|_| "".into())
+    });
+    ipc::register_command("css-timeline", |_| {
+        css_debug::open();
+        Ok("".into())
+    });
+    ipc::register_command("lint", move |_| Ok(lint(&lint_targets).join("\n")));
+    // profile [name|-]: "-" switches back to the base config.
+    ipc::register_command("profile", |args| match args {
+        [] => Ok(profile::current().unwrap_or_else(|| "-".into())),
+        ["-"] => profile::switch(None).map(|_| "".into()),
+        [name] => profile::switch(Some(*name)).map(|_| "".into()),
+        _ => Err("usage: profile [name|-]".into()),
+    });
+    ipc::register_command("profiles", |_| Ok(profile::names().join("\n")));
 }
 
 /// Runs the application.
 pub fn run() -> i32 {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|s| s.as_str()) == Some("msg") {
+        return ipc::send(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("check-keybindings") {
+        #[cfg(feature = "i3")]
+        return i3::keybindings::run_cli(&args[2..]);
+        #[cfg(not(feature = "i3"))]
+        {
+            eprintln!("jiji is built without the \"i3\" feature");
+            return 1;
+        }
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("schema") {
+        return config::schema::run_cli();
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("init") {
+        return config::init::run_cli(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("import") {
+        return config::import::run_cli(&args[2..]);
+    }
+    // --config <path>: a config file or a directory of configs.
+    let config_path = args
+        .iter()
+        .position(|a| a == "--config")
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from);
     let app = gtk::Application::new(Some("org.example.HelloWorld"), Default::default());
-    app.connect_activate(|app| handle_activate(app));
-    app.run()
+    app.connect_activate(move |app| handle_activate(app, config_path.as_deref()));
+    // The arguments are handled above. GApplication rejects unknown options.
+    app.run_with_args(&args[..1])
 }
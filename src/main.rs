@@ -13,5 +13,5 @@
 // limitations under the License.
 
 fn main() {
-    jiji::run();
+    std::process::exit(jiji::run());
 }
@@ -15,25 +15,82 @@
 use std::collections::HashMap;
 
 /// Module is one component in a bar.
-pub(crate) trait Module {
+pub trait Module {
     fn build_ui(&self, container: &gtk::Box);
 }
 
-pub(crate) trait ModuleFactory {
+pub trait ModuleFactory {
     fn name(&self) -> &str;
-    fn create(&self, config: &serde_json::Value, monitor: &gtk::gdk::Monitor) -> Box<dyn Module>;
+
+    /// Returns the JSON Schema of the module-specific config. Any value is accepted by default.
+    fn config_schema(&self, _gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::Schema::Bool(true)
+    }
+
+    /// Creates the module. An error is shown in place of the module.
+    fn create(
+        &self,
+        config: &serde_json::Value,
+        monitor: &gtk::gdk::Monitor,
+    ) -> Result<Box<dyn Module>, crate::error::Error>;
 }
 
-pub(crate) type Plugin = fn(&serde_json::Value) -> Vec<Box<dyn ModuleFactory>>;
+/// Creates the module factories of a plugin from the plugin config.
+///
+/// This must not connect to a backend (e.g. PulseAudio) or start a worker. The factories are also
+/// made only to describe the configs (`jiji schema`), so connect when the first module is
+/// created.
+pub type Plugin = fn(&serde_json::Value) -> Vec<Box<dyn ModuleFactory>>;
 
-pub(crate) fn make_module_factories(
+/// A plugin registered with [`register_plugin!`](crate::register_plugin).
+pub struct PluginRegistration {
+    pub name: &'static str,
+    pub make: Plugin,
+}
+
+inventory::collect!(PluginRegistration);
+
+/// Registers a plugin by the name in the config with the function that creates its module
+/// factories. Call this in the file of the plugin:
+///
+/// ```ignore
+/// jiji::register_plugin!("battery", make_module_factories);
+/// ```
+///
+/// The plugins in other crates are available when the crate is linked to the binary.
+#[macro_export]
+macro_rules! register_plugin {
+    ($name:expr, $make:path) => {
+        $crate::inventory::submit! {
+            $crate::prelude::PluginRegistration {
+                name: $name,
+                make: $make,
+            }
+        }
+    };
+}
+
+/// Creates the module factories of the plugins.
+///
+/// Unknown plugins are skipped. The plugins compiled out are reported as errors.
+pub fn make_module_factories(
     configs: &Vec<crate::config::PluginConfig>,
 ) -> HashMap<String, Box<dyn ModuleFactory>> {
     let mut ret = HashMap::new();
     for config in configs {
-        let plugin = PLUGINS
-            .get(config.name.as_str())
-            .expect("Failed to find a plugin");
+        let plugin = match PLUGINS.get(config.name.as_str()) {
+            Some(plugin) => plugin,
+            None => {
+                // Unknown plugins are reported by the config lint.
+                if let Some(feature) = missing_feature(&config.name) {
+                    crate::error::report(&crate::error::Error::MissingFeature {
+                        plugin: config.name.clone(),
+                        feature: feature.to_owned(),
+                    });
+                }
+                continue;
+            }
+        };
         for mf in plugin(&config.config) {
             ret.insert(mf.name().to_owned(), mf);
         }
@@ -41,13 +98,49 @@ pub(crate) fn make_module_factories(
     ret
 }
 
+/// Creates the module factories of all plugins with the default plugin configs.
+pub(crate) fn make_all_module_factories() -> HashMap<String, Box<dyn ModuleFactory>> {
+    let mut ret = HashMap::new();
+    for plugin in PLUGINS.values() {
+        for mf in plugin(&serde_json::Value::Null) {
+            ret.insert(mf.name().to_owned(), mf);
+        }
+    }
+    ret
+}
+
+/// Returns true if the plugin exists.
+pub(crate) fn plugin_exists(name: &str) -> bool {
+    PLUGINS.contains_key(name)
+}
+
+/// The plugins that can be compiled out, with the cargo feature that provides them.
+const OPTIONAL_PLUGINS: &[(&str, &str)] = &[
+    ("i3", "i3"),
+    ("media-keys", "pulseaudio"),
+    ("privacy", "pulseaudio"),
+    ("pulseaudio", "pulseaudio"),
+];
+
+/// Returns the cargo feature that provides the plugin if it's compiled out.
+pub(crate) fn missing_feature(name: &str) -> Option<&'static str> {
+    if plugin_exists(name) {
+        return None;
+    }
+    OPTIONAL_PLUGINS
+        .iter()
+        .find(|(plugin, _)| *plugin == name)
+        .map(|(_, feature)| *feature)
+}
+
+/// Returns the names of all plugins.
+pub(crate) fn plugin_names() -> Vec<&'static str> {
+    PLUGINS.keys().copied().collect()
+}
+
 lazy_static! {
-    static ref PLUGINS: HashMap<&'static str, Plugin> = {
-        let mut m: HashMap<&'static str, Plugin> = HashMap::new();
-        m.insert("button", crate::plugins::button::make_module_factories);
-        m.insert("i3", crate::plugins::i3::make_module_factories);
-        m.insert("pulseaudio", crate::plugins::pulseaudio::make_module_factories);
-        m.insert("text", crate::plugins::text::make_module_factories);
-        m
-    };
+    static ref PLUGINS: HashMap<&'static str, Plugin> = inventory::iter::<PluginRegistration>
+        .into_iter()
+        .map(|r| (r.name, r.make))
+        .collect();
 }
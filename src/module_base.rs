@@ -12,8 +12,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod command;
+pub mod gauge;
+pub mod device_query;
+pub mod graph;
+pub mod http;
+pub mod hwmon;
+pub mod icon;
+pub mod locale;
+pub mod shutdown;
+pub mod state;
+pub mod supervisor;
+pub mod task;
+pub mod template;
+pub mod touch;
+
+pub use state::StateStore;
+
+use futures_util::future::{select, Either};
+use futures_util::StreamExt;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use schemars::JsonSchema;
+use std::cell::{Cell, RefCell};
 use std::marker::PhantomData;
 use std::rc::Rc;
+use std::time::Duration;
 
 pub struct FnModFactory<Config> {
     name: &'static str,
@@ -40,20 +64,24 @@ impl<Config: 'static> crate::module::ModuleFactory for FnModFactory<Config> {
         self.name
     }
 
+    fn config_schema(&self, gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        self.config_factory.schema(gen)
+    }
+
     fn create(
         &self,
         json_config: &serde_json::Value,
         monitor: &gtk::gdk::Monitor,
-    ) -> Box<dyn crate::module::Module> {
+    ) -> Result<Box<dyn crate::module::Module>, crate::error::Error> {
         let config = self
             .config_factory
             .from_json(json_config, monitor)
-            .expect("Failed to parse the config");
+            .map_err(|e| crate::error::Error::module_config(self.name, e))?;
 
-        Box::new(FnMod {
+        Ok(Box::new(FnMod {
             config: Rc::new(config),
             build_ui_fn: self.build_ui_fn.clone(),
-        })
+        }))
     }
 }
 
@@ -71,31 +99,426 @@ impl<Config> crate::module::Module for FnMod<Config> {
 pub trait ConfigFactory {
     type T;
 
+    /// Parses the config. Err has the reason shown to the user.
     fn from_json<'a>(
         &self,
         json_config: &serde_json::Value,
         monitor: &gtk::gdk::Monitor,
-    ) -> Result<Self::T, ()>;
+    ) -> Result<Self::T, String>;
+
+    /// Returns the JSON Schema of the config for `jiji schema`.
+    fn schema(&self, gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema;
 }
 
 #[derive(Default)]
-pub struct JSONConfigFactory<Config: serde::de::DeserializeOwned + Default> {
+pub struct JSONConfigFactory<Config: serde::de::DeserializeOwned + Default + JsonSchema> {
     _marker: PhantomData<Config>,
 }
 
-impl<Config: serde::de::DeserializeOwned + Default> ConfigFactory for JSONConfigFactory<Config> {
+impl<Config: serde::de::DeserializeOwned + Default + JsonSchema> ConfigFactory
+    for JSONConfigFactory<Config>
+{
     type T = Config;
 
+    fn schema(&self, gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        gen.subschema_for::<Config>()
+    }
+
     fn from_json<'a>(
         &self,
         json_config: &serde_json::Value,
         _monitor: &gtk::gdk::Monitor,
-    ) -> Result<Self::T, ()> {
+    ) -> Result<Self::T, String> {
         if json_config.is_null() {
             Ok(Config::default())
         } else {
-            serde_json::from_str(&json_config.to_string()).map_err(|_| ())
+            serde_json::from_str(&json_config.to_string()).map_err(|e| e.to_string())
+        }
+    }
+}
+
+thread_local! {
+    static REDUCED_MOTION: Cell<bool> = Cell::new(false);
+}
+
+/// Disables animations in all modules. This should be called before creating modules.
+pub fn set_reduced_motion(enabled: bool) {
+    REDUCED_MOTION.with(|r| r.set(enabled));
+}
+
+/// Returns true if animations are disabled.
+///
+/// Modules that animate (e.g. flashing or scrolling text) should show a static state instead.
+pub fn reduced_motion() -> bool {
+    REDUCED_MOTION.with(|r| r.get())
+}
+
+/// Sets the name and the description that screen readers announce for the widget.
+///
+/// ATK notifies assistive technologies when the name changes, so modules call this on every update
+/// with the current state (e.g. "Volume 40%, muted"). Unchanged values are skipped to avoid
+/// repeated announcements.
+pub fn set_accessible<W: IsA<gtk::Widget>>(widget: &W, name: &str, description: Option<&str>) {
+    use gtk::atk::prelude::*;
+    let accessible = match widget.accessible() {
+        Some(a) => a,
+        None => return,
+    };
+    if accessible.name().as_deref() != Some(name) {
+        accessible.set_name(name);
+    }
+    if let Some(description) = description {
+        if accessible.description().as_deref() != Some(description) {
+            accessible.set_description(description);
         }
     }
 }
 
+/// Connects to the property changes of a long-lived object (e.g. a shared backend state) while the
+/// widget is alive. The handler is disconnected when the widget is destroyed, so that the bars
+/// rebuilt for a profile don't leave the handlers of the old widgets.
+pub fn connect_notify_while_alive<O, W, F>(object: &O, widget: &W, name: Option<&str>, f: F)
+where
+    O: IsA<glib::Object>,
+    W: IsA<gtk::Widget>,
+    F: Fn(&O, &glib::ParamSpec) + 'static,
+{
+    let id = RefCell::new(Some(object.connect_notify_local(name, f)));
+    widget.connect_destroy(glib::clone!(@weak object => move |_| {
+        if let Some(id) = id.take() {
+            object.disconnect(id);
+        }
+    }));
+}
+
+/// Returns the module-specific config with the standard update interval applied.
+///
+/// Polling modules read the interval from the "interval" field of their config. The interval in
+/// the module config (outside the module-specific config) overrides it. Err if the interval is 0.
+pub fn apply_interval(
+    json_config: &serde_json::Value,
+    interval: Option<u64>,
+) -> Result<serde_json::Value, String> {
+    let mut ret = json_config.clone();
+    if let Some(interval) = interval {
+        if interval == 0 {
+            return Err("interval must be at least 1 second".to_owned());
+        }
+        if ret.is_null() {
+            ret = serde_json::json!({});
+        }
+        match ret.as_object_mut() {
+            Some(o) => {
+                o.insert("interval".to_owned(), interval.into());
+            }
+            None => eprintln!("Failed to set the interval: the module config is not an object"),
+        }
+    }
+    Ok(ret)
+}
+
+/// Returns true if the module-specific config of the module has the "interval" field.
+pub(crate) fn declares_interval(factory: &dyn crate::module::ModuleFactory) -> bool {
+    let mut gen = schemars::gen::SchemaSettings::draft07()
+        .with(|s| s.inline_subschemas = true)
+        .into_generator();
+    let schema = serde_json::to_value(factory.config_schema(&mut gen)).unwrap_or_default();
+    schema["properties"].get("interval").is_some()
+}
+
+/// Formats a size in bytes with the unit in the locale config (e.g. "1.5GiB" or "1.6GB").
+pub fn format_bytes(bytes: u64) -> String {
+    let (base, units) = match locale::byte_unit() {
+        crate::config::ByteUnit::Binary => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB"]),
+        crate::config::ByteUnit::Si => (1000.0, ["B", "kB", "MB", "GB", "TB"]),
+    };
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= base && unit < units.len() - 1 {
+        value /= base;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, units[unit])
+}
+
+/// A handle of a worker started by [`poll_in_worker`].
+pub struct PollHandle {
+    trigger: futures_channel::mpsc::UnboundedSender<()>,
+}
+
+impl PollHandle {
+    /// Makes the worker run immediately instead of waiting for the next interval.
+    pub fn refresh(&self) {
+        let _ = self.trigger.unbounded_send(());
+    }
+}
+
+/// Runs `work` in the shared thread pool every `interval` and passes the result to `callback` in
+/// the main thread.
+///
+/// This is for modules that need to do blocking operations (e.g. network access) periodically. See
+/// [`task`] for one-off work. The polling stops when `callback` returns `Continue(false)`.
+pub fn poll_in_worker<T, W, C>(interval: Duration, work: W, callback: C) -> PollHandle
+where
+    T: Send + 'static,
+    W: Fn() -> T + Send + 'static,
+    C: FnMut(T) -> glib::Continue + 'static,
+{
+    poll(task::Pool::Shared, interval, work, callback)
+}
+
+/// Same as [`poll_in_worker`], but for running a command from the config, which can take any time.
+pub fn poll_command_in_worker<T, W, C>(interval: Duration, work: W, callback: C) -> PollHandle
+where
+    T: Send + 'static,
+    W: Fn() -> T + Send + 'static,
+    C: FnMut(T) -> glib::Continue + 'static,
+{
+    poll(task::Pool::Commands, interval, work, callback)
+}
+
+fn poll<T, W, C>(pool: task::Pool, interval: Duration, work: W, mut callback: C) -> PollHandle
+where
+    T: Send + 'static,
+    W: Fn() -> T + Send + 'static,
+    C: FnMut(T) -> glib::Continue + 'static,
+{
+    let (trigger, mut triggered) = futures_channel::mpsc::unbounded();
+    task::spawn(async move {
+        let mut work = work;
+        loop {
+            // The work is moved to the pool and back because it's not Sync.
+            let (w, value) = task::unblock_in(pool, move || {
+                let value = work();
+                (work, value)
+            })
+            .await;
+            work = w;
+            if callback(value) == glib::Continue(false) {
+                return;
+            }
+            let timeout = glib::timeout_future(interval);
+            if let Either::Right((None, timeout)) = select(timeout, triggered.next()).await {
+                // Nobody can trigger a refresh anymore.
+                timeout.await;
+            }
+        }
+    });
+    PollHandle { trigger }
+}
+
+thread_local! {
+    static MODULES: RefCell<Vec<(String, glib::WeakRef<gtk::Box>)>> = RefCell::new(vec![]);
+}
+
+/// Registers a module container with the module ID so that its actions can be activated from
+/// outside (e.g. IPC).
+pub fn register_module(id: &str, container: &gtk::Box) {
+    MODULES.with(|m| {
+        let mut m = m.borrow_mut();
+        m.retain(|(_, c)| c.upgrade().is_some());
+        m.push((id.to_owned(), container.downgrade()));
+    });
+}
+
+/// Returns the registered module containers with their IDs.
+pub fn module_containers() -> Vec<(String, gtk::Box)> {
+    MODULES.with(|m| {
+        m.borrow()
+            .iter()
+            .filter_map(|(id, c)| Some((id.clone(), c.upgrade()?)))
+            .collect()
+    })
+}
+
+thread_local! {
+    /// The errors reported by the actions while [`activate_module_action`] runs them.
+    static ACTION_ERRORS: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
+/// Reports an error of an action, e.g. a file that cannot be written.
+///
+/// While the action is activated by [`activate_module_action`] (e.g. from IPC), the error is
+/// returned to the caller. Otherwise it's printed.
+pub fn report_action_error(message: String) {
+    ACTION_ERRORS.with(|e| match e.borrow_mut().as_mut() {
+        Some(errors) => errors.push(message),
+        None => eprintln!("{}", message),
+    });
+}
+
+/// Activates the action on all modules with the ID.
+///
+/// A module can appear on multiple bars (monitors). It's an error if none of the modules with the
+/// ID has the action, or if an action reports an error with [`report_action_error`].
+pub fn activate_module_action(
+    id: &str,
+    action: &str,
+    param: Option<&glib::Variant>,
+) -> Result<(), String> {
+    let containers: Vec<gtk::Box> = MODULES.with(|m| {
+        m.borrow()
+            .iter()
+            .filter(|(i, _)| i == id)
+            .filter_map(|(_, c)| c.upgrade())
+            .collect()
+    });
+    let mut activated = false;
+    // Actions can activate other actions, so the outer errors are kept.
+    let outer = ACTION_ERRORS.with(|e| e.replace(Some(vec![])));
+    for container in containers {
+        if let Some(group) = container.action_group("module") {
+            if group.has_action(action) {
+                group.activate_action(action, param);
+                activated = true;
+            }
+        }
+    }
+    let errors = ACTION_ERRORS.with(|e| e.replace(outer)).unwrap_or_default();
+    if !activated {
+        Err(format!("no module {} with action {}", id, action))
+    } else if !errors.is_empty() {
+        Err(errors.join("; "))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the action group of a module, creating one if it doesn't exist.
+///
+/// The actions are registered under the "module" prefix on the module's container, so that the
+/// widgets in the module can activate them as "module.<name>".
+pub fn module_action_group(container: &gtk::Box) -> gio::SimpleActionGroup {
+    if let Some(group) = container
+        .action_group("module")
+        .and_then(|g| g.downcast::<gio::SimpleActionGroup>().ok())
+    {
+        return group;
+    }
+    let group = gio::SimpleActionGroup::new();
+    container.insert_action_group("module", Some(&group));
+    group
+}
+
+/// Adds the standard "refresh" action to a module.
+///
+/// Polling modules should call this so that users can force an update. The action is also
+/// activated by a middle click on `widget`. It does nothing while the module is pinned.
+pub fn add_refresh_action<W: IsA<gtk::Widget>, F: Fn() + 'static>(
+    container: &gtk::Box,
+    widget: &W,
+    f: F,
+) {
+    let action = gio::SimpleAction::new("refresh", None);
+    action.connect_activate(glib::clone!(@weak container => move |_, _| {
+        if !is_pinned(&container) {
+            f();
+        }
+    }));
+    module_action_group(container).add_action(&action);
+
+    widget.connect_button_release_event(move |_, e| {
+        if e.button() == gtk::gdk::BUTTON_MIDDLE
+            && !e.state().contains(gtk::gdk::ModifierType::SHIFT_MASK)
+        {
+            action.activate(None);
+            return Inhibit(true);
+        }
+        Inhibit(false)
+    });
+}
+
+/// Adds the standard "pin" action to a module.
+///
+/// The action toggles whether the module is pinned. While pinned, the container has the "pinned"
+/// CSS class and [`is_pinned`] returns true. Modules should keep their current content while
+/// pinned. The action is also activated by a shift + middle click on `widget`. The pinned state
+/// is saved and restored by the bar after restarts.
+pub fn add_pin_action<W: IsA<gtk::Widget>>(container: &gtk::Box, widget: &W) {
+    let store = StateStore::new(container);
+    let action = gio::SimpleAction::new_stateful("pin", None, &false.to_variant());
+    action.connect_activate(glib::clone!(@weak container => move |action, _| {
+        let pinned = !action.state().and_then(|v| v.get::<bool>()).unwrap_or(false);
+        action.set_state(&pinned.to_variant());
+        store.set("pinned", &pinned);
+        if pinned {
+            container.style_context().add_class("pinned");
+        } else {
+            container.style_context().remove_class("pinned");
+            // Catch up with the updates skipped while pinned.
+            let group = module_action_group(&container);
+            if group.has_action("refresh") {
+                group.activate_action("refresh", None);
+            }
+        }
+    }));
+    module_action_group(container).add_action(&action);
+
+    widget.connect_button_release_event(move |_, e| {
+        if e.button() == gtk::gdk::BUTTON_MIDDLE
+            && e.state().contains(gtk::gdk::ModifierType::SHIFT_MASK)
+        {
+            action.activate(None);
+            return Inhibit(true);
+        }
+        Inhibit(false)
+    });
+}
+
+/// Returns true if the module is pinned by the "pin" action.
+pub fn is_pinned(container: &gtk::Box) -> bool {
+    container
+        .action_group("module")
+        .map_or(false, |g| pin_state(&g))
+}
+
+/// Restores the pin saved before a restart. This is called once after the module is built.
+///
+/// The module has no content to keep yet, so the pin takes effect when a label in the module
+/// changes for the first time.
+pub(crate) fn restore_pin(container: &gtk::Box) {
+    let group = module_action_group(container);
+    let pinned = StateStore::new(container).get::<bool>("pinned") == Some(true);
+    if !pinned || !group.has_action("pin") {
+        return;
+    }
+    let labels = descendant_labels(container.upcast_ref());
+    if labels.is_empty() {
+        group.activate_action("pin", None);
+        return;
+    }
+    let handlers: Rc<RefCell<Vec<(gtk::Label, glib::SignalHandlerId)>>> = Rc::default();
+    for label in labels {
+        let id =
+            label.connect_label_notify(glib::clone!(@weak group, @strong handlers => move |_| {
+                for (label, id) in handlers.borrow_mut().drain(..) {
+                    label.disconnect(id);
+                }
+                if !pin_state(&group) {
+                    group.activate_action("pin", None);
+                }
+            }));
+        handlers.borrow_mut().push((label, id));
+    }
+}
+
+fn pin_state<G: IsA<gio::ActionGroup>>(group: &G) -> bool {
+    group
+        .action_state("pin")
+        .and_then(|v| v.get::<bool>())
+        .unwrap_or(false)
+}
+
+fn descendant_labels(widget: &gtk::Widget) -> Vec<gtk::Label> {
+    if let Some(label) = widget.downcast_ref::<gtk::Label>() {
+        return vec![label.clone()];
+    }
+    match widget.downcast_ref::<gtk::Container>() {
+        Some(container) => container
+            .children()
+            .iter()
+            .flat_map(descendant_labels)
+            .collect(),
+        None => vec![],
+    }
+}
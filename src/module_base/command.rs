@@ -0,0 +1,50 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running external commands without blocking the UI.
+
+use super::task::{self, Pool};
+use std::process::{Command, Stdio};
+
+/// Runs the command line with `sh -c` in the command thread pool.
+///
+/// `callback` is called in the main thread with the stdout if the command succeeds, or an error
+/// message (stderr or the exit status) if it fails.
+pub fn run<F: FnOnce(Result<String, String>) + 'static>(command_line: &str, callback: F) {
+    let command_line = command_line.to_owned();
+    task::spawn(async move {
+        callback(task::unblock_in(Pool::Commands, move || run_sync(&command_line)).await);
+    });
+}
+
+/// Runs the command line with `sh -c` and waits for it.
+pub fn run_sync(command_line: &str) -> Result<String, String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command_line)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Cannot run {}: {}", command_line, e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        Err(if stderr.is_empty() {
+            format!("{} failed: {}", command_line, output.status)
+        } else {
+            stderr.to_owned()
+        })
+    }
+}
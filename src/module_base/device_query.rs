@@ -0,0 +1,191 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sort and filter expressions for device menus.
+//!
+//! A filter is clauses of `<field> <op> <value>` combined with `&&` and `||`, where `&&` binds
+//! tighter. The operators are `==`, `!=`, `~` (regular expression match), and `!~`. The value can
+//! be quoted with `'` or `"`, and a quoted value can contain `&&` and `||`. For example,
+//! `form_factor == 'headset' || description ~ '^USB'`.
+//!
+//! A sort is comma-separated fields. A field prefixed with `-` is sorted in descending order. For
+//! example, `form_factor,-description`. Items with the same fields keep their original order.
+
+use regex::Regex;
+use std::cmp::Ordering;
+
+/// An item that can be filtered and sorted by fields.
+pub trait Fields {
+    /// Returns the field value. Missing values are treated as an empty string.
+    fn field(&self, name: &str) -> Option<String>;
+}
+
+enum Op {
+    Eq,
+    Ne,
+    Match(Regex),
+    NotMatch(Regex),
+}
+
+struct Clause {
+    field: String,
+    op: Op,
+    value: String,
+}
+
+impl Clause {
+    fn matches<T: Fields>(&self, item: &T) -> bool {
+        let v = item.field(&self.field).unwrap_or_default();
+        match self.op {
+            Op::Eq => v == self.value,
+            Op::Ne => v != self.value,
+            Op::Match(ref r) => r.is_match(&v),
+            Op::NotMatch(ref r) => !r.is_match(&v),
+        }
+    }
+}
+
+fn check_field(field: &str, fields: &[&str]) -> Result<(), String> {
+    if fields.contains(&field) {
+        Ok(())
+    } else {
+        Err(format!(
+            "unknown field \"{}\". Use one of {:?}",
+            field, fields
+        ))
+    }
+}
+
+/// Splits the text at the separators outside the quoted values.
+fn split_unquoted<'a>(s: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if i >= start && s[i..].starts_with(sep) => {
+                parts.push(&s[start..i]);
+                start = i + sep.len();
+            }
+            None => (),
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn parse_clause(s: &str, fields: &[&str]) -> Result<Clause, String> {
+    let s = s.trim();
+    let end = s
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    let field = &s[..end];
+    check_field(field, fields)?;
+    let rest = s[end..].trim_start();
+    let (op, value) = ["==", "!=", "!~", "~"]
+        .iter()
+        .find_map(|op| Some((*op, rest.strip_prefix(op)?)))
+        .ok_or_else(|| format!("expected ==, !=, ~, or !~ after \"{}\" in \"{}\"", field, s))?;
+    let value = value.trim();
+    let value = ['\'', '"']
+        .iter()
+        .find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q))
+        .unwrap_or(value)
+        .to_owned();
+    let regex = || Regex::new(&value).map_err(|e| format!("invalid regex \"{}\": {}", value, e));
+    let op = match op {
+        "==" => Op::Eq,
+        "!=" => Op::Ne,
+        "~" => Op::Match(regex()?),
+        _ => Op::NotMatch(regex()?),
+    };
+    Ok(Clause {
+        field: field.to_owned(),
+        op,
+        value,
+    })
+}
+
+/// A filter and a sort for a device list.
+#[derive(Default)]
+pub struct DeviceQuery {
+    /// Clauses in the disjunctive normal form. Empty means all items match.
+    filter: Vec<Vec<Clause>>,
+    /// Fields and whether they are in the descending order.
+    sort: Vec<(String, bool)>,
+}
+
+impl DeviceQuery {
+    /// Parses the filter and sort expressions. `fields` are the field names the items have.
+    pub fn new(
+        filter: Option<&str>,
+        sort: Option<&str>,
+        fields: &[&str],
+    ) -> Result<DeviceQuery, String> {
+        let mut query = DeviceQuery::default();
+        if let Some(filter) = filter.filter(|f| !f.trim().is_empty()) {
+            for conjunction in split_unquoted(filter, "||") {
+                query.filter.push(
+                    split_unquoted(conjunction, "&&")
+                        .into_iter()
+                        .map(|c| parse_clause(c, fields))
+                        .collect::<Result<_, _>>()?,
+                );
+            }
+        }
+        if let Some(sort) = sort {
+            for key in sort.split(',').map(|k| k.trim()).filter(|k| !k.is_empty()) {
+                let (field, desc) = match key.strip_prefix('-') {
+                    Some(field) => (field, true),
+                    None => (key, false),
+                };
+                check_field(field, fields)?;
+                query.sort.push((field.to_owned(), desc));
+            }
+        }
+        Ok(query)
+    }
+
+    /// Returns true if the item passes the filter.
+    pub fn matches<T: Fields>(&self, item: &T) -> bool {
+        self.filter.is_empty()
+            || self
+                .filter
+                .iter()
+                .any(|clauses| clauses.iter().all(|c| c.matches(item)))
+    }
+
+    /// Filters and sorts the items.
+    pub fn apply<T: Fields>(&self, items: Vec<T>) -> Vec<T> {
+        let mut items: Vec<T> = items.into_iter().filter(|i| self.matches(i)).collect();
+        items.sort_by(|a, b| {
+            self.sort
+                .iter()
+                .map(|(field, desc)| {
+                    let ord = a.field(field).cmp(&b.field(field));
+                    if *desc {
+                        ord.reverse()
+                    } else {
+                        ord
+                    }
+                })
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        items
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A circular gauge widget.
+
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How a module shows a percentage. Modules take this as the `style` config.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValueStyle {
+    /// A text label.
+    Text,
+    /// A [`Gauge`].
+    Ring,
+}
+
+impl Default for ValueStyle {
+    fn default() -> Self {
+        ValueStyle::Text
+    }
+}
+
+/// A ring showing a percentage as an arc with a text in the center.
+///
+/// The arc is drawn with the widget's foreground color, and the track with the same color at 25%
+/// opacity. The widget is 24px wide, and the ring fits in the bar height.
+pub struct Gauge {
+    area: gtk::DrawingArea,
+    /// (percentage, text)
+    value: Rc<RefCell<(f64, String)>>,
+}
+
+impl Gauge {
+    pub fn new() -> Gauge {
+        let area = gtk::DrawingArea::new();
+        area.style_context().add_class("gauge");
+        let value: Rc<RefCell<(f64, String)>> = Rc::default();
+        area.connect_draw(glib::clone!(@strong value => move |area, cr| {
+            let value = value.borrow();
+            let (percentage, text) = (value.0, &value.1);
+            let width = area.allocated_width() as f64;
+            let height = area.allocated_height() as f64;
+            let line_width = (height / 8.0).max(2.0);
+            let radius = (width.min(height) - line_width) / 2.0;
+            let (xc, yc) = (width / 2.0, height / 2.0);
+            let color = area.style_context().color(gtk::StateFlags::NORMAL);
+            cr.set_line_width(line_width);
+
+            cr.set_source_rgba(color.red, color.green, color.blue, color.alpha * 0.25);
+            cr.arc(xc, yc, radius, 0.0, 2.0 * std::f64::consts::PI);
+            let _ = cr.stroke();
+
+            // Clockwise from the top.
+            let start = -std::f64::consts::FRAC_PI_2;
+            let end = start + 2.0 * std::f64::consts::PI * percentage.max(0.0).min(100.0) / 100.0;
+            cr.set_source_rgba(color.red, color.green, color.blue, color.alpha);
+            cr.new_sub_path();
+            cr.arc(xc, yc, radius, start, end);
+            let _ = cr.stroke();
+
+            cr.set_font_size(radius * 0.8);
+            if let Ok(extents) = cr.text_extents(text) {
+                cr.move_to(
+                    xc - extents.width / 2.0 - extents.x_bearing,
+                    yc - extents.height / 2.0 - extents.y_bearing,
+                );
+                let _ = cr.show_text(text);
+            }
+            Inhibit(false)
+        }));
+        area.set_size_request(24, -1);
+        Gauge { area, value }
+    }
+
+    /// Returns the widget to add to a container.
+    pub fn widget(&self) -> &gtk::DrawingArea {
+        &self.area
+    }
+
+    /// Shows the percentage (0-100) with the text in the center.
+    pub fn set(&self, percentage: f64, text: &str) {
+        self.value.replace((percentage, text.to_owned()));
+        let text = format!("{:.0}%", percentage);
+        self.area.set_tooltip_text(Some(&text));
+        super::set_accessible(&self.area, &text, None);
+        self.area.queue_draw();
+    }
+}
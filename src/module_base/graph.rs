@@ -0,0 +1,263 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Widgets for numeric series.
+
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A sample in a History.
+#[derive(Clone, Copy)]
+pub struct Sample {
+    pub time: SystemTime,
+    pub value: f64,
+}
+
+/// A ring buffer of numeric samples.
+pub struct History {
+    samples: VecDeque<Sample>,
+    capacity: usize,
+}
+
+impl History {
+    /// Creates a History that keeps the last `capacity` samples.
+    pub fn new(capacity: usize) -> History {
+        History {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds a sample. The oldest sample is dropped if the buffer is full.
+    pub fn push(&mut self, v: f64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            time: SystemTime::now(),
+            value: v,
+        });
+    }
+
+    /// Returns the samples from the oldest to the newest.
+    pub fn samples(&self) -> &VecDeque<Sample> {
+        &self.samples
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.samples
+            .iter()
+            .map(|s| s.value)
+            .fold(None, |acc, v| match acc {
+                Some(m) if m <= v => Some(m),
+                _ => Some(v),
+            })
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.samples
+            .iter()
+            .map(|s| s.value)
+            .fold(None, |acc, v| match acc {
+                Some(m) if m >= v => Some(m),
+                _ => Some(v),
+            })
+    }
+
+    pub fn avg(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().map(|s| s.value).sum::<f64>() / self.samples.len() as f64)
+    }
+
+    /// Writes the samples to a CSV file with "timestamp,value" rows. The timestamp is in UNIX
+    /// seconds.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut f = std::fs::File::create(path)?;
+        writeln!(f, "timestamp,value")?;
+        for s in &self.samples {
+            let t = s.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            writeln!(f, "{:.3},{}", t.as_secs_f64(), s.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds the "export-history" action to a module.
+///
+/// The action takes a file path as a string parameter and writes the history there as CSV. A write
+/// error is returned to the IPC caller.
+pub fn add_export_action(container: &gtk::Box, history: Rc<RefCell<History>>) {
+    let action = gio::SimpleAction::new("export-history", Some(glib::VariantTy::STRING));
+    action.connect_activate(move |_, param| {
+        if let Some(path) = param.and_then(|p| p.get::<String>()) {
+            if let Err(e) = history.borrow().write_csv(&path) {
+                super::report_action_error(format!(
+                    "Failed to export the history to {}: {}",
+                    path, e
+                ));
+            }
+        }
+    });
+    super::module_action_group(container).add_action(&action);
+}
+
+/// Creates a DrawingArea that renders the history as a sparkline.
+///
+/// The line is drawn with the widget's foreground color. If `range` is not specified, the graph is
+/// scaled to the min and max of the samples. Call `queue_draw` after pushing samples.
+pub fn sparkline(history: Rc<RefCell<History>>, range: Option<(f64, f64)>) -> gtk::DrawingArea {
+    let area = gtk::DrawingArea::new();
+    area.style_context().add_class("sparkline");
+    area.connect_draw(move |area, cr| {
+        let history = history.borrow();
+        let samples = history.samples();
+        if samples.len() < 2 {
+            return Inhibit(false);
+        }
+        let (lo, hi) = range.unwrap_or_else(|| {
+            (
+                history.min().unwrap_or_default(),
+                history.max().unwrap_or_default(),
+            )
+        });
+        let span = if hi > lo { hi - lo } else { 1.0 };
+        let width = area.allocated_width() as f64;
+        let height = area.allocated_height() as f64;
+        let step = width / (history.capacity().max(2) - 1) as f64;
+        let x0 = width - step * (samples.len() - 1) as f64;
+
+        let color = area.style_context().color(gtk::StateFlags::NORMAL);
+        cr.set_source_rgba(color.red, color.green, color.blue, color.alpha);
+        cr.set_line_width(1.0);
+        for (i, s) in samples.iter().enumerate() {
+            let y = height - (s.value - lo) / span * height;
+            let x = x0 + step * i as f64;
+            if i == 0 {
+                cr.move_to(x, y);
+            } else {
+                cr.line_to(x, y);
+            }
+        }
+        let _ = cr.stroke();
+        Inhibit(false)
+    });
+    area
+}
+
+/// A popover showing a larger graph with min/avg/max of a history.
+pub struct HistoryPopup {
+    popover: gtk::Popover,
+    area: gtk::DrawingArea,
+    stats: gtk::Label,
+    history: Rc<RefCell<History>>,
+    unit: String,
+}
+
+impl HistoryPopup {
+    /// Creates a popup that is shown when `button` is clicked.
+    ///
+    /// `unit` is appended to the numbers in the stats (e.g. "%"). The popup works only while the
+    /// returned value is alive.
+    pub fn new(
+        button: &gtk::Button,
+        history: Rc<RefCell<History>>,
+        range: Option<(f64, f64)>,
+        unit: &str,
+    ) -> Rc<HistoryPopup> {
+        let popover = gtk::Popover::new(Some(button));
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        let area = sparkline(history.clone(), range);
+        area.set_size_request(300, 100);
+        let stats = gtk::Label::new(None);
+        let export = gtk::Button::with_label("Export CSV…");
+        export.set_relief(gtk::ReliefStyle::None);
+        vbox.add(&area);
+        vbox.add(&stats);
+        vbox.add(&export);
+        popover.add(&vbox);
+        popover.style_context().add_class("history-popup");
+
+        let popup = Rc::new(HistoryPopup {
+            popover,
+            area,
+            stats,
+            history,
+            unit: unit.to_owned(),
+        });
+        button.connect_clicked(glib::clone!(@weak popup => move |_| {
+            popup.update();
+            popup.popover.show_all();
+            popup.popover.popup();
+        }));
+        export.connect_clicked(glib::clone!(@weak popup => move |_| {
+            popup.popover.popdown();
+            popup.export_with_dialog();
+        }));
+        popup
+    }
+
+    /// Asks a file path and exports the history there.
+    fn export_with_dialog(&self) {
+        let dialog = gtk::FileChooserNative::new(
+            Some("Export history"),
+            None::<&gtk::Window>,
+            gtk::FileChooserAction::Save,
+            None,
+            None,
+        );
+        dialog.set_do_overwrite_confirmation(true);
+        dialog.set_current_name("history.csv");
+        if dialog.run() == gtk::ResponseType::Accept {
+            if let Some(path) = dialog.filename() {
+                if let Err(e) = self.history.borrow().write_csv(&path) {
+                    eprintln!("Failed to export the history to {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+
+    /// Updates the graph and the stats. Call this after pushing samples.
+    pub fn update(&self) {
+        if !self.popover.is_visible() {
+            return;
+        }
+        let history = self.history.borrow();
+        let fmt = |v: Option<f64>| {
+            v.map(|v| format!("{:.1}{}", v, self.unit))
+                .unwrap_or_else(|| "-".to_owned())
+        };
+        self.stats.set_text(&format!(
+            "min {} / avg {} / max {}",
+            fmt(history.min()),
+            fmt(history.avg()),
+            fmt(history.max())
+        ));
+        self.area.queue_draw();
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small HTTP client for modules.
+//!
+//! This uses the `curl` command so that the requests can be made without pulling an HTTP stack
+//! into the binary. All functions block, so call them from the shared thread pool (e.g.
+//! [`super::poll_in_worker`] or [`super::task::unblock`]).
+
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref CACHE: Mutex<HashMap<String, (Instant, String)>> = Mutex::new(HashMap::new());
+}
+
+/// An HTTP GET request.
+pub struct Request {
+    url: String,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl Request {
+    pub fn new(url: &str) -> Request {
+        Request {
+            url: url.to_owned(),
+            headers: vec![("User-Agent".to_owned(), "jiji".to_owned())],
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Request {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Request {
+        self.timeout = timeout;
+        self
+    }
+
+    fn command(&self) -> Command {
+        let mut cmd = Command::new("curl");
+        cmd.args(&["--silent", "--location", "--max-time"])
+            .arg(self.timeout.as_secs().max(1).to_string());
+        for (name, value) in &self.headers {
+            cmd.arg("--header").arg(format!("{}: {}", name, value));
+        }
+        cmd.stdin(Stdio::null()).stderr(Stdio::null());
+        cmd
+    }
+
+    /// Returns the status code. No response is reported as an error.
+    pub fn status(&self) -> Result<u16, String> {
+        let out = self
+            .command()
+            .args(&["--output", "/dev/null", "--write-out", "%{http_code}"])
+            .arg(&self.url)
+            .output()
+            .map_err(|e| format!("Failed to run curl: {}", e))?;
+        match String::from_utf8_lossy(&out.stdout).trim().parse::<u16>() {
+            Ok(0) | Err(_) => Err("No response".to_owned()),
+            Ok(code) => Ok(code),
+        }
+    }
+
+    /// Returns the response body. A non-2xx response is reported as an error.
+    pub fn get(&self) -> Result<String, String> {
+        let out = self
+            .command()
+            .arg("--fail")
+            .arg(&self.url)
+            .output()
+            .map_err(|e| format!("Failed to run curl: {}", e))?;
+        if !out.status.success() {
+            return Err(format!("Failed to get {}", self.url));
+        }
+        String::from_utf8(out.stdout).map_err(|e| e.to_string())
+    }
+
+    /// Same as `get`, but reuses a response fetched within `ttl`.
+    ///
+    /// This is useful when the same module is shown on multiple monitors, and for APIs that ask
+    /// clients to avoid frequent requests.
+    pub fn get_cached(&self, ttl: Duration) -> Result<String, String> {
+        if let Some((t, body)) = CACHE.lock().unwrap().get(&self.url) {
+            if t.elapsed() < ttl {
+                return Ok(body.clone());
+            }
+        }
+        let body = self.get()?;
+        CACHE
+            .lock()
+            .unwrap()
+            .insert(self.url.clone(), (Instant::now(), body.clone()));
+        Ok(body)
+    }
+}
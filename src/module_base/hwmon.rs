@@ -0,0 +1,97 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reading hardware monitoring sensors in /sys/class/hwmon.
+
+use std::path::PathBuf;
+
+/// A sensor input of a hwmon chip (e.g. "fan1_input").
+#[derive(Clone)]
+pub struct Sensor {
+    /// Name of the chip (e.g. "coretemp", "thinkpad").
+    pub chip: String,
+    /// Label of the sensor (e.g. "Package id 0"). Defaults to the input name (e.g. "fan1").
+    pub label: String,
+    path: PathBuf,
+}
+
+impl Sensor {
+    /// Returns "chip/label", which identifies the sensor in configs.
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.chip, self.label)
+    }
+
+    /// Returns true if `name` is the ID or the label of this sensor.
+    pub fn matches(&self, name: &str) -> bool {
+        self.id() == name || self.label == name
+    }
+
+    /// Reads the raw value. Fans are in RPM, and temperatures are in millidegrees Celsius.
+    pub fn read(&self) -> Option<i64> {
+        std::fs::read_to_string(&self.path)
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+}
+
+/// Enumerates the sensors of the kind (e.g. "fan", "temp") sorted by the ID.
+pub fn sensors(kind: &str) -> Vec<Sensor> {
+    let mut ret = vec![];
+    let chips = match std::fs::read_dir("/sys/class/hwmon") {
+        Ok(chips) => chips,
+        Err(_) => return ret,
+    };
+    for chip_dir in chips.flatten().map(|e| e.path()) {
+        let chip = std::fs::read_to_string(chip_dir.join("name"))
+            .map(|s| s.trim().to_owned())
+            .unwrap_or_default();
+        let files = match std::fs::read_dir(&chip_dir) {
+            Ok(files) => files,
+            Err(_) => continue,
+        };
+        for file in files.flatten() {
+            let name = file.file_name().to_string_lossy().to_string();
+            let input = match name.strip_suffix("_input") {
+                Some(input)
+                    if input.starts_with(kind)
+                        && input[kind.len()..].chars().all(|c| c.is_ascii_digit()) =>
+                {
+                    input.to_owned()
+                }
+                _ => continue,
+            };
+            let label = std::fs::read_to_string(chip_dir.join(format!("{}_label", input)))
+                .map(|s| s.trim().to_owned())
+                .unwrap_or(input);
+            ret.push(Sensor {
+                chip: chip.clone(),
+                label,
+                path: file.path(),
+            });
+        }
+    }
+    ret.sort_by_key(|s| s.id());
+    ret
+}
+
+/// Enumerates the sensors of the kind that match one of the names. All sensors of the kind are
+/// returned if `names` is empty.
+pub fn select(kind: &str, names: &[String]) -> Vec<Sensor> {
+    sensors(kind)
+        .into_iter()
+        .filter(|s| names.is_empty() || names.iter().any(|n| s.matches(n)))
+        .collect()
+}
@@ -0,0 +1,331 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Semantic icons.
+//!
+//! Modules request icons by semantic names (e.g. "volume-high", "battery-20"), and they are
+//! resolved through the backend selected in the config. This way, one setting restyles the icons
+//! of all modules consistently.
+//!
+//! The known names are:
+//!
+//! * volume-high, volume-medium, volume-low, volume-muted
+//! * mic, mic-muted, camera, screenshare
+//! * battery-0, battery-10, ..., battery-100, and battery-N-charging variants
+//! * cpu, memory, network-wireless, network-wired, network-offline, bluetooth
+//! * ok, warning, error, refresh, clock, calendar
+//! * weather-clear, weather-cloudy, weather-rain, weather-snow, weather-fog, weather-storm
+
+use crate::config::{IconBackend, IconConfig};
+use gtk::glib;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::time::Duration;
+
+thread_local! {
+    static CONFIG: RefCell<IconConfig> = RefCell::new(IconConfig::default());
+}
+
+/// Sets the icon config. This should be called before creating modules.
+pub fn set_config(config: IconConfig) {
+    CONFIG.with(|c| c.replace(config));
+}
+
+/// A resolved icon.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resolved {
+    /// A text (e.g. an emoji or a Nerd Font glyph).
+    Text(String),
+    /// An icon name in the GTK icon theme.
+    Themed(String),
+}
+
+/// Returns the name of the battery icon for the percentage, rounded to the nearest 10%.
+pub fn battery_icon_name(percentage: f64, charging: bool) -> String {
+    let level = ((percentage / 10.0).round() as i32).max(0).min(10) * 10;
+    if charging {
+        format!("battery-{}-charging", level)
+    } else {
+        format!("battery-{}", level)
+    }
+}
+
+/// Resolves a semantic icon name with the configured backend.
+pub fn resolve(name: &str) -> Resolved {
+    CONFIG.with(|c| {
+        let c = c.borrow();
+        if let Some(v) = c.overrides.get(name) {
+            return match c.backend {
+                IconBackend::Gtk => Resolved::Themed(v.to_owned()),
+                _ => Resolved::Text(v.to_owned()),
+            };
+        }
+        match c.backend {
+            IconBackend::Gtk => Resolved::Themed(gtk_icon(name)),
+            IconBackend::NerdFont => {
+                Resolved::Text(nerd_font_icon(name).unwrap_or(name).to_owned())
+            }
+            IconBackend::Emoji => Resolved::Text(emoji_icon(name).unwrap_or(name).to_owned()),
+        }
+    })
+}
+
+/// Returns the text for the icon if the configured backend is text-based.
+///
+/// This is for embedding icons in a text (e.g. a format string). With the "gtk" backend, this
+/// returns None and the caller should use an [`Icon`] widget instead.
+pub fn text(name: &str) -> Option<String> {
+    match resolve(name) {
+        Resolved::Text(s) => Some(s),
+        Resolved::Themed(_) => None,
+    }
+}
+
+/// Splits "battery-N" and "battery-N-charging" into (N, charging).
+fn parse_battery(name: &str) -> Option<(u32, bool)> {
+    let rest = name.strip_prefix("battery-")?;
+    let (level, charging) = match rest.strip_suffix("-charging") {
+        Some(level) => (level, true),
+        None => (rest, false),
+    };
+    Some((level.parse().ok()?, charging))
+}
+
+fn gtk_icon(name: &str) -> String {
+    if let Some((level, charging)) = parse_battery(name) {
+        return if charging {
+            format!("battery-level-{}-charging-symbolic", level)
+        } else {
+            format!("battery-level-{}-symbolic", level)
+        };
+    }
+    let icon = match name {
+        "volume-high" => "audio-volume-high-symbolic",
+        "volume-medium" => "audio-volume-medium-symbolic",
+        "volume-low" => "audio-volume-low-symbolic",
+        "volume-muted" => "audio-volume-muted-symbolic",
+        "mic" => "audio-input-microphone-symbolic",
+        "mic-muted" => "microphone-sensitivity-muted-symbolic",
+        "camera" => "camera-web-symbolic",
+        "screenshare" => "video-display-symbolic",
+        "cpu" => "computer-symbolic",
+        "memory" => "drive-harddisk-solidstate-symbolic",
+        "network-wireless" => "network-wireless-symbolic",
+        "network-wired" => "network-wired-symbolic",
+        "network-offline" => "network-offline-symbolic",
+        "bluetooth" => "bluetooth-active-symbolic",
+        "ok" => "emblem-ok-symbolic",
+        "warning" => "dialog-warning-symbolic",
+        "error" => "dialog-error-symbolic",
+        "refresh" => "view-refresh-symbolic",
+        "clock" => "preferences-system-time-symbolic",
+        "calendar" => "x-office-calendar-symbolic",
+        "weather-clear" => "weather-clear-symbolic",
+        "weather-cloudy" => "weather-overcast-symbolic",
+        "weather-rain" => "weather-showers-symbolic",
+        "weather-snow" => "weather-snow-symbolic",
+        "weather-fog" => "weather-fog-symbolic",
+        "weather-storm" => "weather-storm-symbolic",
+        // Fall back to the name in the icon theme.
+        _ => name,
+    };
+    icon.to_owned()
+}
+
+fn nerd_font_icon(name: &str) -> Option<&'static str> {
+    if let Some((level, _)) = parse_battery(name) {
+        // There's no charging variant in the glyphs. The charging state is shown by the animation.
+        return Some(match level {
+            0..=10 => "\u{f244}",
+            11..=35 => "\u{f243}",
+            36..=60 => "\u{f242}",
+            61..=85 => "\u{f241}",
+            _ => "\u{f240}",
+        });
+    }
+    Some(match name {
+        "volume-high" => "\u{f028}",
+        "volume-medium" => "\u{f027}",
+        "volume-low" => "\u{f026}",
+        "volume-muted" => "\u{f6a9}",
+        "mic" => "\u{f130}",
+        "mic-muted" => "\u{f131}",
+        "camera" => "\u{f030}",
+        "screenshare" => "\u{f108}",
+        "cpu" => "\u{f2db}",
+        "memory" => "\u{f538}",
+        "network-wireless" => "\u{f1eb}",
+        "network-wired" => "\u{f6ff}",
+        "network-offline" => "\u{f127}",
+        "bluetooth" => "\u{f293}",
+        "ok" => "\u{f00c}",
+        "warning" => "\u{f071}",
+        "error" => "\u{f00d}",
+        "refresh" => "\u{f021}",
+        "clock" => "\u{f017}",
+        "calendar" => "\u{f073}",
+        "weather-clear" => "\u{f185}",
+        "weather-cloudy" => "\u{f0c2}",
+        "weather-rain" => "\u{f73d}",
+        "weather-snow" => "\u{f2dc}",
+        "weather-fog" => "\u{f75f}",
+        "weather-storm" => "\u{f0e7}",
+        _ => return None,
+    })
+}
+
+fn emoji_icon(name: &str) -> Option<&'static str> {
+    if let Some((level, charging)) = parse_battery(name) {
+        if charging {
+            return Some("⚡");
+        }
+        return Some(if level <= 20 { "🪫" } else { "🔋" });
+    }
+    Some(match name {
+        "volume-high" => "🔊",
+        "volume-medium" => "🔉",
+        "volume-low" => "🔈",
+        "volume-muted" => "🔇",
+        "mic" => "🎤",
+        "mic-muted" => "🎙️",
+        "camera" => "📷",
+        "screenshare" => "📺",
+        "cpu" => "🖥️",
+        "memory" => "🧠",
+        "network-wireless" => "📶",
+        "network-wired" => "🔌",
+        "network-offline" => "🚫",
+        "bluetooth" => "🦷",
+        "ok" => "✅",
+        "warning" => "⚠️",
+        "error" => "❌",
+        "refresh" => "🔄",
+        "clock" => "🕒",
+        "calendar" => "📅",
+        "weather-clear" => "☀️",
+        "weather-cloudy" => "☁️",
+        "weather-rain" => "🌧️",
+        "weather-snow" => "❄️",
+        "weather-fog" => "🌫️",
+        "weather-storm" => "⛈️",
+        _ => return None,
+    })
+}
+
+/// Returns the icon names for the battery charging animation.
+///
+/// The animation steps from the current level up to the full level.
+pub fn battery_charging_frames(percentage: f64) -> Vec<String> {
+    let level = ((percentage / 10.0).round() as i32).max(0).min(10);
+    (level..=10)
+        .map(|l| battery_icon_name((l * 10) as f64, true))
+        .collect()
+}
+
+fn show(image: &gtk::Image, label: &gtk::Label, name: &str) {
+    match resolve(name) {
+        Resolved::Text(s) => {
+            label.set_text(&s);
+            label.show();
+            image.hide();
+        }
+        Resolved::Themed(s) => {
+            image.set_from_icon_name(Some(&s), gtk::IconSize::Menu);
+            image.show();
+            label.hide();
+        }
+    }
+}
+
+/// A widget showing a semantic icon.
+pub struct Icon {
+    widget: gtk::Box,
+    image: gtk::Image,
+    label: gtk::Label,
+    animation: RefCell<Option<(Vec<String>, glib::SourceId)>>,
+}
+
+impl Icon {
+    pub fn new(name: &str) -> Icon {
+        let widget = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        widget.style_context().add_class("icon");
+        let image = gtk::Image::new();
+        let label = gtk::Label::new(None);
+        // Only one of them is shown. Keep the other hidden on show_all.
+        image.set_no_show_all(true);
+        label.set_no_show_all(true);
+        widget.add(&image);
+        widget.add(&label);
+        let icon = Icon {
+            widget,
+            image,
+            label,
+            animation: RefCell::new(None),
+        };
+        icon.set(name);
+        icon
+    }
+
+    /// Returns the widget to add to a container.
+    pub fn widget(&self) -> &gtk::Box {
+        &self.widget
+    }
+
+    /// Changes the icon. This stops the animation if any.
+    pub fn set(&self, name: &str) {
+        self.stop_animation();
+        show(&self.image, &self.label, name);
+    }
+
+    /// Shows the icons in turn with the interval.
+    ///
+    /// If the same frames are already animating, the animation continues as-is. With reduced
+    /// motion, only the first frame is shown.
+    pub fn animate(&self, frames: Vec<String>, interval: Duration) {
+        if super::reduced_motion() {
+            if let Some(frame) = frames.first() {
+                self.set(frame);
+            }
+            return;
+        }
+        if let Some((ref current, _)) = *self.animation.borrow() {
+            if current == &frames {
+                return;
+            }
+        }
+        self.stop_animation();
+        if frames.is_empty() {
+            return;
+        }
+        show(&self.image, &self.label, &frames[0]);
+        let mut index = 0;
+        let image = &self.image;
+        let label = &self.label;
+        let source = glib::timeout_add_local(
+            interval,
+            glib::clone!(@weak image, @weak label, @strong frames => @default-return Continue(false), move || {
+                index = (index + 1) % frames.len();
+                show(&image, &label, &frames[index]);
+                Continue(true)
+            }),
+        );
+        self.animation.replace(Some((frames, source)));
+    }
+
+    fn stop_animation(&self) {
+        if let Some((_, source)) = self.animation.take() {
+            glib::source_remove(source);
+        }
+    }
+}
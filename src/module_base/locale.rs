@@ -0,0 +1,140 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Locale and unit settings shared by all modules.
+
+use crate::config::{ByteUnit, HourCycle, LocaleConfig, TemperatureUnit, TextDirection};
+use once_cell::sync::Lazy;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::process::Command;
+
+const TEXT_DOMAIN: &str = "jiji";
+
+/// The directory of the message catalogs (`<dir>/<lang>/LC_MESSAGES/jiji.mo`), which are built from
+/// `po/*.po`. Set JIJI_LOCALEDIR at build time to change it.
+const LOCALE_DIR: &str = match option_env!("JIJI_LOCALEDIR") {
+    Some(dir) => dir,
+    None => "/usr/share/locale",
+};
+
+/// The first day of the week of the locale. The locale doesn't change while running, so `locale` is
+/// run only once, in a thread started by [`set_config`].
+static LOCALE_FIRST_DAY: Lazy<Option<u32>> = Lazy::new(locale_first_day_of_week);
+
+extern "C" {
+    fn dgettext(domain: *const c_char, msgid: *const c_char) -> *mut c_char;
+    fn bindtextdomain(domain: *const c_char, dir: *const c_char) -> *mut c_char;
+    fn bind_textdomain_codeset(domain: *const c_char, codeset: *const c_char) -> *mut c_char;
+}
+
+thread_local! {
+    static CONFIG: RefCell<LocaleConfig> = RefCell::new(LocaleConfig::default());
+}
+
+/// Sets the locale config. This should be called before creating modules.
+pub fn set_config(config: LocaleConfig) {
+    match config.text_direction {
+        // GTK picks the direction of the locale on startup.
+        TextDirection::Auto => {}
+        TextDirection::Ltr => gtk::Widget::set_default_direction(gtk::TextDirection::Ltr),
+        TextDirection::Rtl => gtk::Widget::set_default_direction(gtk::TextDirection::Rtl),
+    }
+    if config.first_day_of_week.is_none() {
+        std::thread::spawn(|| Lazy::force(&LOCALE_FIRST_DAY));
+    }
+    CONFIG.with(|c| c.replace(config));
+    let domain = CString::new(TEXT_DOMAIN).unwrap();
+    let dir = CString::new(LOCALE_DIR).unwrap();
+    let codeset = CString::new("UTF-8").unwrap();
+    unsafe {
+        bindtextdomain(domain.as_ptr(), dir.as_ptr());
+        bind_textdomain_codeset(domain.as_ptr(), codeset.as_ptr());
+    }
+}
+
+/// Returns true if the widgets are laid out from right to left.
+pub fn is_rtl() -> bool {
+    gtk::Widget::default_direction() == gtk::TextDirection::Rtl
+}
+
+/// Translates a built-in string with gettext in the "jiji" domain.
+pub fn tr(msgid: &str) -> String {
+    let domain = CString::new(TEXT_DOMAIN).unwrap();
+    let id = match CString::new(msgid) {
+        Ok(id) => id,
+        Err(_) => return msgid.to_owned(),
+    };
+    // dgettext returns the msgid itself if there's no translation.
+    unsafe { CStr::from_ptr(dgettext(domain.as_ptr(), id.as_ptr())) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns the g_date_time_format format of hours and minutes (e.g. "%H:%M").
+pub fn time_format() -> &'static str {
+    match CONFIG.with(|c| c.borrow().hour_cycle) {
+        HourCycle::H24 => "%H:%M",
+        HourCycle::H12 => "%I:%M %p",
+    }
+}
+
+/// Returns the first day of the week. 0 is Sunday.
+pub fn first_day_of_week() -> u32 {
+    if let Some(day) = CONFIG.with(|c| c.borrow().first_day_of_week) {
+        return day % 7;
+    }
+    LOCALE_FIRST_DAY.unwrap_or(0)
+}
+
+/// Reads the first day of the week of the LC_TIME locale with `locale`.
+///
+/// first_weekday is 1-based from week-1stday, which is a Sunday (19971130) or a Monday (19971201).
+fn locale_first_day_of_week() -> Option<u32> {
+    let output = Command::new("locale")
+        .args(&["week-1stday", "first_weekday"])
+        .output()
+        .ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+    let origin = match lines.next()?.trim() {
+        "19971130" => 0,
+        "19971201" => 1,
+        _ => return None,
+    };
+    let first_weekday: u32 = lines.next()?.trim().parse().ok()?;
+    Some((origin + first_weekday + 6) % 7)
+}
+
+/// Converts a temperature in Celsius to the unit in the config.
+pub fn temperature(celsius: f64) -> f64 {
+    match CONFIG.with(|c| c.borrow().temperature_unit) {
+        TemperatureUnit::Celsius => celsius,
+        TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+    }
+}
+
+/// Returns the symbol of the temperature unit (e.g. "°C").
+pub fn temperature_symbol() -> &'static str {
+    match CONFIG.with(|c| c.borrow().temperature_unit) {
+        TemperatureUnit::Celsius => "°C",
+        TemperatureUnit::Fahrenheit => "°F",
+    }
+}
+
+/// Returns the unit of byte sizes.
+pub fn byte_unit() -> ByteUnit {
+    CONFIG.with(|c| c.borrow().byte_unit)
+}
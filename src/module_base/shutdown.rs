@@ -0,0 +1,40 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cleanup on exit.
+//!
+//! jiji quits on SIGTERM and SIGINT after running the registered hooks. Backends register a hook
+//! to disconnect from their servers (e.g. PulseAudio) so that the servers don't keep the
+//! subscriptions until they notice the dead connection. The module states don't need a hook
+//! because [`crate::module_base::StateStore`] writes them on every change.
+
+use std::cell::RefCell;
+
+thread_local! {
+    static HOOKS: RefCell<Vec<Box<dyn FnOnce()>>> = RefCell::new(vec![]);
+}
+
+/// Registers a function to run on shutdown. The hooks run in the reverse order of the
+/// registration.
+pub fn on_shutdown<F: FnOnce() + 'static>(f: F) {
+    HOOKS.with(|h| h.borrow_mut().push(Box::new(f)));
+}
+
+/// Runs the registered hooks. The hooks run only once even if this is called again.
+pub(crate) fn run_hooks() {
+    let hooks = HOOKS.with(|h| h.take());
+    for hook in hooks.into_iter().rev() {
+        hook();
+    }
+}
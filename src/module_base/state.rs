@@ -0,0 +1,183 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistent state of modules.
+//!
+//! The states are saved in `$XDG_STATE_HOME/jiji/state.json` (`~/.local/state/jiji/state.json` by
+//! default) so that they survive restarts. Each module instance has its own state keyed by the
+//! monitor and the module ID, so that the bars on multiple monitors are restored as they were.
+//! The states that are not tied to a module (e.g. the active profile) use [`StateStore::global`].
+
+use gtk::glib;
+use gtk::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+thread_local! {
+    static STATES: RefCell<Option<serde_json::Map<String, serde_json::Value>>> = RefCell::new(None);
+    static INSTANCES: RefCell<Vec<(glib::WeakRef<gtk::Box>, String)>> = RefCell::new(vec![]);
+}
+
+/// Sets the state key of a module instance. This should be called before building the module.
+///
+/// `bar` identifies the bar by the monitor (and the config name with multiple configs).
+/// `occurrence` distinguishes the modules with the same ID on the same bar.
+pub(crate) fn register_instance(container: &gtk::Box, bar: &str, occurrence: usize) {
+    let id = container.widget_name();
+    let key = if occurrence == 0 {
+        format!("{}/{}", bar, id)
+    } else {
+        format!("{}/{}#{}", bar, id, occurrence)
+    };
+    INSTANCES.with(|i| {
+        let mut i = i.borrow_mut();
+        i.retain(|(c, _)| c.upgrade().is_some());
+        i.push((container.downgrade(), key));
+    });
+}
+
+fn instance_key(container: &gtk::Box) -> Option<String> {
+    INSTANCES.with(|i| {
+        i.borrow()
+            .iter()
+            .find(|(c, _)| c.upgrade().as_ref() == Some(container))
+            .map(|(_, k)| k.clone())
+    })
+}
+
+fn state_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(d) if !d.is_empty() => PathBuf::from(d),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(dir.join("jiji").join("state.json"))
+}
+
+fn with_states<R>(f: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>) -> R) -> R {
+    STATES.with(|s| {
+        let mut s = s.borrow_mut();
+        let states = s.get_or_insert_with(|| state_path().map(|p| load(&p)).unwrap_or_default());
+        f(states)
+    })
+}
+
+fn load(path: &Path) -> serde_json::Map<String, serde_json::Value> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Default::default(),
+        Err(e) => {
+            eprintln!("Cannot read the state from {}: {}", path.display(), e);
+            return Default::default();
+        }
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        eprintln!("Cannot parse the state in {}: {}", path.display(), e);
+        Default::default()
+    })
+}
+
+/// Saves the states. They are written to a temporary file that replaces the state file, so that
+/// a crash or a full disk during the write doesn't leave a broken state file.
+fn save(states: &serde_json::Map<String, serde_json::Value>) {
+    let path = match state_path() {
+        Some(p) => p,
+        None => return,
+    };
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let tmp = path.with_extension("json.tmp");
+    let content = serde_json::Value::Object(states.clone()).to_string();
+    let result = std::fs::File::create(&tmp)
+        .and_then(|mut f| {
+            f.write_all(content.as_bytes())?;
+            f.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp, &path));
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp);
+        eprintln!("Cannot save the state to {}: {}", path.display(), e);
+    }
+}
+
+/// The persistent state of a module instance.
+///
+/// The state is keyed by the monitor and the module ID. Values saved before the per-monitor keys
+/// (keyed only by the module ID) are read as a fallback.
+#[derive(Clone)]
+pub struct StateStore {
+    key: String,
+    fallback_key: String,
+}
+
+impl StateStore {
+    /// Returns the state store of the module in the container.
+    pub fn new(container: &gtk::Box) -> StateStore {
+        let id = container.widget_name().to_string();
+        StateStore {
+            key: instance_key(container).unwrap_or_else(|| id.clone()),
+            fallback_key: id,
+        }
+    }
+
+    /// Returns the state store of the application, not tied to a module instance.
+    pub fn global(name: &str) -> StateStore {
+        let key = format!("global:{}", name);
+        StateStore {
+            key: key.clone(),
+            fallback_key: key,
+        }
+    }
+
+    /// Returns the saved value. None if it's not saved or it cannot be deserialized as T.
+    pub fn get<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        with_states(|states| {
+            let v = states
+                .get(&self.key)
+                .and_then(|s| s.get(name))
+                .or_else(|| states.get(&self.fallback_key)?.get(name))?;
+            serde_json::from_value(v.clone()).ok()
+        })
+    }
+
+    /// Saves the value.
+    pub fn set<T: Serialize>(&self, name: &str, value: &T) {
+        let value = match serde_json::to_value(value) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        with_states(|states| {
+            let entry = states
+                .entry(self.key.clone())
+                .or_insert_with(|| serde_json::Value::Object(Default::default()));
+            if let Some(m) = entry.as_object_mut() {
+                m.insert(name.to_owned(), value);
+            }
+            save(states);
+        })
+    }
+
+    /// Removes the saved value.
+    pub fn remove(&self, name: &str) {
+        with_states(|states| {
+            if let Some(m) = states.get_mut(&self.key).and_then(|e| e.as_object_mut()) {
+                m.remove(name);
+            }
+            save(states);
+        })
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Restarts failed worker threads.
+//!
+//! A backend that talks to another process in a worker thread (e.g. the i3 event listener) runs
+//! the worker with [`supervise`]. When the worker fails, it's restarted with an exponential
+//! backoff, and the health is reported to the main thread so that the backend can expose it as
+//! its "backend-health" property.
+
+use gtk::glib;
+use gtk::prelude::*;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The backoff is reset after the worker runs this long.
+const STABLE_RUN: Duration = Duration::from_secs(60);
+
+/// The health of a supervised worker.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Health {
+    /// The worker is starting and hasn't reported that it's ready.
+    Starting,
+    /// The worker is running.
+    Ok,
+    /// The worker failed with the error, and it's going to be restarted.
+    Restarting(String),
+}
+
+impl Health {
+    /// Returns the value of the "backend-health" property: "starting", "ok", or "restarting".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Health::Starting => "starting",
+            Health::Ok => "ok",
+            Health::Restarting(_) => "restarting",
+        }
+    }
+
+    /// Returns the error of the last failure if the worker is restarting.
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            Health::Restarting(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health::Starting
+    }
+}
+
+/// Shows the health on the widget of a module using the backend.
+///
+/// The widget gets the "backend-starting", "backend-ok", or "backend-restarting" class, and the
+/// error is shown in the tooltip while restarting.
+pub fn show_health<W: IsA<gtk::Widget>>(widget: &W, health: &Health) {
+    let sc = widget.style_context();
+    for state in ["starting", "ok", "restarting"].iter() {
+        sc.remove_class(&format!("backend-{}", state));
+    }
+    sc.add_class(&format!("backend-{}", health.as_str()));
+    widget.set_tooltip_text(health.error());
+}
+
+/// Passed to a supervised worker to report its health.
+pub struct Worker {
+    sender: glib::Sender<Health>,
+}
+
+impl Worker {
+    /// Reports that the worker is running (e.g. connected to the server).
+    pub fn ready(&self) {
+        let _ = self.sender.send(Health::Ok);
+    }
+}
+
+/// Runs `work` in a worker thread named `name`, and calls `on_health` in the main thread when the
+/// health changes.
+///
+/// `work` returns Ok when it finishes normally, and then it's not restarted. It should return Ok
+/// when the main thread stops receiving its updates. An Err or a panic restarts it after the
+/// backoff.
+pub fn supervise<W, C>(name: &str, work: W, on_health: C)
+where
+    W: Fn(&Worker) -> Result<(), String> + Send + 'static,
+    C: Fn(&Health) + 'static,
+{
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    receiver.attach(None, move |health| {
+        on_health(&health);
+        glib::Continue(true)
+    });
+    let thread_name = name.to_owned();
+    let result = thread::Builder::new().name(name.to_owned()).spawn(move || {
+        let worker = Worker { sender };
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let error = match panic::catch_unwind(AssertUnwindSafe(|| work(&worker))) {
+                Ok(Ok(())) => return,
+                Ok(Err(e)) => e,
+                Err(_) => "The worker panicked".to_owned(),
+            };
+            if started.elapsed() >= STABLE_RUN {
+                backoff = MIN_BACKOFF;
+            }
+            eprintln!(
+                "{}: {}. Restarting in {}s",
+                thread_name,
+                error,
+                backoff.as_secs()
+            );
+            if worker.sender.send(Health::Restarting(error)).is_err() {
+                return;
+            }
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("Cannot start the {} worker: {}", name, e);
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Async tasks on the GTK main loop.
+//!
+//! glib's main context runs the futures, so a task can update the widgets after an `.await`
+//! without sending the results through a channel. Blocking work (e.g. [`super::http`] requests
+//! and external commands) is moved to a thread pool shared by all modules with [`unblock`],
+//! instead of each module spawning its own threads. The commands from the config have their own
+//! pool ([`Pool::Commands`]).
+
+use gtk::glib;
+use once_cell::sync::Lazy;
+use std::future::Future;
+
+/// The number of threads for the blocking work. Network requests are short, so a few threads are
+/// enough for all modules.
+const MAX_THREADS: u32 = 8;
+
+static POOL: Lazy<glib::ThreadPool> = Lazy::new(|| {
+    glib::ThreadPool::shared(Some(MAX_THREADS)).expect("Failed to create the thread pool")
+});
+
+/// The commands from the config (e.g. `label_command`) can take any time, so they run in another
+/// pool without a cap. Otherwise a few slow commands would hold all threads of [`POOL`] and stop
+/// the other modules' polling.
+static COMMAND_POOL: Lazy<glib::ThreadPool> =
+    Lazy::new(|| glib::ThreadPool::shared(None).expect("Failed to create the command thread pool"));
+
+/// The thread pool to run blocking work in.
+#[derive(Clone, Copy)]
+pub enum Pool {
+    /// The capped pool for short work, e.g. network requests and reading system files.
+    Shared,
+    /// The uncapped pool for the commands from the config.
+    Commands,
+}
+
+/// Runs the future on the main loop.
+pub fn spawn<F: Future<Output = ()> + 'static>(future: F) {
+    glib::MainContext::default().spawn_local(future);
+}
+
+/// Runs `work` in the shared thread pool and resolves to its result.
+pub async fn unblock<T, W>(work: W) -> T
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+{
+    unblock_in(Pool::Shared, work).await
+}
+
+/// Runs `work` in the thread pool and resolves to its result.
+pub async fn unblock_in<T, W>(pool: Pool, work: W) -> T
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+{
+    let pool = match pool {
+        Pool::Shared => &POOL,
+        Pool::Commands => &COMMAND_POOL,
+    };
+    pool.push_future(work)
+        .expect("Failed to start the work in the thread pool")
+        .await
+}
+
+/// Runs `work` in the shared thread pool and passes the result to `callback` in the main thread.
+pub fn spawn_blocking<T, W, C>(work: W, callback: C)
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+    C: FnOnce(T) + 'static,
+{
+    spawn(async move { callback(unblock(work).await) });
+}
@@ -0,0 +1,354 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Format strings for module labels.
+//!
+//! A template is a text with placeholders:
+//!
+//! - `{name}` is replaced with the value. Numbers are rounded to integers.
+//! - `{name:SPEC}` formats the value with a spec like Rust's format strings: `<`, `>`, or `^` for
+//!   the alignment, `0` for zero padding, the width, and `.N` for the precision (e.g. `{volume:>3}`,
+//!   `{load:.2}`, `{capacity:03}`).
+//! - `{name:TEXT}` shows `TEXT` only if the value is true, a non-zero number, or a non-empty
+//!   string (e.g. `{muted: 🔇}`). `{!name:TEXT}` shows it only otherwise. `TEXT` can contain
+//!   placeholders.
+//! - `{{` and `}}` are `{` and `}`.
+//!
+//! Placeholders with unknown names are shown as is.
+
+/// A value for a placeholder.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Number(n) => *n != 0.0,
+            Value::Text(s) => !s.is_empty(),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Number(v)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(v: u64) -> Self {
+        Value::Number(v as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+#[derive(Default)]
+struct Spec {
+    align: Option<char>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Parses a format spec. None if it's not a spec.
+    fn parse(s: &str) -> Option<Spec> {
+        if s.is_empty() {
+            return None;
+        }
+        let mut rest = s;
+        let mut spec = Spec::default();
+        if let Some(c) = rest.chars().next().filter(|c| "<>^".contains(*c)) {
+            spec.align = Some(c);
+            rest = &rest[1..];
+        }
+        if rest.starts_with('0') {
+            spec.zero = true;
+            rest = &rest[1..];
+        }
+        let (width, precision) = match rest.find('.') {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+        let is_number = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if !is_number(width) {
+            return None;
+        }
+        spec.width = width.parse().unwrap_or(0);
+        if let Some(p) = precision {
+            if p.is_empty() || !is_number(p) {
+                return None;
+            }
+            spec.precision = p.parse().ok();
+        }
+        Some(spec)
+    }
+
+    fn format(&self, value: &Value) -> String {
+        let (s, numeric) = match value {
+            Value::Bool(b) => (b.to_string(), false),
+            Value::Number(n) => {
+                let precision = self.precision.unwrap_or(0);
+                if self.zero && self.align.is_none() {
+                    return format!(
+                        "{:0width$.precision$}",
+                        n,
+                        width = self.width,
+                        precision = precision
+                    );
+                }
+                (format!("{:.*}", precision, n), true)
+            }
+            Value::Text(t) => match self.precision {
+                Some(p) => (t.chars().take(p).collect(), false),
+                None => (t.clone(), false),
+            },
+        };
+        let len = s.chars().count();
+        if len >= self.width {
+            return s;
+        }
+        let pad = self.width - len;
+        let fill = |n: usize| " ".repeat(n);
+        match self.align.unwrap_or(if numeric { '>' } else { '<' }) {
+            '>' => fill(pad) + &s,
+            '^' => fill(pad / 2) + &s + &fill(pad - pad / 2),
+            _ => s + &fill(pad),
+        }
+    }
+}
+
+enum Part {
+    Text(String),
+    Value {
+        name: String,
+        spec: Spec,
+    },
+    Conditional {
+        name: String,
+        negate: bool,
+        body: Vec<Part>,
+    },
+}
+
+/// A parsed format string.
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parses a format string. Braces that don't form a placeholder are shown as is.
+    pub fn new(format: &str) -> Template {
+        let chars: Vec<char> = format.chars().collect();
+        let mut pos = 0;
+        let mut parts = parse_parts(&chars, &mut pos, false);
+        // A stray "}" at the top level.
+        while pos < chars.len() {
+            parts.push(Part::Text(chars[pos].to_string()));
+            pos += 1;
+            parts.extend(parse_parts(&chars, &mut pos, false));
+        }
+        Template { parts }
+    }
+
+    /// Formats the values. `values` are pairs of the placeholder names and the values.
+    pub fn render(&self, values: &[(&str, Value)]) -> String {
+        let mut ret = String::new();
+        render_parts(&self.parts, values, &mut ret);
+        ret
+    }
+}
+
+/// Parses the parts until the end, or a "}" if `nested`.
+fn parse_parts(chars: &[char], pos: &mut usize, nested: bool) -> Vec<Part> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        let next = chars.get(*pos + 1).copied();
+        if c == '}' && nested {
+            break;
+        }
+        if (c == '{' && next == Some('{')) || (c == '}' && next == Some('}')) {
+            text.push(c);
+            *pos += 2;
+            continue;
+        }
+        if c == '{' {
+            let start = *pos;
+            if let Some(part) = parse_placeholder(chars, pos) {
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.push(part);
+                continue;
+            }
+            *pos = start;
+        }
+        text.push(c);
+        *pos += 1;
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    parts
+}
+
+/// Parses a placeholder at the "{". None if it's not a placeholder.
+fn parse_placeholder(chars: &[char], pos: &mut usize) -> Option<Part> {
+    *pos += 1;
+    let negate = chars.get(*pos) == Some(&'!');
+    if negate {
+        *pos += 1;
+    }
+    let mut name = String::new();
+    while let Some(&c) = chars.get(*pos) {
+        if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            break;
+        }
+        name.push(c);
+        *pos += 1;
+    }
+    if name.is_empty() {
+        return None;
+    }
+    match chars.get(*pos)? {
+        '}' if !negate => {
+            *pos += 1;
+            Some(Part::Value {
+                name,
+                spec: Spec::default(),
+            })
+        }
+        ':' => {
+            *pos += 1;
+            if !negate {
+                let rest: String = chars[*pos..].iter().take_while(|c| **c != '}').collect();
+                if *pos + rest.chars().count() < chars.len() {
+                    if let Some(spec) = Spec::parse(&rest) {
+                        *pos += rest.chars().count() + 1;
+                        return Some(Part::Value { name, spec });
+                    }
+                }
+            }
+            let body = parse_parts(chars, pos, true);
+            if chars.get(*pos) != Some(&'}') {
+                return None;
+            }
+            *pos += 1;
+            Some(Part::Conditional { name, negate, body })
+        }
+        _ => None,
+    }
+}
+
+fn render_parts(parts: &[Part], values: &[(&str, Value)], out: &mut String) {
+    let lookup = |name: &str| values.iter().find(|(n, _)| *n == name).map(|(_, v)| v);
+    for part in parts {
+        match part {
+            Part::Text(t) => out.push_str(t),
+            Part::Value { name, spec } => match lookup(name) {
+                Some(v) => out.push_str(&spec.format(v)),
+                None => {
+                    out.push('{');
+                    out.push_str(name);
+                    out.push('}');
+                }
+            },
+            Part::Conditional { name, negate, body } => {
+                let truthy = lookup(name).map(|v| v.is_truthy()).unwrap_or(false);
+                if truthy != *negate {
+                    render_parts(body, values, out);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(format: &str, values: &[(&str, Value)]) -> String {
+        Template::new(format).render(values)
+    }
+
+    #[test]
+    fn plain_values() {
+        assert_eq!(render("{n}%", &[("n", 2.6.into())]), "3%");
+        assert_eq!(render("{s}", &[("s", "abc".into())]), "abc");
+        assert_eq!(render("{b}", &[("b", true.into())]), "true");
+        assert_eq!(render("{unknown}", &[]), "{unknown}");
+    }
+
+    #[test]
+    fn specs() {
+        assert_eq!(render("{v:>3}", &[("v", 5u64.into())]), "  5");
+        assert_eq!(render("{v:<3}|", &[("v", 5u64.into())]), "5  |");
+        assert_eq!(render("{v:^5}|", &[("v", "ab".into())]), " ab  |");
+        assert_eq!(render("{v:.2}", &[("v", 1.234.into())]), "1.23");
+        assert_eq!(render("{v:03}", &[("v", 7u64.into())]), "007");
+        assert_eq!(render("{v:.2}", &[("v", "abcd".into())]), "ab");
+    }
+
+    #[test]
+    fn ambiguous_width_is_a_spec() {
+        assert_eq!(render("{name:5}|", &[("name", "ab".into())]), "ab   |");
+        assert_eq!(render("{name:5}", &[("name", 3u64.into())]), "    3");
+        assert_eq!(render("{name:x5}", &[("name", true.into())]), "x5");
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(render("{muted: M}", &[("muted", true.into())]), " M");
+        assert_eq!(render("{muted: M}", &[("muted", false.into())]), "");
+        assert_eq!(render("{muted: M}", &[]), "");
+        assert_eq!(render("{!muted:on}", &[("muted", false.into())]), "on");
+        assert_eq!(render("{!muted:on}", &[("muted", 1u64.into())]), "");
+        assert_eq!(render("{s:x}", &[("s", "".into())]), "");
+        let values = [("charging", true.into()), ("capacity", 50u64.into())];
+        assert_eq!(render("{charging:+{capacity:>3}%}", &values), "+ 50%");
+    }
+
+    #[test]
+    fn escapes_and_stray_braces() {
+        assert_eq!(render("{{n}}", &[("n", 1u64.into())]), "{n}");
+        assert_eq!(render("a}b", &[]), "a}b");
+        assert_eq!(render("{n", &[("n", 1u64.into())]), "{n");
+        assert_eq!(render("{}", &[]), "{}");
+        assert_eq!(render("{n:{m}", &[("n", true.into())]), "{n:{m}");
+    }
+}
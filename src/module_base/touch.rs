@@ -0,0 +1,123 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Touchscreen gestures.
+//!
+//! GTK emulates mouse events for a tap, so a tap already works as a primary click. The helpers
+//! here add the gestures that have no mouse equivalent. The gestures only react to touch, and the
+//! mouse behavior is unchanged.
+
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Minimum velocity in pixels per second for a swipe.
+const SWIPE_VELOCITY: f64 = 300.0;
+
+/// A direction of a swipe.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Swipe {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Keeps the gesture as long as the widget lives. GTK 3 widgets don't hold their gestures.
+fn keep_alive<W: IsA<gtk::Widget>, G: IsA<gtk::EventController>>(widget: &W, gesture: G) {
+    widget.connect_destroy(move |_| {
+        let _ = &gesture;
+    });
+}
+
+/// Calls `f` on a long press. Modules use this for the action of the secondary click.
+pub fn connect_long_press<W: IsA<gtk::Widget>, F: Fn(&W) + 'static>(widget: &W, f: F) {
+    let gesture = gtk::GestureLongPress::new(widget);
+    gesture.set_touch_only(true);
+    let weak = widget.downgrade();
+    gesture.connect_pressed(move |gesture, _, _| {
+        if let Some(widget) = weak.upgrade() {
+            // Claiming the touch stops the emulated primary click on release.
+            gesture.set_state(gtk::EventSequenceState::Claimed);
+            f(&widget);
+        }
+    });
+    keep_alive(widget, gesture);
+}
+
+/// Calls `f` with the direction of a swipe. The directions are on the screen, and callers mirror
+/// them for the right-to-left layout if needed.
+pub fn connect_swipe<W: IsA<gtk::Widget>, F: Fn(Swipe) + 'static>(widget: &W, f: F) {
+    let gesture = gtk::GestureSwipe::new(widget);
+    gesture.set_touch_only(true);
+    gesture.connect_swipe(move |gesture, vx, vy| {
+        if vx.abs().max(vy.abs()) < SWIPE_VELOCITY {
+            return;
+        }
+        gesture.set_state(gtk::EventSequenceState::Claimed);
+        f(if vx.abs() >= vy.abs() {
+            if vx > 0.0 {
+                Swipe::Right
+            } else {
+                Swipe::Left
+            }
+        } else if vy > 0.0 {
+            Swipe::Down
+        } else {
+            Swipe::Up
+        });
+    });
+    keep_alive(widget, gesture);
+}
+
+/// Calls `begin` when a finger starts moving, and then `update` with the number of steps from the
+/// start each time the finger moves horizontally by `step` pixels. The steps are positive toward
+/// the end of the layout (the right, or the left in the right-to-left direction). This is for
+/// adjusting a value continuously, e.g. the volume.
+pub fn connect_horizontal_pan<W, B, U>(widget: &W, step: f64, begin: B, update: U)
+where
+    W: IsA<gtk::Widget>,
+    B: Fn() + 'static,
+    U: Fn(i32) + 'static,
+{
+    let gesture = gtk::GestureDrag::new(widget);
+    gesture.set_touch_only(true);
+    // None until the pan is recognized as horizontal.
+    let steps: Rc<Cell<Option<i32>>> = Rc::default();
+    gesture.connect_drag_begin({
+        let steps = steps.clone();
+        move |_, _, _| steps.set(None)
+    });
+    gesture.connect_drag_update(move |gesture, offset_x, offset_y| {
+        let mut current = (offset_x / step) as i32;
+        let rtl = gesture
+            .widget()
+            .map_or(false, |w| w.direction() == gtk::TextDirection::Rtl);
+        if rtl {
+            current = -current;
+        }
+        match steps.get() {
+            None if current == 0 || offset_y.abs() > offset_x.abs() => return,
+            None => {
+                gesture.set_state(gtk::EventSequenceState::Claimed);
+                begin();
+            }
+            Some(s) if s == current => return,
+            Some(_) => {}
+        }
+        steps.set(Some(current));
+        update(current);
+    });
+    keep_alive(widget, gesture);
+}
@@ -0,0 +1,226 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::gauge::{Gauge, ValueStyle};
+use crate::module_base::icon::{battery_charging_frames, battery_icon_name, Icon};
+use crate::module_base::locale;
+use crate::module_base::template::Template;
+use crate::module_base::{
+    add_pin_action, add_refresh_action, is_pinned, set_accessible, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+fn default_interval() -> u32 {
+    30
+}
+
+fn default_warning() -> f64 {
+    20.0
+}
+
+fn default_critical() -> f64 {
+    10.0
+}
+
+fn default_format() -> String {
+    "{capacity}%".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct BatteryConfig {
+    /// Name of the battery in /sys/class/power_supply (e.g. "BAT0"). Defaults to the first one
+    /// whose name starts with "BAT".
+    #[serde(default)]
+    battery: Option<String>,
+
+    /// Interval in seconds to read the battery state.
+    #[serde(default = "default_interval")]
+    interval: u32,
+
+    /// The "battery-warning" class is added when the capacity is at or below this percentage.
+    #[serde(default = "default_warning")]
+    warning: f64,
+
+    /// The "battery-critical" class is added when the capacity is at or below this percentage.
+    #[serde(default = "default_critical")]
+    critical: f64,
+
+    /// Animate the icon while charging.
+    #[serde(default = "default_true")]
+    animate_charging: bool,
+
+    /// "text" (default) or "ring".
+    #[serde(default)]
+    style: ValueStyle,
+
+    /// Format of the label for the "text" style with "{capacity}", "{status}" (e.g.
+    /// "Discharging"), and "{charging}". See [`crate::module_base::template`] for the syntax.
+    #[serde(default = "default_format")]
+    format: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        BatteryConfig {
+            battery: None,
+            interval: default_interval(),
+            warning: default_warning(),
+            critical: default_critical(),
+            animate_charging: true,
+            style: ValueStyle::default(),
+            format: default_format(),
+        }
+    }
+}
+
+/// Returns the sysfs directory of the battery.
+fn battery_dir(name: &Option<String>) -> Option<PathBuf> {
+    let base = PathBuf::from("/sys/class/power_supply");
+    if let Some(name) = name {
+        return Some(base.join(name));
+    }
+    let mut names: Vec<String> = std::fs::read_dir(&base)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|n| n.starts_with("BAT"))
+        .collect();
+    names.sort();
+    names.first().map(|n| base.join(n))
+}
+
+/// Reads (capacity, status) of the battery.
+fn read_battery(dir: &Path) -> Option<(f64, String)> {
+    let capacity = std::fs::read_to_string(dir.join("capacity"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    let status = std::fs::read_to_string(dir.join("status"))
+        .map(|s| s.trim().to_owned())
+        .unwrap_or_default();
+    Some((capacity, status))
+}
+
+fn battery_module() -> FnModFactory<BatteryConfig> {
+    FnModFactory::new(
+        "battery",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<BatteryConfig>, container: &gtk::Box| {
+            let event_box = gtk::EventBox::new();
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            hbox.style_context().add_class("battery");
+            let icon = Icon::new("battery-100");
+            let label = gtk::Label::new(None);
+            hbox.add(icon.widget());
+            let gauge = if config.style == ValueStyle::Ring {
+                let gauge = Gauge::new();
+                hbox.add(gauge.widget());
+                Some(gauge)
+            } else {
+                hbox.add(&label);
+                None
+            };
+            event_box.add(&hbox);
+            container.add(&event_box);
+
+            let dir = battery_dir(&config.battery);
+            let template = Template::new(&config.format);
+            let config = config.clone();
+            let update = Rc::new(move |hbox: &gtk::Box, label: &gtk::Label| {
+                let (capacity, status) = match dir.as_deref().and_then(read_battery) {
+                    Some(v) => v,
+                    None => {
+                        match gauge {
+                            Some(ref gauge) => gauge.set(0.0, "?"),
+                            None => label.set_text(&locale::tr("No battery")),
+                        }
+                        return;
+                    }
+                };
+                let charging = status == "Charging";
+                let mut name = format!("{} {:.0}%", locale::tr("Battery"), capacity);
+                if !status.is_empty() {
+                    name += &format!(", {}", locale::tr(&status.to_lowercase()));
+                }
+                set_accessible(hbox, &name, None);
+                match gauge {
+                    Some(ref gauge) => gauge.set(capacity, &format!("{:.0}", capacity)),
+                    None => label.set_text(&template.render(&[
+                        ("capacity", capacity.into()),
+                        ("status", status.as_str().into()),
+                        ("charging", charging.into()),
+                    ])),
+                }
+                if charging && config.animate_charging {
+                    icon.animate(
+                        battery_charging_frames(capacity),
+                        Duration::from_millis(500),
+                    );
+                } else {
+                    icon.set(&battery_icon_name(capacity, charging));
+                }
+                let sc = hbox.style_context();
+                let classes = [
+                    ("battery-charging", charging),
+                    ("battery-full", status == "Full"),
+                    ("battery-warning", !charging && capacity <= config.warning),
+                    ("battery-critical", !charging && capacity <= config.critical),
+                ];
+                for (class, enabled) in classes.iter() {
+                    if *enabled {
+                        sc.add_class(class);
+                    } else {
+                        sc.remove_class(class);
+                    }
+                }
+            });
+            update(&hbox, &label);
+
+            glib::timeout_add_seconds_local(
+                config.interval,
+                glib::clone!(@weak hbox, @weak label, @weak container, @strong update => @default-return Continue(false), move || {
+                    if !is_pinned(&container) {
+                        update(&hbox, &label);
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(
+                container,
+                &event_box,
+                glib::clone!(@weak hbox, @weak label => move || update(&hbox, &label)),
+            );
+            add_pin_action(container, &event_box);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(battery_module())]
+}
+
+crate::register_plugin!("battery", make_module_factories);
@@ -0,0 +1,130 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{FnModFactory, JSONConfigFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::xkb::{self, ConnectionExt as _};
+use x11rb::protocol::Event;
+
+fn default_text() -> String {
+    "🔔".to_owned()
+}
+
+fn default_duration() -> u64 {
+    500
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct BellConfig {
+    /// Text shown while flashing.
+    #[serde(default = "default_text")]
+    text: String,
+
+    /// Duration of the flash in milliseconds.
+    #[serde(default = "default_duration")]
+    duration: u64,
+}
+
+impl Default for BellConfig {
+    fn default() -> Self {
+        BellConfig {
+            text: default_text(),
+            duration: default_duration(),
+        }
+    }
+}
+
+/// Listens for XKB bell notify events. Sends () to the sender for each bell.
+fn listen_bell(tx: glib::Sender<()>) -> Result<(), String> {
+    let (conn, _) = x11rb::connect(None).map_err(|e| e.to_string())?;
+    conn.xkb_use_extension(1, 0)
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| e.to_string())?;
+    conn.xkb_select_events(
+        xkb::ID::USE_CORE_KBD.into(),
+        0u16,
+        xkb::EventType::BELL_NOTIFY,
+        0u16,
+        0u16,
+        &xkb::SelectEventsAux::new(),
+    )
+    .map_err(|e| e.to_string())?
+    .check()
+    .map_err(|e| e.to_string())?;
+    loop {
+        if let Event::XkbBellNotify(_) = conn.wait_for_event().map_err(|e| e.to_string())? {
+            if tx.send(()).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn bell_module() -> FnModFactory<BellConfig> {
+    FnModFactory::new(
+        "bell",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<BellConfig>, container: &gtk::Box| {
+            let label = gtk::Label::new(Some(&config.text));
+            label.style_context().add_class("bell");
+            // Shown only while flashing.
+            label.set_no_show_all(true);
+            container.add(&label);
+
+            let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            std::thread::spawn(move || {
+                if let Err(e) = listen_bell(tx) {
+                    eprintln!("Cannot listen for the X11 bell: {}", e);
+                }
+            });
+            let duration = Duration::from_millis(config.duration);
+            let hide_source: Rc<RefCell<Option<glib::SourceId>>> = Rc::default();
+            rx.attach(
+                None,
+                glib::clone!(@weak label => @default-return Continue(false), move |()| {
+                    label.show();
+                    if let Some(source) = hide_source.take() {
+                        glib::source_remove(source);
+                    }
+                    let hide_source_clone = hide_source.clone();
+                    hide_source.replace(Some(glib::timeout_add_local(
+                        duration,
+                        glib::clone!(@weak label => @default-return Continue(false), move || {
+                            label.hide();
+                            hide_source_clone.take();
+                            Continue(false)
+                        }),
+                    )));
+                    Continue(true)
+                }),
+            );
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(bell_module())]
+}
+
+crate::register_plugin!("bell", make_module_factories);
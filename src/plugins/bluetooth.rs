@@ -0,0 +1,248 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bluetooth::{BluetoothState, DeviceState};
+use crate::module_base::icon::Icon;
+use crate::module_base::{
+    add_refresh_action, connect_notify_while_alive, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+fn default_show_battery() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct BluetoothConfig {
+    /// Show the name of the connected device next to the icon. If multiple devices are connected,
+    /// the number of the devices is shown instead.
+    #[serde(default)]
+    show_device: bool,
+
+    /// Show the battery percentage of the connected device if reported.
+    #[serde(default = "default_show_battery")]
+    show_battery: bool,
+}
+
+impl Default for BluetoothConfig {
+    fn default() -> Self {
+        BluetoothConfig {
+            show_device: false,
+            show_battery: default_show_battery(),
+        }
+    }
+}
+
+/// Returns the name of the device with the battery percentage if reported.
+fn device_label(device: &DeviceState, show_battery: bool) -> String {
+    match device.battery {
+        Some(battery) if show_battery => format!("{} {}%", device.name, battery),
+        _ => device.name.clone(),
+    }
+}
+
+fn bluetooth_module() -> FnModFactory<BluetoothConfig> {
+    FnModFactory::new(
+        "bluetooth",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<BluetoothConfig>, container: &gtk::Box| {
+            let state = BluetoothState::shared();
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("bluetooth");
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            let icon = Icon::new("bluetooth");
+            let label = gtk::Label::new(None);
+            hbox.add(icon.widget());
+            hbox.add(&label);
+            button.add(&hbox);
+            container.add(&button);
+
+            button.connect_clicked(glib::clone!(@weak state => move |_| {
+                if let Some(adapter) = state.adapter() {
+                    state.set_powered(!adapter.powered);
+                }
+            }));
+
+            let config = config.clone();
+            let update = glib::clone!(@weak button, @weak label => move |state: &BluetoothState| {
+                let sc = button.style_context();
+                let powered = state.adapter().map(|a| a.powered).unwrap_or(false);
+                let connected: Vec<DeviceState> = state
+                    .devices()
+                    .into_iter()
+                    .map(|(_, d)| d)
+                    .filter(|d| d.connected)
+                    .collect();
+                if powered {
+                    sc.remove_class("bluetooth-off");
+                } else {
+                    sc.add_class("bluetooth-off");
+                }
+                if connected.is_empty() {
+                    sc.remove_class("bluetooth-connected");
+                } else {
+                    sc.add_class("bluetooth-connected");
+                }
+                let text = match connected.as_slice() {
+                    [device] if config.show_device => device_label(device, config.show_battery),
+                    [device] => match device.battery {
+                        Some(battery) if config.show_battery => format!("{}%", battery),
+                        _ => String::new(),
+                    },
+                    [] => String::new(),
+                    devices => devices.len().to_string(),
+                };
+                label.set_text(&text);
+                label.set_visible(!text.is_empty());
+                let tooltip = match state.adapter() {
+                    None => "No Bluetooth adapter".to_owned(),
+                    Some(_) if !powered => "Bluetooth is off. Click to turn on".to_owned(),
+                    Some(_) if connected.is_empty() => "No devices connected".to_owned(),
+                    Some(_) => connected
+                        .iter()
+                        .map(|d| device_label(d, true))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                };
+                button.set_tooltip_text(Some(&tooltip));
+            });
+            update(&state);
+            connect_notify_while_alive(&state, container, None, move |state, _| update(state));
+            add_refresh_action(
+                container,
+                &button,
+                glib::clone!(@weak state => move || state.refresh()),
+            );
+        }),
+    )
+}
+
+/// Fills the popover list with the paired devices.
+fn fill_device_list(list: &gtk::Grid, state: &BluetoothState) {
+    for ref child in list.children() {
+        list.remove(child);
+    }
+    let powered = state.adapter().map(|a| a.powered).unwrap_or(false);
+    let devices: Vec<DeviceState> = state
+        .devices()
+        .into_iter()
+        .map(|(_, d)| d)
+        .filter(|d| d.paired)
+        .collect();
+    if devices.is_empty() {
+        list.attach(&gtk::Label::new(Some("No paired devices")), 0, 0, 1, 1);
+        return;
+    }
+    for (row, device) in devices.into_iter().enumerate() {
+        let row = row as i32;
+        let name = gtk::Label::new(Some(&device.name));
+        name.set_xalign(0.0);
+        name.set_tooltip_text(Some(&device.address));
+        list.attach(&name, 0, row, 1, 1);
+        let battery = device
+            .battery
+            .map(|b| format!("{}%", b))
+            .unwrap_or_default();
+        list.attach(&gtk::Label::new(Some(&battery)), 1, row, 1, 1);
+        let action = gtk::Button::with_label(if device.connected {
+            "Disconnect"
+        } else {
+            "Connect"
+        });
+        action.set_sensitive(powered);
+        let path = device.path.clone();
+        let connected = device.connected;
+        action.connect_clicked(glib::clone!(@weak state => move |action| {
+            // Connecting takes a while. The list is refilled when the state changes.
+            action.set_sensitive(false);
+            if connected {
+                state.disconnect_device(&path);
+            } else {
+                state.connect_device(&path);
+            }
+        }));
+        list.attach(&action, 2, row, 1, 1);
+    }
+}
+
+fn bluetooth_devices_module() -> FnModFactory<serde_json::Value> {
+    FnModFactory::new(
+        "bluetooth-devices",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |_, container: &gtk::Box| {
+            let state = BluetoothState::shared();
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("bluetooth-devices");
+            button.add(Icon::new("bluetooth").widget());
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            let vbox = gtk::Box::new(gtk::Orientation::Vertical, 4);
+            let power = gtk::Switch::new();
+            let power_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+            power_row.pack_start(&gtk::Label::new(Some("Bluetooth")), false, false, 0);
+            power_row.pack_end(&power, false, false, 0);
+            vbox.add(&power_row);
+            vbox.add(&gtk::Separator::new(gtk::Orientation::Horizontal));
+            let list = gtk::Grid::new();
+            list.set_column_spacing(8);
+            list.set_row_spacing(4);
+            vbox.add(&list);
+            popover.add(&vbox);
+
+            let power_handler = power.connect_state_set(
+                glib::clone!(@weak state => @default-return Inhibit(false), move |_, on| {
+                    state.set_powered(on);
+                    Inhibit(false)
+                }),
+            );
+            let update = glib::clone!(@weak power, @weak list => move |state: &BluetoothState| {
+                let adapter = state.adapter();
+                power.block_signal(&power_handler);
+                power.set_active(adapter.as_ref().map(|a| a.powered).unwrap_or(false));
+                power.unblock_signal(&power_handler);
+                power.set_sensitive(adapter.is_some());
+                fill_device_list(&list, state);
+                list.show_all();
+            });
+            update(&state);
+            connect_notify_while_alive(&state, container, None, move |state, _| update(state));
+
+            button.connect_clicked(glib::clone!(@weak popover, @weak state => move |_| {
+                state.refresh();
+                popover.show_all();
+                popover.popup();
+            }));
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    // The state is created by the first module, so that listing the factories (e.g. for
+    // `jiji schema`) doesn't start polling.
+    vec![
+        Box::new(bluetooth_module()),
+        Box::new(bluetooth_devices_module()),
+    ]
+}
+
+crate::register_plugin!("bluetooth", make_module_factories);
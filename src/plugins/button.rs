@@ -12,16 +12,179 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::module_base::{FnModFactory, JSONConfigFactory};
+use crate::module_base::touch;
+use crate::module_base::{poll_command_in_worker, FnModFactory, JSONConfigFactory};
+use gtk::glib;
 use gtk::prelude::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::rc::Rc;
+use std::time::Duration;
 
-#[derive(Serialize, Deserialize, Default)]
+/// A command to run, either as arguments or as a command line.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(untagged)]
+enum CommandLine {
+    /// A command line run with `sh -c` (e.g. "notify-send $(date)").
+    Shell(String),
+    /// The program and its arguments (e.g. ["notify-send", "hello"]).
+    Args(Vec<String>),
+}
+
+impl Default for CommandLine {
+    fn default() -> Self {
+        CommandLine::Args(vec![])
+    }
+}
+
+impl CommandLine {
+    /// Returns the command to spawn. None if the arguments are empty.
+    fn command(&self) -> Option<Command> {
+        match self {
+            CommandLine::Shell(s) => {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(s);
+                Some(command)
+            }
+            CommandLine::Args(args) => {
+                let mut command = Command::new(args.first()?);
+                command.args(&args[1..]);
+                Some(command)
+            }
+        }
+    }
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 struct ButtonConfig {
+    /// The label. With label_command, this is shown until the first output.
     text: String,
-    command: Vec<String>,
+
+    /// The command whose stdout is shown as the label. It's run every interval and after the
+    /// commands run by clicks exit.
+    #[serde(default)]
+    label_command: Option<CommandLine>,
+
+    /// Interval in seconds to run label_command.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// The command to run on click.
+    command: CommandLine,
+
+    /// The command to run on right click.
+    #[serde(default)]
+    right_command: Option<CommandLine>,
+
+    /// The command to run on middle click.
+    #[serde(default)]
+    middle_command: Option<CommandLine>,
+
+    /// The command to run on scrolling up.
+    #[serde(default)]
+    scroll_up_command: Option<CommandLine>,
+
+    /// The command to run on scrolling down.
+    #[serde(default)]
+    scroll_down_command: Option<CommandLine>,
+
+    /// Working directory of the command. Defaults to the one of jiji.
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+
+    /// Environment variables added to the commands.
+    #[serde(default)]
+    env: HashMap<String, String>,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        ButtonConfig {
+            text: String::new(),
+            label_command: None,
+            interval: default_interval(),
+            command: CommandLine::default(),
+            right_command: None,
+            middle_command: None,
+            scroll_up_command: None,
+            scroll_down_command: None,
+            cwd: None,
+            env: HashMap::new(),
+        }
+    }
+}
+
+/// Returns the command with the working directory and the environment variables.
+fn build_command(
+    command_line: &CommandLine,
+    cwd: &Option<PathBuf>,
+    env: &HashMap<String, String>,
+) -> Result<Command, String> {
+    let mut command = command_line
+        .command()
+        .ok_or_else(|| "The command is empty".to_owned())?;
+    if let Some(ref cwd) = cwd {
+        command.current_dir(cwd);
+    }
+    command.envs(env).stdin(Stdio::null());
+    Ok(command)
+}
+
+/// Spawns the command. `on_exit` is called in the main thread when the command exits.
+fn spawn<F: FnOnce() + 'static>(
+    config: &ButtonConfig,
+    command_line: &CommandLine,
+    on_exit: F,
+) -> Result<(), String> {
+    let mut child = build_command(command_line, &config.cwd, &config.env)?
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Cannot run the command of {}: {}", config.text, e))?;
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    std::thread::spawn(move || {
+        let _ = child.wait();
+        let _ = sender.send(());
+    });
+    let mut on_exit = Some(on_exit);
+    receiver.attach(None, move |()| {
+        if let Some(on_exit) = on_exit.take() {
+            on_exit();
+        }
+        glib::Continue(false)
+    });
+    Ok(())
+}
+
+/// Runs the label command and returns the first line of the stdout.
+fn read_label(
+    command_line: &CommandLine,
+    cwd: &Option<PathBuf>,
+    env: &HashMap<String, String>,
+) -> Result<String, String> {
+    let output = build_command(command_line, cwd, env)?
+        .output()
+        .map_err(|e| format!("Cannot run the label command: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "The label command failed: {} {}",
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned())
 }
 
 fn button_module() -> FnModFactory<ButtonConfig> {
@@ -33,19 +196,98 @@ fn button_module() -> FnModFactory<ButtonConfig> {
             button.set_relief(gtk::ReliefStyle::None);
             container.add(&button);
 
-            let command = config.command.clone();
-            button.connect_button_release_event(move |_, e| {
-                if e.button() == gtk::gdk::BUTTON_PRIMARY {
-                    Command::new(&command[0])
-                        .args(&command[1..])
-                        .stdin(Stdio::null())
-                        .stdout(Stdio::null())
-                        .stderr(Stdio::null())
-                        .spawn()
-                        .unwrap();
-                    return Inhibit(true);
+            let poller = config.label_command.clone().map(|label_command| {
+                let cwd = config.cwd.clone();
+                let env = config.env.clone();
+                Rc::new(poll_command_in_worker(
+                    Duration::from_secs(config.interval),
+                    move || read_label(&label_command, &cwd, &env),
+                    glib::clone!(@weak button => @default-return glib::Continue(false), move |result: Result<String, String>| {
+                        let sc = button.style_context();
+                        match result {
+                            Ok(label) => {
+                                sc.remove_class("error");
+                                button.set_tooltip_text(None);
+                                button.set_label(&label);
+                            }
+                            Err(e) => {
+                                sc.add_class("error");
+                                button.set_tooltip_text(Some(&e));
+                            }
+                        }
+                        glib::Continue(true)
+                    }),
+                ))
+            });
+
+            let run = {
+                let config = config.clone();
+                Rc::new(move |button: &gtk::Button, command_line: &CommandLine| {
+                    let sc = button.style_context();
+                    let poller = poller.clone();
+                    let on_exit = move || {
+                        if let Some(poller) = poller {
+                            poller.refresh();
+                        }
+                    };
+                    match spawn(&config, command_line, on_exit) {
+                        Ok(()) => {
+                            sc.remove_class("error");
+                            button.set_tooltip_text(None);
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            sc.add_class("error");
+                            button.set_tooltip_text(Some(&e));
+                        }
+                    }
+                })
+            };
+
+            {
+                let config = config.clone();
+                let run = run.clone();
+                button.connect_button_release_event(move |button, e| {
+                    let command_line = match e.button() {
+                        gtk::gdk::BUTTON_PRIMARY => Some(&config.command),
+                        gtk::gdk::BUTTON_SECONDARY => config.right_command.as_ref(),
+                        gtk::gdk::BUTTON_MIDDLE => config.middle_command.as_ref(),
+                        _ => None,
+                    };
+                    match command_line {
+                        Some(command_line) => {
+                            run(button, command_line);
+                            Inhibit(true)
+                        }
+                        None => Inhibit(false),
+                    }
+                });
+            }
+            if config.right_command.is_some() {
+                let config = config.clone();
+                let run = run.clone();
+                touch::connect_long_press(&button, move |button| {
+                    if let Some(ref command_line) = config.right_command {
+                        run(button, command_line);
+                    }
+                });
+            }
+
+            button.add_events(gtk::gdk::EventMask::SCROLL_MASK);
+            let config = config.clone();
+            button.connect_scroll_event(move |button, e| {
+                let command_line = match e.direction() {
+                    gtk::gdk::ScrollDirection::Up => config.scroll_up_command.as_ref(),
+                    gtk::gdk::ScrollDirection::Down => config.scroll_down_command.as_ref(),
+                    _ => None,
+                };
+                match command_line {
+                    Some(command_line) => {
+                        run(button, command_line);
+                        Inhibit(true)
+                    }
+                    None => Inhibit(false),
                 }
-                Inhibit(false)
             });
         }),
     )
@@ -56,3 +298,5 @@ pub(crate) fn make_module_factories(
 ) -> Vec<Box<dyn crate::module::ModuleFactory>> {
     vec![Box::new(button_module())]
 }
+
+crate::register_plugin!("button", make_module_factories);
@@ -0,0 +1,254 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{add_refresh_action, poll_in_worker, FnModFactory, JSONConfigFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::Duration;
+
+fn default_format() -> String {
+    "{title} in {until}".to_owned()
+}
+
+fn default_lead_time() -> u64 {
+    10
+}
+
+fn default_interval() -> u64 {
+    300
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct NextEventConfig {
+    /// .ics files or directories containing them. If empty, `khal list` is used instead.
+    #[serde(default)]
+    calendars: Vec<PathBuf>,
+
+    /// Format of the label. "{title}", "{time}" (HH:MM), and "{until}" are replaced.
+    #[serde(default = "default_format")]
+    format: String,
+
+    /// Text shown when there's no upcoming event. The module is hidden if empty.
+    #[serde(default)]
+    empty_text: String,
+
+    /// Minutes before the event to add the "next-event-soon" CSS class.
+    #[serde(default = "default_lead_time")]
+    lead_time: u64,
+
+    /// Command to run on click (e.g. "gnome-calendar").
+    #[serde(default)]
+    command: Option<String>,
+
+    /// Interval in seconds to re-read the calendars.
+    #[serde(default = "default_interval")]
+    interval: u64,
+}
+
+impl Default for NextEventConfig {
+    fn default() -> Self {
+        NextEventConfig {
+            calendars: vec![],
+            format: default_format(),
+            empty_text: String::new(),
+            lead_time: default_lead_time(),
+            command: None,
+            interval: default_interval(),
+        }
+    }
+}
+
+/// Reads the timed events of the next 7 days with `khal list`.
+///
+/// This expects khal's date and time formats to be "%Y-%m-%d" and "%H:%M" (e.g. `[locale]
+/// dateformat` and `timeformat` in the khal config).
+fn khal_events() -> Result<Vec<(i64, String)>, String> {
+    let out = Command::new("khal")
+        .args(&[
+            "list",
+            "--day-format",
+            "",
+            "--format",
+            "{start-date} {start-time}\t{title}",
+            "now",
+            "7d",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Cannot run khal: {}", e))?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_owned());
+    }
+    let tz = glib::TimeZone::new_local();
+    Ok(String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (start, title) = line.split_once('\t')?;
+            let n: Vec<i32> = start
+                .split(|c: char| !c.is_ascii_digit())
+                .filter(|s| !s.is_empty())
+                .filter_map(|s| s.parse().ok())
+                .collect();
+            // All-day events don't have the time.
+            if n.len() < 5 {
+                return None;
+            }
+            let t = glib::DateTime::new(&tz, n[0], n[1], n[2], n[3], n[4], 0.0)?;
+            Some((t.to_unix(), title.to_owned()))
+        })
+        .collect())
+}
+
+/// Returns the timed events that start after now, sorted by the start time.
+fn upcoming_events(calendars: &[PathBuf]) -> Result<Vec<(i64, String)>, String> {
+    let mut events = if calendars.is_empty() {
+        khal_events()?
+    } else {
+        crate::ical::read_events(calendars)
+            .into_iter()
+            .filter(|e| e.start_time.is_some())
+            .filter_map(|e| Some((e.start_unix()?, e.summary)))
+            .collect()
+    };
+    let now = glib::DateTime::new_now_local()
+        .map(|t| t.to_unix())
+        .unwrap_or_default();
+    events.retain(|(start, _)| *start >= now);
+    events.sort();
+    Ok(events)
+}
+
+/// Formats the duration until the event (e.g. "5m", "1h 20m", "2d").
+fn format_until(secs: i64) -> String {
+    let mins = (secs + 59) / 60;
+    if mins < 60 {
+        format!("{}m", mins)
+    } else if mins < 24 * 60 {
+        format!("{}h {}m", mins / 60, mins % 60)
+    } else {
+        format!("{}d", mins / (24 * 60))
+    }
+}
+
+fn next_event_module() -> FnModFactory<NextEventConfig> {
+    FnModFactory::new(
+        "next-event",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<NextEventConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("next-event");
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+
+            if let Some(ref command) = config.command {
+                let command = command.clone();
+                button.connect_clicked(move |_| {
+                    crate::module_base::command::run(&command, |result| {
+                        if let Err(e) = result {
+                            eprintln!("next-event: {}", e);
+                        }
+                    });
+                });
+            }
+
+            let events: Rc<RefCell<Vec<(i64, String)>>> = Rc::default();
+            let show_config = config.clone();
+            let show = Rc::new(
+                glib::clone!(@weak button, @weak label, @strong events => move || {
+                    let now = glib::DateTime::new_now_local()
+                        .map(|t| t.to_unix())
+                        .unwrap_or_default();
+                    let config = &show_config;
+                    let sc = button.style_context();
+                    let events = events.borrow();
+                    let next = events.iter().find(|(start, _)| *start >= now);
+                    let (start, title) = match next {
+                        Some(e) => e,
+                        None => {
+                            label.set_text(&config.empty_text);
+                            button.set_visible(!config.empty_text.is_empty());
+                            sc.remove_class("next-event-soon");
+                            return;
+                        }
+                    };
+                    let time = glib::DateTime::from_unix_local(*start)
+                        .and_then(|t| t.format("%H:%M"))
+                        .map(|s| s.to_string())
+                        .unwrap_or_default();
+                    label.set_text(
+                        &config
+                            .format
+                            .replace("{title}", title)
+                            .replace("{time}", &time)
+                            .replace("{until}", &format_until(start - now)),
+                    );
+                    button.set_visible(true);
+                    if start - now <= config.lead_time as i64 * 60 {
+                        sc.add_class("next-event-soon");
+                    } else {
+                        sc.remove_class("next-event-soon");
+                    }
+                }),
+            );
+
+            let calendars = config.calendars.clone();
+            let poller = poll_in_worker(
+                Duration::from_secs(config.interval),
+                move || upcoming_events(&calendars),
+                glib::clone!(@weak button, @strong events, @strong show => @default-return Continue(false), move |result| {
+                    match result {
+                        Ok(e) => {
+                            events.replace(e);
+                            button.style_context().remove_class("error");
+                            button.set_tooltip_text(None);
+                        }
+                        Err(e) => {
+                            button.style_context().add_class("error");
+                            button.set_tooltip_text(Some(&e));
+                        }
+                    }
+                    show();
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(container, &button, move || poller.refresh());
+
+            // The time until the event changes every minute.
+            let weak_button = button.downgrade();
+            glib::timeout_add_seconds_local(30, move || {
+                if weak_button.upgrade().is_none() {
+                    return Continue(false);
+                }
+                show();
+                Continue(true)
+            });
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(next_event_module())]
+}
+
+crate::register_plugin!("calendar", make_module_factories);
@@ -0,0 +1,228 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{
+    add_pin_action, add_refresh_action, is_pinned, poll_in_worker, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+fn default_warn_days() -> u32 {
+    14
+}
+
+fn default_interval() -> u64 {
+    24 * 60 * 60
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct CertExpiryConfig {
+    /// Domains to check. A port can be specified like "example.com:8443" or "[::1]:8443". The
+    /// default port is 443.
+    #[serde(default)]
+    domains: Vec<String>,
+
+    /// Warn when a certificate expires within this number of days.
+    #[serde(default = "default_warn_days")]
+    warn_days: u32,
+
+    /// Interval in seconds to check the certificates. Defaults to one day.
+    #[serde(default = "default_interval")]
+    interval: u64,
+}
+
+impl Default for CertExpiryConfig {
+    fn default() -> Self {
+        CertExpiryConfig {
+            domains: vec![],
+            warn_days: default_warn_days(),
+            interval: default_interval(),
+        }
+    }
+}
+
+/// Splits a domain with an optional port into the host name and the address to connect.
+///
+/// An IPv6 address needs the brackets to have a port (e.g. "[::1]:443"). Without a port, the
+/// brackets are optional.
+fn host_and_address(domain: &str) -> (&str, String) {
+    if let Some(rest) = domain.strip_prefix('[') {
+        if let Some((host, port)) = rest.split_once(']') {
+            return match port.strip_prefix(':') {
+                Some(port) => (host, format!("[{}]:{}", host, port)),
+                None => (host, format!("[{}]:443", host)),
+            };
+        }
+    }
+    match domain.matches(':').count() {
+        0 => (domain, format!("{}:443", domain)),
+        1 => (
+            domain.split(':').next().unwrap_or_default(),
+            domain.to_owned(),
+        ),
+        _ => (domain, format!("[{}]:443", domain)),
+    }
+}
+
+/// Time to wait for the server to send the certificate.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Returns the output of `openssl s_client` with the certificate of the server.
+///
+/// openssl waits for a server that doesn't respond as long as the OS allows. It's killed after
+/// [`FETCH_TIMEOUT`] so that it doesn't hold a thread of the shared pool.
+fn fetch_cert(host: &str, addr: &str) -> Result<Vec<u8>, String> {
+    let mut child = Command::new("openssl")
+        .args(&["s_client", "-servername", host, "-connect", addr])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    // Read in another thread so that openssl doesn't block on a full pipe.
+    let mut stdout = child.stdout.take();
+    let reader = std::thread::spawn(move || {
+        let mut out = vec![];
+        if let Some(ref mut stdout) = stdout {
+            let _ = stdout.read_to_end(&mut out);
+        }
+        out
+    });
+    let deadline = Instant::now() + FETCH_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(100)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("Timed out connecting to {}", addr));
+            }
+            Err(e) => {
+                let _ = child.kill();
+                return Err(format!("Failed to run openssl: {}", e));
+            }
+        }
+    }
+    reader
+        .join()
+        .map_err(|_| "Failed to read the output of openssl".to_owned())
+}
+
+/// Checks the certificate of the domain.
+///
+/// Returns an error message if the certificate cannot be retrieved or it expires within
+/// `warn_days`. This uses the `openssl` command.
+fn check_domain(domain: &str, warn_days: u32) -> Result<(), String> {
+    let (host, addr) = host_and_address(domain);
+    let cert = fetch_cert(host, &addr)?;
+
+    let mut x509 = Command::new("openssl")
+        .args(&["x509", "-noout", "-enddate", "-checkend"])
+        .arg((warn_days as u64 * 24 * 60 * 60).to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    if let Some(ref mut stdin) = x509.stdin.take() {
+        // openssl x509 can exit before reading everything. The result is checked below.
+        let _ = stdin.write_all(&cert);
+    }
+    let out = x509
+        .wait_with_output()
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let not_after = match stdout.lines().find_map(|l| l.strip_prefix("notAfter=")) {
+        Some(v) => v.to_owned(),
+        None => return Err("Failed to get the certificate".to_owned()),
+    };
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(format!("Expires at {}", not_after))
+    }
+}
+
+fn cert_expiry_module() -> FnModFactory<CertExpiryConfig> {
+    FnModFactory::new(
+        "cert-expiry",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<CertExpiryConfig>, container: &gtk::Box| {
+            let button = gtk::Button::with_label("TLS");
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("cert-expiry");
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            let list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            popover.add(&list);
+            button.connect_clicked(glib::clone!(@weak popover => move |_| {
+                popover.show_all();
+                popover.popup();
+            }));
+
+            let domains = config.domains.clone();
+            let warn_days = config.warn_days;
+            let poller = poll_in_worker(
+                Duration::from_secs(config.interval),
+                move || {
+                    domains
+                        .iter()
+                        .filter_map(|d| check_domain(d, warn_days).err().map(|e| (d.clone(), e)))
+                        .collect::<Vec<(String, String)>>()
+                },
+                glib::clone!(@weak button, @weak list, @weak container => @default-return Continue(false), move |failures| {
+                    if is_pinned(&container) {
+                        return Continue(true);
+                    }
+                    for ref child in list.children() {
+                        list.remove(child);
+                    }
+                    let sc = button.style_context();
+                    if failures.is_empty() {
+                        sc.remove_class("warning");
+                        button.set_label("TLS");
+                        list.add(&gtk::Label::new(Some("All certificates are valid")));
+                    } else {
+                        sc.add_class("warning");
+                        button.set_label(&format!("TLS: {}", failures.len()));
+                        for (domain, err) in failures {
+                            let label = gtk::Label::new(Some(&format!("{}: {}", domain, err)));
+                            label.set_xalign(0.0);
+                            list.add(&label);
+                        }
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(container, &button, move || poller.refresh());
+            add_pin_action(container, &button);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(cert_expiry_module())]
+}
+
+crate::register_plugin!("cert", make_module_factories);
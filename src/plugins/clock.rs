@@ -0,0 +1,252 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{locale, FnModFactory, JSONConfigFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ClockConfig {
+    /// Format of the time. See g_date_time_format for the syntax. Defaults to the date and the
+    /// time in the hour cycle of the locale config.
+    #[serde(default)]
+    format: Option<String>,
+
+    /// Format of the tooltip. No tooltip if not specified.
+    #[serde(default)]
+    tooltip_format: Option<String>,
+
+    /// .ics files or directories containing them. Days with events are highlighted in the
+    /// calendar. For khal, specify the vdir directories of the calendars.
+    #[serde(default)]
+    calendars: Vec<PathBuf>,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        ClockConfig {
+            format: None,
+            tooltip_format: None,
+            calendars: vec![],
+        }
+    }
+}
+
+fn format_now(format: &str) -> String {
+    glib::DateTime::new_now_local()
+        .and_then(|t| t.format(format))
+        .map(|s| s.to_string())
+        .unwrap_or_default()
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A month calendar.
+///
+/// This is used instead of GtkCalendar because GtkCalendar always starts the weeks on the first
+/// day of the LC_TIME locale.
+struct MonthView {
+    widget: gtk::Box,
+    title: gtk::Label,
+    grid: gtk::Grid,
+    /// 0 is Sunday.
+    first_day: u32,
+    /// The shown (year, month). The month is 1-based.
+    shown: Cell<(i32, u32)>,
+    /// Days with events as (year, month, day).
+    marks: RefCell<HashSet<(i32, u32, u32)>>,
+}
+
+impl MonthView {
+    fn new() -> Rc<MonthView> {
+        let widget = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        widget.style_context().add_class("month-view");
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        let prev = gtk::Button::with_label("‹");
+        prev.set_relief(gtk::ReliefStyle::None);
+        let next = gtk::Button::with_label("›");
+        next.set_relief(gtk::ReliefStyle::None);
+        let title = gtk::Label::new(None);
+        header.pack_start(&prev, false, false, 0);
+        header.set_center_widget(Some(&title));
+        header.pack_end(&next, false, false, 0);
+        let grid = gtk::Grid::new();
+        grid.set_column_homogeneous(true);
+        grid.set_column_spacing(4);
+        widget.add(&header);
+        widget.add(&grid);
+
+        let view = Rc::new(MonthView {
+            widget,
+            title,
+            grid,
+            first_day: locale::first_day_of_week(),
+            shown: Cell::new((1970, 1)),
+            marks: RefCell::default(),
+        });
+        prev.connect_clicked(glib::clone!(@weak view => move |_| view.step(-1)));
+        next.connect_clicked(glib::clone!(@weak view => move |_| view.step(1)));
+        view
+    }
+
+    /// Shows the month of today.
+    fn show_today(&self) {
+        if let Some(now) = glib::DateTime::new_now_local() {
+            self.shown.set((now.year(), now.month() as u32));
+            self.render();
+        }
+    }
+
+    /// Moves the shown month by `delta` months.
+    fn step(&self, delta: i32) {
+        let (year, month) = self.shown.get();
+        let i = year * 12 + month as i32 - 1 + delta;
+        self.shown
+            .set((i.div_euclid(12), i.rem_euclid(12) as u32 + 1));
+        self.render();
+    }
+
+    fn set_marks(&self, marks: HashSet<(i32, u32, u32)>) {
+        self.marks.replace(marks);
+        self.render();
+    }
+
+    fn render(&self) {
+        for child in self.grid.children() {
+            self.grid.remove(&child);
+        }
+        let (year, month) = self.shown.get();
+        let tz = glib::TimeZone::new_local();
+        let first = match glib::DateTime::new(&tz, year, month as i32, 1, 0, 0, 0.0) {
+            Some(first) => first,
+            None => return,
+        };
+        self.title.set_text(
+            &first
+                .format("%B %Y")
+                .map(|s| s.to_string())
+                .unwrap_or_default(),
+        );
+        // 2023-01-01 is a Sunday.
+        for column in 0..7 {
+            let day = (self.first_day + column) % 7;
+            let name = glib::DateTime::new(&tz, 2023, 1, 1 + day as i32, 0, 0, 0.0)
+                .and_then(|t| t.format("%a"))
+                .map(|s| s.to_string())
+                .unwrap_or_default();
+            let label = gtk::Label::new(Some(&name));
+            label.style_context().add_class("month-view-weekday");
+            self.grid.attach(&label, column as i32, 0, 1, 1);
+        }
+        let today = glib::DateTime::new_now_local()
+            .map(|t| (t.year(), t.month() as u32, t.day_of_month() as u32));
+        // day_of_week is 1 for Monday and 7 for Sunday.
+        let offset = (first.day_of_week() as u32 % 7 + 7 - self.first_day) % 7;
+        let marks = self.marks.borrow();
+        for day in 1..=days_in_month(year, month) {
+            let cell = offset + day - 1;
+            let label = gtk::Label::new(Some(&day.to_string()));
+            let sc = label.style_context();
+            sc.add_class("month-view-day");
+            if today == Some((year, month, day)) {
+                sc.add_class("month-view-today");
+            }
+            if marks.contains(&(year, month, day)) {
+                sc.add_class("month-view-marked");
+            }
+            self.grid
+                .attach(&label, (cell % 7) as i32, (cell / 7 + 1) as i32, 1, 1);
+        }
+        self.grid.show_all();
+    }
+}
+
+fn clock_module() -> FnModFactory<ClockConfig> {
+    FnModFactory::new(
+        "clock",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<ClockConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("clock");
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            let vbox = gtk::Box::new(gtk::Orientation::Vertical, 4);
+            let calendar = MonthView::new();
+            let today = gtk::Button::with_label(&locale::tr("Today"));
+            vbox.add(&calendar.widget);
+            vbox.add(&today);
+            popover.add(&vbox);
+
+            today.connect_clicked(glib::clone!(@strong calendar => move |_| calendar.show_today()));
+            let calendars = config.calendars.clone();
+            button.connect_clicked(glib::clone!(@weak popover, @strong calendar => move |_| {
+                // Re-read the calendars every time as they can be synced in the background.
+                calendar.set_marks(
+                    crate::ical::read_events(&calendars)
+                        .into_iter()
+                        .map(|e| e.start_date)
+                        .collect(),
+                );
+                calendar.show_today();
+                popover.show_all();
+                popover.popup();
+            }));
+
+            let config = config.clone();
+            let format = config
+                .format
+                .clone()
+                .unwrap_or_else(|| format!("%Y-%m-%d {}", locale::time_format()));
+            let update = move |label: &gtk::Label, button: &gtk::Button| {
+                label.set_text(&format_now(&format));
+                if let Some(ref f) = config.tooltip_format {
+                    button.set_tooltip_text(Some(&format_now(f)));
+                }
+            };
+            update(&label, &button);
+            glib::timeout_add_seconds_local(
+                1,
+                glib::clone!(@weak label, @weak button => @default-return Continue(false), move || {
+                    update(&label, &button);
+                    Continue(true)
+                }),
+            );
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(clock_module())]
+}
+
+crate::register_plugin!("clock", make_module_factories);
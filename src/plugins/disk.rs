@@ -0,0 +1,246 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{
+    add_pin_action, add_refresh_action, command, format_bytes, is_pinned, FnModFactory,
+    JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct CleanupAction {
+    /// Label of the action (e.g. "Empty trash").
+    name: String,
+
+    /// Command line run with `sh -c` (e.g. "paccache -rk1", "docker system prune -f").
+    command: String,
+}
+
+fn default_path() -> PathBuf {
+    PathBuf::from("/")
+}
+
+fn default_interval() -> u32 {
+    60
+}
+
+fn default_format() -> String {
+    "{free} free".to_owned()
+}
+
+fn default_warning() -> f64 {
+    80.0
+}
+
+fn default_critical() -> f64 {
+    90.0
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct DiskConfig {
+    /// A path in the file system to watch.
+    #[serde(default = "default_path")]
+    path: PathBuf,
+
+    /// Interval in seconds to check the usage.
+    #[serde(default = "default_interval")]
+    interval: u32,
+
+    /// Format of the label. "{free}", "{used}", "{total}", and "{percentage}" (used) are replaced.
+    #[serde(default = "default_format")]
+    format: String,
+
+    /// The "disk-warning" class is added when the usage is at or above this percentage.
+    #[serde(default = "default_warning")]
+    warning: f64,
+
+    /// The "disk-critical" class is added when the usage is at or above this percentage. When the
+    /// usage crosses it, the cleanup actions are shown.
+    #[serde(default = "default_critical")]
+    critical: f64,
+
+    /// Actions offered to free up space.
+    #[serde(default)]
+    cleanup: Vec<CleanupAction>,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        DiskConfig {
+            path: default_path(),
+            interval: default_interval(),
+            format: default_format(),
+            warning: default_warning(),
+            critical: default_critical(),
+            cleanup: vec![],
+        }
+    }
+}
+
+/// Returns (available bytes, total bytes) of the file system.
+fn statvfs(path: &Path) -> Option<(u64, u64)> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut buf: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut buf) } != 0 {
+        return None;
+    }
+    let frsize = buf.f_frsize as u64;
+    Some((buf.f_bavail as u64 * frsize, buf.f_blocks as u64 * frsize))
+}
+
+/// Builds the rows of the cleanup actions. Each row shows the progress of the command.
+fn cleanup_rows(actions: &[CleanupAction], on_done: Rc<dyn Fn()>) -> gtk::Box {
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 4);
+    let title = gtk::Label::new(Some("Low disk space. Free up space:"));
+    title.set_xalign(0.0);
+    vbox.add(&title);
+    for action in actions {
+        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let button = gtk::Button::with_label(&action.name);
+        button.set_tooltip_text(Some(&action.command));
+        let spinner = gtk::Spinner::new();
+        let status = gtk::Label::new(None);
+        hbox.add(&button);
+        hbox.add(&spinner);
+        hbox.add(&status);
+        vbox.add(&hbox);
+
+        let command_line = action.command.clone();
+        let on_done = on_done.clone();
+        button.connect_clicked(glib::clone!(@weak spinner, @weak status => move |button| {
+            button.set_sensitive(false);
+            spinner.start();
+            status.set_text("Running…");
+            let on_done = on_done.clone();
+            command::run(
+                &command_line,
+                glib::clone!(@weak button, @weak spinner, @weak status => move |result| {
+                    button.set_sensitive(true);
+                    spinner.stop();
+                    match result {
+                        Ok(_) => status.set_text("Done"),
+                        Err(e) => status.set_text(&e),
+                    }
+                    on_done();
+                }),
+            );
+        }));
+    }
+    vbox
+}
+
+fn disk_module() -> FnModFactory<DiskConfig> {
+    FnModFactory::new(
+        "disk",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<DiskConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("disk");
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            if !config.cleanup.is_empty() {
+                button.connect_clicked(glib::clone!(@weak popover => move |_| {
+                    popover.show_all();
+                    popover.popup();
+                }));
+            }
+
+            let cleanup = config.cleanup.clone();
+            let interval = config.interval;
+            let config = config.clone();
+            let was_critical = Cell::new(false);
+            let update = Rc::new(
+                move |button: &gtk::Button, label: &gtk::Label, popover: &gtk::Popover| {
+                    let (avail, total) = match statvfs(&config.path) {
+                        Some(v) if v.1 > 0 => v,
+                        _ => return,
+                    };
+                    let used = total - avail;
+                    let percentage = used as f64 * 100.0 / total as f64;
+                    label.set_text(
+                        &config
+                            .format
+                            .replace("{free}", &format_bytes(avail))
+                            .replace("{used}", &format_bytes(used))
+                            .replace("{total}", &format_bytes(total))
+                            .replace("{percentage}", &format!("{:.0}", percentage)),
+                    );
+                    button.set_tooltip_text(Some(&format!(
+                        "{}: {:.0}% used",
+                        config.path.display(),
+                        percentage
+                    )));
+                    let sc = button.style_context();
+                    let critical = percentage >= config.critical;
+                    if percentage >= config.warning {
+                        sc.add_class("disk-warning");
+                    } else {
+                        sc.remove_class("disk-warning");
+                    }
+                    if critical {
+                        sc.add_class("disk-critical");
+                    } else {
+                        sc.remove_class("disk-critical");
+                    }
+                    // Raise the alert only when crossing the threshold, not on every update.
+                    if critical && !was_critical.get() && !config.cleanup.is_empty() {
+                        popover.show_all();
+                        popover.popup();
+                    }
+                    was_critical.set(critical);
+                },
+            );
+
+            let refresh = Rc::new(
+                glib::clone!(@weak button, @weak label, @weak popover, @strong update => move || {
+                    update(&button, &label, &popover);
+                }),
+            );
+            popover.add(&cleanup_rows(&cleanup, refresh.clone()));
+            refresh();
+
+            glib::timeout_add_seconds_local(
+                interval,
+                glib::clone!(@weak container, @strong refresh => @default-return Continue(false), move || {
+                    if !is_pinned(&container) {
+                        refresh();
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(container, &button, move || refresh());
+            add_pin_action(container, &button);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(disk_module())]
+}
+
+crate::register_plugin!("disk", make_module_factories);
@@ -0,0 +1,242 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{FnModFactory, JSONConfigFactory};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+fn default_label() -> String {
+    "⬇".to_owned()
+}
+
+fn default_show_duration() -> u64 {
+    10
+}
+
+fn default_recent_count() -> usize {
+    10
+}
+
+fn default_ignore_suffixes() -> Vec<String> {
+    vec![
+        ".part".to_owned(),
+        ".crdownload".to_owned(),
+        ".download".to_owned(),
+        ".tmp".to_owned(),
+    ]
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct DownloadsConfig {
+    /// Directory to watch. Defaults to the XDG download directory.
+    #[serde(default)]
+    directory: Option<PathBuf>,
+
+    /// Label shown when there's no new download.
+    #[serde(default = "default_label")]
+    label: String,
+
+    /// Seconds to show the name of a new download.
+    #[serde(default = "default_show_duration")]
+    show_duration: u64,
+
+    /// Number of files in the popover.
+    #[serde(default = "default_recent_count")]
+    recent_count: usize,
+
+    /// Files with these suffixes are in-progress downloads and ignored.
+    #[serde(default = "default_ignore_suffixes")]
+    ignore_suffixes: Vec<String>,
+}
+
+impl Default for DownloadsConfig {
+    fn default() -> Self {
+        DownloadsConfig {
+            directory: None,
+            label: default_label(),
+            show_duration: default_show_duration(),
+            recent_count: default_recent_count(),
+            ignore_suffixes: default_ignore_suffixes(),
+        }
+    }
+}
+
+impl DownloadsConfig {
+    fn is_ignored(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(n) => n.to_string_lossy(),
+            None => return true,
+        };
+        name.starts_with('.')
+            || self
+                .ignore_suffixes
+                .iter()
+                .any(|s| name.ends_with(s.as_str()))
+    }
+}
+
+/// Returns the most recently modified files in the directory.
+fn recent_files(dir: &Path, config: &DownloadsConfig) -> Vec<PathBuf> {
+    let mut files: Vec<(SystemTime, PathBuf)> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter(|e| !config.is_ignored(&e.path()))
+                .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    files
+        .into_iter()
+        .take(config.recent_count)
+        .map(|(_, p)| p)
+        .collect()
+}
+
+fn open(path: &Path) {
+    let uri = gio::File::for_path(path).uri();
+    if let Err(e) = gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>) {
+        eprintln!("Cannot open {}: {}", path.display(), e);
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn downloads_module() -> FnModFactory<DownloadsConfig> {
+    FnModFactory::new(
+        "downloads",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<DownloadsConfig>, container: &gtk::Box| {
+            let dir = match config
+                .directory
+                .clone()
+                .or_else(|| glib::user_special_dir(glib::UserDirectory::Download))
+            {
+                Some(d) => d,
+                None => {
+                    eprintln!("Cannot find the download directory");
+                    return;
+                }
+            };
+
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("downloads");
+            let label = gtk::Label::new(Some(&config.label));
+            button.add(&label);
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            let list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            popover.add(&list);
+
+            // The new download shown in the bar, and the source to hide it.
+            let shown: Rc<RefCell<Option<(PathBuf, glib::SourceId)>>> = Rc::default();
+
+            let monitor = gio::File::for_path(&dir).monitor_directory(
+                gio::FileMonitorFlags::WATCH_MOVES,
+                None::<&gio::Cancellable>,
+            );
+            let monitor = match monitor {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("Cannot watch {}: {}", dir.display(), e);
+                    return;
+                }
+            };
+            let show_duration = Duration::from_secs(config.show_duration);
+            let idle_label = config.label.clone();
+            let watch_config = config.clone();
+            monitor.connect_changed(glib::clone!(@weak button, @weak label, @strong shown => move |_, file, other, event| {
+                // Browsers rename the temporary file when the download completes.
+                let file = match event {
+                    gio::FileMonitorEvent::ChangesDoneHint | gio::FileMonitorEvent::MovedIn => {
+                        file.clone()
+                    }
+                    gio::FileMonitorEvent::Renamed => match other {
+                        Some(f) => f.clone(),
+                        None => return,
+                    },
+                    _ => return,
+                };
+                let path = match file.path() {
+                    Some(p) if !watch_config.is_ignored(&p) => p,
+                    _ => return,
+                };
+                label.set_text(&file_name(&path));
+                button.style_context().add_class("download-new");
+                let hide = glib::timeout_add_local(
+                    show_duration,
+                    glib::clone!(@weak button, @weak label, @strong shown, @strong idle_label => @default-return Continue(false), move || {
+                        label.set_text(&idle_label);
+                        button.style_context().remove_class("download-new");
+                        shown.take();
+                        Continue(false)
+                    }),
+                );
+                if let Some((_, source)) = shown.replace(Some((path, hide))) {
+                    glib::source_remove(source);
+                }
+            }));
+
+            let config = config.clone();
+            button.connect_clicked(
+                glib::clone!(@weak popover, @weak list, @weak label => move |button| {
+                    // Keep the monitor alive as long as the button.
+                    let _ = &monitor;
+                    if let Some((path, source)) = shown.take() {
+                        glib::source_remove(source);
+                        label.set_text(&config.label);
+                        button.style_context().remove_class("download-new");
+                        open(&path);
+                        return;
+                    }
+                    for ref child in list.children() {
+                        list.remove(child);
+                    }
+                    for path in recent_files(&dir, &config) {
+                        let b = gtk::Button::with_label(&file_name(&path));
+                        b.set_relief(gtk::ReliefStyle::None);
+                        b.connect_clicked(glib::clone!(@weak popover => move |_| {
+                            open(&path);
+                            popover.popdown();
+                        }));
+                        list.add(&b);
+                    }
+                    popover.show_all();
+                    popover.popup();
+                }),
+            );
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(downloads_module())]
+}
+
+crate::register_plugin!("downloads", make_module_factories);
@@ -0,0 +1,186 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{FnModFactory, JSONConfigFactory};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+fn default_format() -> String {
+    "{}".to_owned()
+}
+
+/// Transformation of the file content.
+///
+/// The regex is applied first, and then the math if the result is a number.
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
+struct TransformConfig {
+    /// Regex to extract the value. The first capture group is used if any, otherwise the whole
+    /// match.
+    #[serde(default)]
+    regex: Option<String>,
+
+    /// Divide the value (e.g. 1000 for millidegrees in /sys/class/thermal).
+    #[serde(default)]
+    divide: Option<f64>,
+
+    /// Multiply the value.
+    #[serde(default)]
+    multiply: Option<f64>,
+
+    /// Number of decimal places after the math.
+    #[serde(default)]
+    precision: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
+struct FileConfig {
+    /// Path of the file.
+    path: String,
+
+    /// Text to show. "{}" is replaced with the (transformed) content.
+    #[serde(default = "default_format")]
+    format: String,
+
+    /// Transformation of the content.
+    #[serde(default)]
+    transform: TransformConfig,
+
+    /// Re-read the file every this seconds in addition to the change notifications. Files in /sys
+    /// and /proc don't notify the changes, so set this for them.
+    #[serde(default)]
+    interval: Option<u32>,
+}
+
+/// Applies the transform to the trimmed content.
+fn transform(
+    content: &str,
+    config: &TransformConfig,
+    regex: Option<&Regex>,
+) -> Result<String, String> {
+    let mut value = content.trim().to_owned();
+    if let Some(re) = regex {
+        let caps = re
+            .captures(&value)
+            .ok_or_else(|| format!("No match for {}", re.as_str()))?;
+        value = caps
+            .get(1)
+            .or_else(|| caps.get(0))
+            .map(|m| m.as_str().to_owned())
+            .unwrap_or_default();
+    }
+    if config.divide.is_none() && config.multiply.is_none() {
+        return Ok(value);
+    }
+    let mut n: f64 = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Not a number: {}", value))?;
+    if let Some(m) = config.multiply {
+        n *= m;
+    }
+    if let Some(d) = config.divide {
+        n /= d;
+    }
+    Ok(format!("{:.*}", config.precision, n))
+}
+
+fn file_module() -> FnModFactory<FileConfig> {
+    FnModFactory::new(
+        "file",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<FileConfig>, container: &gtk::Box| {
+            let label = gtk::Label::new(None);
+            label.style_context().add_class("file");
+            container.add(&label);
+
+            let regex = match config.transform.regex.as_deref().map(Regex::new) {
+                Some(Err(e)) => {
+                    eprintln!("file: invalid regex: {}", e);
+                    label.set_text("?");
+                    label.style_context().add_class("error");
+                    label.set_tooltip_text(Some(&e.to_string()));
+                    return;
+                }
+                Some(Ok(re)) => Some(re),
+                None => None,
+            };
+            let update = Rc::new(glib::clone!(@weak label, @strong config => move || {
+                let sc = label.style_context();
+                let result = std::fs::read_to_string(&config.path)
+                    .map_err(|e| format!("Cannot read {}: {}", config.path, e))
+                    .and_then(|content| transform(&content, &config.transform, regex.as_ref()));
+                match result {
+                    Ok(value) => {
+                        label.set_text(&config.format.replace("{}", &value));
+                        sc.remove_class("error");
+                        label.set_tooltip_text(None);
+                    }
+                    Err(e) => {
+                        label.set_text("?");
+                        sc.add_class("error");
+                        label.set_tooltip_text(Some(&e));
+                    }
+                }
+            }));
+            update();
+
+            // The file monitor follows the path rather than the inode, so that it reports the file
+            // re-created or moved in.
+            match gio::File::for_path(&config.path).monitor_file(
+                gio::FileMonitorFlags::WATCH_MOVES,
+                None::<&gio::Cancellable>,
+            ) {
+                Ok(monitor) => {
+                    monitor.connect_changed(glib::clone!(@strong update => move |_, _, _, event| {
+                        match event {
+                            gio::FileMonitorEvent::ChangesDoneHint
+                            | gio::FileMonitorEvent::Created
+                            | gio::FileMonitorEvent::Deleted
+                            | gio::FileMonitorEvent::MovedIn
+                            | gio::FileMonitorEvent::MovedOut
+                            | gio::FileMonitorEvent::Renamed => update(),
+                            _ => {}
+                        }
+                    }));
+                    // Keep the monitor alive as long as the label.
+                    label.connect_destroy(move |_| {
+                        let _ = &monitor;
+                    });
+                }
+                Err(e) => eprintln!("Cannot watch {}: {}", config.path, e),
+            }
+            if let Some(interval) = config.interval {
+                glib::timeout_add_seconds_local(
+                    interval,
+                    glib::clone!(@weak label => @default-return Continue(false), move || {
+                        update();
+                        Continue(true)
+                    }),
+                );
+            }
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(file_module())]
+}
+
+crate::register_plugin!("file", make_module_factories);
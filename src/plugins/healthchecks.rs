@@ -0,0 +1,196 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::http::Request;
+use crate::module_base::{
+    add_pin_action, add_refresh_action, is_pinned, poll_in_worker, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct Service {
+    /// Name of the service shown in the tooltip.
+    name: String,
+
+    /// URL to probe. The service is considered up if the response status is 2xx or 3xx.
+    url: String,
+}
+
+fn default_interval() -> u64 {
+    60
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct HealthchecksConfig {
+    /// Services to probe.
+    #[serde(default)]
+    services: Vec<Service>,
+
+    /// Interval in seconds to probe the services.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Timeout in seconds for each probe.
+    #[serde(default = "default_timeout")]
+    timeout: u64,
+}
+
+impl Default for HealthchecksConfig {
+    fn default() -> Self {
+        HealthchecksConfig {
+            services: vec![],
+            interval: default_interval(),
+            timeout: default_timeout(),
+        }
+    }
+}
+
+/// Probes the URL. Returns an error message if the service is down.
+fn probe(url: &str, timeout: u64) -> Result<(), String> {
+    let code = Request::new(url)
+        .timeout(Duration::from_secs(timeout))
+        .status()?;
+    if (200..400).contains(&code) {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", code))
+    }
+}
+
+/// The last known state of a service.
+#[derive(Default)]
+struct ServiceState {
+    error: Option<String>,
+    last_failure: Option<String>,
+}
+
+fn healthchecks_module() -> FnModFactory<HealthchecksConfig> {
+    FnModFactory::new(
+        "healthchecks",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(
+            move |config: &Rc<HealthchecksConfig>, container: &gtk::Box| {
+                let button = gtk::Button::new();
+                button.set_relief(gtk::ReliefStyle::None);
+                button.style_context().add_class("healthchecks");
+                let dots_box = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+                button.add(&dots_box);
+                container.add(&button);
+
+                let mut dots = vec![];
+                for service in &config.services {
+                    let dot = gtk::Label::new(Some("●"));
+                    dot.set_tooltip_text(Some(&service.name));
+                    dot.style_context().add_class("healthcheck");
+                    dots_box.add(&dot);
+                    dots.push(dot);
+                }
+                let states = Rc::new(RefCell::new(
+                    config
+                        .services
+                        .iter()
+                        .map(|_| ServiceState::default())
+                        .collect::<Vec<_>>(),
+                ));
+
+                let popover = gtk::Popover::new(Some(&button));
+                let list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+                popover.add(&list);
+                {
+                    let config = config.clone();
+                    let states = states.clone();
+                    button.connect_clicked(glib::clone!(@weak popover, @weak list => move |_| {
+                        for ref child in list.children() {
+                            list.remove(child);
+                        }
+                        for (service, state) in config.services.iter().zip(states.borrow().iter()) {
+                            let mut s = format!(
+                                "{}: {}",
+                                service.name,
+                                state.error.as_deref().unwrap_or("up")
+                            );
+                            if let Some(ref t) = state.last_failure {
+                                s += &format!(" (last failure: {})", t);
+                            }
+                            let label = gtk::Label::new(Some(&s));
+                            label.set_xalign(0.0);
+                            list.add(&label);
+                        }
+                        popover.show_all();
+                        popover.popup();
+                    }));
+                }
+
+                let services = config.services.clone();
+                let timeout = config.timeout;
+                let poller = poll_in_worker(
+                    Duration::from_secs(config.interval),
+                    move || {
+                        services
+                            .iter()
+                            .map(|s| probe(&s.url, timeout))
+                            .collect::<Vec<_>>()
+                    },
+                    glib::clone!(@weak container => @default-return Continue(false), move |results| {
+                        if is_pinned(&container) {
+                            return Continue(true);
+                        }
+                        let now = glib::DateTime::new_now_local()
+                            .and_then(|t| t.format("%Y-%m-%d %H:%M:%S"))
+                            .map(|s| s.to_string())
+                            .unwrap_or_default();
+                        let mut states = states.borrow_mut();
+                        for ((dot, state), result) in dots.iter().zip(states.iter_mut()).zip(results) {
+                            let sc = dot.style_context();
+                            match result {
+                                Ok(()) => {
+                                    sc.remove_class("healthcheck-down");
+                                    sc.add_class("healthcheck-up");
+                                    state.error = None;
+                                }
+                                Err(e) => {
+                                    sc.remove_class("healthcheck-up");
+                                    sc.add_class("healthcheck-down");
+                                    state.error = Some(e);
+                                    state.last_failure = Some(now.clone());
+                                }
+                            }
+                        }
+                        Continue(true)
+                    }),
+                );
+                add_refresh_action(container, &button, move || poller.refresh());
+                add_pin_action(container, &button);
+            },
+        ),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(healthchecks_module())]
+}
+
+crate::register_plugin!("healthchecks", make_module_factories);
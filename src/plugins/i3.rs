@@ -12,76 +12,686 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::i3::I3State;
+use crate::error::Error;
+use crate::i3::{keybindings, I3State, WorkspaceState};
+use crate::module_base::supervisor::show_health;
+use crate::module_base::touch::{self, Swipe};
+use crate::module_base::{
+    add_refresh_action, connect_notify_while_alive, reduced_motion, set_accessible, ConfigFactory,
+    FnModFactory, JSONConfigFactory, StateStore,
+};
+use crate::module_base::{icon, locale};
 use gtk::glib;
 use gtk::prelude::*;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum WorkspaceOrder {
+    /// Sort by the workspace number.
+    Number,
+    /// Sort by the workspace name.
+    Name,
+}
+
+impl Default for WorkspaceOrder {
+    fn default() -> Self {
+        WorkspaceOrder::Number
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
+struct WorkspacesConfig {
+    /// Labels for workspaces.
+    ///
+    /// The key is either the exact workspace name (e.g. "1: web") or the workspace number (e.g.
+    /// "1"). The value is shown instead of the workspace name, and it can be a Pango markup. This
+    /// allows showing an icon for a workspace.
+    #[serde(default)]
+    labels: HashMap<String, String>,
+
+    /// The label used when a workspace doesn't match with `labels`.
+    ///
+    /// "{name}" and "{num}" are replaced with the workspace name and number. If not specified, the
+    /// workspace name is shown.
+    #[serde(default)]
+    default_label: Option<String>,
+
+    /// Names of the workspaces that are not shown.
+    #[serde(default)]
+    hidden: Vec<String>,
+
+    /// Regular expressions for the workspace names that are not shown.
+    ///
+    /// The whole workspace name needs to match with the regular expression.
+    #[serde(default)]
+    hidden_regex: Vec<String>,
+
+    /// Show the workspaces on all outputs instead of only the ones on the module's monitor.
+    #[serde(default)]
+    all_outputs: bool,
+
+    /// The order of the workspaces. Either "number" (default) or "name".
+    #[serde(default)]
+    order: WorkspaceOrder,
+
+    /// Strip the workspace number from the name (e.g. "3: web" is shown as "web").
+    ///
+    /// Similar to i3bar's option. If the name consists only of a number, it's shown as-is.
+    #[serde(default)]
+    strip_workspace_numbers: bool,
+
+    /// Interval in milliseconds to flash urgent workspaces. Defaults to 500. Set 0 to disable.
+    ///
+    /// While a workspace is urgent, the "workspace-urgent-flash" class is toggled on its button
+    /// with this interval. Flashing is disabled with the global reduced_motion option.
+    #[serde(default)]
+    urgent_flash_interval: Option<u64>,
+
+    /// Show a "+" button at the end that switches to the lowest unused workspace number.
+    #[serde(default)]
+    new_workspace_button: bool,
+
+    /// The i3 output name of the module's monitor (e.g. "DP-1").
+    ///
+    /// By default, the output is found by matching the monitor geometry with i3's outputs. If not
+    /// found, the monitor's model is used.
+    #[serde(default)]
+    output: Option<String>,
+}
+
+impl WorkspacesConfig {
+    /// Returns the workspace name to show.
+    fn display_name<'a>(&self, ws: &'a WorkspaceState) -> &'a str {
+        if !self.strip_workspace_numbers {
+            return &ws.name;
+        }
+        let prefix = ws.num.to_string();
+        match ws.name.strip_prefix(prefix.as_str()) {
+            Some(rest) if !rest.is_empty() => rest.trim_start_matches(':').trim_start(),
+            _ => &ws.name,
+        }
+    }
+
+    /// Returns the Pango markup shown for the workspace.
+    fn label_markup(&self, ws: &WorkspaceState) -> String {
+        if let Some(label) = self.labels.get(&ws.name) {
+            return label.to_owned();
+        }
+        if let Some(label) = self.labels.get(&ws.num.to_string()) {
+            return label.to_owned();
+        }
+        if let Some(ref default_label) = self.default_label {
+            return default_label
+                .replace("{name}", &glib::markup_escape_text(self.display_name(ws)))
+                .replace("{num}", &ws.num.to_string());
+        }
+        glib::markup_escape_text(self.display_name(ws)).to_string()
+    }
+}
 
 struct WorkspacesModule {
     model: String,
+    /// Candidates of the monitor geometry in i3's coordinates. With HiDPI scaling, i3 might use
+    /// the device pixels instead of GDK's logical pixels.
+    rects: Vec<(i32, i32, i32, i32)>,
+    config: Rc<WorkspacesConfig>,
+    hidden_regex: Rc<Vec<Regex>>,
     state: Rc<I3State>,
 }
 
+/// Returns the i3 output name for the module.
+fn output_name(
+    config: &WorkspacesConfig,
+    model: &str,
+    rects: &[(i32, i32, i32, i32)],
+    state: &I3State,
+) -> String {
+    if let Some(ref output) = config.output {
+        return output.to_owned();
+    }
+    rects
+        .iter()
+        .find_map(|rect| state.find_output_by_rect(*rect))
+        .unwrap_or_else(|| model.to_owned())
+}
+
+/// Returns the workspaces shown in the module, filtered and sorted based on the config.
+fn visible_workspaces(
+    config: &WorkspacesConfig,
+    hidden_regex: &[Regex],
+    output: &str,
+    state: &I3State,
+) -> Vec<WorkspaceState> {
+    let mut wss: Vec<WorkspaceState> = if config.all_outputs {
+        state
+            .workspaces()
+            .into_iter()
+            .flat_map(|(_, v)| v)
+            .collect()
+    } else {
+        state.workspaces().remove(output).unwrap_or_default()
+    };
+    wss.retain(|ws| {
+        !config.hidden.contains(&ws.name) && !hidden_regex.iter().any(|r| r.is_match(&ws.name))
+    });
+    match config.order {
+        WorkspaceOrder::Number => wss.sort_by(|a, b| a.num.cmp(&b.num)),
+        WorkspaceOrder::Name => wss.sort_by(|a, b| a.name.cmp(&b.name)),
+    }
+    wss
+}
+
+/// Toggles the "workspace-urgent-flash" class on the button until the returned source is removed.
+fn start_flashing(button: &gtk::Button, interval: Duration) -> glib::SourceId {
+    glib::timeout_add_local(
+        interval,
+        glib::clone!(@weak button => @default-return Continue(false), move || {
+            let sc = button.style_context();
+            if sc.has_class("workspace-urgent-flash") {
+                sc.remove_class("workspace-urgent-flash");
+            } else {
+                sc.add_class("workspace-urgent-flash");
+            }
+            Continue(true)
+        }),
+    )
+}
+
+/// A button for a workspace.
+///
+/// The buttons are kept across the workspace updates so that only the changed parts are updated.
+struct WorkspaceButton {
+    button: gtk::Button,
+    label: gtk::Label,
+    num: Rc<Cell<i32>>,
+    flash_source: Option<glib::SourceId>,
+}
+
+impl WorkspaceButton {
+    fn new(ws: &WorkspaceState, state: &I3State) -> WorkspaceButton {
+        let label = gtk::Label::new(None);
+        let button = gtk::Button::new();
+        button.add(&label);
+        button.set_relief(gtk::ReliefStyle::None);
+        let sc = button.style_context();
+        sc.add_class("workspace");
+        sc.add_class(&format!("workspace-name-{}", ws.name));
+        sc.add_class(&format!("workspace-num-{}", ws.num));
+        let num = Rc::new(Cell::new(ws.num));
+        button.connect_clicked(glib::clone!(@weak state, @strong num => move |_| {
+            state.switch_workspace(num.get());
+        }));
+        let name = ws.name.clone();
+        button.connect_button_press_event(
+            glib::clone!(@weak state => @default-return Inhibit(false), move |button, e| {
+                if e.button() != gtk::gdk::BUTTON_SECONDARY {
+                    return Inhibit(false);
+                }
+                let menu = output_menu(&name, &state);
+                menu.set_attach_widget(Some(button));
+                menu.popup_at_pointer(None);
+                Inhibit(true)
+            }),
+        );
+        let name = ws.name.clone();
+        touch::connect_long_press(
+            &button,
+            glib::clone!(@weak state => move |button| {
+                let menu = output_menu(&name, &state);
+                menu.set_attach_widget(Some(button));
+                menu.popup_at_widget(button, gtk::gdk::Gravity::South, gtk::gdk::Gravity::North, None);
+            }),
+        );
+        WorkspaceButton {
+            button,
+            label,
+            num,
+            flash_source: None,
+        }
+    }
+
+    /// Updates the label and the CSS classes based on the workspace state.
+    fn update(&mut self, config: &WorkspacesConfig, ws: &WorkspaceState) {
+        let markup = config.label_markup(ws);
+        if self.label.label().as_str() != markup {
+            self.label.set_markup(&markup);
+        }
+        let sc = self.button.style_context();
+        if self.num.get() != ws.num {
+            sc.remove_class(&format!("workspace-num-{}", self.num.get()));
+            sc.add_class(&format!("workspace-num-{}", ws.num));
+            self.num.set(ws.num);
+        }
+        set_class(&sc, "workspace-urgent", ws.urgent);
+        set_class(&sc, "workspace-focused", ws.focused);
+        set_accessible(&self.button, &accessible_name(ws), None);
+
+        let interval = config.urgent_flash_interval.unwrap_or(500);
+        let should_flash = interval > 0 && ws.urgent && !ws.focused && !reduced_motion();
+        if should_flash && self.flash_source.is_none() {
+            self.flash_source = Some(start_flashing(
+                &self.button,
+                Duration::from_millis(interval),
+            ));
+        } else if !should_flash {
+            self.stop_flashing();
+        }
+    }
+
+    fn stop_flashing(&mut self) {
+        if let Some(source) = self.flash_source.take() {
+            glib::source_remove(source);
+            self.button
+                .style_context()
+                .remove_class("workspace-urgent-flash");
+        }
+    }
+}
+
+/// Returns the name announced by screen readers (e.g. "Workspace 3, urgent").
+fn accessible_name(ws: &WorkspaceState) -> String {
+    let mut name = format!("{} {}", locale::tr("Workspace"), ws.name);
+    if ws.focused {
+        name += &format!(", {}", locale::tr("focused"));
+    }
+    if ws.urgent {
+        name += &format!(", {}", locale::tr("urgent"));
+    }
+    name
+}
+
+/// Quotes the argument of an i3 command.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Returns a menu to move the workspace to another output.
+fn output_menu(workspace: &str, state: &I3State) -> gtk::Menu {
+    let menu = gtk::Menu::new();
+    let current = state
+        .workspaces()
+        .into_iter()
+        .find(|(_, wss)| wss.iter().any(|ws| ws.name == workspace))
+        .map(|(output, _)| output);
+    for output in state.outputs().into_iter().filter(|o| o.active) {
+        let item = gtk::MenuItem::with_label(
+            &locale::tr("Move to {output}").replace("{output}", &output.name),
+        );
+        item.set_sensitive(current.as_deref() != Some(output.name.as_str()));
+        let workspace = workspace.to_owned();
+        item.connect_activate(glib::clone!(@weak state => move |_| {
+            // "move workspace to output" moves the focused workspace.
+            let commands = [
+                format!("workspace {}", quote(&workspace)),
+                format!("move workspace to output {}", quote(&output.name)),
+            ];
+            let commands: Vec<&str> = commands.iter().map(|c| c.as_str()).collect();
+            if let Err(e) = state.run_commands(&commands) {
+                eprintln!("{}", e);
+            }
+        }));
+        menu.append(&item);
+    }
+    menu.show_all();
+    menu
+}
+
+fn set_class(sc: &gtk::StyleContext, class: &str, enabled: bool) {
+    if enabled {
+        sc.add_class(class);
+    } else {
+        sc.remove_class(class);
+    }
+}
+
 impl crate::module::Module for WorkspacesModule {
     fn build_ui(&self, container: &gtk::Box) {
         let model = self.model.clone();
-        self.state.connect_notify_local(
-            Some("workspaces"),
-            glib::clone!(@weak container => move |state, _| {
-                for ref child in container.children() {
-                    container.remove(child);
+        let rects = self.rects.clone();
+        let config = self.config.clone();
+        let hidden_regex = self.hidden_regex.clone();
+        let buttons: RefCell<HashMap<String, WorkspaceButton>> = RefCell::new(HashMap::new());
+
+        let state: &I3State = &self.state;
+        touch::connect_swipe(
+            container,
+            glib::clone!(@weak state => move |swipe| {
+                // The buttons are in the reverse order in the right-to-left layout.
+                let command = match (swipe, locale::is_rtl()) {
+                    (Swipe::Left, false) | (Swipe::Right, true) => "workspace next_on_output",
+                    (Swipe::Right, false) | (Swipe::Left, true) => "workspace prev_on_output",
+                    _ => return,
+                };
+                if let Err(e) = state.run_commands(&[command]) {
+                    eprintln!("{}", e);
                 }
-                if let Some(wss) = state.workspaces().get(model.as_str()) {
-                    for ref ws in wss {
-                        let button = gtk::Button::with_label(&ws.name);
-                        button.set_relief(gtk::ReliefStyle::None);
-                        let sc = button.style_context();
-                        sc.add_class("workspace");
-                        sc.add_class(&format!("workspace-name-{}", ws.name));
-                        sc.add_class(&format!("workspace-num-{}", ws.num));
-                        if ws.urgent {
-                            sc.add_class("workspace-urgent");
-                        }
-                        if ws.focused {
-                            sc.add_class("workspace-focused");
-                        }
-                        let ws_num = ws.num;
-                        button.connect_clicked(glib::clone!(@weak state => move |_| {
-                            state.switch_workspace(ws_num);
-                        }));
-                        container.add(&button);
-                    }
+            }),
+        );
+
+        let new_button = if config.new_workspace_button {
+            let button = gtk::Button::with_label("+");
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("workspace-new");
+            button.connect_clicked(glib::clone!(@weak state => move |_| {
+                state.switch_workspace(state.lowest_unused_workspace_num());
+            }));
+            container.add(&button);
+            Some(button)
+        } else {
+            None
+        };
+
+        let render = Rc::new(glib::clone!(@weak container => move |state: &I3State| {
+            let output = output_name(&config, &model, &rects, state);
+            let wss = visible_workspaces(&config, &hidden_regex, &output, state);
+            let mut buttons = buttons.borrow_mut();
+            buttons.retain(|name, wb| {
+                if wss.iter().any(|ws| &ws.name == name) {
+                    return true;
                 }
-                container.show_all();
+                wb.stop_flashing();
+                container.remove(&wb.button);
+                false
+            });
+            for (i, ws) in wss.iter().enumerate() {
+                let wb = buttons.entry(ws.name.clone()).or_insert_with(|| {
+                    let wb = WorkspaceButton::new(ws, state);
+                    container.add(&wb.button);
+                    wb
+                });
+                wb.update(&config, ws);
+                container.reorder_child(&wb.button, i as i32);
+            }
+            if let Some(ref button) = new_button {
+                container.reorder_child(button, -1);
+            }
+            container.show_all();
+        }));
+
+        show_health(container, &self.state.backend_health());
+        connect_notify_while_alive(
+            &*self.state,
+            container,
+            Some("backend-health"),
+            glib::clone!(@weak container => move |state, _| {
+                show_health(&container, &state.backend_health());
             }),
         );
+        connect_notify_while_alive(
+            &*self.state,
+            container,
+            Some("workspaces"),
+            glib::clone!(@strong render => move |state, _| render(state)),
+        );
+        // The shared state can have the workspaces already (e.g. after switching the profile).
+        render(&self.state);
     }
 }
 
-struct WorkspacesModuleFactory {
-    state: Rc<I3State>,
-}
+struct WorkspacesModuleFactory;
 
 impl crate::module::ModuleFactory for WorkspacesModuleFactory {
     fn name(&self) -> &str {
         "i3-workspaces"
     }
 
+    fn config_schema(&self, gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        gen.subschema_for::<WorkspacesConfig>()
+    }
+
     fn create(
         &self,
-        _config: &serde_json::Value,
+        config: &serde_json::Value,
         monitor: &gtk::gdk::Monitor,
-    ) -> Box<dyn crate::module::Module> {
-        Box::new(WorkspacesModule {
+    ) -> Result<Box<dyn crate::module::Module>, Error> {
+        let config = JSONConfigFactory::<WorkspacesConfig>::default()
+            .from_json(config, monitor)
+            .map_err(|e| Error::module_config(self.name(), e))?;
+        let hidden_regex = config
+            .hidden_regex
+            .iter()
+            .map(|r| Regex::new(&format!("^(?:{})$", r)))
+            .collect::<Result<Vec<Regex>, _>>()
+            .map_err(|e| {
+                Error::module_config(self.name(), format!("invalid hidden_regex: {}", e))
+            })?;
+        let geom = monitor.geometry();
+        let scale = monitor.scale_factor();
+        Ok(Box::new(WorkspacesModule {
             model: monitor.model().map(|v| v.to_string()).unwrap_or_default(),
-            state: self.state.clone(),
-        })
+            rects: vec![
+                (geom.x, geom.y, geom.width, geom.height),
+                (
+                    geom.x * scale,
+                    geom.y * scale,
+                    geom.width * scale,
+                    geom.height * scale,
+                ),
+            ],
+            config: Rc::new(config),
+            hidden_regex: Rc::new(hidden_regex),
+            state: Rc::new(I3State::shared()),
+        }))
+    }
+}
+
+fn default_focus_label() -> String {
+    "focus".to_owned()
+}
+
+fn default_focus_on() -> Vec<String> {
+    vec![
+        "gaps inner all set 0".to_owned(),
+        "gaps outer all set 0".to_owned(),
+        "[class=\".*\"] border none".to_owned(),
+        "exec --no-startup-id dunstctl set-paused true".to_owned(),
+    ]
+}
+
+fn default_focus_off() -> Vec<String> {
+    vec![
+        "gaps inner all set 10".to_owned(),
+        "gaps outer all set 0".to_owned(),
+        "[class=\".*\"] border pixel 2".to_owned(),
+        "exec --no-startup-id dunstctl set-paused false".to_owned(),
+    ]
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct FocusModeConfig {
+    #[serde(default = "default_focus_label")]
+    label: String,
+
+    /// i3 commands run when the focus mode is turned on.
+    #[serde(default = "default_focus_on")]
+    on: Vec<String>,
+
+    /// i3 commands run when the focus mode is turned off.
+    #[serde(default = "default_focus_off")]
+    off: Vec<String>,
+}
+
+impl Default for FocusModeConfig {
+    fn default() -> Self {
+        FocusModeConfig {
+            label: default_focus_label(),
+            on: default_focus_on(),
+            off: default_focus_off(),
+        }
     }
 }
 
+fn focus_mode_module() -> FnModFactory<FocusModeConfig> {
+    FnModFactory::new(
+        "i3-focus-mode",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<FocusModeConfig>, container: &gtk::Box| {
+            let state = I3State::shared();
+            let button = gtk::ToggleButton::with_label(&config.label);
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("focus-mode");
+            container.add(&button);
+
+            // i3 keeps the settings across restarts of the bar. Restore the button state without
+            // running the commands.
+            let store = StateStore::new(container);
+            button.set_active(store.get("enabled").unwrap_or(false));
+
+            let config = config.clone();
+            button.connect_toggled(glib::clone!(@weak state => move |button| {
+                let enabled = button.is_active();
+                let commands = if enabled { &config.on } else { &config.off };
+                let commands: Vec<&str> = commands.iter().map(|c| c.as_str()).collect();
+                let errors: Vec<String> = match state.run_commands(&commands) {
+                    Ok(outcomes) => outcomes
+                        .into_iter()
+                        .filter(|o| !o.success)
+                        .map(|o| format!("{}: {}", o.command, o.error.unwrap_or_default()))
+                        .collect(),
+                    Err(e) => vec![e],
+                };
+                let sc = button.style_context();
+                if errors.is_empty() {
+                    sc.remove_class("error");
+                    button.set_tooltip_text(None);
+                } else {
+                    sc.add_class("error");
+                    button.set_tooltip_text(Some(&errors.join("\n")));
+                }
+                store.set("enabled", &enabled);
+            }));
+        }),
+    )
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
+struct MarksConfig {
+    /// Marks that are not shown. Marks starting with "_" are hidden too, following i3bar's
+    /// convention for internal marks.
+    #[serde(default)]
+    hidden: Vec<String>,
+}
+
+fn marks_module() -> FnModFactory<MarksConfig> {
+    FnModFactory::new(
+        "i3-marks",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<MarksConfig>, container: &gtk::Box| {
+            let state = I3State::shared();
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            hbox.style_context().add_class("marks");
+            container.add(&hbox);
+
+            let config = config.clone();
+            let update = move |hbox: &gtk::Box, state: &I3State| {
+                for ref child in hbox.children() {
+                    hbox.remove(child);
+                }
+                for mark in state.marks() {
+                    if mark.starts_with('_') || config.hidden.contains(&mark) {
+                        continue;
+                    }
+                    let button = gtk::Button::with_label(&mark);
+                    button.set_relief(gtk::ReliefStyle::None);
+                    button.style_context().add_class("mark");
+                    button.connect_clicked(glib::clone!(@weak state => move |_| {
+                        state.focus_mark(&mark);
+                    }));
+                    hbox.add(&button);
+                }
+                hbox.show_all();
+            };
+            update(&hbox, &state);
+            connect_notify_while_alive(
+                &state,
+                container,
+                Some("marks"),
+                glib::clone!(@weak hbox => move |state, _| update(&hbox, state)),
+            );
+        }),
+    )
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
+struct KeybindingsConfig {
+    /// Path of the i3 config. Defaults to the one i3 uses.
+    #[serde(default)]
+    config: Option<std::path::PathBuf>,
+}
+
+/// Shows the keybinding conflicts in the i3 config. Hidden if there's no conflict.
+fn keybindings_module() -> FnModFactory<KeybindingsConfig> {
+    FnModFactory::new(
+        "i3-keybindings",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(
+            move |config: &Rc<KeybindingsConfig>, container: &gtk::Box| {
+                let button = gtk::Button::new();
+                button.set_relief(gtk::ReliefStyle::None);
+                button.style_context().add_class("keybinding-conflicts");
+                let label = gtk::Label::new(None);
+                button.add(&label);
+                button.set_no_show_all(true);
+                label.show();
+                container.add(&button);
+
+                let popover = gtk::Popover::new(Some(&button));
+                let list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+                popover.add(&list);
+                button.connect_clicked(glib::clone!(@weak popover => move |_| {
+                    popover.show_all();
+                    popover.popup();
+                }));
+
+                let path = config.config.clone().or_else(keybindings::config_path);
+                let update = move |button: &gtk::Button, label: &gtk::Label, list: &gtk::Box| {
+                    let issues: Vec<String> = match path.as_deref().map(keybindings::check_file) {
+                        Some(Ok(issues)) => issues.iter().map(|i| i.to_string()).collect(),
+                        Some(Err(e)) => vec![e],
+                        None => vec![],
+                    };
+                    for ref child in list.children() {
+                        list.remove(child);
+                    }
+                    for issue in &issues {
+                        let l = gtk::Label::new(Some(issue));
+                        l.set_xalign(0.0);
+                        list.add(&l);
+                    }
+                    label.set_text(&format!(
+                        "{} {}",
+                        icon::text("warning").unwrap_or_default(),
+                        issues.len()
+                    ));
+                    button.set_visible(!issues.is_empty());
+                };
+                update(&button, &label, &list);
+                add_refresh_action(
+                    container,
+                    &button,
+                    glib::clone!(@weak button, @weak label, @weak list => move || update(&button, &label, &list)),
+                );
+            },
+        ),
+    )
+}
+
 pub(crate) fn make_module_factories(
     _config: &serde_json::Value,
 ) -> Vec<Box<dyn crate::module::ModuleFactory>> {
-    let state = Rc::new(I3State::new());
-    vec![Box::new(WorkspacesModuleFactory { state })]
+    vec![
+        Box::new(WorkspacesModuleFactory),
+        Box::new(focus_mode_module()),
+        Box::new(marks_module()),
+        Box::new(keybindings_module()),
+    ]
 }
+
+crate::register_plugin!("i3", make_module_factories);
@@ -0,0 +1,79 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Modules for the layout within the left, center, and right boxes.
+
+use crate::module_base::{FnModFactory, JSONConfigFactory};
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+fn default_width() -> i32 {
+    8
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct SpacerConfig {
+    /// Width in pixels.
+    #[serde(default = "default_width")]
+    width: i32,
+}
+
+impl Default for SpacerConfig {
+    fn default() -> Self {
+        SpacerConfig {
+            width: default_width(),
+        }
+    }
+}
+
+/// A fixed-width empty space.
+fn spacer_module() -> FnModFactory<SpacerConfig> {
+    FnModFactory::new(
+        "spacer",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<SpacerConfig>, container: &gtk::Box| {
+            let space = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            space.style_context().add_class("spacer");
+            space.set_size_request(config.width, -1);
+            container.add(&space);
+        }),
+    )
+}
+
+/// An empty space that takes all the remaining width.
+///
+/// The hexpand propagates to the enclosing box, so that the box grows and the modules after this
+/// are pushed to the end. This has no effect in the center box, which has its natural width.
+fn expand_module() -> FnModFactory<serde_json::Value> {
+    FnModFactory::new(
+        "expand",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |_, container: &gtk::Box| {
+            let space = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            space.style_context().add_class("expand");
+            space.set_hexpand(true);
+            container.pack_start(&space, true, true, 0);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(expand_module()), Box::new(spacer_module())]
+}
+
+crate::register_plugin!("layout", make_module_factories);
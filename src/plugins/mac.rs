@@ -0,0 +1,143 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{
+    add_pin_action, add_refresh_action, is_pinned, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+/// The enforcement mode of the mandatory access control (SELinux or AppArmor).
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mode {
+    Enforcing,
+    Permissive,
+    Complain,
+    Disabled,
+}
+
+impl Mode {
+    fn text(&self) -> &'static str {
+        match self {
+            Mode::Enforcing => "enforcing",
+            Mode::Permissive => "permissive",
+            Mode::Complain => "complain",
+            Mode::Disabled => "disabled",
+        }
+    }
+}
+
+/// Reads the current mode.
+///
+/// SELinux is checked first. If SELinux is not enabled, AppArmor is checked. For AppArmor, the mode
+/// is "complain" if any of the loaded profiles is in the complain mode.
+fn read_mode() -> Mode {
+    if let Ok(s) = std::fs::read_to_string("/sys/fs/selinux/enforce") {
+        return if s.trim() == "1" {
+            Mode::Enforcing
+        } else {
+            Mode::Permissive
+        };
+    }
+    let enabled = std::fs::read_to_string("/sys/module/apparmor/parameters/enabled")
+        .map(|s| s.trim() == "Y")
+        .unwrap_or(false);
+    if !enabled {
+        return Mode::Disabled;
+    }
+    match std::fs::read_to_string("/sys/kernel/security/apparmor/profiles") {
+        Ok(s) if s.lines().any(|l| l.ends_with("(complain)")) => Mode::Complain,
+        _ => Mode::Enforcing,
+    }
+}
+
+fn default_interval() -> u32 {
+    60
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct MacConfig {
+    /// Interval in seconds to check the mode. 0 is treated as 1.
+    #[serde(default = "default_interval")]
+    interval: u32,
+}
+
+impl Default for MacConfig {
+    fn default() -> Self {
+        MacConfig {
+            interval: default_interval(),
+        }
+    }
+}
+
+fn mac_module() -> FnModFactory<MacConfig> {
+    FnModFactory::new(
+        "mac-enforcement",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<MacConfig>, container: &gtk::Box| {
+            let label = gtk::Label::new(None);
+            label.style_context().add_class("mac-enforcement");
+            let event_box = gtk::EventBox::new();
+            event_box.add(&label);
+            container.add(&event_box);
+
+            let update = |label: &gtk::Label| {
+                let mode = read_mode();
+                let sc = label.style_context();
+                for m in &[
+                    Mode::Enforcing,
+                    Mode::Permissive,
+                    Mode::Complain,
+                    Mode::Disabled,
+                ] {
+                    sc.remove_class(&format!("mac-{}", m.text()));
+                }
+                sc.add_class(&format!("mac-{}", mode.text()));
+                if mode == Mode::Enforcing {
+                    sc.remove_class("warning");
+                } else {
+                    sc.add_class("warning");
+                }
+                label.set_text(mode.text());
+            };
+            update(&label);
+            glib::timeout_add_seconds_local(
+                config.interval.max(1),
+                glib::clone!(@weak label, @weak container => @default-return Continue(false), move || {
+                    if !is_pinned(&container) {
+                        update(&label);
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(
+                container,
+                &event_box,
+                glib::clone!(@weak label => move || update(&label)),
+            );
+            add_pin_action(container, &event_box);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(mac_module())]
+}
+
+crate::register_plugin!("mac", make_module_factories);
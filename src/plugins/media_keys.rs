@@ -0,0 +1,422 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Handles the media keys (XF86Audio*) with the PulseAudio state and MPRIS players.
+//!
+//! The keys are grabbed on the X11 root window. If the window manager already binds them (e.g. in
+//! the i3 config), bind them to the IPC command instead:
+//!
+//! ```text
+//! bindsym XF86AudioRaiseVolume exec --no-startup-id jiji msg media-key volume-up
+//! ```
+
+use crate::module_base::icon::Icon;
+use crate::module_base::{task, FnModFactory, JSONConfigFactory};
+use crate::plugins::pulseaudio::sink_icon_name;
+use crate::pulseaudio::{PulseAudioState, WithState};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt as _, GrabMode, ModMask};
+use x11rb::protocol::Event;
+
+fn default_grab() -> bool {
+    true
+}
+
+fn default_step() -> f64 {
+    5.0
+}
+
+fn default_duration() -> u64 {
+    1000
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct MediaKeysConfig {
+    /// Grab the keys on the X11 root window. Disable this if the keys are bound to the IPC command
+    /// in the window manager.
+    #[serde(default = "default_grab")]
+    grab: bool,
+
+    /// Volume change per key press in percent.
+    #[serde(default = "default_step")]
+    step: f64,
+
+    /// Duration of the feedback flash in milliseconds.
+    #[serde(default = "default_duration")]
+    duration: u64,
+}
+
+impl Default for MediaKeysConfig {
+    fn default() -> Self {
+        MediaKeysConfig {
+            grab: default_grab(),
+            step: default_step(),
+            duration: default_duration(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MediaKey {
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    MicMute,
+    PlayPause,
+    Next,
+    Previous,
+}
+
+impl MediaKey {
+    const ALL: [MediaKey; 7] = [
+        MediaKey::VolumeUp,
+        MediaKey::VolumeDown,
+        MediaKey::Mute,
+        MediaKey::MicMute,
+        MediaKey::PlayPause,
+        MediaKey::Next,
+        MediaKey::Previous,
+    ];
+
+    /// Returns the X11 keysym (from XF86keysym.h).
+    fn keysym(self) -> u32 {
+        match self {
+            MediaKey::VolumeUp => 0x1008_ff13,
+            MediaKey::VolumeDown => 0x1008_ff11,
+            MediaKey::Mute => 0x1008_ff12,
+            MediaKey::MicMute => 0x1008_ffb2,
+            MediaKey::PlayPause => 0x1008_ff14,
+            MediaKey::Next => 0x1008_ff17,
+            MediaKey::Previous => 0x1008_ff16,
+        }
+    }
+
+    /// Returns the name used in the IPC command.
+    fn name(self) -> &'static str {
+        match self {
+            MediaKey::VolumeUp => "volume-up",
+            MediaKey::VolumeDown => "volume-down",
+            MediaKey::Mute => "mute",
+            MediaKey::MicMute => "mic-mute",
+            MediaKey::PlayPause => "play-pause",
+            MediaKey::Next => "next",
+            MediaKey::Previous => "previous",
+        }
+    }
+}
+
+/// Grabs the media keys on the root window. Sends the key to the sender for each key press.
+fn listen_keys(tx: glib::Sender<MediaKey>) -> Result<(), String> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+    let setup = conn.setup();
+    let root = setup.roots[screen_num].root;
+    let (min, max) = (setup.min_keycode, setup.max_keycode);
+    let mapping = conn
+        .get_keyboard_mapping(min, max - min + 1)
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| e.to_string())?;
+    let mut keys = HashMap::new();
+    let per_keycode = (mapping.keysyms_per_keycode as usize).max(1);
+    for (i, keysyms) in mapping.keysyms.chunks(per_keycode).enumerate() {
+        for key in MediaKey::ALL.iter() {
+            if keysyms.contains(&key.keysym()) {
+                keys.insert(min + i as u8, *key);
+            }
+        }
+    }
+    let mut grabbed = vec![];
+    for (keycode, key) in &keys {
+        // Fails with BadAccess if another client (e.g. the window manager) grabs the key. The
+        // other keys are still handled.
+        let result = conn
+            .grab_key(
+                false,
+                root,
+                ModMask::ANY,
+                *keycode,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )
+            .map_err(|e| e.to_string())?
+            .check();
+        match result {
+            Ok(()) => grabbed.push(*keycode),
+            Err(e) => eprintln!("Cannot grab the media key {}: {}", key.name(), e),
+        }
+    }
+    if grabbed.is_empty() {
+        // The errors are already reported.
+        return Ok(());
+    }
+    keys.retain(|keycode, _| grabbed.contains(keycode));
+    loop {
+        if let Event::KeyPress(e) = conn.wait_for_event().map_err(|e| e.to_string())? {
+            if let Some(key) = keys.get(&e.detail) {
+                if tx.send(*key).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Calls a method of the MPRIS player.
+///
+/// The first player that is playing is used. If none is playing, the first player is used.
+async fn mpris_call(method: &str) -> Result<(), String> {
+    let conn = gio::bus_get_future(gio::BusType::Session)
+        .await
+        .map_err(|e| e.to_string())?;
+    let call =
+        |name: &str, path: &str, interface: &str, method: &str, params: Option<&glib::Variant>| {
+            conn.call_future(
+                Some(name),
+                path,
+                interface,
+                method,
+                params,
+                None,
+                gio::DBusCallFlags::NONE,
+                1000,
+            )
+        };
+    let names: Vec<String> = call(
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "ListNames",
+        None,
+    )
+    .await
+    .map_err(|e| e.to_string())?
+    .child_value(0)
+    .get()
+    .unwrap_or_default();
+    let players: Vec<&String> = names
+        .iter()
+        .filter(|n| n.starts_with("org.mpris.MediaPlayer2."))
+        .collect();
+    let mut playing = None;
+    for name in &players {
+        let status = call(
+            name,
+            "/org/mpris/MediaPlayer2",
+            "org.freedesktop.DBus.Properties",
+            "Get",
+            Some(&("org.mpris.MediaPlayer2.Player", "PlaybackStatus").to_variant()),
+        )
+        .await
+        .ok()
+        .and_then(|v| v.child_value(0).as_variant())
+        .and_then(|v| v.get::<String>());
+        if status.as_deref() == Some("Playing") {
+            playing = Some(name);
+            break;
+        }
+    }
+    let player = playing
+        .or_else(|| players.first())
+        .ok_or_else(|| "No MPRIS player".to_owned())?;
+    call(
+        player,
+        "/org/mpris/MediaPlayer2",
+        "org.mpris.MediaPlayer2.Player",
+        method,
+        None,
+    )
+    .await
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Handles the media keys and shows the feedback in the bars.
+struct MediaKeys {
+    state: PulseAudioState,
+    step: f64,
+    duration: Duration,
+    /// The feedback widgets in the bars.
+    feedbacks: RefCell<Vec<(gtk::Box, Icon, gtk::Label)>>,
+    hide_source: RefCell<Option<glib::SourceId>>,
+}
+
+impl MediaKeys {
+    /// Runs the action for the key and shows the feedback.
+    fn handle(self: &Rc<Self>, key: MediaKey) {
+        let feedback = match key {
+            MediaKey::VolumeUp | MediaKey::VolumeDown => self.state.default_sink().map(|sink| {
+                let adjustment = sink.adjustment();
+                let delta = if key == MediaKey::VolumeUp {
+                    self.step
+                } else {
+                    -self.step
+                };
+                // The adjustment clamps the value to 0-100%.
+                adjustment.set_value(adjustment.value() + delta);
+                let percentage = adjustment.value().round();
+                let volume = crate::pulseaudio::util::percentage_to_volume(percentage, sink.volume);
+                (
+                    sink_icon_name(&volume, sink.mute),
+                    format!("{}%", percentage),
+                )
+            }),
+            MediaKey::Mute => self.state.default_sink().map(|sink| {
+                sink.toggle_mute();
+                (
+                    sink_icon_name(&sink.volume, !sink.mute),
+                    if sink.mute { "on" } else { "muted" }.to_owned(),
+                )
+            }),
+            MediaKey::MicMute => self.state.default_source().map(|source| {
+                source.toggle_mute();
+                if source.mute {
+                    ("mic", "on".to_owned())
+                } else {
+                    ("mic-muted", "muted".to_owned())
+                }
+            }),
+            MediaKey::PlayPause | MediaKey::Next | MediaKey::Previous => {
+                let method = match key {
+                    MediaKey::PlayPause => "PlayPause",
+                    MediaKey::Next => "Next",
+                    _ => "Previous",
+                };
+                task::spawn(async move {
+                    if let Err(e) = mpris_call(method).await {
+                        eprintln!("media-keys: {} failed: {}", method, e);
+                    }
+                });
+                None
+            }
+        };
+        if let Some((icon, text)) = feedback {
+            self.flash(icon, &text);
+        }
+    }
+
+    /// Shows the feedback in the bars for a while.
+    fn flash(self: &Rc<Self>, icon: &str, text: &str) {
+        for (hbox, icon_widget, label) in self.feedbacks.borrow().iter() {
+            icon_widget.set(icon);
+            label.set_text(text);
+            hbox.show();
+        }
+        if let Some(source) = self.hide_source.take() {
+            glib::source_remove(source);
+        }
+        let obj = Rc::downgrade(self);
+        self.hide_source
+            .replace(Some(glib::timeout_add_local(self.duration, move || {
+                if let Some(obj) = obj.upgrade() {
+                    for (hbox, _, _) in obj.feedbacks.borrow().iter() {
+                        hbox.hide();
+                    }
+                    obj.hide_source.take();
+                }
+                Continue(false)
+            })));
+    }
+
+    /// Starts handling the keys from X11 and the IPC.
+    fn start(self: &Rc<Self>, grab: bool) {
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        if grab {
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = listen_keys(tx) {
+                    eprintln!("Cannot grab the media keys: {}", e);
+                }
+            });
+        }
+        // media-key <key>
+        crate::ipc::register_command("media-key", move |args| {
+            let key = match args {
+                [name] => MediaKey::ALL.iter().find(|k| k.name() == *name),
+                _ => None,
+            };
+            match key {
+                Some(key) => tx.send(*key).map(|_| "".into()).map_err(|e| e.to_string()),
+                None => Err(format!(
+                    "usage: media-key <{}>",
+                    MediaKey::ALL
+                        .iter()
+                        .map(|k| k.name())
+                        .collect::<Vec<_>>()
+                        .join("|")
+                )),
+            }
+        });
+        let obj = Rc::downgrade(self);
+        rx.attach(None, move |key| match obj.upgrade() {
+            Some(obj) => {
+                obj.handle(key);
+                Continue(true)
+            }
+            None => Continue(false),
+        });
+    }
+}
+
+fn media_keys_module(state: Rc<PulseAudioState>) -> FnModFactory<MediaKeysConfig> {
+    // Shared by all the bars so that the keys are handled once. The first module's config is
+    // used.
+    let media_keys: RefCell<Option<Rc<MediaKeys>>> = RefCell::new(None);
+    FnModFactory::new(
+        "media-keys",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<MediaKeysConfig>, container: &gtk::Box| {
+            if media_keys.borrow().is_none() {
+                let obj = Rc::new(MediaKeys {
+                    state: (*state).clone(),
+                    step: config.step,
+                    duration: Duration::from_millis(config.duration),
+                    feedbacks: RefCell::new(vec![]),
+                    hide_source: RefCell::new(None),
+                });
+                obj.start(config.grab);
+                media_keys.replace(Some(obj));
+            }
+            let media_keys = media_keys.borrow().clone().unwrap();
+
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            hbox.style_context().add_class("media-keys");
+            let icon = Icon::new("volume-muted");
+            let label = gtk::Label::new(None);
+            hbox.add(icon.widget());
+            hbox.add(&label);
+            // Shown only while flashing.
+            hbox.set_no_show_all(true);
+            icon.widget().show();
+            label.show();
+            container.add(&hbox);
+            media_keys.feedbacks.borrow_mut().push((hbox, icon, label));
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(WithState::new("media-keys", media_keys_module))]
+}
+
+crate::register_plugin!("media-keys", make_module_factories);
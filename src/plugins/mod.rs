@@ -12,7 +12,45 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub(crate) mod battery;
+pub(crate) mod bell;
+pub(crate) mod bluetooth;
 pub(crate) mod button;
+pub(crate) mod calendar;
+pub(crate) mod cert;
+pub(crate) mod clock;
+pub(crate) mod disk;
+pub(crate) mod downloads;
+pub(crate) mod file;
+pub(crate) mod healthchecks;
+#[cfg(feature = "i3")]
 pub(crate) mod i3;
+pub(crate) mod layout;
+pub(crate) mod mac;
+#[cfg(feature = "pulseaudio")]
+pub(crate) mod media_keys;
+pub(crate) mod mounts;
+pub(crate) mod mqtt;
+pub(crate) mod note;
+pub(crate) mod notifications;
+pub(crate) mod obs;
+#[cfg(feature = "pulseaudio")]
+pub(crate) mod privacy;
+pub(crate) mod profile;
+#[cfg(feature = "pulseaudio")]
 pub(crate) mod pulseaudio;
+pub(crate) mod screenshot;
+pub(crate) mod suspend;
+pub(crate) mod sysinfo;
 pub(crate) mod text;
+pub(crate) mod ticker;
+pub(crate) mod timer;
+pub(crate) mod todo;
+pub(crate) mod toggle;
+pub(crate) mod trash;
+pub(crate) mod vpn;
+pub(crate) mod weather;
+pub(crate) mod wifi;
+pub(crate) mod wol;
+pub(crate) mod worldclock;
+pub(crate) mod zoom;
@@ -0,0 +1,201 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::icon::Icon;
+use crate::module_base::{
+    add_pin_action, add_refresh_action, is_pinned, poll_in_worker, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+fn default_interval() -> u64 {
+    30
+}
+
+fn default_timeout() -> u64 {
+    5
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct MountsConfig {
+    /// Mount points to watch (e.g. NFS, SMB, or sshfs mounts).
+    #[serde(default)]
+    mounts: Vec<PathBuf>,
+
+    /// Interval in seconds to check the mounts.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// A mount is considered hung if statfs doesn't return within this many seconds.
+    #[serde(default = "default_timeout")]
+    timeout: u64,
+}
+
+impl Default for MountsConfig {
+    fn default() -> Self {
+        MountsConfig {
+            mounts: vec![],
+            interval: default_interval(),
+            timeout: default_timeout(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MountState {
+    Ok,
+    NotMounted,
+    Error,
+    Hung,
+}
+
+impl MountState {
+    fn description(self) -> &'static str {
+        match self {
+            MountState::Ok => "ok",
+            MountState::NotMounted => "not mounted",
+            MountState::Error => "error",
+            MountState::Hung => "hung",
+        }
+    }
+}
+
+fn is_mounted(path: &Path) -> bool {
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    mounts.lines().any(|line| {
+        // The mount point is the second field. Spaces are escaped as "\040".
+        line.split(' ')
+            .nth(1)
+            .map(|p| Path::new(&p.replace("\\040", " ")) == path)
+            .unwrap_or(false)
+    })
+}
+
+fn statfs(path: &Path) -> bool {
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    let mut buf: libc::statfs = unsafe { std::mem::zeroed() };
+    unsafe { libc::statfs(path.as_ptr(), &mut buf) == 0 }
+}
+
+/// Checks a mount. `in_flight` is true while a statfs call on the mount hasn't returned.
+///
+/// A statfs call on a hung mount can block forever and the thread cannot be cancelled. Instead of
+/// piling up threads, the mount is reported as hung until the previous call returns.
+fn check(path: &Path, timeout: Duration, in_flight: &Arc<AtomicBool>) -> MountState {
+    if !is_mounted(path) {
+        return MountState::NotMounted;
+    }
+    if in_flight.load(Ordering::SeqCst) {
+        return MountState::Hung;
+    }
+    in_flight.store(true, Ordering::SeqCst);
+    let (tx, rx) = mpsc::channel();
+    let path = path.to_owned();
+    let in_flight = in_flight.clone();
+    std::thread::spawn(move || {
+        let ok = statfs(&path);
+        in_flight.store(false, Ordering::SeqCst);
+        let _ = tx.send(ok);
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(true) => MountState::Ok,
+        Ok(false) => MountState::Error,
+        Err(_) => MountState::Hung,
+    }
+}
+
+fn mounts_module() -> FnModFactory<MountsConfig> {
+    FnModFactory::new(
+        "mounts",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<MountsConfig>, container: &gtk::Box| {
+            let event_box = gtk::EventBox::new();
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            hbox.style_context().add_class("mounts");
+            let icon = Icon::new("ok");
+            let label = gtk::Label::new(None);
+            hbox.add(icon.widget());
+            hbox.add(&label);
+            event_box.add(&hbox);
+            container.add(&event_box);
+
+            let mounts = config.mounts.clone();
+            let timeout = Duration::from_secs(config.timeout);
+            let in_flight: Vec<_> = mounts
+                .iter()
+                .map(|_| Arc::new(AtomicBool::new(false)))
+                .collect();
+            let poller = poll_in_worker(
+                Duration::from_secs(config.interval),
+                move || {
+                    mounts
+                        .iter()
+                        .zip(in_flight.iter())
+                        .map(|(m, f)| (m.clone(), check(m, timeout, f)))
+                        .collect::<Vec<_>>()
+                },
+                glib::clone!(@weak hbox, @weak label, @weak container => @default-return Continue(false), move |states| {
+                    if is_pinned(&container) {
+                        return Continue(true);
+                    }
+                    let problems: Vec<String> = states
+                        .iter()
+                        .filter(|(_, s)| *s != MountState::Ok)
+                        .map(|(m, _)| {
+                            m.file_name()
+                                .map(|n| n.to_string_lossy().to_string())
+                                .unwrap_or_else(|| m.display().to_string())
+                        })
+                        .collect();
+                    label.set_text(&problems.join(" "));
+                    icon.set(if problems.is_empty() { "ok" } else { "warning" });
+                    let tooltip: Vec<String> = states
+                        .iter()
+                        .map(|(m, s)| format!("{}: {}", m.display(), s.description()))
+                        .collect();
+                    hbox.set_tooltip_text(Some(&tooltip.join("\n")));
+                    let sc = hbox.style_context();
+                    if states.iter().any(|(_, s)| *s == MountState::Hung) {
+                        sc.add_class("mount-hung");
+                    } else {
+                        sc.remove_class("mount-hung");
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(container, &event_box, move || poller.refresh());
+            add_pin_action(container, &event_box);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(mounts_module())]
+}
+
+crate::register_plugin!("mounts", make_module_factories);
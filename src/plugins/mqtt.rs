@@ -0,0 +1,315 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! MQTT modules.
+//!
+//! This uses the `mosquitto_sub` and `mosquitto_pub` commands so that an MQTT client isn't pulled
+//! into the binary.
+
+use crate::module_base::{task, FnModFactory, JSONConfigFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn default_host() -> String {
+    "localhost".to_owned()
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_format() -> String {
+    "{}".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct MqttConfig {
+    /// Host of the broker.
+    #[serde(default = "default_host")]
+    host: String,
+
+    #[serde(default = "default_port")]
+    port: u16,
+
+    #[serde(default)]
+    username: Option<String>,
+
+    /// Password. It's passed to mosquitto through a file in the runtime directory that only the
+    /// user can read.
+    #[serde(default)]
+    password: Option<String>,
+
+    /// Topic to subscribe. If empty, only `text` is shown.
+    #[serde(default)]
+    topic: String,
+
+    /// JSON pointer (e.g. "/temperature") to the value in a JSON payload. If not specified, the
+    /// payload is shown as-is.
+    #[serde(default)]
+    pointer: Option<String>,
+
+    /// Text to show. "{}" is replaced with the value.
+    #[serde(default = "default_format")]
+    format: String,
+
+    /// Text shown until a message is received.
+    #[serde(default)]
+    text: String,
+
+    /// Topic to publish on click.
+    #[serde(default)]
+    publish_topic: Option<String>,
+
+    /// Payload to publish on click.
+    #[serde(default)]
+    publish_payload: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            host: default_host(),
+            port: default_port(),
+            username: None,
+            password: None,
+            topic: String::new(),
+            pointer: None,
+            format: default_format(),
+            text: String::new(),
+            publish_topic: None,
+            publish_payload: String::new(),
+        }
+    }
+}
+
+impl MqttConfig {
+    /// Returns a mosquitto_sub or mosquitto_pub command connecting to the broker. The password is
+    /// read from the options files in `options_dir` so that it doesn't show up in the command line.
+    fn command(&self, program: &str, options_dir: Option<&Path>) -> Command {
+        let mut cmd = Command::new(program);
+        cmd.arg("-h")
+            .arg(&self.host)
+            .arg("-p")
+            .arg(self.port.to_string());
+        if let Some(ref username) = self.username {
+            cmd.arg("-u").arg(username);
+        }
+        if let Some(dir) = options_dir {
+            // mosquitto_sub and mosquitto_pub read $XDG_CONFIG_HOME/<program> for default options.
+            cmd.env("XDG_CONFIG_HOME", dir);
+        }
+        cmd
+    }
+
+    /// Writes the password to the options files of mosquitto_sub and mosquitto_pub in a private
+    /// runtime directory. Returns None if no password is set.
+    fn write_options(&self) -> Result<Option<PathBuf>, String> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let password = match self.password {
+            Some(ref p) => p,
+            None => return Ok(None),
+        };
+        if password.contains('\n') {
+            return Err("password cannot contain a newline".to_owned());
+        }
+        let name = format!(
+            "mqtt-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = xdg::BaseDirectories::with_prefix("jiji")
+            .map_err(|e| e.to_string())
+            .and_then(|d| d.create_runtime_directory(name).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Cannot create the mosquitto options directory: {}", e))?;
+        for program in &["mosquitto_sub", "mosquitto_pub"] {
+            let path = dir.join(program);
+            let _ = std::fs::remove_file(&path);
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+                .and_then(|mut f| writeln!(f, "-P {}", password))
+                .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        }
+        Ok(Some(dir))
+    }
+
+    /// Extracts the value to show from the payload.
+    fn value(&self, payload: &str) -> Result<String, String> {
+        let pointer = match self.pointer {
+            Some(ref p) => p,
+            None => return Ok(payload.trim().to_owned()),
+        };
+        let v: serde_json::Value = serde_json::from_str(payload).map_err(|e| e.to_string())?;
+        match v.pointer(pointer) {
+            Some(serde_json::Value::String(s)) => Ok(s.clone()),
+            Some(v) => Ok(v.to_string()),
+            None => Err(format!("{} is not in the payload", pointer)),
+        }
+    }
+}
+
+/// Runs mosquitto_sub and sends the payloads (one per line) to the sender. Reconnects when it
+/// exits.
+fn subscribe(
+    config: &MqttConfig,
+    options_dir: Option<&Path>,
+    tx: glib::Sender<Result<String, String>>,
+) {
+    loop {
+        let child = config
+            .command("mosquitto_sub", options_dir)
+            .arg("-t")
+            .arg(&config.topic)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(c) => c,
+            Err(e) => {
+                let _ = tx.send(Err(format!("Cannot run mosquitto_sub: {}", e)));
+                return;
+            }
+        };
+        for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+            if tx.send(Ok(line)).is_err() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+        }
+        let mut stderr = String::new();
+        if let Some(mut s) = child.stderr.take() {
+            let _ = s.read_to_string(&mut stderr);
+        }
+        let status = child.wait();
+        let message = match stderr.trim() {
+            "" => format!("mosquitto_sub exited: {:?}", status),
+            s => s.to_owned(),
+        };
+        if tx.send(Err(message)).is_err() {
+            return;
+        }
+        std::thread::sleep(Duration::from_secs(10));
+    }
+}
+
+/// Publishes the payload in a worker thread. Errors are logged.
+fn publish(config: &MqttConfig, options_dir: Option<&Path>, topic: &str, payload: &str) {
+    let mut cmd = config.command("mosquitto_pub", options_dir);
+    cmd.arg("-t")
+        .arg(topic)
+        .arg("-m")
+        .arg(payload)
+        .stdin(Stdio::null());
+    task::spawn_blocking(
+        move || cmd.output(),
+        |output| match output {
+            Ok(out) if out.status.success() => {}
+            Ok(out) => eprintln!(
+                "mosquitto_pub failed: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+            Err(e) => eprintln!("Cannot run mosquitto_pub: {}", e),
+        },
+    );
+}
+
+fn mqtt_module() -> FnModFactory<MqttConfig> {
+    FnModFactory::new(
+        "mqtt",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<MqttConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("mqtt");
+            let label = gtk::Label::new(Some(&config.text));
+            button.add(&label);
+            container.add(&button);
+
+            let options_dir = match config.write_options() {
+                Ok(dir) => dir,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    button.style_context().add_class("error");
+                    button.set_tooltip_text(Some(&e));
+                    return;
+                }
+            };
+
+            if let Some(ref topic) = config.publish_topic {
+                let topic = topic.clone();
+                let config = config.clone();
+                let options_dir = options_dir.clone();
+                button.connect_clicked(move |_| {
+                    publish(
+                        &config,
+                        options_dir.as_deref(),
+                        &topic,
+                        &config.publish_payload,
+                    );
+                });
+            }
+
+            if config.topic.is_empty() {
+                return;
+            }
+            let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            let sub_config = MqttConfig::clone(config);
+            std::thread::spawn(move || subscribe(&sub_config, options_dir.as_deref(), tx));
+            let config = config.clone();
+            rx.attach(
+                None,
+                glib::clone!(@weak button, @weak label => @default-return Continue(false), move |payload: Result<String, String>| {
+                    let sc = button.style_context();
+                    match payload.and_then(|p| config.value(&p)) {
+                        Ok(value) => {
+                            label.set_text(&config.format.replace("{}", &value));
+                            sc.remove_class("error");
+                            button.set_tooltip_text(None);
+                        }
+                        Err(e) => {
+                            sc.add_class("error");
+                            button.set_tooltip_text(Some(&e));
+                        }
+                    }
+                    Continue(true)
+                }),
+            );
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(mqtt_module())]
+}
+
+crate::register_plugin!("mqtt", make_module_factories);
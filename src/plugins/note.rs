@@ -0,0 +1,131 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{
+    activate_module_action, module_action_group, FnModFactory, JSONConfigFactory, StateStore,
+};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+fn default_placeholder() -> String {
+    "📝".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct NoteConfig {
+    /// Text shown when there's no note.
+    #[serde(default = "default_placeholder")]
+    placeholder: String,
+}
+
+impl Default for NoteConfig {
+    fn default() -> Self {
+        NoteConfig {
+            placeholder: default_placeholder(),
+        }
+    }
+}
+
+fn show_note(button: &gtk::Button, label: &gtk::Label, note: &str, placeholder: &str) {
+    let sc = button.style_context();
+    if note.is_empty() {
+        label.set_text(placeholder);
+        sc.add_class("note-empty");
+    } else {
+        label.set_text(note);
+        sc.remove_class("note-empty");
+    }
+}
+
+fn note_module() -> FnModFactory<NoteConfig> {
+    FnModFactory::new(
+        "note",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<NoteConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("note");
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+
+            let store = StateStore::new(container);
+            let note: String = store.get("note").unwrap_or_default();
+            show_note(&button, &label, &note, &config.placeholder);
+
+            // The note is set through the action so that the modules with the same ID on other
+            // bars are updated together. An empty string dismisses the note.
+            let action = gio::SimpleAction::new("set-note", Some(glib::VariantTy::STRING));
+            let placeholder = config.placeholder.clone();
+            action.connect_activate(glib::clone!(@weak button, @weak label => move |_, param| {
+                let note = param.and_then(|p| p.get::<String>()).unwrap_or_default();
+                let note = note.trim();
+                if note.is_empty() {
+                    store.remove("note");
+                } else {
+                    store.set("note", &note);
+                }
+                show_note(&button, &label, note, &placeholder);
+            }));
+            module_action_group(container).add_action(&action);
+
+            let id = container.widget_name().to_string();
+            let set_note = Rc::new(move |note: &str| {
+                if let Err(e) = activate_module_action(&id, "set-note", Some(&note.to_variant())) {
+                    eprintln!("Cannot set the note: {}", e);
+                }
+            });
+            button.connect_clicked(glib::clone!(@weak label => move |button| {
+                // The bar is a dock window that doesn't take the keyboard focus. Use a dialog
+                // window for the text entry.
+                let window = gtk::Window::new(gtk::WindowType::Toplevel);
+                window.set_title("Note");
+                window.set_type_hint(gtk::gdk::WindowTypeHint::Dialog);
+                window.set_position(gtk::WindowPosition::Mouse);
+                let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+                let entry = gtk::Entry::new();
+                entry.set_placeholder_text(Some("Note"));
+                if !button.style_context().has_class("note-empty") {
+                    entry.set_text(&label.text());
+                }
+                let dismiss = gtk::Button::with_label("Dismiss");
+                hbox.add(&entry);
+                hbox.add(&dismiss);
+                window.add(&hbox);
+
+                entry.connect_activate(glib::clone!(@weak window, @strong set_note => move |entry| {
+                    set_note(entry.text().as_str());
+                    window.close();
+                }));
+                dismiss.connect_clicked(glib::clone!(@weak window, @strong set_note => move |_| {
+                    set_note("");
+                    window.close();
+                }));
+                window.show_all();
+                entry.grab_focus();
+            }));
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(note_module())]
+}
+
+crate::register_plugin!("note", make_module_factories);
@@ -0,0 +1,269 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Do-not-disturb toggle and notification history for dunst and mako.
+//!
+//! jiji doesn't act as the notification server. The state and the history are read from the
+//! running notification daemon through its control command (dunstctl or makoctl).
+
+use crate::module_base::touch;
+use crate::module_base::{
+    add_refresh_action, command, is_pinned, poll_in_worker, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Daemon {
+    Dunst,
+    Mako,
+}
+
+impl Default for Daemon {
+    fn default() -> Self {
+        Daemon::Dunst
+    }
+}
+
+impl Daemon {
+    fn is_paused_command(self) -> &'static str {
+        match self {
+            Daemon::Dunst => "dunstctl is-paused",
+            Daemon::Mako => "makoctl mode",
+        }
+    }
+
+    fn toggle_command(self) -> &'static str {
+        match self {
+            Daemon::Dunst => "dunstctl set-paused toggle",
+            Daemon::Mako => "makoctl mode -t do-not-disturb",
+        }
+    }
+
+    fn history_command(self) -> &'static str {
+        match self {
+            Daemon::Dunst => "dunstctl history",
+            Daemon::Mako => "makoctl history",
+        }
+    }
+
+    fn parse_paused(self, output: &str) -> bool {
+        match self {
+            Daemon::Dunst => output.trim() == "true",
+            Daemon::Mako => output.lines().any(|l| l.trim() == "do-not-disturb"),
+        }
+    }
+}
+
+fn default_on_label() -> String {
+    "🔔".to_owned()
+}
+
+fn default_off_label() -> String {
+    "🔕".to_owned()
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+fn default_history_count() -> usize {
+    10
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct NotificationsConfig {
+    /// The notification daemon. "dunst" (default) or "mako". For mako, a "do-not-disturb" mode
+    /// needs to be defined in its config.
+    #[serde(default)]
+    daemon: Daemon,
+
+    /// Label shown while notifications are shown.
+    #[serde(default = "default_on_label")]
+    on_label: String,
+
+    /// Label shown in the do-not-disturb mode.
+    #[serde(default = "default_off_label")]
+    off_label: String,
+
+    /// Interval in seconds to read the state, in case it's changed outside of the module.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Number of notifications in the history popover shown by a right click. 0 disables it.
+    #[serde(default = "default_history_count")]
+    history_count: usize,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig {
+            daemon: Daemon::default(),
+            on_label: default_on_label(),
+            off_label: default_off_label(),
+            interval: default_interval(),
+            history_count: default_history_count(),
+        }
+    }
+}
+
+/// Parses the history printed by dunstctl and makoctl. Returns (app name, summary, body).
+///
+/// Both print a JSON form of a GVariant "aa{sv}": {"data": [[{"summary": {"data": ...}}]]}.
+fn parse_history(output: &str) -> Vec<(String, String, String)> {
+    let v: serde_json::Value = match serde_json::from_str(output) {
+        Ok(v) => v,
+        Err(_) => return vec![],
+    };
+    let field = |n: &serde_json::Value, name: &str| {
+        n.pointer(&format!("/{}/data", name))
+            .and_then(|d| d.as_str())
+            .unwrap_or_default()
+            .to_owned()
+    };
+    v.pointer("/data/0")
+        .and_then(|d| d.as_array())
+        .map(|list| {
+            list.iter()
+                .map(|n| (field(n, "appname"), field(n, "summary"), field(n, "body")))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn notifications_module() -> FnModFactory<NotificationsConfig> {
+    FnModFactory::new(
+        "notifications",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(
+            move |config: &Rc<NotificationsConfig>, container: &gtk::Box| {
+                let button = gtk::Button::with_label(&config.on_label);
+                button.set_relief(gtk::ReliefStyle::None);
+                button.style_context().add_class("notifications");
+                container.add(&button);
+
+                let daemon = config.daemon;
+                let labels = (config.on_label.clone(), config.off_label.clone());
+                let poller = Rc::new(poll_in_worker(
+                    Duration::from_secs(config.interval),
+                    move || {
+                        command::run_sync(daemon.is_paused_command())
+                            .map(|out| daemon.parse_paused(&out))
+                    },
+                    glib::clone!(@weak button, @weak container => @default-return Continue(false), move |result| {
+                        if is_pinned(&container) {
+                            return Continue(true);
+                        }
+                        let sc = button.style_context();
+                        match result {
+                            Ok(paused) => {
+                                button.set_label(if paused { &labels.1 } else { &labels.0 });
+                                button.set_tooltip_text(None);
+                                sc.remove_class("error");
+                                if paused {
+                                    sc.add_class("dnd");
+                                } else {
+                                    sc.remove_class("dnd");
+                                }
+                            }
+                            Err(e) => {
+                                button.set_tooltip_text(Some(&e));
+                                sc.add_class("error");
+                            }
+                        }
+                        Continue(true)
+                    }),
+                ));
+                button.connect_clicked(glib::clone!(@strong poller => move |_| {
+                    let poller = poller.clone();
+                    command::run(daemon.toggle_command(), move |result| {
+                        if let Err(e) = result {
+                            eprintln!("Cannot toggle do-not-disturb: {}", e);
+                        }
+                        poller.refresh();
+                    });
+                }));
+
+                if config.history_count > 0 {
+                    let popover = gtk::Popover::new(Some(&button));
+                    let list = gtk::Box::new(gtk::Orientation::Vertical, 4);
+                    popover.add(&list);
+                    let history_count = config.history_count;
+                    let show_history = Rc::new(glib::clone!(@weak popover, @weak list => move || {
+                        command::run(
+                            daemon.history_command(),
+                            glib::clone!(@weak popover, @weak list => move |result| {
+                                for ref child in list.children() {
+                                    list.remove(child);
+                                }
+                                let mut history = match result {
+                                    Ok(out) => parse_history(&out),
+                                    Err(e) => {
+                                        list.add(&gtk::Label::new(Some(&e)));
+                                        popover.show_all();
+                                        popover.popup();
+                                        return;
+                                    }
+                                };
+                                if history.is_empty() {
+                                    list.add(&gtk::Label::new(Some("No notifications")));
+                                }
+                                history.truncate(history_count);
+                                for (app, summary, body) in history {
+                                    let label = gtk::Label::new(None);
+                                    label.set_markup(&format!(
+                                        "<b>{}</b> {}\n{}",
+                                        glib::markup_escape_text(&app),
+                                        glib::markup_escape_text(&summary),
+                                        glib::markup_escape_text(&body)
+                                    ));
+                                    label.set_xalign(0.0);
+                                    label.set_line_wrap(true);
+                                    label.set_max_width_chars(60);
+                                    list.add(&label);
+                                }
+                                popover.show_all();
+                                popover.popup();
+                            }),
+                        );
+                    }));
+                    button.connect_button_release_event(
+                        glib::clone!(@strong show_history => move |_, e| {
+                            if e.button() != gtk::gdk::BUTTON_SECONDARY {
+                                return Inhibit(false);
+                            }
+                            show_history();
+                            Inhibit(true)
+                        }),
+                    );
+                    touch::connect_long_press(&button, move |_| show_history());
+                }
+                add_refresh_action(container, &button, move || poller.refresh());
+            },
+        ),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(notifications_module())]
+}
+
+crate::register_plugin!("notifications", make_module_factories);
@@ -0,0 +1,350 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OBS recording and streaming indicator.
+//!
+//! This speaks obs-websocket (protocol v5, OBS 28+) through the `websocat` command so that a
+//! WebSocket stack isn't pulled into the binary. The password authentication uses `openssl` and
+//! `base64`.
+
+use crate::module_base::{FnModFactory, JSONConfigFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{ChildStdin, Command, Stdio};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// EventSubscription::Outputs.
+const OUTPUTS_EVENTS: u64 = 1 << 6;
+
+fn default_url() -> String {
+    "ws://127.0.0.1:4455".to_owned()
+}
+
+fn default_recording_format() -> String {
+    "⏺ {elapsed}".to_owned()
+}
+
+fn default_streaming_format() -> String {
+    "📡 {elapsed}".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ObsConfig {
+    /// URL of obs-websocket.
+    #[serde(default = "default_url")]
+    url: String,
+
+    /// Password of obs-websocket if the authentication is enabled.
+    #[serde(default)]
+    password: Option<String>,
+
+    /// Text shown while recording. "{elapsed}" is replaced.
+    #[serde(default = "default_recording_format")]
+    recording_format: String,
+
+    /// Text shown while streaming. "{elapsed}" is replaced.
+    #[serde(default = "default_streaming_format")]
+    streaming_format: String,
+}
+
+impl Default for ObsConfig {
+    fn default() -> Self {
+        ObsConfig {
+            url: default_url(),
+            password: None,
+            recording_format: default_recording_format(),
+            streaming_format: default_streaming_format(),
+        }
+    }
+}
+
+enum ObsEvent {
+    Connected,
+    Disconnected,
+    /// (active, elapsed) of the recording.
+    Recording(bool, Duration),
+    /// (active, elapsed) of the streaming.
+    Streaming(bool, Duration),
+}
+
+/// Returns base64(sha256(s)). The input is fed through stdin so that the password doesn't show up
+/// in the command line.
+fn sha256_base64(s: &str) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("openssl dgst -sha256 -binary | base64")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    // Dropping stdin closes it.
+    let written = child.stdin.take().unwrap().write_all(s.as_bytes());
+    let out = child.wait_with_output().map_err(|e| e.to_string())?;
+    written.map_err(|e| e.to_string())?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_owned());
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_owned())
+}
+
+/// Returns the Identify message for the Hello message.
+fn identify(hello: &serde_json::Value, password: Option<&str>) -> Result<String, String> {
+    let mut d = json!({
+        "rpcVersion": 1,
+        "eventSubscriptions": OUTPUTS_EVENTS,
+    });
+    if let Some(auth) = hello.pointer("/d/authentication") {
+        let password = password.ok_or("OBS requires a password")?;
+        let salt = auth["salt"].as_str().unwrap_or_default();
+        let challenge = auth["challenge"].as_str().unwrap_or_default();
+        let secret = sha256_base64(&format!("{}{}", password, salt))?;
+        d["authentication"] = json!(sha256_base64(&format!("{}{}", secret, challenge))?);
+    }
+    Ok(json!({"op": 1, "d": d}).to_string())
+}
+
+fn request(request_type: &str) -> String {
+    json!({
+        "op": 6,
+        "d": {"requestType": request_type, "requestId": request_type},
+    })
+    .to_string()
+}
+
+/// Converts an event or a request response to an ObsEvent.
+fn to_event(message: &serde_json::Value) -> Option<ObsEvent> {
+    let d = &message["d"];
+    let (kind, data) = match message["op"].as_u64()? {
+        // Event
+        5 => (d["eventType"].as_str()?, &d["eventData"]),
+        // RequestResponse
+        7 => (d["requestType"].as_str()?, &d["responseData"]),
+        _ => return None,
+    };
+    let active = data["outputActive"].as_bool()?;
+    // Events don't have the duration. The output has just started or stopped.
+    let elapsed = Duration::from_millis(data["outputDuration"].as_u64().unwrap_or(0));
+    match kind {
+        "RecordStateChanged" | "GetRecordStatus" => Some(ObsEvent::Recording(active, elapsed)),
+        "StreamStateChanged" | "GetStreamStatus" => Some(ObsEvent::Streaming(active, elapsed)),
+        _ => None,
+    }
+}
+
+/// Connects to OBS and sends the events until the connection is closed.
+fn run_session(
+    config_url: &str,
+    password: Option<&str>,
+    tx: &glib::Sender<ObsEvent>,
+    stdin: &Mutex<Option<ChildStdin>>,
+) -> Result<(), String> {
+    let mut child = Command::new("websocat")
+        .arg("--text")
+        .arg(config_url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Cannot run websocat: {}", e))?;
+    let stdout = child.stdout.take().unwrap();
+    stdin.lock().unwrap().replace(child.stdin.take().unwrap());
+    let send = |message: &str| -> Result<(), String> {
+        match stdin.lock().unwrap().as_mut() {
+            Some(s) => writeln!(s, "{}", message).map_err(|e| e.to_string()),
+            None => Err("Not connected".to_owned()),
+        }
+    };
+    let mut result = Ok(());
+    for line in BufReader::new(stdout).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        let message: serde_json::Value = match serde_json::from_str(&line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        match message["op"].as_u64() {
+            // Hello
+            Some(0) => {
+                if let Err(e) = identify(&message, password).and_then(|m| send(&m)) {
+                    result = Err(e);
+                    break;
+                }
+            }
+            // Identified
+            Some(2) => {
+                let _ = tx.send(ObsEvent::Connected);
+                let _ = send(&request("GetRecordStatus"));
+                let _ = send(&request("GetStreamStatus"));
+            }
+            _ => {
+                if let Some(event) = to_event(&message) {
+                    if tx.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    stdin.lock().unwrap().take();
+    let _ = child.kill();
+    let _ = child.wait();
+    result
+}
+
+fn format_elapsed(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+fn obs_module() -> FnModFactory<ObsConfig> {
+    FnModFactory::new(
+        "obs",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<ObsConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("obs");
+            button.set_tooltip_text(Some("Click to start or stop recording"));
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+            // Hidden while OBS isn't running.
+            button.set_no_show_all(true);
+
+            let stdin: Arc<Mutex<Option<ChildStdin>>> = Arc::default();
+            button.connect_clicked(glib::clone!(@strong stdin => move |_| {
+                if let Some(s) = stdin.lock().unwrap().as_mut() {
+                    if let Err(e) = writeln!(s, "{}", request("ToggleRecord")) {
+                        eprintln!("Cannot toggle the OBS recording: {}", e);
+                    }
+                }
+            }));
+
+            let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            let url = config.url.clone();
+            let password = config.password.clone();
+            std::thread::spawn(move || {
+                let mut last_error = None;
+                let mut wait = Duration::from_secs(5);
+                loop {
+                    match run_session(&url, password.as_deref(), &tx, &stdin) {
+                        Ok(()) => {
+                            last_error = None;
+                            wait = Duration::from_secs(5);
+                        }
+                        Err(e) => {
+                            // Report an error once (e.g. websocat is not installed) and retry
+                            // less often while it persists.
+                            if last_error.as_ref() != Some(&e) {
+                                eprintln!("OBS: {}", e);
+                            } else {
+                                wait = std::cmp::min(wait * 2, Duration::from_secs(300));
+                            }
+                            last_error = Some(e);
+                        }
+                    }
+                    if tx.send(ObsEvent::Disconnected).is_err() {
+                        return;
+                    }
+                    // Reconnect when OBS starts.
+                    std::thread::sleep(wait);
+                }
+            });
+
+            // The start time of the recording and the streaming.
+            let started: Rc<RefCell<(Option<Instant>, Option<Instant>)>> = Rc::default();
+            let config = config.clone();
+            let show = Rc::new(
+                glib::clone!(@weak button, @weak label, @strong started => move || {
+                    let (recording, streaming) = *started.borrow();
+                    let mut texts = vec![];
+                    if let Some(t) = recording {
+                        texts.push(config.recording_format.replace("{elapsed}", &format_elapsed(t.elapsed())));
+                    }
+                    if let Some(t) = streaming {
+                        texts.push(config.streaming_format.replace("{elapsed}", &format_elapsed(t.elapsed())));
+                    }
+                    let sc = button.style_context();
+                    if texts.is_empty() {
+                        label.set_text("⏺");
+                        sc.remove_class("obs-active");
+                    } else {
+                        label.set_text(&texts.join(" "));
+                        sc.add_class("obs-active");
+                    }
+                }),
+            );
+            rx.attach(
+                None,
+                glib::clone!(@weak button, @weak label, @strong show => @default-return Continue(false), move |event| {
+                    let since = |active: bool, elapsed: Duration| {
+                        if active {
+                            Instant::now().checked_sub(elapsed)
+                        } else {
+                            None
+                        }
+                    };
+                    match event {
+                        ObsEvent::Connected => {
+                            button.show();
+                            label.show();
+                        }
+                        ObsEvent::Disconnected => {
+                            started.replace((None, None));
+                            button.hide();
+                        }
+                        ObsEvent::Recording(active, elapsed) => {
+                            started.borrow_mut().0 = since(active, elapsed);
+                        }
+                        ObsEvent::Streaming(active, elapsed) => {
+                            started.borrow_mut().1 = since(active, elapsed);
+                        }
+                    }
+                    show();
+                    Continue(true)
+                }),
+            );
+            let weak_button = button.downgrade();
+            glib::timeout_add_seconds_local(1, move || {
+                if weak_button.upgrade().is_none() {
+                    return Continue(false);
+                }
+                show();
+                Continue(true)
+            });
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(obs_module())]
+}
+
+crate::register_plugin!("obs", make_module_factories);
@@ -0,0 +1,284 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::icon::Icon;
+use crate::module_base::{
+    add_refresh_action, connect_notify_while_alive, poll_in_worker, FnModFactory, JSONConfigFactory,
+};
+use crate::pulseaudio::{PulseAudioState, WithState};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
+
+fn default_interval() -> u64 {
+    2
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct PrivacyConfig {
+    /// Interval in seconds to check the camera and the screencast. The microphone is updated by
+    /// PulseAudio events.
+    #[serde(default = "default_interval")]
+    interval: u64,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        PrivacyConfig {
+            interval: default_interval(),
+        }
+    }
+}
+
+/// Users of the camera and the screencast.
+#[derive(Default)]
+struct Users {
+    camera: Vec<String>,
+    screencast: Vec<String>,
+}
+
+/// Returns the names of the processes that open /dev/video*.
+///
+/// Only the processes that this user can inspect are found.
+fn camera_users() -> Vec<String> {
+    let mut ret = vec![];
+    let procs = match std::fs::read_dir("/proc") {
+        Ok(procs) => procs,
+        Err(_) => return ret,
+    };
+    for p in procs.flatten() {
+        if !p
+            .file_name()
+            .to_string_lossy()
+            .chars()
+            .all(|c| c.is_ascii_digit())
+        {
+            continue;
+        }
+        let fds = match std::fs::read_dir(p.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        let uses_camera = fds.flatten().any(|fd| {
+            std::fs::read_link(fd.path())
+                .map(|t| t.to_string_lossy().starts_with("/dev/video"))
+                .unwrap_or(false)
+        });
+        if uses_camera {
+            let comm = std::fs::read_to_string(p.path().join("comm")).unwrap_or_default();
+            ret.push(comm.trim().to_owned());
+        }
+    }
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+/// Returns the names of the running screencast streams in PipeWire.
+///
+/// Screencast portals (e.g. xdg-desktop-portal-wlr) create a video source node that is not a
+/// camera. None is returned if pw-dump is not available.
+fn pipewire_screencasts() -> Option<Vec<String>> {
+    let output = match Command::new("pw-dump").output() {
+        Ok(output) if output.status.success() => output.stdout,
+        _ => return None,
+    };
+    let objects: Vec<serde_json::Value> = serde_json::from_slice(&output).unwrap_or_default();
+    Some(
+        objects
+            .iter()
+            .filter(|o| o.get("type").and_then(|t| t.as_str()) == Some("PipeWire:Interface:Node"))
+            .filter_map(|o| o.get("info"))
+            .filter(|info| info.get("state").and_then(|s| s.as_str()) == Some("running"))
+            .filter_map(|info| info.get("props"))
+            .filter(|props| {
+                props.get("media.class").and_then(|c| c.as_str()) == Some("Video/Source")
+                    && props.get("device.api").and_then(|a| a.as_str()) != Some("v4l2")
+            })
+            .filter_map(|props| Some(props.get("node.name")?.as_str()?.to_owned()))
+            .collect(),
+    )
+}
+
+/// Returns the names of the processes that have xdg-desktop-portal sessions.
+///
+/// The sessions are exported at /org/freedesktop/portal/desktop/session/<sender>/<token>, where
+/// <sender> is the unique bus name of the app (e.g. "1_23" for ":1.23"). ScreenCast and
+/// RemoteDesktop create the sessions.
+fn portal_session_apps() -> Vec<String> {
+    let conn = match gio::bus_get_sync(gio::BusType::Session, None::<&gio::Cancellable>) {
+        Ok(conn) => conn,
+        Err(_) => return vec![],
+    };
+    let call =
+        |name: &str, path: &str, interface: &str, method: &str, params: Option<&glib::Variant>| {
+            conn.call_sync(
+                Some(name),
+                path,
+                interface,
+                method,
+                params,
+                None,
+                gio::DBusCallFlags::NONE,
+                1000,
+                None::<&gio::Cancellable>,
+            )
+            .ok()
+        };
+    let xml: String = match call(
+        "org.freedesktop.portal.Desktop",
+        "/org/freedesktop/portal/desktop/session",
+        "org.freedesktop.DBus.Introspectable",
+        "Introspect",
+        None,
+    )
+    .and_then(|v| v.child_value(0).get())
+    {
+        Some(xml) => xml,
+        None => return vec![],
+    };
+    let re = Regex::new(r#"<node name="([0-9_]+)""#).unwrap();
+    let mut ret: Vec<String> = re
+        .captures_iter(&xml)
+        .filter_map(|c| {
+            let sender = format!(":{}", c[1].replace('_', "."));
+            let pid: u32 = call(
+                "org.freedesktop.DBus",
+                "/org/freedesktop/DBus",
+                "org.freedesktop.DBus",
+                "GetConnectionUnixProcessID",
+                Some(&(sender.as_str(),).to_variant()),
+            )?
+            .child_value(0)
+            .get()?;
+            let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+            Some(comm.trim().to_owned())
+        })
+        .collect();
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+/// Returns the names of the screencast users.
+///
+/// The running streams in PipeWire tell whether the screen is captured, and the portal sessions
+/// tell which apps capture it. Without pw-dump, the portal sessions are used alone.
+fn screencast_users() -> Vec<String> {
+    let apps = portal_session_apps();
+    match pipewire_screencasts() {
+        Some(streams) if streams.is_empty() => vec![],
+        Some(streams) if apps.is_empty() => streams,
+        _ => apps,
+    }
+}
+
+/// Creates an indicator icon that is shown only while active.
+fn indicator(hbox: &gtk::Box, name: &str, class: &str) -> Icon {
+    let icon = Icon::new(name);
+    icon.widget().style_context().add_class(class);
+    icon.widget().set_no_show_all(true);
+    hbox.add(icon.widget());
+    icon
+}
+
+/// Shows or hides the indicator with the users in the tooltip.
+fn update_indicator(icon: &Icon, what: &str, users: &[String]) {
+    let widget = icon.widget();
+    widget.set_visible(!users.is_empty());
+    widget.set_tooltip_text(Some(&format!("{}: {}", what, users.join(", "))));
+}
+
+/// Sets the "privacy-active" class if any indicator is shown.
+fn update_active(hbox: &gtk::Box) {
+    let sc = hbox.style_context();
+    if hbox.children().iter().any(|c| c.is_visible()) {
+        sc.add_class("privacy-active");
+    } else {
+        sc.remove_class("privacy-active");
+    }
+}
+
+fn privacy_module(state: Rc<PulseAudioState>) -> FnModFactory<PrivacyConfig> {
+    FnModFactory::new(
+        "privacy",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<PrivacyConfig>, container: &gtk::Box| {
+            let state = state.clone();
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            hbox.style_context().add_class("privacy");
+            let mic = indicator(&hbox, "mic", "privacy-mic");
+            let camera = indicator(&hbox, "camera", "privacy-camera");
+            let screencast = indicator(&hbox, "screenshare", "privacy-screencast");
+            // gtk::Box doesn't receive button events.
+            let event_box = gtk::EventBox::new();
+            event_box.add(&hbox);
+            container.add(&event_box);
+
+            connect_notify_while_alive(
+                &*state,
+                container,
+                None,
+                glib::clone!(@weak hbox => move |state, _| {
+                    let sources = state.sources();
+                    let mut users: Vec<String> = state
+                        .source_outputs()
+                        .into_iter()
+                        .map(|(_, o)| o)
+                        // Level meters and recorders of the speaker output are not the microphone.
+                        .filter(|o| {
+                            sources
+                                .get(&o.source)
+                                .map(|s| !s.is_monitor)
+                                .unwrap_or(true)
+                        })
+                        .map(|o| o.application)
+                        .collect();
+                    users.sort();
+                    users.dedup();
+                    update_indicator(&mic, "Microphone", &users);
+                    update_active(&hbox);
+                }),
+            );
+
+            let poller = poll_in_worker(
+                Duration::from_secs(config.interval),
+                || Users {
+                    camera: camera_users(),
+                    screencast: screencast_users(),
+                },
+                glib::clone!(@weak hbox => @default-return Continue(false), move |users| {
+                    update_indicator(&camera, "Camera", &users.camera);
+                    update_indicator(&screencast, "Screencast", &users.screencast);
+                    update_active(&hbox);
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(container, &event_box, move || poller.refresh());
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(WithState::new("privacy", privacy_module))]
+}
+
+crate::register_plugin!("privacy", make_module_factories);
@@ -0,0 +1,86 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{locale, FnModFactory, JSONConfigFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
+struct ProfileSwitcherConfig {
+    /// Label of the base config without a profile. Defaults to "Default".
+    #[serde(default)]
+    default_label: Option<String>,
+}
+
+/// Adds a button to switch to the profile. None is the base config.
+fn add_item(list: &gtk::Box, label: &str, profile: Option<String>, popover: &gtk::Popover) {
+    let button = gtk::Button::with_label(label);
+    button.set_relief(gtk::ReliefStyle::None);
+    if crate::profile::current() == profile {
+        button.style_context().add_class("profile-active");
+    }
+    button.connect_clicked(glib::clone!(@weak popover => move |_| {
+        popover.popdown();
+        if let Err(e) = crate::profile::switch(profile.as_deref()) {
+            eprintln!("Cannot switch the profile: {}", e);
+        }
+    }));
+    list.add(&button);
+}
+
+/// Shows the active profile and switches the profiles from the popover.
+///
+/// The bars are rebuilt on a switch, so the label is not updated.
+fn profile_switcher_module() -> FnModFactory<ProfileSwitcherConfig> {
+    FnModFactory::new(
+        "profile-switcher",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(
+            move |config: &Rc<ProfileSwitcherConfig>, container: &gtk::Box| {
+                let default_label = config
+                    .default_label
+                    .clone()
+                    .unwrap_or_else(|| locale::tr("Default"));
+                let current = crate::profile::current();
+                let button = gtk::Button::with_label(current.as_deref().unwrap_or(&default_label));
+                button.set_relief(gtk::ReliefStyle::None);
+                button.style_context().add_class("profile-switcher");
+                container.add(&button);
+
+                let popover = gtk::Popover::new(Some(&button));
+                let list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+                add_item(&list, &default_label, None, &popover);
+                for name in crate::profile::names() {
+                    add_item(&list, &name, Some(name.clone()), &popover);
+                }
+                popover.add(&list);
+                button.connect_clicked(move |_| {
+                    popover.show_all();
+                    popover.popup();
+                });
+            },
+        ),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(profile_switcher_module())]
+}
+
+crate::register_plugin!("profile", make_module_factories);
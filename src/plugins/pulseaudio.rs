@@ -12,58 +12,264 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::module_base::{FnModFactory, JSONConfigFactory};
-use crate::pulseaudio::PulseAudioState;
+use crate::bluetooth::BluetoothState;
+use crate::module_base::device_query::{DeviceQuery, Fields};
+use crate::module_base::gauge::{Gauge, ValueStyle};
+use crate::module_base::icon::Icon;
+use crate::module_base::locale;
+use crate::module_base::template::Template;
+use crate::module_base::touch;
+use crate::module_base::{
+    connect_notify_while_alive, set_accessible, FnModFactory, JSONConfigFactory, StateStore,
+};
+use crate::pulseaudio::sink::SinkState;
+use crate::pulseaudio::source::SourceState;
+use crate::pulseaudio::{PulseAudioState, WithState};
 use gtk::glib;
 use gtk::prelude::*;
+use pulse::volume::{ChannelVolumes, Volume};
+use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::process::{Command, Stdio};
 use std::rc::Rc;
 
+fn default_volume_format() -> String {
+    format!("{{volume}}%{{muted: {}}}", locale::tr("(muted)"))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct VolumeToggleConfig {
+    /// Show an icon for the volume level before the volume.
+    #[serde(default)]
+    show_icon: bool,
+
+    /// "text" (default) or "ring".
+    #[serde(default)]
+    style: ValueStyle,
+
+    /// Format of the label for the "text" style with "{volume}" and "{muted}". See
+    /// [`crate::module_base::template`] for the syntax.
+    #[serde(default = "default_volume_format")]
+    format: String,
+}
+
+impl Default for VolumeToggleConfig {
+    fn default() -> Self {
+        VolumeToggleConfig {
+            show_icon: false,
+            style: ValueStyle::default(),
+            format: default_volume_format(),
+        }
+    }
+}
+
+/// Returns the percentage of the volume.
+pub(crate) fn volume_percentage(volume: &ChannelVolumes) -> f64 {
+    // From pa_volume_snprint_verbose.
+    (volume.max().0 as f64) * 100.0 / (Volume::NORMAL.0 as f64)
+}
+
+/// Returns the semantic icon name for the sink volume.
+pub(crate) fn sink_icon_name(volume: &ChannelVolumes, mute: bool) -> &'static str {
+    let percentage = volume_percentage(volume);
+    if mute || percentage < 0.5 {
+        "volume-muted"
+    } else if percentage < 33.0 {
+        "volume-low"
+    } else if percentage < 66.0 {
+        "volume-medium"
+    } else {
+        "volume-high"
+    }
+}
+
+/// Opens the volume control application.
+fn open_pavucontrol() {
+    if let Err(e) = Command::new("pavucontrol")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        eprintln!("Cannot start pavucontrol: {}", e);
+    }
+}
+
+/// Pixels of a horizontal touch pan per volume step.
+const PAN_STEP: f64 = 8.0;
+
+/// Volume change in percent per step of a touch pan.
+const PAN_VOLUME_STEP: f64 = 2.0;
+
+/// Makes a horizontal touch pan on the widget adjust the volume. `adjustment` returns a connected
+/// adjustment of the device (e.g. [`SinkState::adjustment`]) when the pan starts.
+fn connect_volume_pan<W, F>(widget: &W, adjustment: F)
+where
+    W: IsA<gtk::Widget>,
+    F: Fn() -> Option<gtk::Adjustment> + 'static,
+{
+    // The adjustment and the volume at the start of the pan.
+    let current: Rc<RefCell<Option<(gtk::Adjustment, f64)>>> = Rc::default();
+    touch::connect_horizontal_pan(
+        widget,
+        PAN_STEP,
+        glib::clone!(@strong current => move || {
+            current.replace(adjustment().map(|a| {
+                let value = a.value();
+                (a, value)
+            }));
+        }),
+        move |steps| {
+            if let Some((ref adjustment, start)) = *current.borrow() {
+                adjustment.set_value(start + steps as f64 * PAN_VOLUME_STEP);
+            }
+        },
+    );
+}
+
+/// Returns the battery percentage of the Bluetooth device of the sink if reported.
+fn sink_battery(sink: &SinkState) -> Option<u8> {
+    let address = sink.bluetooth_address.as_ref()?;
+    BluetoothState::shared().find_by_address(address)?.battery
+}
+
+/// Appends the battery percentage of the Bluetooth device to the sink name.
+fn with_battery(name: &str, sink: &SinkState) -> String {
+    match sink_battery(sink) {
+        Some(battery) => format!("{} ({}%)", name, battery),
+        None => name.to_owned(),
+    }
+}
+
+/// The widgets in a volume toggle button.
+struct VolumeToggleContent {
+    button: gtk::Button,
+    /// Announced by screen readers before the level (e.g. "Microphone").
+    accessible_name: String,
+    icon: Icon,
+    label: gtk::Label,
+    gauge: Option<Gauge>,
+    template: Template,
+}
+
+impl VolumeToggleContent {
+    /// Shows the volume.
+    fn set(&self, volume: &ChannelVolumes, mute: bool) {
+        let percentage = volume_percentage(volume);
+        let name = if mute {
+            format!(
+                "{} {:.0}%, {}",
+                self.accessible_name,
+                percentage,
+                locale::tr("muted")
+            )
+        } else {
+            format!("{} {:.0}%", self.accessible_name, percentage)
+        };
+        set_accessible(&self.button, &name, None);
+        match self.gauge {
+            Some(ref gauge) => {
+                if mute {
+                    gauge.set(0.0, "-");
+                } else {
+                    gauge.set(percentage, &format!("{:.0}", percentage));
+                }
+            }
+            None => self.label.set_text(
+                &self
+                    .template
+                    .render(&[("volume", percentage.into()), ("muted", mute.into())]),
+            ),
+        }
+    }
+}
+
+/// Creates the content of a volume toggle button.
+fn volume_toggle_content(
+    button: &gtk::Button,
+    config: &VolumeToggleConfig,
+    accessible_name: &str,
+) -> VolumeToggleContent {
+    let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    let icon = Icon::new("volume-muted");
+    let label = gtk::Label::new(None);
+    if config.show_icon {
+        hbox.add(icon.widget());
+    }
+    let gauge = if config.style == ValueStyle::Ring {
+        let gauge = Gauge::new();
+        hbox.add(gauge.widget());
+        Some(gauge)
+    } else {
+        hbox.add(&label);
+        None
+    };
+    button.add(&hbox);
+    VolumeToggleContent {
+        button: button.clone(),
+        accessible_name: accessible_name.to_owned(),
+        icon,
+        label,
+        gauge,
+        template: Template::new(&config.format),
+    }
+}
+
 fn default_source_volume_toggle_module(
     state: Rc<PulseAudioState>,
-) -> FnModFactory<serde_json::Value> {
+) -> FnModFactory<VolumeToggleConfig> {
     FnModFactory::new(
         "pulseaudio-default-source-volume-toggle",
         Box::new(JSONConfigFactory::default()),
-        Box::new(move |_, container: &gtk::Box| {
-            let button = gtk::Button::new();
-            button.set_relief(gtk::ReliefStyle::None);
-            button
-                .style_context()
-                .add_class("default-source-volume-toggle");
+        Box::new(
+            move |config: &Rc<VolumeToggleConfig>, container: &gtk::Box| {
+                let button = gtk::Button::new();
+                button.set_relief(gtk::ReliefStyle::None);
+                let content = volume_toggle_content(&button, config, &locale::tr("Microphone"));
+                button
+                    .style_context()
+                    .add_class("default-source-volume-toggle");
 
-            let state = state.clone();
-            button.connect_button_release_event(
+                let state = state.clone();
+                button.connect_button_release_event(
                     glib::clone!(@weak state => @default-return Inhibit(false), move |_, e| {
                         if e.button() == gtk::gdk::BUTTON_PRIMARY {
                             state.default_source().map(|s| s.toggle_mute());
                         } else if e.button() == gtk::gdk::BUTTON_SECONDARY {
-                            Command::new("pavucontrol").stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn().unwrap();
+                            open_pavucontrol();
                         }
                         return Inhibit(true);
                     }),
                 );
+                connect_volume_pan(
+                    &button,
+                    glib::clone!(@weak state => @default-return None, move || {
+                        state.default_source().map(|s| s.adjustment())
+                    }),
+                );
+                touch::connect_long_press(&button, |_| open_pavucontrol());
 
-            state.connect_notify_local(
-                None,
-                glib::clone!(@weak button => move |state, _| {
-                    if let Some(source) = state.default_source() {
-                        button.set_sensitive(true);
-                        let mut s = source.volume.max().print();
-                        if source.mute {
-                            s += " (muted)"
+                connect_notify_while_alive(
+                    &*state,
+                    container,
+                    None,
+                    glib::clone!(@weak button => move |state, _| {
+                        if let Some(source) = state.default_source() {
+                            button.set_sensitive(true);
+                            content.set(&source.volume, source.mute);
+                            content.icon.set(if source.mute { "mic-muted" } else { "mic" });
+                        } else {
+                            button.set_sensitive(false);
                         }
-                        button.set_label(&s);
-                    } else {
-                        button.set_sensitive(false);
-                    }
-                }),
-            );
+                    }),
+                );
 
-            container.add(&button);
-        }),
+                container.add(&button);
+            },
+        ),
     )
 }
 
@@ -81,7 +287,9 @@ fn default_source_volume_module(state: Rc<PulseAudioState>) -> FnModFactory<serd
                 .add_class("pulseaudio-default-source-volume");
             container.add(&scale);
 
-            state.connect_notify_local(
+            connect_notify_while_alive(
+                &*state,
+                container,
                 None,
                 glib::clone!(@weak scale => move |state, _| {
                     if let Some(adjustment) = state.default_source().map(|s| s.adjustment()) {
@@ -94,15 +302,184 @@ fn default_source_volume_module(state: Rc<PulseAudioState>) -> FnModFactory<serd
     )
 }
 
-#[derive(Serialize, Deserialize, Default)]
+/// Fields of sinks and sources for the `filter` and `sort` options.
+const DEVICE_FIELDS: &[&str] = &["name", "description", "form_factor"];
+
+impl Fields for SinkState {
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "name" => Some(self.name.clone()),
+            "description" => Some(self.description.clone()),
+            "form_factor" => Some(self.form_factor.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Fields for SourceState {
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "name" => Some(self.name.clone()),
+            "description" => Some(self.description.clone()),
+            "form_factor" => Some(self.form_factor.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the `filter` and `sort` options. Invalid options are logged and ignored.
+fn device_query(module: &str, filter: &Option<String>, sort: &Option<String>) -> DeviceQuery {
+    DeviceQuery::new(filter.as_deref(), sort.as_deref(), DEVICE_FIELDS).unwrap_or_else(|e| {
+        eprintln!("{}: invalid filter or sort: {}", module, e);
+        DeviceQuery::default()
+    })
+}
+
+/// Compiles a device name pattern. The regular expression must match the whole name, so that a
+/// plain device name (e.g. "alsa_output.usb-foo") doesn't match other devices.
+fn name_regex(pattern: &str) -> Option<Regex> {
+    Regex::new(&format!("^(?:{})$", pattern)).ok()
+}
+
+/// Returns true if the pattern (a device name or a regular expression) matches the device name.
+fn matches_name(pattern: &str, name: &str) -> bool {
+    pattern == name || name_regex(pattern).map_or(false, |r| r.is_match(name))
+}
+
+/// The nicknames in a config, compiled once for the module.
+struct Nicknames {
+    exact: HashMap<String, String>,
+    /// Sorted by the key.
+    patterns: Vec<(Regex, String)>,
+}
+
+impl Nicknames {
+    fn new(nicknames: &HashMap<String, String>) -> Nicknames {
+        let mut keys: Vec<&String> = nicknames.keys().collect();
+        keys.sort();
+        Nicknames {
+            exact: nicknames.clone(),
+            patterns: keys
+                .into_iter()
+                .filter_map(|k| Some((name_regex(k)?, nicknames[k].clone())))
+                .collect(),
+        }
+    }
+
+    /// Returns the nickname of the device.
+    ///
+    /// The keys are device names or regular expressions. An exact name match takes precedence.
+    /// Otherwise, the first matching regular expression in the sorted key order is used, and "$1"
+    /// etc. in the nickname are replaced with the captured groups.
+    fn get(&self, name: &str) -> Option<String> {
+        if let Some(nickname) = self.exact.get(name) {
+            return Some(nickname.clone());
+        }
+        self.patterns.iter().find_map(|(regex, nickname)| {
+            let captures = regex.captures(name)?;
+            let mut expanded = String::new();
+            captures.expand(nickname, &mut expanded);
+            Some(expanded)
+        })
+    }
+}
+
+/// Device name patterns (e.g. `exclude`), compiled once for the module.
+struct NamePatterns(Vec<(String, Option<Regex>)>);
+
+impl NamePatterns {
+    fn new(patterns: &[String]) -> NamePatterns {
+        NamePatterns(
+            patterns
+                .iter()
+                .map(|p| (p.clone(), name_regex(p)))
+                .collect(),
+        )
+    }
+
+    /// Returns true if the device name matches any of the patterns.
+    fn matches(&self, name: &str) -> bool {
+        self.0
+            .iter()
+            .any(|(p, r)| p == name || r.as_ref().map_or(false, |r| r.is_match(name)))
+    }
+}
+
+/// Creates a menu item with a volume slider for a device.
+fn slider_item(adjustment: &gtk::Adjustment) -> gtk::MenuItem {
+    let item = gtk::MenuItem::new();
+    let scale = gtk::Scale::new(gtk::Orientation::Horizontal, Some(adjustment));
+    scale.set_draw_value(false);
+    scale.set_width_request(150);
+    item.add(&scale);
+    item.style_context().add_class("pulseaudio-menu-slider");
+    item
+}
+
+/// Returns the names of the sinks.
+fn sink_names(state: &PulseAudioState) -> Vec<String> {
+    state.sinks().values().map(|s| s.name.clone()).collect()
+}
+
+/// Registers a lint rule for the device names in the config that don't match any device.
+///
+/// `what` describes the names in the messages (e.g. "nickname for"). `devices` returns the names
+/// of the existing devices.
+fn register_device_lint<F: Fn() -> Vec<String> + 'static>(
+    module: &'static str,
+    kind: &'static str,
+    what: &'static str,
+    names: Vec<String>,
+    devices: F,
+) {
+    if names.is_empty() {
+        return;
+    }
+    crate::config::lint::register(move || {
+        let devices = devices();
+        names
+            .iter()
+            // The names can be regular expressions (e.g. nickname keys).
+            .filter(|n| !devices.iter().any(|d| matches_name(n, d)))
+            .map(|n| {
+                format!(
+                    "{}: {} \"{}\" doesn't match any {}. Use one of {:?}",
+                    module, what, n, kind, devices
+                )
+            })
+            .collect()
+    });
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
 struct DefaultSourceSelectorConfig {
     /// Nicknames for sources.
     ///
     /// By default, the widget shows the source descriptions. However, this might be too long for
     /// the menu bar. This allows mapping from a name (e.g. "alsa_input.usb-foo-bar.analog-stereo")
-    /// to a name of your choice.
+    /// to a name of your choice. The key can be a regular expression that matches the whole name,
+    /// and the nickname can refer to its groups (e.g. "alsa_input\\.usb-(\\w+)-.*" to "USB $1").
     #[serde(default)]
     nicknames: HashMap<String, String>,
+
+    /// Devices not shown in the menu. Each entry is a device name or a regular expression that
+    /// matches the whole name (e.g. ".*hdmi.*").
+    #[serde(default)]
+    exclude: Vec<String>,
+
+    /// Show a volume slider under each device in the menu.
+    #[serde(default)]
+    show_sliders: bool,
+
+    /// Filter expression for the menu items (e.g. "form_factor != 'webcam'"). The fields are
+    /// "name", "description", and "form_factor". See [`crate::module_base::device_query`].
+    #[serde(default)]
+    filter: Option<String>,
+
+    /// Sort expression for the menu items (e.g. "form_factor,description"). By default, the items
+    /// are in the PulseAudio index order.
+    #[serde(default)]
+    sort: Option<String>,
 }
 
 fn default_source_selector_module(
@@ -116,30 +493,55 @@ fn default_source_selector_module(
                 let button = gtk::Button::new();
                 button.set_relief(gtk::ReliefStyle::None);
                 container.add(&button);
+                let query = device_query(
+                    "pulseaudio-default-source-selector",
+                    &config.filter,
+                    &config.sort,
+                );
+                let nicknames = Rc::new(Nicknames::new(&config.nicknames));
+                let exclude = NamePatterns::new(&config.exclude);
+
+                {
+                    let state = state.clone();
+                    register_device_lint(
+                        "pulseaudio-default-source-selector",
+                        "source",
+                        "nickname for",
+                        config.nicknames.keys().cloned().collect(),
+                        move || {
+                            state
+                                .sources()
+                                .values()
+                                .filter(|s| !s.is_monitor)
+                                .map(|s| s.name.clone())
+                                .collect()
+                        },
+                    );
+                }
 
                 {
                     let state = state.clone();
                     let config = config.clone();
+                    let nicknames = nicknames.clone();
                     button.connect_button_release_event(glib::clone!(@weak button, @weak state => @default-return Inhibit(false), move |_, e| {
                         if e.button() == gtk::gdk::BUTTON_PRIMARY {
                             let menu = gtk::Menu::new();
-                            for (_, ref source) in state.sources() {
-                                if source.is_monitor {
-                                    continue
-                                }
+                            let sources = state.sources().into_iter().map(|(_, s)| s).filter(|s| !s.is_monitor && !exclude.matches(&s.name)).collect();
+                            for ref source in query.apply(sources) {
                                 let name = source.name.to_string();
-                                let mut shown_name = &source.description;
-                                for (ref k, ref nickname) in &config.nicknames {
-                                    if k.as_str() == name.as_str() {
-                                        shown_name = nickname;
-                                    }
-                                }
+                                let shown_name = nicknames.get(&name).unwrap_or_else(|| source.description.clone());
 
-                                let item = gtk::MenuItem::with_label(shown_name);
+                                let item = gtk::CheckMenuItem::with_label(&shown_name);
+                                item.set_draw_as_radio(true);
+                                // Set before connecting so that this doesn't change the default.
+                                item.set_active(name == state.default_source_name());
                                 item.connect_activate(glib::clone!(@weak state => move |_| {
                                     state.set_default_source(&name);
                                 }));
                                 menu.append(&item);
+                                if config.show_sliders {
+                                    menu.append(&slider_item(&source.adjustment()));
+                                }
                             }
                             menu.show_all();
                             menu.popup_at_widget(&button, gtk::gdk::Gravity::South, gtk::gdk::Gravity::North, None);
@@ -149,21 +551,16 @@ fn default_source_selector_module(
                     }));
                 }
 
-                {
-                    let config = config.clone();
-                    state.connect_notify_local(
-                        None,
-                        glib::clone!(@weak button => move |state, _| {
-                            if let Some(source) = state.default_source() {
-                                if let Some(ref nickname) = config.nicknames.get(&source.name) {
-                                    button.set_label(nickname);
-                                    return;
-                                }
-                                button.set_label(&source.description);
-                            }
-                        }),
-                    );
-                }
+                connect_notify_while_alive(
+                    &*state,
+                    container,
+                    None,
+                    glib::clone!(@weak button => move |state, _| {
+                        if let Some(source) = state.default_source() {
+                            button.set_label(&nicknames.get(&source.name).unwrap_or(source.description));
+                        }
+                    }),
+                );
             },
         ),
     )
@@ -171,43 +568,66 @@ fn default_source_selector_module(
 
 fn default_sink_volume_toggle_module(
     state: Rc<PulseAudioState>,
-) -> FnModFactory<serde_json::Value> {
+) -> FnModFactory<VolumeToggleConfig> {
     FnModFactory::new(
         "pulseaudio-default-sink-volume-toggle",
         Box::new(JSONConfigFactory::default()),
-        Box::new(move |_, container: &gtk::Box| {
-            let button = gtk::Button::new();
-            button.set_relief(gtk::ReliefStyle::None);
-            container.add(&button);
+        Box::new(
+            move |config: &Rc<VolumeToggleConfig>, container: &gtk::Box| {
+                let button = gtk::Button::new();
+                button.set_relief(gtk::ReliefStyle::None);
+                let content = volume_toggle_content(&button, config, &locale::tr("Volume"));
+                container.add(&button);
 
-            let state = state.clone();
-            button.connect_button_release_event(
-                glib::clone!(@weak state => @default-return Inhibit(false), move |_, e| {
-                    if e.button() == gtk::gdk::BUTTON_PRIMARY {
-                        state.default_sink().map(|s| s.toggle_mute());
-                    } else if e.button() == gtk::gdk::BUTTON_SECONDARY {
-                        Command::new("pavucontrol").stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn().unwrap();
-                    }
-                    return Inhibit(true);
-                }),
-            );
+                // Computed on demand so that the battery level is up to date.
+                button.set_has_tooltip(true);
+                button.connect_query_tooltip(
+                    glib::clone!(@weak state => @default-return false, move |_, _, _, _, tooltip| {
+                        match state.default_sink() {
+                            Some(sink) => {
+                                tooltip.set_text(Some(&with_battery(&sink.description, &sink)));
+                                true
+                            }
+                            None => false,
+                        }
+                    }),
+                );
 
-            state.connect_notify_local(
-                None,
-                glib::clone!(@weak button => move |state, _| {
-                    if let Some(sink) = state.default_sink() {
-                        button.set_sensitive(true);
-                        let mut s = sink.volume.max().print();
-                        if sink.mute {
-                            s += " (muted)"
+                let state = state.clone();
+                button.connect_button_release_event(
+                    glib::clone!(@weak state => @default-return Inhibit(false), move |_, e| {
+                        if e.button() == gtk::gdk::BUTTON_PRIMARY {
+                            state.default_sink().map(|s| s.toggle_mute());
+                        } else if e.button() == gtk::gdk::BUTTON_SECONDARY {
+                            open_pavucontrol();
                         }
-                        button.set_label(&s);
-                    } else {
-                        button.set_sensitive(false);
-                    }
-                }),
-            );
-        }),
+                        return Inhibit(true);
+                    }),
+                );
+                connect_volume_pan(
+                    &button,
+                    glib::clone!(@weak state => @default-return None, move || {
+                        state.default_sink().map(|s| s.adjustment())
+                    }),
+                );
+                touch::connect_long_press(&button, |_| open_pavucontrol());
+
+                connect_notify_while_alive(
+                    &*state,
+                    container,
+                    None,
+                    glib::clone!(@weak button => move |state, _| {
+                        if let Some(sink) = state.default_sink() {
+                            button.set_sensitive(true);
+                            content.set(&sink.volume, sink.mute);
+                            content.icon.set(sink_icon_name(&sink.volume, sink.mute));
+                        } else {
+                            button.set_sensitive(false);
+                        }
+                    }),
+                );
+            },
+        ),
     )
 }
 
@@ -222,7 +642,9 @@ fn default_sink_volume_module(state: Rc<PulseAudioState>) -> FnModFactory<serde_
             scale.set_draw_value(false);
             container.add(&scale);
 
-            state.connect_notify_local(
+            connect_notify_while_alive(
+                &*state,
+                container,
                 None,
                 glib::clone!(@weak scale => move |state, _| {
                     if let Some(adjustment) = state.default_sink().map(|s| s.adjustment()) {
@@ -235,15 +657,35 @@ fn default_sink_volume_module(state: Rc<PulseAudioState>) -> FnModFactory<serde_
     )
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
 struct DefaultSinkSelectorConfig {
     /// Nicknames for sinks.
     ///
     /// By default, the widget shows the sink descriptions. However, this might be too long for
     /// the menu bar. This allows mapping from a name (e.g.
-    /// "alsa_output.usb-foo-bar.analog-stereo") to a name of your choice.
+    /// "alsa_output.usb-foo-bar.analog-stereo") to a name of your choice. The key can be a
+    /// regular expression as in the pulseaudio-default-source-selector module.
     #[serde(default)]
     nicknames: HashMap<String, String>,
+
+    /// Devices not shown in the menu. Each entry is a device name or a regular expression that
+    /// matches the whole name (e.g. ".*hdmi.*").
+    #[serde(default)]
+    exclude: Vec<String>,
+
+    /// Show a volume slider under each device in the menu.
+    #[serde(default)]
+    show_sliders: bool,
+
+    /// Filter expression for the menu items (e.g. "form_factor != 'webcam'"). The fields are
+    /// "name", "description", and "form_factor". See [`crate::module_base::device_query`].
+    #[serde(default)]
+    filter: Option<String>,
+
+    /// Sort expression for the menu items (e.g. "form_factor,description"). By default, the items
+    /// are in the PulseAudio index order.
+    #[serde(default)]
+    sort: Option<String>,
 }
 
 fn default_sink_selector_module(
@@ -257,27 +699,48 @@ fn default_sink_selector_module(
                 let button = gtk::Button::new();
                 button.set_relief(gtk::ReliefStyle::None);
                 container.add(&button);
+                let query = device_query(
+                    "pulseaudio-default-sink-selector",
+                    &config.filter,
+                    &config.sort,
+                );
+                let nicknames = Rc::new(Nicknames::new(&config.nicknames));
+                let exclude = NamePatterns::new(&config.exclude);
+
+                {
+                    let state = state.clone();
+                    register_device_lint(
+                        "pulseaudio-default-sink-selector",
+                        "sink",
+                        "nickname for",
+                        config.nicknames.keys().cloned().collect(),
+                        move || sink_names(&state),
+                    );
+                }
 
                 {
                     let state = state.clone();
                     let config = config.clone();
+                    let nicknames = nicknames.clone();
                     button.connect_button_release_event(glib::clone!(@weak button => @default-return Inhibit(false), move |_, e| {
                         if e.button() == gtk::gdk::BUTTON_PRIMARY {
                             let menu = gtk::Menu::new();
-                            for (_, ref sink) in state.sinks() {
+                            let sinks = state.sinks().into_iter().map(|(_, s)| s).filter(|s| !exclude.matches(&s.name)).collect();
+                            for ref sink in query.apply(sinks) {
                                 let name = sink.name.to_string();
-                                let mut shown_name = &sink.description;
-                                for (ref k, ref nickname) in &config.nicknames {
-                                    if k.as_str() == name.as_str() {
-                                        shown_name = nickname;
-                                    }
-                                }
+                                let shown_name = nicknames.get(&name).unwrap_or_else(|| sink.description.clone());
 
-                                let item = gtk::MenuItem::with_label(shown_name);
+                                let item = gtk::CheckMenuItem::with_label(&with_battery(&shown_name, sink));
+                                item.set_draw_as_radio(true);
+                                // Set before connecting so that this doesn't change the default.
+                                item.set_active(name == state.default_sink_name());
                                 item.connect_activate(glib::clone!(@weak state => move |_| {
                                     state.set_default_sink(&name);
                                 }));
                                 menu.append(&item);
+                                if config.show_sliders {
+                                    menu.append(&slider_item(&sink.adjustment()));
+                                }
                             }
                             menu.show_all();
                             menu.popup_at_widget(&button, gtk::gdk::Gravity::South, gtk::gdk::Gravity::North, None);
@@ -287,37 +750,472 @@ fn default_sink_selector_module(
                     }));
                 }
 
-                {
-                    let config = config.clone();
-                    state.connect_notify_local(
-                        None,
-                        glib::clone!(@weak button => move |state, _| {
-                            if let Some(sink) = state.default_sink() {
-                                if let Some(ref nickname) = config.nicknames.get(&sink.name) {
-                                    button.set_label(nickname);
-                                    return;
-                                }
-                                button.set_label(&sink.description);
-                            }
-                        }),
-                    );
-                }
+                connect_notify_while_alive(
+                    &*state,
+                    container,
+                    None,
+                    glib::clone!(@weak button => move |state, _| {
+                        if let Some(sink) = state.default_sink() {
+                            button.set_label(&nicknames.get(&sink.name).unwrap_or(sink.description));
+                        }
+                    }),
+                );
             },
         ),
     )
 }
 
+fn default_show_volume() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct CombinedConfig {
+    /// Show the output volume between the icons.
+    #[serde(default = "default_show_volume")]
+    show_volume: bool,
+
+    /// Nicknames for sinks. See the pulseaudio-default-sink-selector module.
+    #[serde(default)]
+    sink_nicknames: HashMap<String, String>,
+
+    /// Nicknames for sources. See the pulseaudio-default-source-selector module.
+    #[serde(default)]
+    source_nicknames: HashMap<String, String>,
+}
+
+impl Default for CombinedConfig {
+    fn default() -> Self {
+        CombinedConfig {
+            show_volume: default_show_volume(),
+            sink_nicknames: HashMap::new(),
+            source_nicknames: HashMap::new(),
+        }
+    }
+}
+
+/// Widgets in the popover of the combined module.
+struct CombinedPopover {
+    sink_combo: gtk::ComboBoxText,
+    sink_adjustment: gtk::Adjustment,
+    source_combo: gtk::ComboBoxText,
+    source_adjustment: gtk::Adjustment,
+    sink_nicknames: Nicknames,
+    source_nicknames: Nicknames,
+    /// The (name, shown name) of the devices in the combo boxes.
+    sink_entries: RefCell<Vec<(String, String)>>,
+    source_entries: RefCell<Vec<(String, String)>>,
+}
+
+impl CombinedPopover {
+    fn new(popover: &gtk::Popover, config: &CombinedConfig) -> CombinedPopover {
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(4);
+        grid.set_column_spacing(8);
+        let new_scale = |adjustment: &gtk::Adjustment| {
+            let scale = gtk::Scale::new(gtk::Orientation::Horizontal, Some(adjustment));
+            scale.set_width_request(150);
+            scale.set_draw_value(false);
+            scale
+        };
+        let new_adjustment = || gtk::Adjustment::new(0.0, 0.0, 100.0, 0.0, 0.0, 0.0);
+        let ret = CombinedPopover {
+            sink_combo: gtk::ComboBoxText::new(),
+            sink_adjustment: new_adjustment(),
+            source_combo: gtk::ComboBoxText::new(),
+            source_adjustment: new_adjustment(),
+            sink_nicknames: Nicknames::new(&config.sink_nicknames),
+            source_nicknames: Nicknames::new(&config.source_nicknames),
+            sink_entries: RefCell::default(),
+            source_entries: RefCell::default(),
+        };
+        let output = gtk::Label::new(Some("Output"));
+        output.set_xalign(0.0);
+        grid.attach(&output, 0, 0, 1, 1);
+        grid.attach(&ret.sink_combo, 1, 0, 1, 1);
+        grid.attach(&new_scale(&ret.sink_adjustment), 1, 1, 1, 1);
+        let input = gtk::Label::new(Some("Input"));
+        input.set_xalign(0.0);
+        grid.attach(&input, 0, 2, 1, 1);
+        grid.attach(&ret.source_combo, 1, 2, 1, 1);
+        grid.attach(&new_scale(&ret.source_adjustment), 1, 3, 1, 1);
+        popover.add(&grid);
+        ret
+    }
+
+    /// Updates the widgets with the current state. The combo boxes are rebuilt only when the
+    /// devices change.
+    fn update(&self, state: &PulseAudioState) {
+        let sinks = state
+            .sinks()
+            .into_values()
+            .map(|sink| {
+                let shown_name = self
+                    .sink_nicknames
+                    .get(&sink.name)
+                    .unwrap_or_else(|| sink.description.clone());
+                let shown_name = with_battery(&shown_name, &sink);
+                (sink.name, shown_name)
+            })
+            .collect();
+        update_combo(
+            &self.sink_combo,
+            &self.sink_entries,
+            sinks,
+            &state.default_sink_name(),
+        );
+        if let Some(sink) = state.default_sink() {
+            set_adjustment_value(&self.sink_adjustment, volume_percentage(&sink.volume));
+        }
+
+        let sources = state
+            .sources()
+            .into_values()
+            .filter(|source| !source.is_monitor)
+            .map(|source| {
+                let shown_name = self
+                    .source_nicknames
+                    .get(&source.name)
+                    .unwrap_or_else(|| source.description.clone());
+                (source.name, shown_name)
+            })
+            .collect();
+        update_combo(
+            &self.source_combo,
+            &self.source_entries,
+            sources,
+            &state.default_source_name(),
+        );
+        if let Some(source) = state.default_source() {
+            set_adjustment_value(&self.source_adjustment, volume_percentage(&source.volume));
+        }
+    }
+}
+
+/// Replaces the entries of the combo box if they are changed, and selects the active one.
+fn update_combo(
+    combo: &gtk::ComboBoxText,
+    current: &RefCell<Vec<(String, String)>>,
+    entries: Vec<(String, String)>,
+    active: &str,
+) {
+    if *current.borrow() != entries {
+        combo.remove_all();
+        for (name, shown_name) in &entries {
+            combo.append(Some(name), shown_name);
+        }
+        current.replace(entries);
+    }
+    if combo.active_id().as_deref() != Some(active) {
+        combo.set_active_id(Some(active));
+    }
+}
+
+/// Sets the value without moving the slider under the user's drag for a rounding difference.
+fn set_adjustment_value(adjustment: &gtk::Adjustment, value: f64) {
+    if (adjustment.value() - value).abs() >= 0.5 {
+        adjustment.set_value(value);
+    }
+}
+
+fn combined_module(state: Rc<PulseAudioState>) -> FnModFactory<CombinedConfig> {
+    FnModFactory::new(
+        "pulseaudio-combined",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<CombinedConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("pulseaudio-combined");
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            let sink_icon = Icon::new("volume-muted");
+            let label = gtk::Label::new(None);
+            let source_icon = Icon::new("mic-muted");
+            hbox.add(sink_icon.widget());
+            if config.show_volume {
+                hbox.add(&label);
+            }
+            hbox.add(source_icon.widget());
+            button.add(&hbox);
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            let widgets = Rc::new(CombinedPopover::new(&popover, config));
+            // Changing the combo boxes and the sliders in update() shouldn't change the devices.
+            let updating = Rc::new(std::cell::Cell::new(false));
+            {
+                let state = state.clone();
+                let updating = updating.clone();
+                widgets.sink_combo.connect_changed(move |combo| {
+                    if let (false, Some(name)) = (updating.get(), combo.active_id()) {
+                        state.set_default_sink(&name);
+                    }
+                });
+            }
+            {
+                let state = state.clone();
+                let updating = updating.clone();
+                widgets.source_combo.connect_changed(move |combo| {
+                    if let (false, Some(name)) = (updating.get(), combo.active_id()) {
+                        state.set_default_source(&name);
+                    }
+                });
+            }
+            {
+                let state = state.clone();
+                let updating = updating.clone();
+                widgets
+                    .sink_adjustment
+                    .connect_value_changed(move |adjustment| {
+                        if let (false, Some(sink)) = (updating.get(), state.default_sink()) {
+                            sink.set_volume_percentage(adjustment.value());
+                        }
+                    });
+            }
+            {
+                let state = state.clone();
+                let updating = updating.clone();
+                widgets
+                    .source_adjustment
+                    .connect_value_changed(move |adjustment| {
+                        if let (false, Some(source)) = (updating.get(), state.default_source()) {
+                            source.set_volume_percentage(adjustment.value());
+                        }
+                    });
+            }
+            let update_popover = {
+                let state = state.clone();
+                let widgets = widgets.clone();
+                move || {
+                    updating.set(true);
+                    widgets.update(&state);
+                    updating.set(false);
+                }
+            };
+            let update_popover = Rc::new(update_popover);
+
+            // The secondary click, also run by a long press on touchscreens.
+            let secondary_action = Rc::new(glib::clone!(@weak state => move || {
+                if let Some(sink) = state.default_sink() {
+                    sink.toggle_mute();
+                }
+            }));
+            {
+                let update_popover = update_popover.clone();
+                let secondary_action = secondary_action.clone();
+                button.connect_button_release_event(
+                    glib::clone!(@weak popover => @default-return Inhibit(false), move |_, e| {
+                        if e.button() == gtk::gdk::BUTTON_PRIMARY {
+                            update_popover();
+                            popover.show_all();
+                            popover.popup();
+                            return Inhibit(true);
+                        }
+                        if e.button() == gtk::gdk::BUTTON_SECONDARY {
+                            secondary_action();
+                            return Inhibit(true);
+                        }
+                        Inhibit(false)
+                    }),
+                );
+            }
+            connect_volume_pan(
+                &button,
+                glib::clone!(@weak state => @default-return None, move || {
+                    state.default_sink().map(|s| s.adjustment())
+                }),
+            );
+            touch::connect_long_press(&button, move |_| secondary_action());
+
+            connect_notify_while_alive(
+                &*state,
+                container,
+                None,
+                glib::clone!(@weak button, @weak label, @weak popover => move |state, _| {
+                    match state.default_sink() {
+                        Some(sink) => {
+                            sink_icon.set(sink_icon_name(&sink.volume, sink.mute));
+                            label.set_text(&format!("{:.0}%", volume_percentage(&sink.volume)));
+                        }
+                        None => label.set_text(""),
+                    }
+                    let mic_muted = state.default_source().map(|s| s.mute).unwrap_or(true);
+                    source_icon.set(if mic_muted { "mic-muted" } else { "mic" });
+                    if popover.is_visible() {
+                        update_popover();
+                    }
+                }),
+            );
+        }),
+    )
+}
+
+fn default_move_streams() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct SinkToggleConfig {
+    /// Names of the sinks to cycle through (e.g. "alsa_output.pci-0000_00_1f.3.analog-stereo").
+    #[serde(default)]
+    sinks: Vec<String>,
+
+    /// Move the playing streams to the new default sink.
+    #[serde(default = "default_move_streams")]
+    move_streams: bool,
+
+    /// Nicknames for sinks. See the pulseaudio-default-sink-selector module.
+    #[serde(default)]
+    nicknames: HashMap<String, String>,
+}
+
+impl Default for SinkToggleConfig {
+    fn default() -> Self {
+        SinkToggleConfig {
+            sinks: vec![],
+            move_streams: default_move_streams(),
+            nicknames: HashMap::new(),
+        }
+    }
+}
+
+/// Returns the sink after the current one in the cycle. Sinks that don't exist are skipped.
+fn next_sink<'a>(cycle: &'a [String], current: &str, state: &PulseAudioState) -> Option<&'a str> {
+    let existing: Vec<String> = state.sinks().into_iter().map(|(_, s)| s.name).collect();
+    let start = cycle.iter().position(|s| s == current).map_or(0, |i| i + 1);
+    (0..cycle.len())
+        .map(|i| &cycle[(start + i) % cycle.len()])
+        .find(|s| s.as_str() != current && existing.contains(s))
+        .map(|s| s.as_str())
+}
+
+fn sink_toggle_module(state: Rc<PulseAudioState>) -> FnModFactory<SinkToggleConfig> {
+    FnModFactory::new(
+        "pulseaudio-sink-toggle",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<SinkToggleConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("pulseaudio-sink-toggle");
+            container.add(&button);
+
+            {
+                let sinks_state = state.clone();
+                register_device_lint(
+                    "pulseaudio-sink-toggle",
+                    "sink",
+                    "sink",
+                    config.sinks.clone(),
+                    move || sink_names(&sinks_state),
+                );
+                let nicknames_state = state.clone();
+                register_device_lint(
+                    "pulseaudio-sink-toggle",
+                    "sink",
+                    "nickname for",
+                    config.nicknames.keys().cloned().collect(),
+                    move || sink_names(&nicknames_state),
+                );
+            }
+
+            // The last chosen sink is restored once it appears.
+            let store = StateStore::new(container);
+            let restored = Cell::new(false);
+            {
+                let config = config.clone();
+                let store = store.clone();
+                button.connect_clicked(glib::clone!(@weak state => move |_| {
+                    let current = state.default_sink_name();
+                    if let Some(next) = next_sink(&config.sinks, &current, &state) {
+                        state.set_default_sink(next);
+                        if config.move_streams {
+                            state.move_sink_inputs(next);
+                        }
+                        store.set("sink", &next);
+                    }
+                }));
+            }
+
+            let config = config.clone();
+            let nicknames = Nicknames::new(&config.nicknames);
+            connect_notify_while_alive(
+                &*state,
+                container,
+                None,
+                glib::clone!(@weak button => move |state, _| {
+                    if !restored.get() {
+                        match store.get::<String>("sink") {
+                            Some(saved) if state.sinks().into_iter().any(|(_, s)| s.name == saved) => {
+                                restored.set(true);
+                                if saved != state.default_sink_name() {
+                                    state.set_default_sink(&saved);
+                                    if config.move_streams {
+                                        state.move_sink_inputs(&saved);
+                                    }
+                                }
+                            }
+                            Some(_) => {}
+                            None => restored.set(true),
+                        }
+                    }
+                    let shown_name = |name: &str| match nicknames.get(name) {
+                        Some(nickname) => nickname,
+                        None => state
+                            .sinks()
+                            .into_iter()
+                            .map(|(_, s)| s)
+                            .find(|s| s.name == name)
+                            .map(|s| s.description)
+                            .unwrap_or_else(|| name.to_owned()),
+                    };
+                    let current = state.default_sink_name();
+                    button.set_label(&shown_name(&current));
+                    match next_sink(&config.sinks, &current, state) {
+                        Some(next) => {
+                            button.set_sensitive(true);
+                            button.set_tooltip_text(Some(&format!("Switch to {}", shown_name(next))));
+                        }
+                        None => {
+                            button.set_sensitive(false);
+                            button.set_tooltip_text(None);
+                        }
+                    }
+                }),
+            );
+        }),
+    )
+}
+
 pub(crate) fn make_module_factories(
     _config: &serde_json::Value,
 ) -> Vec<Box<dyn crate::module::ModuleFactory>> {
-    let state = Rc::new(PulseAudioState::new());
-
     vec![
-        Box::new(default_source_volume_toggle_module(state.clone())),
-        Box::new(default_source_volume_module(state.clone())),
-        Box::new(default_source_selector_module(state.clone())),
-        Box::new(default_sink_volume_toggle_module(state.clone())),
-        Box::new(default_sink_volume_module(state.clone())),
-        Box::new(default_sink_selector_module(state.clone())),
+        Box::new(WithState::new(
+            "pulseaudio-default-source-volume-toggle",
+            default_source_volume_toggle_module,
+        )),
+        Box::new(WithState::new(
+            "pulseaudio-default-source-volume",
+            default_source_volume_module,
+        )),
+        Box::new(WithState::new(
+            "pulseaudio-default-source-selector",
+            default_source_selector_module,
+        )),
+        Box::new(WithState::new(
+            "pulseaudio-default-sink-volume-toggle",
+            default_sink_volume_toggle_module,
+        )),
+        Box::new(WithState::new(
+            "pulseaudio-default-sink-volume",
+            default_sink_volume_module,
+        )),
+        Box::new(WithState::new(
+            "pulseaudio-default-sink-selector",
+            default_sink_selector_module,
+        )),
+        Box::new(WithState::new("pulseaudio-combined", combined_module)),
+        Box::new(WithState::new("pulseaudio-sink-toggle", sink_toggle_module)),
     ]
 }
+
+crate::register_plugin!("pulseaudio", make_module_factories);
@@ -0,0 +1,500 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Screenshot module.
+//!
+//! On X11, the screen is captured with GDK and the region is selected on a frozen full-screen
+//! capture. On Wayland, the xdg-desktop-portal Screenshot portal is used, and it selects the
+//! region and the window interactively.
+
+use crate::module_base::touch;
+use crate::module_base::{FnModFactory, JSONConfigFactory};
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::prelude::*;
+use gtk::{gdk, gio, glib};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum Mode {
+    /// The whole screen.
+    Screen,
+    /// The focused window (i3).
+    Window,
+    /// A region selected with the mouse.
+    Region,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Region
+    }
+}
+
+/// What to copy to the clipboard.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum ClipboardContent {
+    Image,
+    Path,
+    None,
+}
+
+impl Default for ClipboardContent {
+    fn default() -> Self {
+        ClipboardContent::Image
+    }
+}
+
+fn default_label() -> String {
+    "📷".to_owned()
+}
+
+fn default_filename_format() -> String {
+    "Screenshot_%Y-%m-%d_%H-%M-%S.png".to_owned()
+}
+
+fn default_notify() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ScreenshotConfig {
+    #[serde(default = "default_label")]
+    label: String,
+
+    /// Capture mode on click. "screen", "window", or "region" (default). Other modes are in the
+    /// right click menu.
+    #[serde(default)]
+    mode: Mode,
+
+    /// Directory to save the screenshots. Defaults to the XDG pictures directory.
+    #[serde(default)]
+    directory: Option<PathBuf>,
+
+    /// File name of the screenshots. See g_date_time_format for the syntax.
+    #[serde(default = "default_filename_format")]
+    filename_format: String,
+
+    /// What to copy to the clipboard. "image" (default), "path", or "none".
+    #[serde(default)]
+    clipboard: ClipboardContent,
+
+    /// Seconds to wait before capturing.
+    #[serde(default)]
+    delay: u32,
+
+    /// Send a notification with the path.
+    #[serde(default = "default_notify")]
+    notify: bool,
+}
+
+impl Default for ScreenshotConfig {
+    fn default() -> Self {
+        ScreenshotConfig {
+            label: default_label(),
+            mode: Mode::default(),
+            directory: None,
+            filename_format: default_filename_format(),
+            clipboard: ClipboardContent::default(),
+            delay: 0,
+            notify: default_notify(),
+        }
+    }
+}
+
+type Callback = Box<dyn FnOnce(Result<Pixbuf, String>)>;
+
+/// Returns true if GDK runs on Wayland.
+fn is_wayland() -> bool {
+    gdk::Display::default()
+        .map(|d| d.type_().name() == "GdkWaylandDisplay")
+        .unwrap_or(false)
+}
+
+/// Captures the rectangle of the root window.
+fn grab_root(rect: Option<(i32, i32, i32, i32)>) -> Result<Pixbuf, String> {
+    let root = gdk::Screen::default()
+        .and_then(|s| s.root_window())
+        .ok_or("No root window")?;
+    let (x, y, w, h) = rect.unwrap_or((0, 0, root.width(), root.height()));
+    gdk::pixbuf_get_from_window(&root, x, y, w, h).ok_or_else(|| "Cannot capture".to_owned())
+}
+
+/// Returns the rectangle of the focused window in i3.
+#[cfg(feature = "i3")]
+fn focused_window_rect() -> Result<(i32, i32, i32, i32), String> {
+    fn find(node: &i3ipc::reply::Node) -> Option<(i32, i32, i32, i32)> {
+        if node.focused {
+            return Some(node.rect);
+        }
+        node.nodes
+            .iter()
+            .chain(node.floating_nodes.iter())
+            .find_map(find)
+    }
+    let tree = i3ipc::I3Connection::connect()
+        .map_err(|e| e.to_string())?
+        .get_tree()
+        .map_err(|e| e.to_string())?;
+    find(&tree).ok_or_else(|| "No focused window".to_owned())
+}
+
+#[cfg(not(feature = "i3"))]
+fn focused_window_rect() -> Result<(i32, i32, i32, i32), String> {
+    Err("jiji is built without the \"i3\" feature".to_owned())
+}
+
+/// Copies the rectangle of the pixbuf.
+fn crop(pixbuf: &Pixbuf, (x, y, w, h): (i32, i32, i32, i32)) -> Option<Pixbuf> {
+    let dest = Pixbuf::new(
+        pixbuf.colorspace(),
+        pixbuf.has_alpha(),
+        pixbuf.bits_per_sample(),
+        w,
+        h,
+    )?;
+    pixbuf.copy_area(x, y, w, h, &dest, 0, 0);
+    Some(dest)
+}
+
+/// Shows the frozen capture in a full-screen window and lets the user drag a region. Escape
+/// cancels the selection.
+fn select_region(pixbuf: Pixbuf, callback: Callback) {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_decorated(false);
+    window.set_keep_above(true);
+    window.fullscreen();
+    let area = gtk::DrawingArea::new();
+    area.add_events(
+        gdk::EventMask::BUTTON_PRESS_MASK
+            | gdk::EventMask::BUTTON_RELEASE_MASK
+            | gdk::EventMask::POINTER_MOTION_MASK,
+    );
+    window.add(&area);
+
+    // (start x, start y, current x, current y) in the window coordinates.
+    let selection: Rc<Cell<Option<(f64, f64, f64, f64)>>> = Rc::default();
+    let callback = Rc::new(RefCell::new(Some(callback)));
+    let rect = |(x0, y0, x1, y1): (f64, f64, f64, f64)| {
+        (x0.min(x1), y0.min(y1), (x1 - x0).abs(), (y1 - y0).abs())
+    };
+
+    let frozen = pixbuf.clone();
+    area.connect_draw(glib::clone!(@strong selection => move |area, cr| {
+        let scale = area.scale_factor() as f64;
+        let _ = cr.save();
+        cr.scale(1.0 / scale, 1.0 / scale);
+        cr.set_source_pixbuf(&frozen, 0.0, 0.0);
+        let _ = cr.paint();
+        let _ = cr.restore();
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.4);
+        if let Some(sel) = selection.get() {
+            let (x, y, w, h) = rect(sel);
+            // Darken outside of the selection.
+            cr.set_fill_rule(gtk::cairo::FillRule::EvenOdd);
+            cr.rectangle(0.0, 0.0, area.allocated_width() as f64, area.allocated_height() as f64);
+            cr.rectangle(x, y, w, h);
+            let _ = cr.fill();
+            cr.set_source_rgb(1.0, 1.0, 1.0);
+            cr.set_line_width(1.0);
+            cr.rectangle(x + 0.5, y + 0.5, w, h);
+            let _ = cr.stroke();
+        } else {
+            let _ = cr.paint();
+        }
+        Inhibit(true)
+    }));
+    area.connect_button_press_event(glib::clone!(@strong selection => move |_, e| {
+        let (x, y) = e.position();
+        selection.set(Some((x, y, x, y)));
+        Inhibit(true)
+    }));
+    area.connect_motion_notify_event(glib::clone!(@strong selection => move |area, e| {
+        if let Some((x0, y0, _, _)) = selection.get() {
+            let (x, y) = e.position();
+            selection.set(Some((x0, y0, x, y)));
+            area.queue_draw();
+        }
+        Inhibit(true)
+    }));
+    area.connect_button_release_event(
+        glib::clone!(@weak window, @strong selection, @strong callback => @default-return Inhibit(true), move |area, _| {
+            let sel = match selection.take() {
+                Some(sel) => sel,
+                None => return Inhibit(true),
+            };
+            let scale = area.scale_factor() as f64;
+            let (x, y, w, h) = rect(sel);
+            window.close();
+            if let Some(callback) = callback.borrow_mut().take() {
+                let region = (
+                    (x * scale) as i32,
+                    (y * scale) as i32,
+                    ((w * scale) as i32).max(1),
+                    ((h * scale) as i32).max(1),
+                );
+                callback(crop(&pixbuf, region).ok_or_else(|| "Cannot crop".to_owned()));
+            }
+            Inhibit(true)
+        }),
+    );
+    window.connect_key_press_event(|window, e| {
+        if e.keyval() == gdk::keys::constants::Escape {
+            window.close();
+        }
+        Inhibit(false)
+    });
+    window.connect_destroy(move |_| {
+        if let Some(callback) = callback.borrow_mut().take() {
+            callback(Err("Cancelled".to_owned()));
+        }
+    });
+    window.connect_realize(|window| {
+        if let Some(w) = window.window() {
+            let cursor = gdk::Cursor::for_display(&w.display(), gdk::CursorType::Crosshair);
+            w.set_cursor(Some(&cursor));
+        }
+    });
+    window.show_all();
+}
+
+/// Takes a screenshot with the xdg-desktop-portal Screenshot portal.
+fn portal_screenshot(interactive: bool, callback: Callback) {
+    let conn = match gio::bus_get_sync(gio::BusType::Session, None::<&gio::Cancellable>) {
+        Ok(conn) => conn,
+        Err(e) => return callback(Err(e.to_string())),
+    };
+    // The response is sent to a Request object whose path is made from the unique name and the
+    // token. Subscribe to it before the call so that the response isn't missed.
+    let token = format!("jiji{}", glib::random_int());
+    let sender = conn
+        .unique_name()
+        .map(|n| n.trim_start_matches(':').replace('.', "_"))
+        .unwrap_or_default();
+    let request_path = format!(
+        "/org/freedesktop/portal/desktop/request/{}/{}",
+        sender, token
+    );
+    let callback = RefCell::new(Some(callback));
+    let subscription = Rc::new(Cell::new(None));
+    let id = conn.signal_subscribe(
+        Some("org.freedesktop.portal.Desktop"),
+        Some("org.freedesktop.portal.Request"),
+        Some("Response"),
+        Some(&request_path),
+        None,
+        gio::DBusSignalFlags::NONE,
+        glib::clone!(@strong subscription => move |conn, _, _, _, _, params| {
+            if let Some(id) = subscription.take() {
+                conn.signal_unsubscribe(id);
+            }
+            let callback = match callback.borrow_mut().take() {
+                Some(c) => c,
+                None => return,
+            };
+            // (u response, a{sv} results). The response is 0 on success.
+            if params.child_value(0).get::<u32>() != Some(0) {
+                return callback(Err("Cancelled".to_owned()));
+            }
+            let results = params.child_value(1);
+            let uri = (0..results.n_children())
+                .map(|i| results.child_value(i))
+                .find(|e| e.child_value(0).str() == Some("uri"))
+                .and_then(|e| e.child_value(1).as_variant())
+                .and_then(|v| v.get::<String>());
+            let path = match uri.and_then(|u| gio::File::for_uri(&u).path()) {
+                Some(p) => p,
+                None => return callback(Err("No screenshot in the response".to_owned())),
+            };
+            callback(Pixbuf::from_file(&path).map_err(|e| e.to_string()));
+        }),
+    );
+    subscription.set(Some(id));
+
+    let mut options = HashMap::new();
+    options.insert("handle_token".to_owned(), token.to_variant());
+    options.insert("interactive".to_owned(), interactive.to_variant());
+    conn.call(
+        Some("org.freedesktop.portal.Desktop"),
+        "/org/freedesktop/portal/desktop",
+        "org.freedesktop.portal.Screenshot",
+        "Screenshot",
+        Some(&("", options).to_variant()),
+        None,
+        gio::DBusCallFlags::NONE,
+        -1,
+        None::<&gio::Cancellable>,
+        move |result| {
+            if let Err(e) = result {
+                eprintln!("Screenshot portal failed: {}", e);
+            }
+        },
+    );
+}
+
+/// Captures the screen in the mode.
+fn capture(mode: Mode, callback: Callback) {
+    if is_wayland() {
+        // The portal lets the user choose the region or the window.
+        return portal_screenshot(mode != Mode::Screen, callback);
+    }
+    match mode {
+        Mode::Screen => callback(grab_root(None)),
+        Mode::Window => callback(focused_window_rect().and_then(|r| grab_root(Some(r)))),
+        Mode::Region => match grab_root(None) {
+            Ok(pixbuf) => select_region(pixbuf, callback),
+            Err(e) => callback(Err(e)),
+        },
+    }
+}
+
+/// Saves the screenshot, copies it to the clipboard, and notifies.
+fn save(config: &ScreenshotConfig, pixbuf: &Pixbuf) -> Result<PathBuf, String> {
+    let dir = config
+        .directory
+        .clone()
+        .or_else(|| glib::user_special_dir(glib::UserDirectory::Pictures))
+        .ok_or("No directory to save the screenshot")?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let name = glib::DateTime::new_now_local()
+        .and_then(|t| t.format(&config.filename_format))
+        .map(|s| s.to_string())
+        .ok_or("Invalid filename_format")?;
+    let path = dir.join(name);
+    pixbuf.savev(&path, "png", &[]).map_err(|e| e.to_string())?;
+
+    let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+    match config.clipboard {
+        ClipboardContent::Image => clipboard.set_image(pixbuf),
+        ClipboardContent::Path => clipboard.set_text(&path.to_string_lossy()),
+        ClipboardContent::None => {}
+    }
+    if config.notify {
+        let notification = gio::Notification::new("Screenshot saved");
+        notification.set_body(Some(&path.to_string_lossy()));
+        if let Some(app) = gio::Application::default() {
+            app.send_notification(None, &notification);
+        }
+    }
+    Ok(path)
+}
+
+/// Returns a menu to capture in another mode.
+fn mode_menu<F: Fn(Mode) + 'static>(start: &Rc<F>) -> gtk::Menu {
+    let menu = gtk::Menu::new();
+    for (mode, name) in &[
+        (Mode::Screen, "Screen"),
+        (Mode::Window, "Window"),
+        (Mode::Region, "Region"),
+    ] {
+        let item = gtk::MenuItem::with_label(name);
+        let mode = *mode;
+        item.connect_activate(glib::clone!(@strong start => move |_| start(mode)));
+        menu.append(&item);
+    }
+    menu.show_all();
+    menu
+}
+
+fn screenshot_module() -> FnModFactory<ScreenshotConfig> {
+    FnModFactory::new(
+        "screenshot",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<ScreenshotConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("screenshot");
+            button.set_tooltip_text(Some("Click to capture, right click for other modes"));
+            let label = gtk::Label::new(Some(&config.label));
+            button.add(&label);
+            container.add(&button);
+
+            let config = config.clone();
+            let start = Rc::new(
+                glib::clone!(@weak button, @weak label => move |mode: Mode| {
+                    let sc = button.style_context();
+                    if sc.has_class("screenshot-busy") {
+                        return;
+                    }
+                    sc.add_class("screenshot-busy");
+                    let remaining = Rc::new(Cell::new(config.delay));
+                    let config = config.clone();
+                    let done: Callback = Box::new(glib::clone!(@weak button, @weak label, @strong config => move |result| {
+                        button.style_context().remove_class("screenshot-busy");
+                        label.set_text(&config.label);
+                        match result.and_then(|pixbuf| save(&config, &pixbuf)) {
+                            Ok(path) => button.set_tooltip_text(Some(&format!("Saved {}", path.display()))),
+                            Err(e) => eprintln!("Screenshot: {}", e),
+                        }
+                    }));
+                    let done = RefCell::new(Some(done));
+                    // Counts down the delay in the label every second.
+                    let tick = Rc::new(glib::clone!(@weak label => @default-return Continue(false), move || {
+                        let secs = remaining.get();
+                        if secs > 0 {
+                            label.set_text(&secs.to_string());
+                            remaining.set(secs - 1);
+                            return Continue(true);
+                        }
+                        if let Some(done) = done.borrow_mut().take() {
+                            capture(mode, done);
+                        }
+                        Continue(false)
+                    }));
+                    // The first tick is short so that the menu disappears before capturing.
+                    glib::timeout_add_local(Duration::from_millis(200), move || {
+                        if tick().0 {
+                            let tick = tick.clone();
+                            glib::timeout_add_local(Duration::from_secs(1), move || tick());
+                        }
+                        Continue(false)
+                    });
+                }),
+            );
+
+            let default_mode = config.mode;
+            button.connect_clicked(glib::clone!(@strong start => move |_| start(default_mode)));
+            button.connect_button_release_event(glib::clone!(@strong start => move |_, e| {
+                if e.button() != gdk::BUTTON_SECONDARY {
+                    return Inhibit(false);
+                }
+                mode_menu(&start).popup_at_pointer(None);
+                Inhibit(true)
+            }));
+            touch::connect_long_press(&button, move |button| {
+                mode_menu(&start).popup_at_widget(
+                    button,
+                    gdk::Gravity::South,
+                    gdk::Gravity::North,
+                    None,
+                );
+            });
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(screenshot_module())]
+}
+
+crate::register_plugin!("screenshot", make_module_factories);
@@ -0,0 +1,158 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{poll_in_worker, FnModFactory, JSONConfigFactory};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn default_warn_before() -> u64 {
+    60
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct SuspendCountdownConfig {
+    /// Show the countdown when the idle action triggers within this many seconds.
+    #[serde(default = "default_warn_before")]
+    warn_before: u64,
+}
+
+impl Default for SuspendCountdownConfig {
+    fn default() -> Self {
+        SuspendCountdownConfig {
+            warn_before: default_warn_before(),
+        }
+    }
+}
+
+const LOGIND: &str = "org.freedesktop.login1";
+
+fn get_property(
+    conn: &gio::DBusConnection,
+    path: &str,
+    interface: &str,
+    name: &str,
+) -> Option<glib::Variant> {
+    conn.call_sync(
+        Some(LOGIND),
+        path,
+        "org.freedesktop.DBus.Properties",
+        "Get",
+        Some(&(interface, name).to_variant()),
+        None,
+        gio::DBusCallFlags::NONE,
+        1000,
+        None::<&gio::Cancellable>,
+    )
+    .ok()?
+    .child_value(0)
+    .as_variant()
+}
+
+/// Returns (idle action, remaining time) if logind is going to take the idle action.
+fn idle_action_remaining() -> Option<(String, Duration)> {
+    let conn = gio::bus_get_sync(gio::BusType::System, None::<&gio::Cancellable>).ok()?;
+    let manager = |name| {
+        get_property(
+            &conn,
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+            name,
+        )
+    };
+    let action: String = manager("IdleAction")?.get()?;
+    if action == "ignore" || !manager("IdleHint")?.get::<bool>()? {
+        return None;
+    }
+    // Both are in microseconds. IdleSinceHint is in CLOCK_REALTIME.
+    let since: u64 = manager("IdleSinceHint")?.get()?;
+    let delay: u64 = manager("IdleActionUSec")?.get()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_micros() as u64;
+    let remaining = (since + delay).saturating_sub(now);
+    Some((action, Duration::from_micros(remaining)))
+}
+
+/// Clears the idle hint of the session so that logind restarts counting.
+fn postpone() {
+    if let Ok(conn) = gio::bus_get_sync(gio::BusType::System, None::<&gio::Cancellable>) {
+        let _ = conn.call_sync(
+            Some(LOGIND),
+            "/org/freedesktop/login1/session/auto",
+            "org.freedesktop.login1.Session",
+            "SetIdleHint",
+            Some(&(false,).to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            1000,
+            None::<&gio::Cancellable>,
+        );
+    }
+}
+
+fn suspend_countdown_module() -> FnModFactory<SuspendCountdownConfig> {
+    FnModFactory::new(
+        "suspend-countdown",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(
+            move |config: &Rc<SuspendCountdownConfig>, container: &gtk::Box| {
+                let button = gtk::Button::new();
+                button.set_relief(gtk::ReliefStyle::None);
+                button.style_context().add_class("suspend-countdown");
+                button.set_tooltip_text(Some("Click to postpone"));
+                let label = gtk::Label::new(None);
+                button.add(&label);
+                // Shown only while counting down.
+                button.set_no_show_all(true);
+                label.show();
+                container.add(&button);
+
+                let warn_before = Duration::from_secs(config.warn_before);
+                let poller = poll_in_worker(
+                    Duration::from_secs(1),
+                    idle_action_remaining,
+                    glib::clone!(@weak button, @weak label => @default-return Continue(false), move |result| {
+                        match result {
+                            Some((action, remaining)) if remaining <= warn_before => {
+                                let secs = remaining.as_secs();
+                                label.set_text(&format!("{} in {}:{:02}", action, secs / 60, secs % 60));
+                                button.show();
+                            }
+                            _ => button.hide(),
+                        }
+                        Continue(true)
+                    }),
+                );
+                button.connect_clicked(move |button| {
+                    postpone();
+                    button.hide();
+                    poller.refresh();
+                });
+            },
+        ),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(suspend_countdown_module())]
+}
+
+crate::register_plugin!("suspend", make_module_factories);
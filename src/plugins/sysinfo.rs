@@ -0,0 +1,957 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::gauge::{Gauge, ValueStyle};
+use crate::module_base::graph::{add_export_action, sparkline, History, HistoryPopup};
+use crate::module_base::hwmon;
+use crate::module_base::locale;
+use crate::module_base::{
+    add_pin_action, add_refresh_action, format_bytes, is_pinned, poll_in_worker, FnModFactory,
+    JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Reads the aggregated CPU times from /proc/stat. Returns (idle, total).
+fn read_cpu_times() -> Option<(u64, u64)> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let line = stat.lines().find(|l| l.starts_with("cpu "))?;
+    let values: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    // user nice system idle iowait irq softirq steal ...
+    let idle = values.get(3)? + values.get(4).unwrap_or(&0);
+    Some((idle, values.iter().sum()))
+}
+
+/// Returns the CPU usage in percentage between two [`read_cpu_times`] samples. None if no CPU time
+/// elapsed.
+///
+/// The counters can go backwards (e.g. iowait on some kernels), so they are subtracted with
+/// saturation.
+fn cpu_usage((idle0, total0): (u64, u64), (idle1, total1): (u64, u64)) -> Option<f64> {
+    let total = total1.saturating_sub(total0);
+    if total == 0 {
+        return None;
+    }
+    let busy = total.saturating_sub(idle1.saturating_sub(idle0));
+    Some(busy as f64 * 100.0 / total as f64)
+}
+
+fn default_interval() -> u32 {
+    2
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct CpuConfig {
+    /// Interval in seconds to update the usage.
+    #[serde(default = "default_interval")]
+    interval: u32,
+
+    /// Number of samples kept for the history popup. The popup is disabled if 0.
+    #[serde(default)]
+    history: usize,
+
+    /// "text" (default) or "ring".
+    #[serde(default)]
+    style: ValueStyle,
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        CpuConfig {
+            interval: default_interval(),
+            history: 0,
+            style: ValueStyle::default(),
+        }
+    }
+}
+
+fn cpu_module() -> FnModFactory<CpuConfig> {
+    FnModFactory::new(
+        "cpu",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<CpuConfig>, container: &gtk::Box| {
+            let button = gtk::Button::with_label("CPU");
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("cpu");
+            container.add(&button);
+            let gauge = if config.style == ValueStyle::Ring {
+                let gauge = Gauge::new();
+                button.remove(&button.child().unwrap());
+                button.add(gauge.widget());
+                Some(gauge)
+            } else {
+                None
+            };
+
+            let history = Rc::new(RefCell::new(History::new(config.history)));
+            let popup = if config.history > 0 {
+                add_export_action(container, history.clone());
+                Some(HistoryPopup::new(
+                    &button,
+                    history.clone(),
+                    Some((0.0, 100.0)),
+                    "%",
+                ))
+            } else {
+                None
+            };
+
+            let prev = Cell::new(read_cpu_times());
+            let update = move |button: &gtk::Button| {
+                let cur = read_cpu_times();
+                if let (Some(prev), Some(cur)) = (prev.get(), cur) {
+                    if let Some(usage) = cpu_usage(prev, cur) {
+                        match gauge {
+                            Some(ref gauge) => gauge.set(usage, &format!("{:.0}", usage)),
+                            None => button.set_label(&format!("CPU {:.0}%", usage)),
+                        }
+                        history.borrow_mut().push(usage);
+                        if let Some(ref popup) = popup {
+                            popup.update();
+                        }
+                    }
+                }
+                prev.set(cur);
+            };
+            let update = Rc::new(update);
+
+            glib::timeout_add_seconds_local(
+                config.interval,
+                glib::clone!(@weak button, @weak container, @strong update => @default-return Continue(false), move || {
+                    if !is_pinned(&container) {
+                        update(&button);
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(
+                container,
+                &button,
+                glib::clone!(@weak button => move || update(&button)),
+            );
+            add_pin_action(container, &button);
+        }),
+    )
+}
+
+/// Returns the number of CPUs from /proc/stat.
+fn cpu_count() -> usize {
+    std::fs::read_to_string("/proc/stat")
+        .map(|stat| {
+            stat.lines()
+                .filter(|l| l.starts_with("cpu") && !l.starts_with("cpu "))
+                .count()
+        })
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Reads /proc/loadavg. Returns ([1m, 5m, 15m], running, total).
+fn read_loadavg() -> Option<([f64; 3], u64, u64)> {
+    let content = std::fs::read_to_string("/proc/loadavg").ok()?;
+    let fields: Vec<&str> = content.split_whitespace().collect();
+    let mut procs = fields.get(3)?.split('/');
+    Some((
+        [
+            fields.first()?.parse().ok()?,
+            fields.get(1)?.parse().ok()?,
+            fields.get(2)?.parse().ok()?,
+        ],
+        procs.next()?.parse().ok()?,
+        procs.next()?.parse().ok()?,
+    ))
+}
+
+fn default_loadavg_interval() -> u32 {
+    5
+}
+
+fn default_loadavg_format() -> String {
+    "{1m} {5m} {15m}".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct LoadavgConfig {
+    /// Interval in seconds to update the load average.
+    #[serde(default = "default_loadavg_interval")]
+    interval: u32,
+
+    /// Format of the label. "{1m}", "{5m}", "{15m}", "{running}" (runnable processes), and
+    /// "{total}" (all processes) are replaced.
+    #[serde(default = "default_loadavg_format")]
+    format: String,
+}
+
+impl Default for LoadavgConfig {
+    fn default() -> Self {
+        LoadavgConfig {
+            interval: default_loadavg_interval(),
+            format: default_loadavg_format(),
+        }
+    }
+}
+
+fn loadavg_module() -> FnModFactory<LoadavgConfig> {
+    FnModFactory::new(
+        "loadavg",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<LoadavgConfig>, container: &gtk::Box| {
+            let event_box = gtk::EventBox::new();
+            let label = gtk::Label::new(None);
+            label.style_context().add_class("loadavg");
+            event_box.add(&label);
+            container.add(&event_box);
+
+            let cpus = cpu_count();
+            let config = config.clone();
+            let update = Rc::new(move |label: &gtk::Label| {
+                let (load, running, total) = match read_loadavg() {
+                    Some(v) => v,
+                    None => return,
+                };
+                label.set_text(
+                    &config
+                        .format
+                        .replace("{1m}", &format!("{:.2}", load[0]))
+                        .replace("{5m}", &format!("{:.2}", load[1]))
+                        .replace("{15m}", &format!("{:.2}", load[2]))
+                        .replace("{running}", &running.to_string())
+                        .replace("{total}", &total.to_string()),
+                );
+                label.set_tooltip_text(Some(&format!("{} CPUs", cpus)));
+                let sc = label.style_context();
+                if load[0] > cpus as f64 {
+                    sc.add_class("load-high");
+                } else {
+                    sc.remove_class("load-high");
+                }
+            });
+            update(&label);
+
+            glib::timeout_add_seconds_local(
+                config.interval,
+                glib::clone!(@weak label, @weak container, @strong update => @default-return Continue(false), move || {
+                    if !is_pinned(&container) {
+                        update(&label);
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(
+                container,
+                &event_box,
+                glib::clone!(@weak label => move || update(&label)),
+            );
+            add_pin_action(container, &event_box);
+        }),
+    )
+}
+
+/// Reads the current frequencies of the CPUs in kHz from sysfs.
+fn read_cpu_freqs() -> Vec<u64> {
+    let entries = match std::fs::read_dir("/sys/devices/system/cpu") {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    entries
+        .flatten()
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            name.starts_with("cpu") && name[3..].chars().all(|c| c.is_ascii_digit())
+        })
+        .filter_map(|e| {
+            std::fs::read_to_string(e.path().join("cpufreq/scaling_cur_freq"))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+        })
+        .collect()
+}
+
+/// Reads a cpufreq attribute of cpu0 (e.g. "scaling_governor").
+fn read_cpufreq_attr(name: &str) -> String {
+    std::fs::read_to_string(format!("/sys/devices/system/cpu/cpu0/cpufreq/{}", name))
+        .map(|s| s.trim().to_owned())
+        .unwrap_or_default()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum FreqAggregate {
+    Avg,
+    Max,
+}
+
+impl Default for FreqAggregate {
+    fn default() -> Self {
+        FreqAggregate::Avg
+    }
+}
+
+fn default_cpufreq_format() -> String {
+    "{freq} {governor}".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct CpufreqConfig {
+    /// Interval in seconds to update the frequency.
+    #[serde(default = "default_interval")]
+    interval: u32,
+
+    /// How to aggregate the frequencies of the CPUs. "avg" (default) or "max".
+    #[serde(default)]
+    aggregate: FreqAggregate,
+
+    /// Format of the label. "{freq}" (e.g. "2.40GHz") and "{governor}" are replaced.
+    #[serde(default = "default_cpufreq_format")]
+    format: String,
+
+    /// Command to switch the governor. "{governor}" is replaced. Switching needs the root
+    /// privilege, e.g. "pkexec cpupower frequency-set -g {governor}". The menu to switch is
+    /// disabled if not specified.
+    #[serde(default)]
+    governor_command: Option<String>,
+}
+
+impl Default for CpufreqConfig {
+    fn default() -> Self {
+        CpufreqConfig {
+            interval: default_interval(),
+            aggregate: FreqAggregate::default(),
+            format: default_cpufreq_format(),
+            governor_command: None,
+        }
+    }
+}
+
+fn cpufreq_module() -> FnModFactory<CpufreqConfig> {
+    FnModFactory::new(
+        "cpufreq",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<CpufreqConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("cpufreq");
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+
+            let update_config = config.clone();
+            let update = Rc::new(move |label: &gtk::Label| {
+                let config = &update_config;
+                let freqs = read_cpu_freqs();
+                if freqs.is_empty() {
+                    label.set_text("?");
+                    label.set_tooltip_text(Some("No cpufreq in sysfs"));
+                    return;
+                }
+                let khz = match config.aggregate {
+                    FreqAggregate::Avg => freqs.iter().sum::<u64>() / freqs.len() as u64,
+                    FreqAggregate::Max => *freqs.iter().max().unwrap(),
+                };
+                label.set_text(
+                    &config
+                        .format
+                        .replace("{freq}", &format!("{:.2}GHz", khz as f64 / 1e6))
+                        .replace("{governor}", &read_cpufreq_attr("scaling_governor")),
+                );
+                label.set_tooltip_text(Some(&format!(
+                    "{} CPUs, {:.2}-{:.2}GHz",
+                    freqs.len(),
+                    *freqs.iter().min().unwrap() as f64 / 1e6,
+                    *freqs.iter().max().unwrap() as f64 / 1e6,
+                )));
+            });
+            update(&label);
+
+            if let Some(ref command) = config.governor_command {
+                let command = command.clone();
+                button.connect_clicked(glib::clone!(@weak label, @strong update => move |_| {
+                    let menu = gtk::Menu::new();
+                    let current = read_cpufreq_attr("scaling_governor");
+                    for governor in read_cpufreq_attr("scaling_available_governors").split_whitespace() {
+                        let item = gtk::CheckMenuItem::with_label(governor);
+                        item.set_draw_as_radio(true);
+                        // Set before connecting so that this doesn't run the command.
+                        item.set_active(governor == current);
+                        let command_line = command.replace("{governor}", governor);
+                        item.connect_activate(glib::clone!(@weak label, @strong update => move |_| {
+                            crate::module_base::command::run(
+                                &command_line,
+                                glib::clone!(@weak label, @strong update => move |result| {
+                                    if let Err(e) = result {
+                                        eprintln!("cpufreq: {}", e);
+                                    }
+                                    update(&label);
+                                }),
+                            );
+                        }));
+                        menu.append(&item);
+                    }
+                    menu.show_all();
+                    menu.popup_at_pointer(None);
+                }));
+            }
+
+            glib::timeout_add_seconds_local(
+                config.interval,
+                glib::clone!(@weak label, @weak container, @strong update => @default-return Continue(false), move || {
+                    if !is_pinned(&container) {
+                        update(&label);
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(
+                container,
+                &button,
+                glib::clone!(@weak label => move || update(&label)),
+            );
+            add_pin_action(container, &button);
+        }),
+    )
+}
+
+/// Sets one of the CSS classes by the value and the thresholds. No class is set below `warning`.
+fn set_level_class(
+    widget: &impl IsA<gtk::Widget>,
+    prefix: &str,
+    value: i64,
+    warning: Option<i64>,
+    critical: Option<i64>,
+) {
+    let sc = widget.style_context();
+    let level = if critical.map_or(false, |c| value >= c) {
+        Some("critical")
+    } else if warning.map_or(false, |w| value >= w) {
+        Some("warning")
+    } else {
+        None
+    };
+    for l in &["warning", "critical"] {
+        let class = format!("{}-{}", prefix, l);
+        if level == Some(*l) {
+            sc.add_class(&class);
+        } else {
+            sc.remove_class(&class);
+        }
+    }
+}
+
+fn default_fan_format() -> String {
+    "{rpm}RPM".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct FanConfig {
+    /// Fans to show, by "chip/label" (e.g. "thinkpad/fan1") or label. All fans if empty.
+    #[serde(default)]
+    sensors: Vec<String>,
+
+    /// Interval in seconds to update the speed.
+    #[serde(default = "default_interval")]
+    interval: u32,
+
+    /// Format of each fan. "{rpm}" and "{label}" are replaced.
+    #[serde(default = "default_fan_format")]
+    format: String,
+
+    /// RPM to add the "fan-warning" CSS class.
+    #[serde(default)]
+    warning: Option<i64>,
+
+    /// RPM to add the "fan-critical" CSS class.
+    #[serde(default)]
+    critical: Option<i64>,
+}
+
+impl Default for FanConfig {
+    fn default() -> Self {
+        FanConfig {
+            sensors: vec![],
+            interval: default_interval(),
+            format: default_fan_format(),
+            warning: None,
+            critical: None,
+        }
+    }
+}
+
+fn fan_module() -> FnModFactory<FanConfig> {
+    FnModFactory::new(
+        "fan",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<FanConfig>, container: &gtk::Box| {
+            let event_box = gtk::EventBox::new();
+            let label = gtk::Label::new(None);
+            label.style_context().add_class("fan");
+            event_box.add(&label);
+            container.add(&event_box);
+
+            let fans = hwmon::select("fan", &config.sensors);
+            let config = config.clone();
+            let update = Rc::new(move |label: &gtk::Label| {
+                let speeds: Vec<(&hwmon::Sensor, i64)> =
+                    fans.iter().filter_map(|f| Some((f, f.read()?))).collect();
+                if speeds.is_empty() {
+                    label.set_text("?");
+                    label.set_tooltip_text(Some("No fan in /sys/class/hwmon"));
+                    return;
+                }
+                label.set_text(
+                    &speeds
+                        .iter()
+                        .map(|(f, rpm)| {
+                            config
+                                .format
+                                .replace("{rpm}", &rpm.to_string())
+                                .replace("{label}", &f.label)
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                );
+                label.set_tooltip_text(Some(
+                    &speeds
+                        .iter()
+                        .map(|(f, rpm)| format!("{}: {}RPM", f.id(), rpm))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ));
+                let max = speeds.iter().map(|(_, rpm)| *rpm).max().unwrap_or(0);
+                set_level_class(label, "fan", max, config.warning, config.critical);
+                let sc = label.style_context();
+                if max == 0 {
+                    sc.add_class("fan-stopped");
+                } else {
+                    sc.remove_class("fan-stopped");
+                }
+            });
+            update(&label);
+
+            glib::timeout_add_seconds_local(
+                config.interval,
+                glib::clone!(@weak label, @weak container, @strong update => @default-return Continue(false), move || {
+                    if !is_pinned(&container) {
+                        update(&label);
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(
+                container,
+                &event_box,
+                glib::clone!(@weak label => move || update(&label)),
+            );
+            add_pin_action(container, &event_box);
+        }),
+    )
+}
+
+fn default_temperature_format() -> String {
+    "{temp}{unit}".to_owned()
+}
+
+fn default_temperature_warning() -> Option<i64> {
+    Some(70)
+}
+
+fn default_temperature_critical() -> Option<i64> {
+    Some(85)
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct TemperatureConfig {
+    /// Sensors to read, by "chip/label" (e.g. "coretemp/Package id 0") or label. All
+    /// temperature sensors if empty. The highest temperature is shown.
+    #[serde(default)]
+    sensors: Vec<String>,
+
+    /// Interval in seconds to update the temperature.
+    #[serde(default = "default_interval")]
+    interval: u32,
+
+    /// Format of the label. "{temp}" and "{unit}" (e.g. "°C") are replaced. The temperature is in
+    /// the unit of the locale config.
+    #[serde(default = "default_temperature_format")]
+    format: String,
+
+    /// Degrees Celsius to add the "temperature-warning" CSS class.
+    #[serde(default = "default_temperature_warning")]
+    warning: Option<i64>,
+
+    /// Degrees Celsius to add the "temperature-critical" CSS class.
+    #[serde(default = "default_temperature_critical")]
+    critical: Option<i64>,
+
+    /// Number of samples kept for the history popup. The popup is disabled if 0.
+    #[serde(default)]
+    history: usize,
+}
+
+impl Default for TemperatureConfig {
+    fn default() -> Self {
+        TemperatureConfig {
+            sensors: vec![],
+            interval: default_interval(),
+            format: default_temperature_format(),
+            warning: default_temperature_warning(),
+            critical: default_temperature_critical(),
+            history: 0,
+        }
+    }
+}
+
+fn temperature_module() -> FnModFactory<TemperatureConfig> {
+    FnModFactory::new(
+        "temperature",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(
+            move |config: &Rc<TemperatureConfig>, container: &gtk::Box| {
+                let event_box = gtk::EventBox::new();
+                let label = gtk::Label::new(None);
+                label.style_context().add_class("temperature");
+                container.add(&event_box);
+
+                let history = Rc::new(RefCell::new(History::new(config.history)));
+                let popup = if config.history > 0 {
+                    let button = gtk::Button::new();
+                    button.set_relief(gtk::ReliefStyle::None);
+                    button.add(&label);
+                    event_box.add(&button);
+                    add_export_action(container, history.clone());
+                    Some(HistoryPopup::new(
+                        &button,
+                        history.clone(),
+                        None,
+                        locale::temperature_symbol(),
+                    ))
+                } else {
+                    event_box.add(&label);
+                    None
+                };
+
+                let sensors = hwmon::select("temp", &config.sensors);
+                let config = config.clone();
+                let update = Rc::new(move |label: &gtk::Label| {
+                    // Millidegrees.
+                    let temps: Vec<(&hwmon::Sensor, i64)> = sensors
+                        .iter()
+                        .filter_map(|s| Some((s, s.read()? / 1000)))
+                        .collect();
+                    let max = match temps.iter().map(|(_, t)| *t).max() {
+                        Some(max) => max,
+                        None => {
+                            label.set_text("?");
+                            label.set_tooltip_text(Some("No sensor in /sys/class/hwmon"));
+                            return;
+                        }
+                    };
+                    let converted = |t: i64| format!("{:.0}", locale::temperature(t as f64));
+                    label.set_text(
+                        &config
+                            .format
+                            .replace("{temp}", &converted(max))
+                            .replace("{unit}", locale::temperature_symbol()),
+                    );
+                    label.set_tooltip_text(Some(
+                        &temps
+                            .iter()
+                            .map(|(s, t)| {
+                                format!(
+                                    "{}: {}{}",
+                                    s.id(),
+                                    converted(*t),
+                                    locale::temperature_symbol()
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    ));
+                    set_level_class(label, "temperature", max, config.warning, config.critical);
+                    history.borrow_mut().push(locale::temperature(max as f64));
+                    if let Some(ref popup) = popup {
+                        popup.update();
+                    }
+                });
+                update(&label);
+
+                glib::timeout_add_seconds_local(
+                    config.interval,
+                    glib::clone!(@weak label, @weak container, @strong update => @default-return Continue(false), move || {
+                        if !is_pinned(&container) {
+                            update(&label);
+                        }
+                        Continue(true)
+                    }),
+                );
+                add_refresh_action(
+                    container,
+                    &event_box,
+                    glib::clone!(@weak label => move || update(&label)),
+                );
+                add_pin_action(container, &event_box);
+            },
+        ),
+    )
+}
+
+/// Reads the memory usage in percent from /proc/meminfo.
+fn read_memory_usage() -> Option<f64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let field = |name: &str| -> Option<f64> {
+        meminfo
+            .lines()
+            .find(|l| l.starts_with(name))?
+            .split_whitespace()
+            .nth(1)?
+            .parse()
+            .ok()
+    };
+    let total = field("MemTotal:")?;
+    let available = field("MemAvailable:")?;
+    Some((total - available) * 100.0 / total)
+}
+
+/// Reads the total received and transmitted bytes from /proc/net/dev. The loopback is excluded.
+/// If `interface` is specified, only that interface is counted.
+fn read_net_bytes(interface: Option<&str>) -> Option<u64> {
+    let dev = std::fs::read_to_string("/proc/net/dev").ok()?;
+    Some(
+        dev.lines()
+            // Two header lines.
+            .skip(2)
+            .filter_map(|l| {
+                let (name, values) = l.split_once(':')?;
+                let name = name.trim();
+                if name == "lo" || interface.map_or(false, |i| i != name) {
+                    return None;
+                }
+                let values: Vec<u64> = values
+                    .split_whitespace()
+                    .filter_map(|v| v.parse().ok())
+                    .collect();
+                // Received bytes is the 1st column, and transmitted bytes is the 9th.
+                Some(values.get(0)? + values.get(8)?)
+            })
+            .sum(),
+    )
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum GraphSource {
+    /// CPU usage in percent.
+    Cpu,
+    /// Memory usage in percent.
+    Memory,
+    /// Network throughput (received + transmitted) in bytes per second.
+    Net,
+    /// The first number in the output of `command`.
+    Command,
+}
+
+impl Default for GraphSource {
+    fn default() -> Self {
+        GraphSource::Cpu
+    }
+}
+
+fn default_graph_samples() -> usize {
+    60
+}
+
+fn default_graph_width() -> i32 {
+    60
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct GraphConfig {
+    /// "cpu" (default), "memory", "net", or "command".
+    #[serde(default)]
+    source: GraphSource,
+
+    /// Command line for the "command" source.
+    #[serde(default)]
+    command: String,
+
+    /// Interface for the "net" source. All interfaces except the loopback if not specified.
+    #[serde(default)]
+    interface: Option<String>,
+
+    /// Interval in seconds to take a sample.
+    #[serde(default = "default_interval")]
+    interval: u32,
+
+    /// Number of samples in the graph.
+    #[serde(default = "default_graph_samples")]
+    samples: usize,
+
+    /// Width of the graph in pixels.
+    #[serde(default = "default_graph_width")]
+    width: i32,
+
+    /// [min, max] of the graph. Defaults to [0, 100] for percentages, and the min and max of the
+    /// samples for others.
+    #[serde(default)]
+    range: Option<(f64, f64)>,
+
+    /// Text shown next to the graph. "{value}" is replaced with the latest sample. No text if
+    /// empty.
+    #[serde(default)]
+    format: String,
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        GraphConfig {
+            source: GraphSource::default(),
+            command: String::new(),
+            interface: None,
+            interval: default_interval(),
+            samples: default_graph_samples(),
+            width: default_graph_width(),
+            range: None,
+            format: String::new(),
+        }
+    }
+}
+
+/// Returns a function that takes a sample. This runs in a worker thread.
+///
+/// The function returns None to skip the sample, e.g. if no CPU time elapsed.
+fn graph_sampler(config: &GraphConfig) -> Box<dyn Fn() -> Result<Option<f64>, String> + Send> {
+    match config.source {
+        GraphSource::Cpu => {
+            let prev = Cell::new(read_cpu_times());
+            Box::new(move || {
+                let cur = read_cpu_times().ok_or("Cannot read /proc/stat")?;
+                let prev = prev.replace(Some(cur)).unwrap_or((0, 0));
+                Ok(cpu_usage(prev, cur))
+            })
+        }
+        GraphSource::Memory => Box::new(|| {
+            read_memory_usage()
+                .map(Some)
+                .ok_or_else(|| "Cannot read /proc/meminfo".to_owned())
+        }),
+        GraphSource::Net => {
+            let interface = config.interface.clone();
+            let prev = Cell::new(None);
+            Box::new(move || {
+                let bytes =
+                    read_net_bytes(interface.as_deref()).ok_or("Cannot read /proc/net/dev")?;
+                let now = Instant::now();
+                // The first sample has no rate.
+                Ok(prev.replace(Some((bytes, now))).map(|(b, t)| {
+                    bytes.saturating_sub(b) as f64 / now.duration_since(t).as_secs_f64()
+                }))
+            })
+        }
+        GraphSource::Command => {
+            let command = config.command.clone();
+            Box::new(move || {
+                let out = crate::module_base::command::run_sync(&command)?;
+                out.split(|c: char| c.is_whitespace() || c == '%')
+                    .find_map(|w| w.parse().ok())
+                    .map(Some)
+                    .ok_or_else(|| format!("No number in the output of {}", command))
+            })
+        }
+    }
+}
+
+fn graph_module() -> FnModFactory<GraphConfig> {
+    FnModFactory::new(
+        "graph",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<GraphConfig>, container: &gtk::Box| {
+            let event_box = gtk::EventBox::new();
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+            hbox.style_context().add_class("graph");
+            let label = gtk::Label::new(None);
+            let history = Rc::new(RefCell::new(History::new(config.samples)));
+            let range = config.range.or(match config.source {
+                GraphSource::Cpu | GraphSource::Memory => Some((0.0, 100.0)),
+                GraphSource::Net | GraphSource::Command => None,
+            });
+            let area = sparkline(history.clone(), range);
+            area.set_size_request(config.width, -1);
+            hbox.add(&label);
+            hbox.add(&area);
+            label.set_no_show_all(config.format.is_empty());
+            event_box.add(&hbox);
+            container.add(&event_box);
+            add_export_action(container, history.clone());
+
+            let source = config.source;
+            let format = config.format.clone();
+            let poller = poll_in_worker(
+                Duration::from_secs(config.interval as u64),
+                graph_sampler(config),
+                glib::clone!(@weak hbox, @weak label, @weak area, @weak container => @default-return Continue(false), move |sample| {
+                    if is_pinned(&container) {
+                        return Continue(true);
+                    }
+                    let value = match sample {
+                        Ok(Some(v)) => v,
+                        Ok(None) => return Continue(true),
+                        Err(e) => {
+                            hbox.style_context().add_class("error");
+                            hbox.set_tooltip_text(Some(&e));
+                            return Continue(true);
+                        }
+                    };
+                    hbox.style_context().remove_class("error");
+                    history.borrow_mut().push(value);
+                    let text = match source {
+                        GraphSource::Cpu | GraphSource::Memory => format!("{:.0}%", value),
+                        GraphSource::Net => format!("{}/s", format_bytes(value as u64)),
+                        GraphSource::Command => format!("{:.1}", value),
+                    };
+                    label.set_text(&format.replace("{value}", &text));
+                    hbox.set_tooltip_text(Some(&text));
+                    area.queue_draw();
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(container, &event_box, move || poller.refresh());
+            add_pin_action(container, &event_box);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![
+        Box::new(cpu_module()),
+        Box::new(cpufreq_module()),
+        Box::new(fan_module()),
+        Box::new(graph_module()),
+        Box::new(loadavg_module()),
+        Box::new(temperature_module()),
+    ]
+}
+
+crate::register_plugin!("sysinfo", make_module_factories);
@@ -12,25 +12,169 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::module_base::{command, poll_command_in_worker};
 use gtk::prelude::*;
+use gtk::{gio, glib, pango};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use std::time::Duration;
 
-#[derive(Serialize, Deserialize)]
+/// Where the text is read from. Set either `file` or `command`.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct SourceConfig {
+    /// Path of a file. The text is updated when the file changes.
+    #[serde(default)]
+    file: Option<String>,
+
+    /// Command line run with `sh -c` every interval. The stdout is shown.
+    #[serde(default)]
+    command: Option<String>,
+}
+
+/// Where to put the ellipsis in a text longer than max_width_chars.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum Ellipsize {
+    Start,
+    Middle,
+    End,
+}
+
+impl Default for Ellipsize {
+    fn default() -> Self {
+        Ellipsize::End
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 struct TextModuleConfig {
+    /// The text. With a source, this is shown until the first read.
+    #[serde(default)]
     text: String,
+
+    /// Reads the text from a file or a command instead. The text is trimmed.
+    #[serde(default)]
+    source: Option<SourceConfig>,
+
+    /// Interval in seconds to run the command source, or to re-read the file source. Files in
+    /// /sys and /proc don't notify the changes, so set this for them. Defaults to 5 for the
+    /// command source.
+    #[serde(default)]
+    interval: Option<u64>,
+
+    /// Maximum width in characters. A longer text is ellipsized and the whole text is shown in
+    /// the tooltip.
+    #[serde(default)]
+    max_width_chars: Option<i32>,
+
+    /// "start", "middle", or "end" (default).
+    #[serde(default)]
+    ellipsize: Ellipsize,
 }
 
 struct TextModule {
-    config: TextModuleConfig,
+    config: Rc<TextModuleConfig>,
+}
+
+/// Shows the text or the error.
+fn show(label: &gtk::Label, config: &TextModuleConfig, result: Result<String, String>) {
+    let sc = label.style_context();
+    match result {
+        Ok(text) => {
+            let text = text.trim();
+            label.set_text(text);
+            sc.remove_class("error");
+            label.set_tooltip_text(config.max_width_chars.map(|_| text));
+        }
+        Err(e) => {
+            label.set_text("?");
+            sc.add_class("error");
+            label.set_tooltip_text(Some(&e));
+        }
+    }
+}
+
+/// Updates the label when the file changes, and every interval if set.
+fn watch_file(label: &gtk::Label, config: &Rc<TextModuleConfig>, path: &str) {
+    let path = path.to_owned();
+    let update = Rc::new(
+        glib::clone!(@weak label, @strong config, @strong path => move || {
+            let result = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Cannot read {}: {}", path, e));
+            show(&label, &config, result);
+        }),
+    );
+    update();
+
+    match gio::File::for_path(&path).monitor_file(
+        gio::FileMonitorFlags::WATCH_MOVES,
+        None::<&gio::Cancellable>,
+    ) {
+        Ok(monitor) => {
+            monitor.connect_changed(glib::clone!(@strong update => move |_, _, _, event| {
+                match event {
+                    gio::FileMonitorEvent::ChangesDoneHint
+                    | gio::FileMonitorEvent::Created
+                    | gio::FileMonitorEvent::Deleted
+                    | gio::FileMonitorEvent::MovedIn
+                    | gio::FileMonitorEvent::MovedOut
+                    | gio::FileMonitorEvent::Renamed => update(),
+                    _ => {}
+                }
+            }));
+            // Keep the monitor alive as long as the label.
+            label.connect_destroy(move |_| {
+                let _ = &monitor;
+            });
+        }
+        Err(e) => eprintln!("Cannot watch {}: {}", path, e),
+    }
+    if let Some(interval) = config.interval {
+        glib::timeout_add_seconds_local(
+            interval as u32,
+            glib::clone!(@weak label => @default-return Continue(false), move || {
+                update();
+                Continue(true)
+            }),
+        );
+    }
 }
 
 impl crate::module::Module for TextModule {
     fn build_ui(&self, container: &gtk::Box) {
         let label = gtk::Label::builder().label(&self.config.text).build();
+        if let Some(max_width_chars) = self.config.max_width_chars {
+            label.set_max_width_chars(max_width_chars);
+            label.set_ellipsize(match self.config.ellipsize {
+                Ellipsize::Start => pango::EllipsizeMode::Start,
+                Ellipsize::Middle => pango::EllipsizeMode::Middle,
+                Ellipsize::End => pango::EllipsizeMode::End,
+            });
+            label.set_tooltip_text(Some(&self.config.text));
+        }
         container.pack_start(&label, false, false, 0);
+
+        let source = match self.config.source {
+            Some(ref source) => source,
+            None => return,
+        };
+        if let Some(ref path) = source.file {
+            watch_file(&label, &self.config, path);
+        } else if let Some(ref command_line) = source.command {
+            let command_line = command_line.clone();
+            let config = self.config.clone();
+            poll_command_in_worker(
+                Duration::from_secs(config.interval.unwrap_or(5)),
+                move || command::run_sync(&command_line),
+                glib::clone!(@weak label => @default-return Continue(false), move |result: Result<String, String>| {
+                    show(&label, &config, result);
+                    Continue(true)
+                }),
+            );
+        }
     }
 }
-
 struct TextModuleFactory {}
 
 impl crate::module::ModuleFactory for TextModuleFactory {
@@ -38,13 +182,20 @@ impl crate::module::ModuleFactory for TextModuleFactory {
         "text"
     }
 
+    fn config_schema(&self, gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        gen.subschema_for::<TextModuleConfig>()
+    }
+
     fn create(
         &self,
         config: &serde_json::Value,
         _monitor: &gtk::gdk::Monitor,
-    ) -> Box<dyn crate::module::Module> {
-        let config = serde_json::from_str(&config.to_string()).expect("Failed to parse the config");
-        Box::new(TextModule { config })
+    ) -> Result<Box<dyn crate::module::Module>, crate::error::Error> {
+        let config = serde_json::from_str(&config.to_string())
+            .map_err(|e| crate::error::Error::module_config(self.name(), e))?;
+        Ok(Box::new(TextModule {
+            config: Rc::new(config),
+        }))
     }
 }
 
@@ -53,3 +204,5 @@ pub(crate) fn make_module_factories(
 ) -> Vec<Box<dyn crate::module::ModuleFactory>> {
     vec![Box::new(TextModuleFactory {})]
 }
+
+crate::register_plugin!("text", make_module_factories);
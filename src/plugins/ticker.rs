@@ -0,0 +1,204 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::http::Request;
+use crate::module_base::{
+    add_pin_action, add_refresh_action, is_pinned, poll_in_worker, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum Provider {
+    /// A generic JSON endpoint. `url` and `price_pointer` are needed.
+    Json,
+    /// CoinGecko. `coin` (e.g. "bitcoin") and `currency` (e.g. "usd") are needed.
+    Coingecko,
+}
+
+impl Default for Provider {
+    fn default() -> Self {
+        Provider::Json
+    }
+}
+
+fn default_interval() -> u64 {
+    300
+}
+
+fn default_decimals() -> usize {
+    2
+}
+
+fn default_currency() -> String {
+    "usd".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct TickerConfig {
+    /// Label shown before the price.
+    #[serde(default)]
+    symbol: String,
+
+    #[serde(default)]
+    provider: Provider,
+
+    /// URL of the JSON endpoint.
+    #[serde(default)]
+    url: String,
+
+    /// JSON pointer (e.g. "/data/price") to the price in the response.
+    #[serde(default)]
+    price_pointer: String,
+
+    /// JSON pointer to the percent change in the response. If not specified, the change from the
+    /// previous poll is shown.
+    #[serde(default)]
+    change_pointer: Option<String>,
+
+    #[serde(default)]
+    coin: String,
+
+    #[serde(default = "default_currency")]
+    currency: String,
+
+    /// Interval in seconds to poll the price.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Number of decimal places of the price.
+    #[serde(default = "default_decimals")]
+    decimals: usize,
+}
+
+impl Default for TickerConfig {
+    fn default() -> Self {
+        TickerConfig {
+            symbol: String::new(),
+            provider: Provider::default(),
+            url: String::new(),
+            price_pointer: String::new(),
+            change_pointer: None,
+            coin: String::new(),
+            currency: default_currency(),
+            interval: default_interval(),
+            decimals: default_decimals(),
+        }
+    }
+}
+
+/// Reads a number at the pointer. Numbers in strings (e.g. "123.4") are accepted too.
+fn number_at(v: &serde_json::Value, pointer: &str) -> Option<f64> {
+    let v = v.pointer(pointer)?;
+    v.as_f64().or_else(|| v.as_str()?.parse().ok())
+}
+
+/// Fetches (price, percent change).
+fn fetch(config: &TickerConfig) -> Result<(f64, Option<f64>), String> {
+    let (url, price_pointer, change_pointer) = match config.provider {
+        Provider::Json => (
+            config.url.clone(),
+            config.price_pointer.clone(),
+            config.change_pointer.clone(),
+        ),
+        Provider::Coingecko => (
+            format!(
+                "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies={}&include_24hr_change=true",
+                config.coin, config.currency
+            ),
+            format!("/{}/{}", config.coin, config.currency),
+            Some(format!("/{}/{}_24h_change", config.coin, config.currency)),
+        ),
+    };
+    let body = Request::new(&url).get()?;
+    let v: serde_json::Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    let price =
+        number_at(&v, &price_pointer).ok_or_else(|| format!("No number at {}", price_pointer))?;
+    Ok((price, change_pointer.and_then(|p| number_at(&v, &p))))
+}
+
+fn ticker_module() -> FnModFactory<TickerConfig> {
+    FnModFactory::new(
+        "ticker",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<TickerConfig>, container: &gtk::Box| {
+            let event_box = gtk::EventBox::new();
+            let label = gtk::Label::new(Some(&format!("{} …", config.symbol)));
+            label.style_context().add_class("ticker");
+            event_box.add(&label);
+            container.add(&event_box);
+
+            let worker_config = (**config).clone();
+            let config = config.clone();
+            let previous: Cell<Option<f64>> = Cell::new(None);
+            let poller = poll_in_worker(
+                Duration::from_secs(config.interval),
+                move || fetch(&worker_config),
+                glib::clone!(@weak label, @weak container => @default-return Continue(false), move |result| {
+                    if is_pinned(&container) {
+                        return Continue(true);
+                    }
+                    let sc = label.style_context();
+                    let (price, change) = match result {
+                        Ok(v) => v,
+                        Err(e) => {
+                            sc.add_class("error");
+                            label.set_tooltip_text(Some(&e));
+                            return Continue(true);
+                        }
+                    };
+                    sc.remove_class("error");
+                    label.set_tooltip_text(None);
+                    let change = change.or_else(|| {
+                        previous
+                            .get()
+                            .filter(|p| *p != 0.0)
+                            .map(|p| (price - p) / p * 100.0)
+                    });
+                    previous.set(Some(price));
+
+                    let mut text = format!("{} {:.*}", config.symbol, config.decimals, price);
+                    sc.remove_class("ticker-up");
+                    sc.remove_class("ticker-down");
+                    if let Some(change) = change {
+                        text += &format!(" {:+.2}%", change);
+                        if change > 0.0 {
+                            sc.add_class("ticker-up");
+                        } else if change < 0.0 {
+                            sc.add_class("ticker-down");
+                        }
+                    }
+                    label.set_text(text.trim());
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(container, &event_box, move || poller.refresh());
+            add_pin_action(container, &event_box);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(ticker_module())]
+}
+
+crate::register_plugin!("ticker", make_module_factories);
@@ -0,0 +1,270 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{FnModFactory, JSONConfigFactory, StateStore};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct Preset {
+    name: String,
+
+    /// Duration like "45m", "1h30m", or "90s".
+    duration: String,
+}
+
+fn default_label() -> String {
+    "⏲".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct TimerConfig {
+    /// Label shown when no timer is running.
+    #[serde(default = "default_label")]
+    label: String,
+
+    /// Presets shown in the popover. Each click starts an independent timer. Running timers
+    /// continue after a restart.
+    #[serde(default)]
+    presets: Vec<Preset>,
+}
+
+impl Default for TimerConfig {
+    fn default() -> Self {
+        TimerConfig {
+            label: default_label(),
+            presets: vec![],
+        }
+    }
+}
+
+/// Parses a duration like "45m", "1h30m", or "90s". A number without a unit is in minutes.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut secs = 0;
+    let mut num = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        let n: u64 = num.parse().ok()?;
+        num.clear();
+        secs += n * match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+    }
+    if !num.is_empty() {
+        secs += num.parse::<u64>().ok()? * 60;
+    }
+    Some(Duration::from_secs(secs))
+}
+
+fn format_remaining(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+struct Timer {
+    name: String,
+    end: Instant,
+}
+
+/// A running timer in the state store. `end` is in seconds since the UNIX epoch.
+#[derive(Serialize, Deserialize)]
+struct SavedTimer {
+    name: String,
+    end: u64,
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Saves the running timers so that they continue after a restart.
+fn save(store: &StateStore, timers: &[Timer]) {
+    let now = Instant::now();
+    let saved: Vec<SavedTimer> = timers
+        .iter()
+        .map(|t| SavedTimer {
+            name: t.name.clone(),
+            end: (unix_now() + t.end.saturating_duration_since(now)).as_secs(),
+        })
+        .collect();
+    store.set("timers", &saved);
+}
+
+/// Returns the saved timers. The timers that ended while jiji was not running are dropped.
+fn restore(store: &StateStore) -> Vec<Timer> {
+    let now = Instant::now();
+    store
+        .get::<Vec<SavedTimer>>("timers")
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|t| {
+            let remaining = Duration::from_secs(t.end).checked_sub(unix_now())?;
+            Some(Timer {
+                name: t.name,
+                end: now + remaining,
+            })
+        })
+        .collect()
+}
+
+fn notify(name: &str) {
+    let notification = gio::Notification::new(&format!("{} is done", name));
+    notification.set_priority(gio::NotificationPriority::High);
+    match gio::Application::default() {
+        Some(app) => app.send_notification(None, &notification),
+        None => eprintln!("Timer {} is done", name),
+    }
+}
+
+/// Updates the label and fires the finished timers.
+fn update(label: &gtk::Label, timers: &RefCell<Vec<Timer>>, idle_label: &str, store: &StateStore) {
+    let now = Instant::now();
+    let mut timers = timers.borrow_mut();
+    let count = timers.len();
+    timers.retain(|t| {
+        if t.end <= now {
+            notify(&t.name);
+            false
+        } else {
+            true
+        }
+    });
+    if timers.len() != count {
+        save(store, &timers);
+    }
+    if timers.is_empty() {
+        label.set_text(idle_label);
+        return;
+    }
+    let texts: Vec<String> = timers
+        .iter()
+        .map(|t| format!("{} {}", t.name, format_remaining(t.end - now)))
+        .collect();
+    label.set_text(&texts.join(" · "));
+}
+
+fn timer_module() -> FnModFactory<TimerConfig> {
+    FnModFactory::new(
+        "timer",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<TimerConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("timer");
+            let label = gtk::Label::new(Some(&config.label));
+            button.add(&label);
+            container.add(&button);
+
+            let store = StateStore::new(container);
+            let timers = Rc::new(RefCell::new(restore(&store)));
+            let popover = gtk::Popover::new(Some(&button));
+            let vbox = gtk::Box::new(gtk::Orientation::Vertical, 4);
+            let presets = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            let running = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            vbox.add(&presets);
+            vbox.add(&gtk::Separator::new(gtk::Orientation::Horizontal));
+            vbox.add(&running);
+            popover.add(&vbox);
+
+            for preset in &config.presets {
+                let duration = match parse_duration(&preset.duration) {
+                    Some(d) => d,
+                    None => {
+                        eprintln!("Invalid duration for {}: {}", preset.name, preset.duration);
+                        continue;
+                    }
+                };
+                let b = gtk::Button::with_label(&format!("{} ({})", preset.name, preset.duration));
+                b.set_relief(gtk::ReliefStyle::None);
+                let name = preset.name.clone();
+                let idle_label = config.label.clone();
+                b.connect_clicked(
+                    glib::clone!(@weak popover, @weak label, @strong timers, @strong store => move |_| {
+                        timers.borrow_mut().push(Timer {
+                            name: name.clone(),
+                            end: Instant::now() + duration,
+                        });
+                        save(&store, &timers.borrow());
+                        update(&label, &timers, &idle_label, &store);
+                        popover.popdown();
+                    }),
+                );
+                presets.add(&b);
+            }
+
+            let config = config.clone();
+            button.connect_clicked(glib::clone!(@weak popover, @weak running, @weak label, @strong timers, @strong config, @strong store => move |_| {
+                for ref child in running.children() {
+                    running.remove(child);
+                }
+                let now = Instant::now();
+                for t in timers.borrow().iter() {
+                    let b = gtk::Button::with_label(&format!(
+                        "Cancel {} ({})",
+                        t.name,
+                        format_remaining(t.end.saturating_duration_since(now))
+                    ));
+                    b.set_relief(gtk::ReliefStyle::None);
+                    let idle_label = config.label.clone();
+                    let end = t.end;
+                    b.connect_clicked(glib::clone!(@weak popover, @weak label, @strong timers, @strong store => move |_| {
+                        // Timers are identified by the end time.
+                        timers.borrow_mut().retain(|t| t.end != end);
+                        save(&store, &timers.borrow());
+                        update(&label, &timers, &idle_label, &store);
+                        popover.popdown();
+                    }));
+                    running.add(&b);
+                }
+                popover.show_all();
+                popover.popup();
+            }));
+
+            let idle_label = config.label.clone();
+            update(&label, &timers, &idle_label, &store);
+            glib::timeout_add_seconds_local(
+                1,
+                glib::clone!(@weak label => @default-return Continue(false), move || {
+                    update(&label, &timers, &idle_label, &store);
+                    Continue(true)
+                }),
+            );
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(timer_module())]
+}
+
+crate::register_plugin!("timer", make_module_factories);
@@ -0,0 +1,318 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{add_refresh_action, poll_in_worker, FnModFactory, JSONConfigFactory};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum Source {
+    /// `task export`.
+    Taskwarrior,
+    /// A todo.txt file. Tasks are due by the "due:YYYY-MM-DD" tag.
+    TodoTxt,
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source::Taskwarrior
+    }
+}
+
+fn default_format() -> String {
+    "☑ {count}".to_owned()
+}
+
+fn default_limit() -> usize {
+    5
+}
+
+fn default_interval() -> u64 {
+    300
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct TodoConfig {
+    #[serde(default)]
+    source: Source,
+
+    /// Path to watch for changes. This is the todo.txt file for "todo-txt" (defaults to
+    /// ~/todo.txt), and the data directory for "taskwarrior" (defaults to ~/.task).
+    #[serde(default)]
+    path: Option<PathBuf>,
+
+    /// Format of the label. "{count}" is replaced with the number of tasks due today or overdue.
+    #[serde(default = "default_format")]
+    format: String,
+
+    /// Number of tasks listed in the popover.
+    #[serde(default = "default_limit")]
+    limit: usize,
+
+    /// Command to open the task app from the popover.
+    #[serde(default)]
+    command: Option<String>,
+
+    /// Interval in seconds to re-read the tasks. The path is also watched for changes.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Hide the module while there's no task due.
+    #[serde(default)]
+    hide_empty: bool,
+}
+
+impl Default for TodoConfig {
+    fn default() -> Self {
+        TodoConfig {
+            source: Source::default(),
+            path: None,
+            format: default_format(),
+            limit: default_limit(),
+            command: None,
+            interval: default_interval(),
+            hide_empty: false,
+        }
+    }
+}
+
+impl TodoConfig {
+    fn path(&self) -> PathBuf {
+        self.path.clone().unwrap_or_else(|| {
+            glib::home_dir().join(match self.source {
+                Source::Taskwarrior => ".task",
+                Source::TodoTxt => "todo.txt",
+            })
+        })
+    }
+}
+
+/// A task due today or overdue.
+struct Task {
+    description: String,
+    /// Due date in "YYYY-MM-DD".
+    due: String,
+}
+
+/// Returns today in "YYYY-MM-DD".
+fn today() -> String {
+    glib::DateTime::new_now_local()
+        .and_then(|t| t.format("%Y-%m-%d"))
+        .map(|s| s.to_string())
+        .unwrap_or_default()
+}
+
+/// Reads the pending tasks due by today, sorted by the urgency.
+fn read_taskwarrior() -> Result<Vec<Task>, String> {
+    let out = Command::new("task")
+        .args(&[
+            "rc.verbose=nothing",
+            "rc.confirmation=off",
+            "status:pending",
+            "due.before:tomorrow",
+            "export",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Cannot run task: {}", e))?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_owned());
+    }
+    let mut tasks: Vec<serde_json::Value> =
+        serde_json::from_slice(&out.stdout).map_err(|e| e.to_string())?;
+    let urgency = |t: &serde_json::Value| t["urgency"].as_f64().unwrap_or(0.0);
+    tasks.sort_by(|a, b| {
+        urgency(b)
+            .partial_cmp(&urgency(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(tasks
+        .iter()
+        .map(|t| {
+            // "YYYYMMDDTHHMMSSZ"
+            let due = t["due"].as_str().unwrap_or_default();
+            Task {
+                description: t["description"].as_str().unwrap_or_default().to_owned(),
+                due: match (due.get(0..4), due.get(4..6), due.get(6..8)) {
+                    (Some(y), Some(m), Some(d)) => format!("{}-{}-{}", y, m, d),
+                    _ => String::new(),
+                },
+            }
+        })
+        .collect())
+}
+
+/// Reads the incomplete tasks due by today, sorted by the priority and the due date.
+fn read_todo_txt(path: &PathBuf) -> Result<Vec<Task>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let today = today();
+    let mut tasks: Vec<(String, Task)> = text
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with("x "))
+        .filter_map(|l| {
+            let due = l
+                .split_whitespace()
+                .find_map(|w| w.strip_prefix("due:"))?
+                .to_owned();
+            if due > today {
+                return None;
+            }
+            // "(A) " is the priority. Tasks without a priority come last.
+            let priority = match l.get(0..4) {
+                Some(p) if p.starts_with('(') && p.ends_with(") ") => p.to_owned(),
+                _ => "(~) ".to_owned(),
+            };
+            let description = l
+                .trim_start_matches(priority.as_str())
+                .split_whitespace()
+                .filter(|w| !w.starts_with("due:"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            Some((priority, Task { description, due }))
+        })
+        .collect();
+    tasks.sort_by(|a, b| (&a.0, &a.1.due).cmp(&(&b.0, &b.1.due)));
+    Ok(tasks.into_iter().map(|(_, t)| t).collect())
+}
+
+fn todo_module() -> FnModFactory<TodoConfig> {
+    FnModFactory::new(
+        "todo",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<TodoConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("todo");
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            let vbox = gtk::Box::new(gtk::Orientation::Vertical, 4);
+            vbox.set_border_width(6);
+            let list = gtk::Box::new(gtk::Orientation::Vertical, 2);
+            vbox.add(&list);
+            if let Some(ref command) = config.command {
+                let open = gtk::Button::with_label("Open");
+                let command = command.clone();
+                open.connect_clicked(glib::clone!(@weak popover => move |_| {
+                    popover.popdown();
+                    crate::module_base::command::run(&command, |result| {
+                        if let Err(e) = result {
+                            eprintln!("todo: {}", e);
+                        }
+                    });
+                }));
+                vbox.add(&open);
+            }
+            vbox.show_all();
+            popover.add(&vbox);
+            button.connect_clicked(glib::clone!(@weak popover => move |_| {
+                popover.popup();
+            }));
+
+            let source = config.source;
+            let path = config.path();
+            let watched = path.clone();
+            let config = config.clone();
+            let poller = Rc::new(poll_in_worker(
+                Duration::from_secs(config.interval),
+                move || match source {
+                    Source::Taskwarrior => read_taskwarrior(),
+                    Source::TodoTxt => read_todo_txt(&path),
+                },
+                glib::clone!(@weak button, @weak label, @weak list => @default-return Continue(false), move |result: Result<Vec<Task>, String>| {
+                    let sc = button.style_context();
+                    let tasks = match result {
+                        Ok(tasks) => tasks,
+                        Err(e) => {
+                            label.set_text(&config.format.replace("{count}", "?"));
+                            sc.add_class("error");
+                            button.set_tooltip_text(Some(&e));
+                            return Continue(true);
+                        }
+                    };
+                    sc.remove_class("error");
+                    button.set_tooltip_text(None);
+                    label.set_text(&config.format.replace("{count}", &tasks.len().to_string()));
+                    if tasks.is_empty() {
+                        sc.add_class("todo-empty");
+                    } else {
+                        sc.remove_class("todo-empty");
+                    }
+                    if config.hide_empty {
+                        button.set_visible(!tasks.is_empty());
+                    }
+                    for ref child in list.children() {
+                        list.remove(child);
+                    }
+                    for task in tasks.iter().take(config.limit) {
+                        let row = gtk::Label::new(Some(&format!("{}  {}", task.due, task.description)));
+                        row.set_xalign(0.0);
+                        list.add(&row);
+                    }
+                    if tasks.is_empty() {
+                        list.add(&gtk::Label::new(Some("No tasks due")));
+                    }
+                    list.show_all();
+                    Continue(true)
+                }),
+            ));
+            add_refresh_action(
+                container,
+                &button,
+                glib::clone!(@strong poller => move || poller.refresh()),
+            );
+
+            // Catch up with the changes immediately.
+            let file = gio::File::for_path(&watched);
+            let monitor = if source == Source::Taskwarrior {
+                file.monitor_directory(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+            } else {
+                file.monitor_file(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+            };
+            match monitor {
+                Ok(monitor) => {
+                    monitor.connect_changed(move |_, _, _, event| {
+                        if event == gio::FileMonitorEvent::ChangesDoneHint {
+                            poller.refresh();
+                        }
+                    });
+                    // Keep the monitor alive as long as the button.
+                    button.connect_destroy(move |_| {
+                        let _ = &monitor;
+                    });
+                }
+                Err(e) => eprintln!("Cannot watch {}: {}", watched.display(), e),
+            }
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(todo_module())]
+}
+
+crate::register_plugin!("todo", make_module_factories);
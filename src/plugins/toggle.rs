@@ -0,0 +1,186 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{command, poll_command_in_worker, FnModFactory, JSONConfigFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::Duration;
+
+fn default_interval() -> u64 {
+    5
+}
+
+fn default_on_label() -> String {
+    "on".to_owned()
+}
+
+fn default_off_label() -> String {
+    "off".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ToggleConfig {
+    /// Command line run with `sh -c` to check the state. The state is on if it exits with 0.
+    check_command: String,
+
+    /// Command line run with `sh -c` on click while the state is off.
+    on_command: String,
+
+    /// Command line run with `sh -c` on click while the state is on.
+    off_command: String,
+
+    /// Interval in seconds to run check_command. It's also run after on_command and off_command.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Label shown while the state is on.
+    #[serde(default = "default_on_label")]
+    on_label: String,
+
+    /// Label shown while the state is off.
+    #[serde(default = "default_off_label")]
+    off_label: String,
+
+    /// CSS class added while the state is on, in addition to "toggle-on".
+    #[serde(default)]
+    on_class: Option<String>,
+
+    /// CSS class added while the state is off, in addition to "toggle-off".
+    #[serde(default)]
+    off_class: Option<String>,
+}
+
+impl Default for ToggleConfig {
+    fn default() -> Self {
+        ToggleConfig {
+            check_command: String::new(),
+            on_command: String::new(),
+            off_command: String::new(),
+            interval: default_interval(),
+            on_label: default_on_label(),
+            off_label: default_off_label(),
+            on_class: None,
+            off_class: None,
+        }
+    }
+}
+
+/// Runs the check command and returns true if the state is on.
+fn check(command_line: &str) -> Result<bool, String> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(command_line)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .map_err(|e| format!("Cannot run {}: {}", command_line, e))
+}
+
+/// Shows the state with the label and the CSS classes.
+fn show_state(button: &gtk::Button, config: &ToggleConfig, on: bool) {
+    let sc = button.style_context();
+    let classes = [
+        (Some("toggle-on"), on),
+        (Some("toggle-off"), !on),
+        (config.on_class.as_deref(), on),
+        (config.off_class.as_deref(), !on),
+    ];
+    // Remove first so that the same class in on_class and off_class stays.
+    for (class, enabled) in classes.iter() {
+        if let (Some(class), false) = (class, enabled) {
+            sc.remove_class(class);
+        }
+    }
+    for (class, enabled) in classes.iter() {
+        if let (Some(class), true) = (class, enabled) {
+            sc.add_class(class);
+        }
+    }
+    button.set_label(if on {
+        &config.on_label
+    } else {
+        &config.off_label
+    });
+}
+
+fn toggle_module() -> FnModFactory<ToggleConfig> {
+    FnModFactory::new(
+        "toggle",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<ToggleConfig>, container: &gtk::Box| {
+            let button = gtk::Button::with_label(&config.off_label);
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("toggle");
+            container.add(&button);
+
+            let check_command = config.check_command.clone();
+            let poller = Rc::new(poll_command_in_worker(
+                Duration::from_secs(config.interval),
+                move || check(&check_command),
+                glib::clone!(@weak button, @strong config => @default-return Continue(false), move |result: Result<bool, String>| {
+                    match result {
+                        Ok(on) => {
+                            button.style_context().remove_class("error");
+                            button.set_tooltip_text(None);
+                            show_state(&button, &config, on);
+                        }
+                        Err(e) => {
+                            button.style_context().add_class("error");
+                            button.set_tooltip_text(Some(&e));
+                        }
+                    }
+                    Continue(true)
+                }),
+            ));
+
+            let config = config.clone();
+            button.connect_clicked(move |button| {
+                // The label shows the last checked state.
+                let on = button.style_context().has_class("toggle-on");
+                let command_line = if on {
+                    &config.off_command
+                } else {
+                    &config.on_command
+                };
+                button.set_sensitive(false);
+                command::run(
+                    command_line,
+                    glib::clone!(@weak button, @strong poller => move |result| {
+                        button.set_sensitive(true);
+                        if let Err(e) = result {
+                            eprintln!("{}", e);
+                            button.style_context().add_class("error");
+                            button.set_tooltip_text(Some(&e));
+                        }
+                        poller.refresh();
+                    }),
+                );
+            });
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(toggle_module())]
+}
+
+crate::register_plugin!("toggle", make_module_factories);
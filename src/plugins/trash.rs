@@ -0,0 +1,218 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{
+    add_pin_action, add_refresh_action, format_bytes, is_pinned, poll_in_worker, FnModFactory,
+    JSONConfigFactory,
+};
+use crate::module_base::{task, touch};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use std::time::Duration;
+
+const TRASH_URI: &str = "trash:///";
+
+fn default_interval() -> u64 {
+    300
+}
+
+fn default_format() -> String {
+    "🗑 {count}".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct TrashConfig {
+    /// Interval in seconds to re-read the trash. The trash is also watched for changes.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Format of the label. "{count}" and "{size}" are replaced.
+    #[serde(default = "default_format")]
+    format: String,
+
+    /// Hide the module while the trash is empty.
+    #[serde(default)]
+    hide_empty: bool,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            interval: default_interval(),
+            format: default_format(),
+            hide_empty: false,
+        }
+    }
+}
+
+fn children(dir: &gio::File) -> Vec<(gio::File, gio::FileInfo)> {
+    let enumerator = match dir.enumerate_children(
+        "standard::name,standard::type,standard::size",
+        gio::FileQueryInfoFlags::NOFOLLOW_SYMLINKS,
+        None::<&gio::Cancellable>,
+    ) {
+        Ok(e) => e,
+        Err(_) => return vec![],
+    };
+    let mut ret = vec![];
+    while let Ok(Some(info)) = enumerator.next_file(None::<&gio::Cancellable>) {
+        ret.push((dir.child(info.name()), info));
+    }
+    ret
+}
+
+fn total_size(dir: &gio::File) -> u64 {
+    children(dir)
+        .iter()
+        .map(|(file, info)| {
+            if info.file_type() == gio::FileType::Directory {
+                total_size(file)
+            } else {
+                info.size().max(0) as u64
+            }
+        })
+        .sum()
+}
+
+/// Returns (item count, total size in bytes) of the trash.
+fn read_trash() -> (u32, u64) {
+    let trash = gio::File::for_uri(TRASH_URI);
+    let count = trash
+        .query_info(
+            "trash::item-count",
+            gio::FileQueryInfoFlags::NONE,
+            None::<&gio::Cancellable>,
+        )
+        .map(|info| info.attribute_uint32("trash::item-count"))
+        .unwrap_or(0);
+    let size = if count > 0 { total_size(&trash) } else { 0 };
+    (count, size)
+}
+
+fn empty_trash() {
+    let trash = gio::File::for_uri(TRASH_URI);
+    for (file, _) in children(&trash) {
+        if let Err(e) = file.delete(None::<&gio::Cancellable>) {
+            eprintln!("Cannot delete {}: {}", file.uri(), e);
+        }
+    }
+}
+
+fn confirm_empty(parent: &gtk::Button) -> bool {
+    let window = parent
+        .toplevel()
+        .and_then(|w| w.downcast::<gtk::Window>().ok());
+    let dialog = gtk::MessageDialog::new(
+        window.as_ref(),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::OkCancel,
+        "Permanently delete all items in the trash?",
+    );
+    let response = dialog.run();
+    dialog.close();
+    response == gtk::ResponseType::Ok
+}
+
+fn trash_module() -> FnModFactory<TrashConfig> {
+    FnModFactory::new(
+        "trash",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<TrashConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("trash");
+            button.set_tooltip_text(Some("Click to open, right click to empty"));
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+
+            let config = config.clone();
+            let poller = Rc::new(poll_in_worker(
+                Duration::from_secs(config.interval),
+                read_trash,
+                glib::clone!(@weak button, @weak label, @weak container => @default-return Continue(false), move |(count, size)| {
+                    if is_pinned(&container) {
+                        return Continue(true);
+                    }
+                    label.set_text(
+                        &config
+                            .format
+                            .replace("{count}", &count.to_string())
+                            .replace("{size}", &format_bytes(size)),
+                    );
+                    let sc = button.style_context();
+                    if count == 0 {
+                        sc.add_class("trash-empty");
+                    } else {
+                        sc.remove_class("trash-empty");
+                    }
+                    if config.hide_empty {
+                        button.set_visible(count > 0);
+                    }
+                    Continue(true)
+                }),
+            ));
+
+            // Catch up with the changes immediately.
+            let monitor = gio::File::for_uri(TRASH_URI)
+                .monitor_directory(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+                .ok();
+            if let Some(ref monitor) = monitor {
+                monitor.connect_changed(glib::clone!(@strong poller => move |_, _, _, _| {
+                    poller.refresh();
+                }));
+            }
+
+            button.connect_clicked(|_| {
+                if let Err(e) =
+                    gio::AppInfo::launch_default_for_uri(TRASH_URI, None::<&gio::AppLaunchContext>)
+                {
+                    eprintln!("Cannot open the trash: {}", e);
+                }
+            });
+            let empty = Rc::new(glib::clone!(@strong poller => move |button: &gtk::Button| {
+                if confirm_empty(button) {
+                    let poller = poller.clone();
+                    task::spawn_blocking(empty_trash, move |()| poller.refresh());
+                }
+            }));
+            button.connect_button_release_event(glib::clone!(@strong empty => move |button, e| {
+                if e.button() != gtk::gdk::BUTTON_SECONDARY {
+                    return Inhibit(false);
+                }
+                empty(button);
+                Inhibit(true)
+            }));
+            touch::connect_long_press(&button, move |button| empty(button));
+            add_refresh_action(container, &button, move || {
+                // Keep the monitor alive as long as the module.
+                let _ = &monitor;
+                poller.refresh();
+            });
+            add_pin_action(container, &button);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(trash_module())]
+}
+
+crate::register_plugin!("trash", make_module_factories);
@@ -0,0 +1,198 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{
+    add_pin_action, add_refresh_action, command, is_pinned, poll_in_worker, FnModFactory,
+    JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use std::time::Duration;
+
+fn default_interfaces() -> Vec<String> {
+    vec!["wg".to_owned(), "tun".to_owned(), "tailscale".to_owned()]
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+fn default_off_label() -> String {
+    "VPN off".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct VpnConfig {
+    /// Prefixes of the tunnel interface names.
+    #[serde(default = "default_interfaces")]
+    interfaces: Vec<String>,
+
+    /// Interval in seconds to check the interfaces.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Label shown when no tunnel is up.
+    #[serde(default = "default_off_label")]
+    off_label: String,
+
+    /// Command run on click while disconnected.
+    #[serde(default)]
+    connect_command: Option<String>,
+
+    /// Command run on click while connected.
+    #[serde(default)]
+    disconnect_command: Option<String>,
+
+    /// NetworkManager connection toggled on click if the commands are not specified.
+    #[serde(default)]
+    nm_connection: Option<String>,
+}
+
+impl Default for VpnConfig {
+    fn default() -> Self {
+        VpnConfig {
+            interfaces: default_interfaces(),
+            interval: default_interval(),
+            off_label: default_off_label(),
+            connect_command: None,
+            disconnect_command: None,
+            nm_connection: None,
+        }
+    }
+}
+
+/// Returns the host name of the Tailscale exit node in use.
+fn tailscale_exit_node() -> Option<String> {
+    let out = command::run_sync("tailscale status --json").ok()?;
+    let v: serde_json::Value = serde_json::from_str(&out).ok()?;
+    v.get("Peer")?.as_object()?.values().find_map(|p| {
+        if p.get("ExitNode")?.as_bool()? {
+            Some(p.get("HostName")?.as_str()?.to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the names of the tunnels that are up.
+fn active_tunnels(prefixes: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir("/sys/class/net")
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|n| prefixes.iter().any(|p| n.starts_with(p.as_str())))
+                .filter(|n| {
+                    // WireGuard and tun devices report "unknown" while up.
+                    let state = std::fs::read_to_string(format!("/sys/class/net/{}/operstate", n))
+                        .unwrap_or_default();
+                    state.trim() != "down"
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    for name in names.iter_mut() {
+        if name.starts_with("tailscale") {
+            if let Some(exit_node) = tailscale_exit_node() {
+                *name = format!("{} via {}", name, exit_node);
+            }
+        }
+    }
+    names
+}
+
+fn toggle_command(config: &VpnConfig, connected: bool) -> Option<String> {
+    let command = if connected {
+        &config.disconnect_command
+    } else {
+        &config.connect_command
+    };
+    if let Some(ref c) = command {
+        return Some(c.clone());
+    }
+    let name = config.nm_connection.as_ref()?;
+    Some(format!(
+        "nmcli connection {} '{}'",
+        if connected { "down" } else { "up" },
+        name.replace('\'', "'\\''")
+    ))
+}
+
+fn vpn_module() -> FnModFactory<VpnConfig> {
+    FnModFactory::new(
+        "vpn",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<VpnConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("vpn");
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+
+            let prefixes = config.interfaces.clone();
+            let off_label = config.off_label.clone();
+            let poller = Rc::new(poll_in_worker(
+                Duration::from_secs(config.interval),
+                move || active_tunnels(&prefixes),
+                glib::clone!(@weak button, @weak label, @weak container => @default-return Continue(false), move |tunnels| {
+                    if is_pinned(&container) {
+                        return Continue(true);
+                    }
+                    let sc = button.style_context();
+                    if tunnels.is_empty() {
+                        label.set_text(&off_label);
+                        sc.add_class("vpn-off");
+                    } else {
+                        label.set_text(&tunnels.join(" "));
+                        sc.remove_class("vpn-off");
+                    }
+                    Continue(true)
+                }),
+            ));
+
+            let config = config.clone();
+            button.connect_clicked(glib::clone!(@strong poller => move |button| {
+                let connected = !button.style_context().has_class("vpn-off");
+                let command_line = match toggle_command(&config, connected) {
+                    Some(c) => c,
+                    None => return,
+                };
+                button.set_sensitive(false);
+                command::run(
+                    &command_line,
+                    glib::clone!(@weak button, @strong poller => move |result| {
+                        button.set_sensitive(true);
+                        button.set_tooltip_text(result.err().as_deref());
+                        poller.refresh();
+                    }),
+                );
+            }));
+            add_refresh_action(container, &button, move || poller.refresh());
+            add_pin_action(container, &button);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(vpn_module())]
+}
+
+crate::register_plugin!("vpn", make_module_factories);
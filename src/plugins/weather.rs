@@ -0,0 +1,315 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::http::Request;
+use crate::module_base::icon::{self, Icon};
+use crate::module_base::locale;
+use crate::module_base::{
+    add_pin_action, add_refresh_action, is_pinned, poll_in_worker, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum Provider {
+    /// api.met.no. No API key is needed.
+    MetNo,
+    /// OpenWeatherMap. `api_key` is needed.
+    Openweathermap,
+}
+
+impl Default for Provider {
+    fn default() -> Self {
+        Provider::MetNo
+    }
+}
+
+fn default_interval() -> u64 {
+    30 * 60
+}
+
+fn default_format() -> String {
+    "{icon} {temp}°".to_owned()
+}
+
+fn default_forecast_count() -> usize {
+    8
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct WeatherConfig {
+    /// The weather provider. "met-no" (default) or "openweathermap".
+    #[serde(default)]
+    provider: Provider,
+
+    latitude: f64,
+    longitude: f64,
+
+    /// API key for OpenWeatherMap.
+    #[serde(default)]
+    api_key: String,
+
+    /// Interval in seconds to fetch the weather.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Format of the label. "{icon}", "{temp}" (in the unit of the locale config), and
+    /// "{conditions}" are replaced.
+    #[serde(default = "default_format")]
+    format: String,
+
+    /// Number of entries shown in the forecast popover.
+    #[serde(default = "default_forecast_count")]
+    forecast_count: usize,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        WeatherConfig {
+            provider: Provider::default(),
+            latitude: 0.0,
+            longitude: 0.0,
+            api_key: String::new(),
+            interval: default_interval(),
+            format: default_format(),
+            forecast_count: default_forecast_count(),
+        }
+    }
+}
+
+/// Weather at a point of time.
+#[derive(Clone, Debug)]
+struct Conditions {
+    /// UNIX time.
+    time: i64,
+    /// Temperature in Celsius.
+    temp: f64,
+    description: String,
+    /// Semantic icon name.
+    icon: &'static str,
+}
+
+/// Maps the met.no symbol code or OpenWeatherMap condition to a semantic icon name.
+fn icon_name(condition: &str) -> &'static str {
+    let c = condition.to_lowercase();
+    if c.contains("thunder") {
+        "weather-storm"
+    } else if c.contains("snow") || c.contains("sleet") {
+        "weather-snow"
+    } else if c.contains("rain") || c.contains("drizzle") {
+        "weather-rain"
+    } else if c.contains("fog") || c.contains("mist") || c.contains("haze") {
+        "weather-fog"
+    } else if c.contains("cloud") {
+        "weather-cloudy"
+    } else {
+        "weather-clear"
+    }
+}
+
+fn parse_met_no(body: &str) -> Option<Vec<Conditions>> {
+    let v: serde_json::Value = serde_json::from_str(body).ok()?;
+    let series = v.pointer("/properties/timeseries")?.as_array()?;
+    Some(
+        series
+            .iter()
+            .filter_map(|e| {
+                let symbol = e
+                    .pointer("/data/next_1_hours/summary/symbol_code")
+                    .or_else(|| e.pointer("/data/next_6_hours/summary/symbol_code"))?
+                    .as_str()?;
+                // e.g. "partlycloudy_day" -> "partlycloudy"
+                let description = symbol.split('_').next().unwrap_or(symbol).to_owned();
+                Some(Conditions {
+                    time: glib::DateTime::from_iso8601(e.get("time")?.as_str()?, None)?.to_unix(),
+                    temp: e
+                        .pointer("/data/instant/details/air_temperature")?
+                        .as_f64()?,
+                    icon: icon_name(&description),
+                    description,
+                })
+            })
+            .collect(),
+    )
+}
+
+fn parse_openweathermap_entry(e: &serde_json::Value) -> Option<Conditions> {
+    let main = e.pointer("/weather/0/main")?.as_str()?;
+    Some(Conditions {
+        time: e.get("dt")?.as_i64()?,
+        temp: e.pointer("/main/temp")?.as_f64()?,
+        description: e.pointer("/weather/0/description")?.as_str()?.to_owned(),
+        icon: icon_name(main),
+    })
+}
+
+/// Fetches the current conditions followed by the forecast. With `force`, a cached response isn't
+/// used.
+fn fetch(config: &WeatherConfig, force: bool) -> Result<Vec<Conditions>, String> {
+    let ttl = if force {
+        Duration::ZERO
+    } else {
+        Duration::from_secs(config.interval / 2)
+    };
+    let parse_error = || "Failed to parse the weather".to_owned();
+    match config.provider {
+        Provider::MetNo => {
+            let url = format!(
+                "https://api.met.no/weatherapi/locationforecast/2.0/compact?lat={:.4}&lon={:.4}",
+                config.latitude, config.longitude
+            );
+            let body = Request::new(&url)
+                .header("User-Agent", "jiji https://github.com/draftcode/jiji")
+                .get_cached(ttl)?;
+            parse_met_no(&body).ok_or_else(parse_error)
+        }
+        Provider::Openweathermap => {
+            let query = format!(
+                "lat={}&lon={}&units=metric&appid={}",
+                config.latitude, config.longitude, config.api_key
+            );
+            let current = Request::new(&format!(
+                "https://api.openweathermap.org/data/2.5/weather?{}",
+                query
+            ))
+            .get_cached(ttl)?;
+            let forecast = Request::new(&format!(
+                "https://api.openweathermap.org/data/2.5/forecast?{}",
+                query
+            ))
+            .get_cached(ttl)?;
+
+            let current: serde_json::Value =
+                serde_json::from_str(&current).map_err(|_| parse_error())?;
+            let forecast: serde_json::Value =
+                serde_json::from_str(&forecast).map_err(|_| parse_error())?;
+            let mut ret = vec![parse_openweathermap_entry(&current).ok_or_else(parse_error)?];
+            if let Some(list) = forecast.get("list").and_then(|l| l.as_array()) {
+                ret.extend(list.iter().filter_map(parse_openweathermap_entry));
+            }
+            Ok(ret)
+        }
+    }
+}
+
+fn format_time(time: i64) -> String {
+    glib::DateTime::from_unix_local(time)
+        .and_then(|t| t.format(&format!("%a {}", locale::time_format())))
+        .map(|s| s.to_string())
+        .unwrap_or_default()
+}
+
+fn weather_module() -> FnModFactory<WeatherConfig> {
+    FnModFactory::new(
+        "weather",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<WeatherConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("weather");
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            let icon = Icon::new("weather-clear");
+            let label = gtk::Label::new(Some("…"));
+            hbox.add(icon.widget());
+            hbox.add(&label);
+            button.add(&hbox);
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            let grid = gtk::Grid::new();
+            grid.set_column_spacing(8);
+            popover.add(&grid);
+            button.connect_clicked(glib::clone!(@weak popover => move |_| {
+                popover.show_all();
+                popover.popup();
+            }));
+
+            let worker_config = (**config).clone();
+            let config = config.clone();
+            // Set by the refresh action so that it gets a new response.
+            let force = Arc::new(AtomicBool::new(false));
+            let worker_force = force.clone();
+            let poller = poll_in_worker(
+                Duration::from_secs(config.interval),
+                move || fetch(&worker_config, worker_force.swap(false, Ordering::Relaxed)),
+                glib::clone!(@weak button, @weak label, @weak grid, @weak container => @default-return Continue(false), move |result| {
+                    if is_pinned(&container) {
+                        return Continue(true);
+                    }
+                    let entries = match result {
+                        Ok(entries) if !entries.is_empty() => entries,
+                        Ok(_) => return Continue(true),
+                        Err(e) => {
+                            button.style_context().add_class("error");
+                            button.set_tooltip_text(Some(&e));
+                            return Continue(true);
+                        }
+                    };
+                    button.style_context().remove_class("error");
+                    button.set_tooltip_text(None);
+
+                    let current = &entries[0];
+                    let icon_text = icon::text(current.icon);
+                    let show_image = config.format.contains("{icon}") && icon_text.is_none();
+                    icon.set(current.icon);
+                    icon.widget().set_visible(show_image);
+                    label.set_text(
+                        config
+                            .format
+                            .replace("{icon}", &icon_text.unwrap_or_default())
+                            .replace("{temp}", &format!("{:.0}", locale::temperature(current.temp)))
+                            .replace("{conditions}", &current.description)
+                            .trim(),
+                    );
+
+                    for ref child in grid.children() {
+                        grid.remove(child);
+                    }
+                    for (i, e) in entries.iter().skip(1).take(config.forecast_count).enumerate() {
+                        let row = i as i32;
+                        let time = gtk::Label::new(Some(&format_time(e.time)));
+                        time.set_xalign(0.0);
+                        grid.attach(&time, 0, row, 1, 1);
+                        grid.attach(Icon::new(e.icon).widget(), 1, row, 1, 1);
+                        grid.attach(&gtk::Label::new(Some(&format!("{:.0}°", locale::temperature(e.temp)))), 2, row, 1, 1);
+                        let desc = gtk::Label::new(Some(&e.description));
+                        desc.set_xalign(0.0);
+                        grid.attach(&desc, 3, row, 1, 1);
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(container, &button, move || {
+                force.store(true, Ordering::Relaxed);
+                poller.refresh();
+            });
+            add_pin_action(container, &button);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(weather_module())]
+}
+
+crate::register_plugin!("weather", make_module_factories);
@@ -0,0 +1,186 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::icon::Icon;
+use crate::module_base::template::Template;
+use crate::module_base::{
+    add_pin_action, add_refresh_action, is_pinned, poll_in_worker, FnModFactory, JSONConfigFactory,
+};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
+
+fn default_interval() -> u64 {
+    5
+}
+
+fn default_format() -> String {
+    "{ssid} {quality}%".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct WifiConfig {
+    /// Wireless interface (e.g. "wlan0"). Defaults to the first one in /proc/net/wireless.
+    #[serde(default)]
+    interface: Option<String>,
+
+    /// Interval in seconds to read the state.
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    /// Format of the label with "{ssid}" and "{quality}". See [`crate::module_base::template`]
+    /// for the syntax.
+    #[serde(default = "default_format")]
+    format: String,
+}
+
+impl Default for WifiConfig {
+    fn default() -> Self {
+        WifiConfig {
+            interface: None,
+            interval: default_interval(),
+            format: default_format(),
+        }
+    }
+}
+
+/// Reads (interface, link quality in percentage) from /proc/net/wireless.
+///
+/// The kernel reports the link quality out of 70 for most drivers.
+fn read_quality(interface: Option<&str>) -> Option<(String, f64)> {
+    let content = std::fs::read_to_string("/proc/net/wireless").ok()?;
+    content.lines().skip(2).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let name = fields.next()?.trim_end_matches(':');
+        if interface.map(|i| i != name).unwrap_or(false) {
+            return None;
+        }
+        let link: f64 = fields.nth(1)?.trim_end_matches('.').parse().ok()?;
+        Some((name.to_owned(), (link / 70.0 * 100.0).max(0.0).min(100.0)))
+    })
+}
+
+/// Reads the SSID with `iw`. None if not connected.
+fn read_ssid(interface: &str) -> Result<Option<String>, String> {
+    let output = Command::new("iw")
+        .args(&["dev", interface, "link"])
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => "iw not found".to_owned(),
+            _ => format!("Cannot run iw: {}", e),
+        })?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("SSID: ").map(|s| s.to_owned())))
+}
+
+fn quality_class(quality: f64) -> &'static str {
+    if quality >= 75.0 {
+        "wifi-excellent"
+    } else if quality >= 50.0 {
+        "wifi-good"
+    } else if quality >= 25.0 {
+        "wifi-fair"
+    } else {
+        "wifi-weak"
+    }
+}
+
+const CLASSES: [&str; 5] = [
+    "wifi-excellent",
+    "wifi-good",
+    "wifi-fair",
+    "wifi-weak",
+    "wifi-disconnected",
+];
+
+fn wifi_module() -> FnModFactory<WifiConfig> {
+    FnModFactory::new(
+        "wifi",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<WifiConfig>, container: &gtk::Box| {
+            let event_box = gtk::EventBox::new();
+            let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            hbox.style_context().add_class("wifi");
+            let icon = Icon::new("network-wireless");
+            let label = gtk::Label::new(None);
+            hbox.add(icon.widget());
+            hbox.add(&label);
+            event_box.add(&hbox);
+            container.add(&event_box);
+
+            let interface = config.interface.clone();
+            let template = Template::new(&config.format);
+            let poller = poll_in_worker(
+                Duration::from_secs(config.interval),
+                move || -> Result<Option<(String, f64)>, String> {
+                    let (name, quality) = match read_quality(interface.as_deref()) {
+                        Some(v) => v,
+                        None => return Ok(None),
+                    };
+                    Ok(read_ssid(&name)?.map(|ssid| (ssid, quality)))
+                },
+                glib::clone!(@weak event_box, @weak hbox, @weak label, @weak container => @default-return Continue(false), move |state| {
+                    if is_pinned(&container) {
+                        return Continue(true);
+                    }
+                    let sc = hbox.style_context();
+                    let state = match state {
+                        Ok(state) => state,
+                        Err(e) => {
+                            sc.add_class("error");
+                            event_box.set_tooltip_text(Some(&e));
+                            return Continue(true);
+                        }
+                    };
+                    sc.remove_class("error");
+                    event_box.set_tooltip_text(None);
+                    for class in CLASSES.iter() {
+                        sc.remove_class(class);
+                    }
+                    match state {
+                        Some((ssid, quality)) => {
+                            icon.set("network-wireless");
+                            label.set_text(&template.render(&[
+                                ("ssid", ssid.into()),
+                                ("quality", quality.into()),
+                            ]));
+                            sc.add_class(quality_class(quality));
+                        }
+                        None => {
+                            icon.set("network-offline");
+                            label.set_text("");
+                            sc.add_class("wifi-disconnected");
+                        }
+                    }
+                    Continue(true)
+                }),
+            );
+            add_refresh_action(container, &event_box, move || poller.refresh());
+            add_pin_action(container, &event_box);
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(wifi_module())]
+}
+
+crate::register_plugin!("wifi", make_module_factories);
@@ -0,0 +1,187 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{task, FnModFactory, JSONConfigFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::net::UdpSocket;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+fn default_broadcast() -> String {
+    "255.255.255.255:9".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct Machine {
+    name: String,
+
+    /// MAC address (e.g. "01:23:45:67:89:ab").
+    mac: String,
+
+    /// Address to send the magic packet to.
+    #[serde(default = "default_broadcast")]
+    broadcast: String,
+
+    /// Host to ping after sending the packet to confirm that the machine came up.
+    #[serde(default)]
+    ping: Option<String>,
+}
+
+fn default_label() -> String {
+    "WoL".to_owned()
+}
+
+fn default_ping_timeout() -> u64 {
+    120
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct WakeOnLanConfig {
+    /// Label of the button.
+    #[serde(default = "default_label")]
+    label: String,
+
+    #[serde(default)]
+    machines: Vec<Machine>,
+
+    /// Seconds to keep pinging until giving up.
+    #[serde(default = "default_ping_timeout")]
+    ping_timeout: u64,
+}
+
+impl Default for WakeOnLanConfig {
+    fn default() -> Self {
+        WakeOnLanConfig {
+            label: default_label(),
+            machines: vec![],
+            ping_timeout: default_ping_timeout(),
+        }
+    }
+}
+
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let mut ret = [0u8; 6];
+    let mut parts = mac.split(|c| c == ':' || c == '-');
+    for b in ret.iter_mut() {
+        *b = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(ret)
+}
+
+/// Sends a magic packet: 6 bytes of 0xFF followed by the MAC address repeated 16 times.
+fn send_magic_packet(machine: &Machine) -> Result<(), String> {
+    let mac = parse_mac(&machine.mac).ok_or_else(|| format!("Invalid MAC: {}", machine.mac))?;
+    let mut packet = vec![0xFFu8; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac);
+    }
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    socket.set_broadcast(true).map_err(|e| e.to_string())?;
+    socket
+        .send_to(&packet, &machine.broadcast)
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn ping(host: &str) -> bool {
+    Command::new("ping")
+        .args(&["-c", "1", "-W", "1", host])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Sends the packet and reports the progress to the label.
+fn wake(machine: &Machine, ping_timeout: Duration, status: &gtk::Label) {
+    if let Err(e) = send_magic_packet(machine) {
+        status.set_text(&e);
+        return;
+    }
+    let host = match machine.ping {
+        Some(ref host) => host.clone(),
+        None => {
+            status.set_text("Sent");
+            return;
+        }
+    };
+    status.set_text("Waiting…");
+    let status = status.downgrade();
+    task::spawn(async move {
+        let start = Instant::now();
+        let mut up = false;
+        while start.elapsed() < ping_timeout {
+            let host = host.clone();
+            if task::unblock(move || ping(&host)).await {
+                up = true;
+                break;
+            }
+            glib::timeout_future(Duration::from_secs(1)).await;
+        }
+        if let Some(status) = status.upgrade() {
+            status.set_text(if up { "Up" } else { "No response" });
+        }
+    });
+}
+
+fn wake_on_lan_module() -> FnModFactory<WakeOnLanConfig> {
+    FnModFactory::new(
+        "wake-on-lan",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<WakeOnLanConfig>, container: &gtk::Box| {
+            let button = gtk::Button::with_label(&config.label);
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("wake-on-lan");
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            let grid = gtk::Grid::new();
+            grid.set_column_spacing(8);
+            let ping_timeout = Duration::from_secs(config.ping_timeout);
+            for (i, machine) in config.machines.iter().enumerate() {
+                let wake_button = gtk::Button::with_label(&machine.name);
+                wake_button.set_tooltip_text(Some(&machine.mac));
+                let status = gtk::Label::new(None);
+                status.set_xalign(0.0);
+                let machine = machine.clone();
+                wake_button.connect_clicked(glib::clone!(@weak status => move |_| {
+                    wake(&machine, ping_timeout, &status);
+                }));
+                grid.attach(&wake_button, 0, i as i32, 1, 1);
+                grid.attach(&status, 1, i as i32, 1, 1);
+            }
+            popover.add(&grid);
+            button.connect_clicked(glib::clone!(@weak popover => move |_| {
+                popover.show_all();
+                popover.popup();
+            }));
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(wake_on_lan_module())]
+}
+
+crate::register_plugin!("wol", make_module_factories);
@@ -0,0 +1,148 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{locale, FnModFactory, JSONConfigFactory};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct Zone {
+    /// IANA time zone (e.g. "America/New_York").
+    tz: String,
+
+    /// Label shown next to the time. Defaults to the time zone.
+    #[serde(default)]
+    label: Option<String>,
+}
+
+fn default_separator() -> String {
+    " | ".to_owned()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct WorldClockConfig {
+    zones: Vec<Zone>,
+
+    /// Format of the time. See g_date_time_format for the syntax. Defaults to hours and minutes
+    /// in the hour cycle of the locale config.
+    #[serde(default)]
+    format: Option<String>,
+
+    /// Show all zones in the bar. Otherwise, only the first zone is shown in the bar and the
+    /// others are in a popover.
+    #[serde(default = "default_true")]
+    inline: bool,
+
+    /// Separator between zones in the inline mode.
+    #[serde(default = "default_separator")]
+    separator: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for WorldClockConfig {
+    fn default() -> Self {
+        WorldClockConfig {
+            zones: vec![],
+            format: None,
+            inline: true,
+            separator: default_separator(),
+        }
+    }
+}
+
+fn format_zone(zone: &Zone, format: &str) -> String {
+    let time = glib::DateTime::new_now(&glib::TimeZone::new(Some(&zone.tz)))
+        .and_then(|t| t.format(format))
+        .map(|s| s.to_string())
+        .unwrap_or_default();
+    format!("{} {}", zone.label.as_deref().unwrap_or(&zone.tz), time)
+}
+
+fn worldclock_module() -> FnModFactory<WorldClockConfig> {
+    FnModFactory::new(
+        "worldclock",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<WorldClockConfig>, container: &gtk::Box| {
+            let button = gtk::Button::new();
+            button.set_relief(gtk::ReliefStyle::None);
+            button.style_context().add_class("worldclock");
+            let label = gtk::Label::new(None);
+            button.add(&label);
+            container.add(&button);
+
+            let popover = gtk::Popover::new(Some(&button));
+            let list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            let mut zone_labels = vec![];
+            if !config.inline {
+                for _ in config.zones.iter().skip(1) {
+                    let l = gtk::Label::new(None);
+                    l.set_xalign(0.0);
+                    list.add(&l);
+                    zone_labels.push(l);
+                }
+                button.connect_clicked(glib::clone!(@weak popover => move |_| {
+                    popover.show_all();
+                    popover.popup();
+                }));
+            }
+            popover.add(&list);
+
+            let config = config.clone();
+            let format = config
+                .format
+                .clone()
+                .unwrap_or_else(|| locale::time_format().to_owned());
+            let update = move |label: &gtk::Label| {
+                if config.inline {
+                    let zones: Vec<String> = config
+                        .zones
+                        .iter()
+                        .map(|z| format_zone(z, &format))
+                        .collect();
+                    label.set_text(&zones.join(&config.separator));
+                    return;
+                }
+                let mut zones = config.zones.iter();
+                if let Some(z) = zones.next() {
+                    label.set_text(&format_zone(z, &format));
+                }
+                for (z, l) in zones.zip(zone_labels.iter()) {
+                    l.set_text(&format_zone(z, &format));
+                }
+            };
+            update(&label);
+            glib::timeout_add_seconds_local(
+                1,
+                glib::clone!(@weak label => @default-return Continue(false), move || {
+                    update(&label);
+                    Continue(true)
+                }),
+            );
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(worldclock_module())]
+}
+
+crate::register_plugin!("worldclock", make_module_factories);
@@ -0,0 +1,160 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::module_base::{command, FnModFactory, JSONConfigFactory, StateStore};
+use gtk::glib;
+use gtk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::rc::Rc;
+
+fn default_step() -> f64 {
+    0.25
+}
+
+fn default_min() -> f64 {
+    1.0
+}
+
+fn default_max() -> f64 {
+    8.0
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ZoomConfig {
+    /// Command that prints the current zoom level (e.g. "1.5"). If not specified, the level set
+    /// by this module is remembered instead.
+    #[serde(default)]
+    get_command: Option<String>,
+
+    /// Command that sets the zoom level. "{level}" is replaced with the level. Required.
+    set_command: String,
+
+    /// Amount of the change by one scroll step.
+    #[serde(default = "default_step")]
+    step: f64,
+
+    #[serde(default = "default_min")]
+    min: f64,
+
+    #[serde(default = "default_max")]
+    max: f64,
+}
+
+impl Default for ZoomConfig {
+    fn default() -> Self {
+        ZoomConfig {
+            get_command: None,
+            set_command: String::new(),
+            step: default_step(),
+            min: default_min(),
+            max: default_max(),
+        }
+    }
+}
+
+fn show_level(label: &gtk::Label, level: f64) {
+    label.set_text(&format!("🔍 {:.0}%", level * 100.0));
+    let sc = label.style_context();
+    if level > 1.0 {
+        sc.add_class("zoomed");
+    } else {
+        sc.remove_class("zoomed");
+    }
+}
+
+fn zoom_module() -> FnModFactory<ZoomConfig> {
+    FnModFactory::new(
+        "zoom",
+        Box::new(JSONConfigFactory::default()),
+        Box::new(move |config: &Rc<ZoomConfig>, container: &gtk::Box| {
+            let event_box = gtk::EventBox::new();
+            event_box.add_events(gtk::gdk::EventMask::SCROLL_MASK);
+            let label = gtk::Label::new(None);
+            label.style_context().add_class("zoom");
+            label.set_tooltip_text(Some("Scroll to zoom, click to reset"));
+            event_box.add(&label);
+            container.add(&event_box);
+            if config.set_command.trim().is_empty() {
+                label.set_text("🔍");
+                label.style_context().add_class("error");
+                label.set_tooltip_text(Some("set_command is not set"));
+                return;
+            }
+
+            let store = StateStore::new(container);
+            let level = Rc::new(Cell::new(store.get("level").unwrap_or(config.min)));
+            show_level(&label, level.get());
+            if let Some(ref get_command) = config.get_command {
+                command::run(
+                    get_command,
+                    glib::clone!(@weak label, @strong level => move |result| {
+                        if let Some(l) = result.ok().and_then(|out| out.trim().parse().ok()) {
+                            level.set(l);
+                            show_level(&label, l);
+                        }
+                    }),
+                );
+            }
+
+            let step = config.step;
+            let min = config.min;
+            let config = config.clone();
+            let set_level = Rc::new(
+                glib::clone!(@weak label, @strong level => move |new_level: f64| {
+                    let new_level = new_level.max(config.min).min(config.max);
+                    level.set(new_level);
+                    show_level(&label, new_level);
+                    store.set("level", &new_level);
+                    let command_line = config.set_command.replace("{level}", &new_level.to_string());
+                    command::run(&command_line, |result| {
+                        if let Err(e) = result {
+                            eprintln!("Cannot set the zoom level: {}", e);
+                        }
+                    });
+                }),
+            );
+            event_box.connect_scroll_event(
+                glib::clone!(@strong set_level, @strong level => move |_, e| {
+                    let delta = match e.direction() {
+                        gtk::gdk::ScrollDirection::Up => 1.0,
+                        gtk::gdk::ScrollDirection::Down => -1.0,
+                        gtk::gdk::ScrollDirection::Smooth => -e.delta().1,
+                        _ => 0.0,
+                    };
+                    if delta != 0.0 {
+                        set_level(level.get() + delta * step);
+                    }
+                    Inhibit(true)
+                }),
+            );
+            event_box.connect_button_release_event(move |_, e| {
+                if e.button() == gtk::gdk::BUTTON_PRIMARY {
+                    set_level(min);
+                    return Inhibit(true);
+                }
+                Inhibit(false)
+            });
+        }),
+    )
+}
+
+pub(crate) fn make_module_factories(
+    _config: &serde_json::Value,
+) -> Vec<Box<dyn crate::module::ModuleFactory>> {
+    vec![Box::new(zoom_module())]
+}
+
+crate::register_plugin!("zoom", make_module_factories);
@@ -0,0 +1,60 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The public API for embedding jiji bars in other GTK applications and writing modules.
+//!
+//! The items here follow semantic versioning. Other items of the crate are internal and can
+//! change at any time.
+//!
+//! The fields of [`Config`], [`MonitorConfig`], and [`ModuleConfig`] are private. Build the
+//! configs with their methods (e.g. [`Config::add_plugin`] and [`MonitorConfig::add_left`]), or
+//! parse the JSON config with [`Config::from_json`] or [`read_config`] for the other options.
+//!
+//! ```no_run
+//! use jiji::prelude::*;
+//!
+//! // Call this in the GTK application's activate handler.
+//! fn build(gdk_monitor: &gtk::gdk::Monitor) -> gtk::Box {
+//!     let mut monitor = MonitorConfig::default();
+//!     monitor.add_left(ModuleConfig::new("i3-workspaces", serde_json::Value::Null));
+//!     let mut config = Config::default();
+//!     config.add_plugin("i3", serde_json::Value::Null);
+//!     config.set_default_monitor(monitor);
+//!
+//!     let factories = make_module_factories(config.plugins());
+//!     let bar = Bar::new("", find_monitor_config(&config, gdk_monitor), &factories, gdk_monitor);
+//!     bar.widget()
+//! }
+//! ```
+
+pub use crate::bar::Bar;
+pub use crate::bluetooth::{AdapterState, BluetoothState, DeviceState};
+pub use crate::config::{
+    find_monitor_config, read_config, Config, ModuleConfig, MonitorConfig, PluginConfig,
+};
+pub use crate::error::Error;
+#[cfg(feature = "i3")]
+pub use crate::i3::{CommandOutcome, I3Backend, I3State, OutputState, WorkspaceState};
+pub use crate::module::{make_module_factories, Module, ModuleFactory, Plugin, PluginRegistration};
+pub use crate::module_base::icon::Icon;
+pub use crate::module_base::{
+    add_pin_action, add_refresh_action, is_pinned, module_action_group, poll_command_in_worker,
+    poll_in_worker, ConfigFactory, FnModFactory, JSONConfigFactory, PollHandle, StateStore,
+};
+#[cfg(feature = "pulseaudio")]
+pub use crate::pulseaudio::sink::SinkState;
+#[cfg(feature = "pulseaudio")]
+pub use crate::pulseaudio::source::SourceState;
+#[cfg(feature = "pulseaudio")]
+pub use crate::pulseaudio::{PulseAudioBackend, PulseAudioState, SourceOutputState};
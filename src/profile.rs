@@ -0,0 +1,92 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runtime profiles.
+//!
+//! A profile (e.g. "work") overrides the monitor configs and the CSS of the config. The active
+//! profile is switched with `jiji msg profile <name>` or the "profile-switcher" module, which
+//! rebuilds all bars. With multiple configs, each config applies its own profile of the name.
+//!
+//! The active profile is saved in the state store and restored at startup.
+
+use crate::module_base::StateStore;
+use gtk::glib;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Default)]
+struct State {
+    current: Option<String>,
+    names: Vec<String>,
+    on_switch: Option<Rc<dyn Fn()>>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+fn store() -> StateStore {
+    StateStore::global("profile")
+}
+
+/// Sets the profile names and the startup profile. `on_switch` rebuilds the bars for the new
+/// profile.
+///
+/// The profile saved by the last switch takes precedence over `current`.
+pub(crate) fn setup<F: Fn() + 'static>(names: Vec<String>, current: Option<String>, on_switch: F) {
+    let current = store().get::<Option<String>>("name").unwrap_or(current);
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.current = current.filter(|c| names.contains(c));
+        s.names = names;
+        s.on_switch = Some(Rc::new(on_switch));
+    });
+}
+
+/// Returns the active profile. None if the base config is used.
+pub(crate) fn current() -> Option<String> {
+    STATE.with(|s| s.borrow().current.clone())
+}
+
+/// Returns the names of the profiles, sorted.
+pub(crate) fn names() -> Vec<String> {
+    STATE.with(|s| s.borrow().names.clone())
+}
+
+/// Switches to the profile. None switches back to the base config.
+///
+/// The bars are rebuilt after returning, because this can be called from a module in the bars.
+pub(crate) fn switch(name: Option<&str>) -> Result<(), String> {
+    let on_switch = STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        if let Some(name) = name {
+            if !s.names.iter().any(|n| n == name) {
+                return Err(format!("unknown profile: {}", name));
+            }
+        }
+        if s.current.as_deref() == name {
+            return Ok(None);
+        }
+        s.current = name.map(|n| n.to_owned());
+        Ok(s.on_switch.clone())
+    })?;
+    store().set("name", &name);
+    if let Some(on_switch) = on_switch {
+        glib::idle_add_local(move || {
+            on_switch();
+            glib::Continue(false)
+        });
+    }
+    Ok(())
+}
@@ -12,10 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::error::Error;
+use crate::module::ModuleFactory;
+use crate::module_base::FnModFactory;
 use gtk::glib;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
-use std::collections::HashMap;
+use pulse::context::Context;
+use pulse::volume::ChannelVolumes;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
 
 pub mod sink;
 pub mod source;
@@ -23,20 +30,190 @@ pub mod util;
 
 #[derive(Clone, Default, glib::GBoxed)]
 #[gboxed(type_name = "Sinks")]
-pub struct Sinks(HashMap<u32, sink::SinkState>);
+pub struct Sinks(BTreeMap<u32, sink::SinkState>);
 
 #[derive(Clone, Default, glib::GBoxed)]
 #[gboxed(type_name = "Sources")]
-pub struct Sources(HashMap<u32, source::SourceState>);
+pub struct Sources(BTreeMap<u32, source::SourceState>);
+
+/// A recording stream (e.g. an application using a microphone).
+#[derive(Clone, Debug)]
+pub struct SourceOutputState {
+    /// Index of the source that this records from.
+    pub source: u32,
+    /// Name of the application. Empty if unknown.
+    pub application: String,
+}
+
+#[derive(Clone, Default, glib::GBoxed)]
+#[gboxed(type_name = "SourceOutputs")]
+pub struct SourceOutputs(BTreeMap<u32, SourceOutputState>);
+
+/// The requests to the PulseAudio server.
+///
+/// [`PulseAudioState::new`] sends them to the server. A mock can be injected with
+/// [`PulseAudioState::with_backend`] to drive the modules without PulseAudio.
+pub trait PulseAudioBackend {
+    fn set_default_sink(&self, name: &str);
+    fn set_default_source(&self, name: &str);
+    /// Moves all playing streams to the sink.
+    fn move_sink_inputs(&self, sink: &str);
+    fn set_sink_mute(&self, name: &str, mute: bool);
+    fn set_sink_volume(&self, name: &str, volume: &ChannelVolumes);
+    fn set_source_mute(&self, name: &str, mute: bool);
+    fn set_source_volume(&self, name: &str, volume: &ChannelVolumes);
+}
+
+/// Sends the requests to the server. The requests are dropped while disconnected.
+struct ContextBackend(Rc<RefCell<Option<Context>>>);
+
+impl PulseAudioBackend for ContextBackend {
+    fn set_default_sink(&self, name: &str) {
+        if let Some(pa_context) = self.0.borrow_mut().as_mut() {
+            pa_context.set_default_sink(name, move |_| {});
+        }
+    }
+
+    fn set_default_source(&self, name: &str) {
+        if let Some(pa_context) = self.0.borrow_mut().as_mut() {
+            pa_context.set_default_source(name, move |_| {});
+        }
+    }
+
+    fn move_sink_inputs(&self, sink: &str) {
+        if let Some(pa_context) = self.0.borrow_mut().as_mut() {
+            let mut introspector = pa_context.introspect();
+            let sink = sink.to_owned();
+            pa_context
+                .introspect()
+                .get_sink_input_info_list(move |res| {
+                    if let pulse::callbacks::ListResult::Item(si) = res {
+                        introspector.move_sink_input_by_name(si.index, &sink, None);
+                    }
+                });
+        }
+    }
+
+    fn set_sink_mute(&self, name: &str, mute: bool) {
+        if let Some(pa_context) = self.0.borrow_mut().as_mut() {
+            pa_context.introspect().set_sink_mute_by_name(name, mute, None);
+        }
+    }
+
+    fn set_sink_volume(&self, name: &str, volume: &ChannelVolumes) {
+        if let Some(pa_context) = self.0.borrow_mut().as_mut() {
+            pa_context
+                .introspect()
+                .set_sink_volume_by_name(name, volume, None);
+        }
+    }
+
+    fn set_source_mute(&self, name: &str, mute: bool) {
+        if let Some(pa_context) = self.0.borrow_mut().as_mut() {
+            pa_context
+                .introspect()
+                .set_source_mute_by_name(name, mute, None);
+        }
+    }
+
+    fn set_source_volume(&self, name: &str, volume: &ChannelVolumes) {
+        if let Some(pa_context) = self.0.borrow_mut().as_mut() {
+            pa_context
+                .introspect()
+                .set_source_volume_by_name(name, volume, None);
+        }
+    }
+}
 
 gtk::glib::wrapper! {
     pub struct PulseAudioState(ObjectSubclass<imp::PulseAudioState>);
 }
 
+thread_local! {
+    static SHARED: RefCell<Option<PulseAudioState>> = RefCell::new(None);
+}
+
 impl PulseAudioState {
     /// Makes a new PulseAudioState.
-    pub fn new() -> Self {
-        glib::Object::new(&[]).expect("Failed to create an PulseAudioState")
+    ///
+    /// Err if the PulseAudio server cannot be connected.
+    pub fn new() -> Result<Self, String> {
+        let obj: Self = glib::Object::new(&[]).expect("Failed to create an PulseAudioState");
+        imp::PulseAudioState::from_instance(&obj).start(&obj)?;
+        Ok(obj)
+    }
+
+    /// Returns the state shared in the application. It's created on the first call, so that
+    /// PulseAudio is connected only when a module uses it. A failed connection is retried on the
+    /// next call.
+    pub fn shared() -> Result<Self, String> {
+        SHARED.with(|s| {
+            let mut s = s.borrow_mut();
+            if let Some(state) = s.as_ref() {
+                return Ok(state.clone());
+            }
+            let state = PulseAudioState::new()?;
+            s.replace(state.clone());
+            Ok(state)
+        })
+    }
+
+    /// Replaces the state returned by [`PulseAudioState::shared`], e.g. with one made by
+    /// [`PulseAudioState::with_backend`]. This must be called before the modules are created.
+    pub fn set_shared(state: PulseAudioState) {
+        SHARED.with(|s| s.replace(Some(state)));
+    }
+
+    /// Makes a PulseAudioState that sends the requests to the backend instead of connecting to
+    /// the server.
+    ///
+    /// The state is updated only by [`PulseAudioState::push_defaults`],
+    /// [`PulseAudioState::push_sinks`], [`PulseAudioState::push_sources`], and
+    /// [`PulseAudioState::push_source_outputs`].
+    pub fn with_backend(backend: Rc<dyn PulseAudioBackend>) -> Self {
+        let obj: Self = glib::Object::new(&[]).expect("Failed to create an PulseAudioState");
+        imp::PulseAudioState::from_instance(&obj)
+            .backend
+            .replace(Some(backend));
+        obj
+    }
+
+    /// Returns the backend that the sink and source states send the requests to.
+    pub fn backend(&self) -> Rc<dyn PulseAudioBackend> {
+        imp::PulseAudioState::from_instance(self).backend()
+    }
+
+    /// Replaces the default sink and source as if the server info changed.
+    pub fn push_defaults(&self, sink: &str, source: &str) {
+        let self_ = imp::PulseAudioState::from_instance(self);
+        self_.default_sink.replace(sink.to_owned());
+        self.notify("defaultSink");
+        self_.default_source.replace(source.to_owned());
+        self.notify("defaultSource");
+    }
+
+    /// Replaces the sinks as if the server sent sink events.
+    pub fn push_sinks(&self, sinks: BTreeMap<u32, sink::SinkState>) {
+        imp::PulseAudioState::from_instance(self)
+            .sinks
+            .replace(Sinks(sinks));
+        self.notify("sinks");
+    }
+
+    /// Replaces the sources as if the server sent source events.
+    pub fn push_sources(&self, sources: BTreeMap<u32, source::SourceState>) {
+        imp::PulseAudioState::from_instance(self)
+            .sources
+            .replace(Sources(sources));
+        self.notify("sources");
+    }
+
+    /// Replaces the recording streams as if the server sent source output events.
+    pub fn push_source_outputs(&self, source_outputs: BTreeMap<u32, SourceOutputState>) {
+        imp::PulseAudioState::from_instance(self)
+            .source_outputs
+            .replace(SourceOutputs(source_outputs));
+        self.notify("sourceOutputs");
     }
 
     /// Returns the default sink name.
@@ -79,48 +256,105 @@ impl PulseAudioState {
 
     /// Sets the default sink.
     pub fn set_default_sink(&self, name: &str) {
-        let self_ = imp::PulseAudioState::from_instance(self);
-        self_.pa_context
-            .borrow_mut()
-            .as_mut()
-            .unwrap()
-            .set_default_sink(name, move |_| {});
+        self.backend().set_default_sink(name);
+    }
+
+    /// Moves all playing streams to the sink.
+    ///
+    /// Changing the default sink affects only new streams. This moves the existing ones too.
+    pub fn move_sink_inputs(&self, name: &str) {
+        self.backend().move_sink_inputs(name);
     }
 
     /// Sets the default source.
     pub fn set_default_source(&self, name: &str) {
-        let self_ = imp::PulseAudioState::from_instance(self);
-        self_.pa_context
-            .borrow_mut()
-            .as_mut()
-            .unwrap()
-            .set_default_source(name, move |_| {});
+        self.backend().set_default_source(name);
     }
 
-    /// Returns all sink states.
-    pub fn sinks(&self) -> HashMap<u32, sink::SinkState> {
+    /// Returns all sink states, ordered by the index.
+    pub fn sinks(&self) -> BTreeMap<u32, sink::SinkState> {
         self.property("sinks").unwrap().get::<Sinks>().unwrap().0
     }
 
-    /// Returns all sink sources.
-    pub fn sources(&self) -> HashMap<u32, source::SourceState> {
+    /// Returns all source states, ordered by the index.
+    pub fn sources(&self) -> BTreeMap<u32, source::SourceState> {
         self.property("sources")
             .unwrap()
             .get::<Sources>()
             .unwrap()
             .0
     }
+
+    /// Returns all recording streams, ordered by the index.
+    pub fn source_outputs(&self) -> BTreeMap<u32, SourceOutputState> {
+        self.property("sourceOutputs")
+            .unwrap()
+            .get::<SourceOutputs>()
+            .unwrap()
+            .0
+    }
+}
+
+/// A module factory that needs the shared [`PulseAudioState`].
+///
+/// The state is created when the first module is created, so that PulseAudio is connected only if
+/// a module uses it. If the server cannot be connected, the error is shown in place of the module.
+pub(crate) struct WithState<C> {
+    name: &'static str,
+    make: fn(Rc<PulseAudioState>) -> FnModFactory<C>,
+    /// Made once so that the modules share the factory's data (e.g. the media keys).
+    factory: RefCell<Option<FnModFactory<C>>>,
+}
+
+impl<C> WithState<C> {
+    /// `name` must be the name of the factory returned by `make`.
+    pub(crate) fn new(
+        name: &'static str,
+        make: fn(Rc<PulseAudioState>) -> FnModFactory<C>,
+    ) -> WithState<C> {
+        WithState {
+            name,
+            make,
+            factory: RefCell::new(None),
+        }
+    }
+}
+
+impl<C: schemars::JsonSchema + 'static> ModuleFactory for WithState<C> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn config_schema(&self, gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        gen.subschema_for::<C>()
+    }
+
+    fn create(
+        &self,
+        config: &serde_json::Value,
+        monitor: &gtk::gdk::Monitor,
+    ) -> Result<Box<dyn crate::module::Module>, Error> {
+        let mut factory = self.factory.borrow_mut();
+        if factory.is_none() {
+            let state = PulseAudioState::shared().map_err(|e| Error::module_config(self.name, e))?;
+            factory.replace((self.make)(Rc::new(state)));
+        }
+        factory.as_ref().unwrap().create(config, monitor)
+    }
 }
 
 mod imp {
-    use super::{sink::SinkState, source::SourceState, Sinks, Sources};
+    use super::{
+        sink::SinkState, source::SourceState, ContextBackend, PulseAudioBackend, Sinks,
+        SourceOutputState, SourceOutputs, Sources,
+    };
     use glib::{ParamFlags, ParamSpec};
     use gtk::glib;
     use gtk::prelude::*;
     use gtk::subclass::prelude::*;
     use once_cell::sync::Lazy;
     use pulse::callbacks::ListResult;
-    use pulse::context::introspect::{ServerInfo, SinkInfo, SourceInfo};
+    use pulse::context::introspect::{ServerInfo, SinkInfo, SourceInfo, SourceOutputInfo};
     use pulse::context::subscribe::{Facility, InterestMaskSet, Operation};
     use pulse::context::{Context, FlagSet};
     use pulse_glib::Mainloop;
@@ -131,11 +365,13 @@ mod imp {
     pub struct PulseAudioState {
         pub(crate) pa_mainloop: RefCell<Option<Mainloop>>,
         pub(crate) pa_context: Rc<RefCell<Option<Context>>>,
+        pub(crate) backend: RefCell<Option<Rc<dyn PulseAudioBackend>>>,
 
         pub(crate) default_sink: RefCell<String>,
         pub(crate) default_source: RefCell<String>,
         pub(crate) sinks: RefCell<Sinks>,
         pub(crate) sources: RefCell<Sources>,
+        pub(crate) source_outputs: RefCell<SourceOutputs>,
     }
 
     #[glib::object_subclass]
@@ -153,6 +389,7 @@ mod imp {
                     DEFAULT_SOURCE.clone(),
                     SINKS.clone(),
                     SOURCES.clone(),
+                    SOURCE_OUTPUTS.clone(),
                 ]
             });
             PROPERTIES.as_ref()
@@ -164,17 +401,22 @@ mod imp {
                 "defaultSource" => self.default_source.borrow().to_value(),
                 "sinks" => self.sinks.borrow().to_value(),
                 "sources" => self.sources.borrow().to_value(),
+                "sourceOutputs" => self.source_outputs.borrow().to_value(),
                 _ => unimplemented!(),
             }
         }
+    }
 
-        fn constructed(&self, obj: &Self::Type) {
-            self.parent_constructed(obj);
-            self.pa_mainloop.replace(Some(
-                Mainloop::new(None).expect("Failed to create a PulseAudio main loop"),
-            ));
-            let mut pa_context = Context::new(self.pa_mainloop.borrow().as_ref().unwrap(), "jiji")
-                .expect("Failed to create PulseAudio context");
+    impl PulseAudioState {
+        /// Connects to the server.
+        pub(super) fn start(&self, obj: &super::PulseAudioState) -> Result<(), String> {
+            self.backend
+                .replace(Some(Rc::new(ContextBackend(self.pa_context.clone()))));
+            let mainloop = Mainloop::new(None)
+                .ok_or_else(|| "Failed to create a PulseAudio main loop".to_owned())?;
+            let mut pa_context = Context::new(&mainloop, "jiji")
+                .ok_or_else(|| "Failed to create a PulseAudio context".to_owned())?;
+            self.pa_mainloop.replace(Some(mainloop));
 
             pa_context.set_state_callback(Some(Box::new(glib::clone!(@weak obj => move || {
                 PulseAudioState::from_instance(&obj).on_state_change(&obj);
@@ -186,12 +428,24 @@ mod imp {
             )));
             pa_context
                 .connect(None, FlagSet::NOFLAGS, None)
-                .expect("Failed to connect to the PulseAudio server");
+                .map_err(|e| format!("Failed to connect to the PulseAudio server: {}", e))?;
             self.pa_context.replace(Some(pa_context));
+            let pa_context = self.pa_context.clone();
+            crate::module_base::shutdown::on_shutdown(move || {
+                if let Some(pa_context) = pa_context.borrow_mut().as_mut() {
+                    pa_context.disconnect();
+                }
+            });
+            Ok(())
+        }
+
+        pub(super) fn backend(&self) -> Rc<dyn PulseAudioBackend> {
+            self.backend
+                .borrow()
+                .clone()
+                .expect("PulseAudioState has no backend")
         }
-    }
 
-    impl PulseAudioState {
         fn on_state_change(&self, obj: &super::PulseAudioState) {
             if self.pa_context.borrow().is_none() {
                 // Not initialized yet. This happens because pa_context.connect call above calls
@@ -205,7 +459,10 @@ mod imp {
             }
             if let Some(ref mut pa_context) = self.pa_context.borrow_mut().as_mut() {
                 pa_context.subscribe(
-                    InterestMaskSet::SINK | InterestMaskSet::SOURCE | InterestMaskSet::SERVER,
+                    InterestMaskSet::SINK
+                        | InterestMaskSet::SOURCE
+                        | InterestMaskSet::SOURCE_OUTPUT
+                        | InterestMaskSet::SERVER,
                     move |e| {
                         assert!(e, "Failed to subscribe to PulseAudio events");
                     },
@@ -233,6 +490,15 @@ mod imp {
                         }
                     }),
                 );
+                pa_context.introspect().get_source_output_info_list(
+                    glib::clone!(@weak obj => move |res| {
+                        match res {
+                            ListResult::Item(si) => {PulseAudioState::from_instance(&obj).on_source_output_info(si)}
+                            ListResult::End => {obj.notify_by_pspec(&SOURCE_OUTPUTS)}
+                            _ => (),
+                        }
+                    }),
+                );
             }
         }
         fn on_event(
@@ -293,6 +559,31 @@ mod imp {
                         _ => (),
                     }
                 }
+                Some(Facility::SourceOutput) => {
+                    match operation {
+                        Some(Operation::Removed) => {
+                            self.source_outputs.borrow_mut().0.remove(&index);
+                            obj.notify_by_pspec(&SOURCE_OUTPUTS);
+                        }
+                        Some(Operation::Changed) | Some(Operation::New) => {
+                            self.pa_context
+                                .borrow_mut()
+                                .as_mut()
+                                .unwrap()
+                                .introspect()
+                                .get_source_output_info(index, glib::clone!(@weak obj => move |res| {
+                                    match res {
+                                        ListResult::Item(si) => {
+                                            PulseAudioState::from_instance(&obj).on_source_output_info(si)
+                                        }
+                                        ListResult::End => {obj.notify_by_pspec(&SOURCE_OUTPUTS)}
+                                        _ => (),
+                                    }
+                                }));
+                        }
+                        _ => (),
+                    }
+                }
                 Some(Facility::Server) => match operation {
                     Some(Operation::Changed) => {
                         self.pa_context
@@ -332,14 +623,28 @@ mod imp {
             self.sinks
                 .borrow_mut()
                 .0
-                .insert(si.index, SinkState::new(self.pa_context.clone(), si));
+                .insert(si.index, SinkState::new(self.backend(), si));
         }
 
         fn on_source_info(&self, si: &SourceInfo) {
             self.sources
                 .borrow_mut()
                 .0
-                .insert(si.index, SourceState::new(self.pa_context.clone(), si));
+                .insert(si.index, SourceState::new(self.backend(), si));
+        }
+
+        fn on_source_output_info(&self, si: &SourceOutputInfo) {
+            let application = si
+                .proplist
+                .get_str(pulse::proplist::properties::APPLICATION_NAME)
+                .unwrap_or_default();
+            self.source_outputs.borrow_mut().0.insert(
+                si.index,
+                SourceOutputState {
+                    source: si.source,
+                    application,
+                },
+            );
         }
     }
 
@@ -372,5 +677,12 @@ mod imp {
             Sources::static_type(),
             ParamFlags::READABLE,
         );
+        static ref SOURCE_OUTPUTS: ParamSpec = ParamSpec::new_boxed(
+            "sourceOutputs",
+            "sourceOutputs",
+            "sourceOutputs",
+            SourceOutputs::static_type(),
+            ParamFlags::READABLE,
+        );
     }
 }
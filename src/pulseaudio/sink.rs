@@ -12,46 +12,79 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::PulseAudioBackend;
 use gtk::prelude::*;
 use pulse::context::introspect::SinkInfo;
-use pulse::context::Context;
 use pulse::volume::{ChannelVolumes, Volume};
-use std::cell::RefCell;
 use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct SinkState {
-    pa_context: Rc<RefCell<Option<Context>>>,
+    backend: Rc<dyn PulseAudioBackend>,
     pub name: String,
     pub description: String,
+    /// "device.form_factor" property (e.g. "headset", "speaker"). Empty if unknown.
+    pub form_factor: String,
+    /// Address of the Bluetooth device (e.g. "00:11:22:33:44:55") if this is a Bluetooth sink.
+    pub bluetooth_address: Option<String>,
     pub mute: bool,
     pub volume: ChannelVolumes,
 }
 
+/// Returns the Bluetooth address in the sink properties.
+fn bluetooth_address(proplist: &pulse::proplist::Proplist) -> Option<String> {
+    // PipeWire sets api.bluez5.address. PulseAudio sets the address to device.string.
+    proplist.get_str("api.bluez5.address").or_else(|| {
+        if proplist
+            .get_str(pulse::proplist::properties::DEVICE_BUS)
+            .as_deref()
+            == Some("bluetooth")
+        {
+            proplist.get_str(pulse::proplist::properties::DEVICE_STRING)
+        } else {
+            None
+        }
+    })
+}
+
 impl SinkState {
     /// Creates a new SinkState.
-    pub fn new(pa_context: Rc<RefCell<Option<Context>>>, si: &SinkInfo) -> SinkState {
+    pub fn new(backend: Rc<dyn PulseAudioBackend>, si: &SinkInfo) -> SinkState {
         SinkState {
-            pa_context,
+            backend,
             name: si.name.as_ref().map(|v| v.to_string()).unwrap_or_default(),
             description: si
                 .description
                 .as_ref()
                 .map(|v| v.to_string())
                 .unwrap_or_default(),
+            form_factor: si
+                .proplist
+                .get_str(pulse::proplist::properties::DEVICE_FORM_FACTOR)
+                .unwrap_or_default(),
+            bluetooth_address: bluetooth_address(&si.proplist),
             mute: si.mute,
             volume: si.volume,
         }
     }
 
+    /// Creates an unmuted SinkState without the server, e.g. for a mock backend. The public
+    /// fields can be set afterwards.
+    pub fn with_name(backend: Rc<dyn PulseAudioBackend>, name: &str) -> SinkState {
+        SinkState {
+            backend,
+            name: name.to_owned(),
+            description: String::new(),
+            form_factor: String::new(),
+            bluetooth_address: None,
+            mute: false,
+            volume: ChannelVolumes::default(),
+        }
+    }
+
     /// Toggles the mute state.
     pub fn toggle_mute(&self) {
-        self.pa_context
-            .borrow_mut()
-            .as_mut()
-            .unwrap()
-            .introspect()
-            .set_sink_mute_by_name(&self.name, !self.mute, None);
+        self.backend.set_sink_mute(&self.name, !self.mute);
     }
 
     /// Creates a connected adjustment.
@@ -65,18 +98,14 @@ impl SinkState {
             0.0,
             0.0,
         );
-        let name = self.name.clone();
-        let pa_context = self.pa_context.clone();
-        let cv = self.volume.clone();
-        obj.connect_value_changed(move |obj| {
-            let cv = super::util::percentage_to_volume(obj.value(), cv);
-            pa_context
-                .borrow_mut()
-                .as_mut()
-                .unwrap()
-                .introspect()
-                .set_sink_volume_by_name(&name, &cv, None);
-        });
+        let sink = self.clone();
+        obj.connect_value_changed(move |obj| sink.set_volume_percentage(obj.value()));
         obj
     }
+
+    /// Sets the volume of all channels.
+    pub fn set_volume_percentage(&self, percentage: f64) {
+        let cv = super::util::percentage_to_volume(percentage, self.volume);
+        self.backend.set_sink_volume(&self.name, &cv);
+    }
 }
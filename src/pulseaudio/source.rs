@@ -12,18 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::PulseAudioBackend;
 use gtk::prelude::*;
 use pulse::context::introspect::SourceInfo;
-use pulse::context::Context;
 use pulse::volume::{ChannelVolumes, Volume};
-use std::cell::RefCell;
 use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct SourceState {
-    pa_context: Rc<RefCell<Option<Context>>>,
+    backend: Rc<dyn PulseAudioBackend>,
     pub name: String,
     pub description: String,
+    /// "device.form_factor" property (e.g. "headset", "speaker"). Empty if unknown.
+    pub form_factor: String,
     pub mute: bool,
     pub volume: ChannelVolumes,
     pub is_monitor: bool,
@@ -31,29 +32,42 @@ pub struct SourceState {
 
 impl SourceState {
     /// Creates a new SourceState.
-    pub fn new(pa_context: Rc<RefCell<Option<Context>>>, si: &SourceInfo) -> SourceState {
+    pub fn new(backend: Rc<dyn PulseAudioBackend>, si: &SourceInfo) -> SourceState {
         SourceState {
-            pa_context,
+            backend,
             name: si.name.as_ref().map(|v| v.to_string()).unwrap_or_default(),
             description: si
                 .description
                 .as_ref()
                 .map(|v| v.to_string())
                 .unwrap_or_default(),
+            form_factor: si
+                .proplist
+                .get_str(pulse::proplist::properties::DEVICE_FORM_FACTOR)
+                .unwrap_or_default(),
             mute: si.mute,
             volume: si.volume,
             is_monitor: si.monitor_of_sink.is_some(),
         }
     }
 
+    /// Creates an unmuted SourceState without the server, e.g. for a mock backend. The public
+    /// fields can be set afterwards.
+    pub fn with_name(backend: Rc<dyn PulseAudioBackend>, name: &str) -> SourceState {
+        SourceState {
+            backend,
+            name: name.to_owned(),
+            description: String::new(),
+            form_factor: String::new(),
+            mute: false,
+            volume: ChannelVolumes::default(),
+            is_monitor: false,
+        }
+    }
+
     /// Toggles the mute state.
     pub fn toggle_mute(&self) {
-        self.pa_context
-            .borrow_mut()
-            .as_mut()
-            .unwrap()
-            .introspect()
-            .set_source_mute_by_name(&self.name, !self.mute, None);
+        self.backend.set_source_mute(&self.name, !self.mute);
     }
 
     /// Creates a connected adjustment.
@@ -67,18 +81,14 @@ impl SourceState {
             0.0,
             0.0,
         );
-        let name = self.name.clone();
-        let pa_context = self.pa_context.clone();
-        let cv = self.volume.clone();
-        obj.connect_value_changed(move |obj| {
-            let cv = super::util::percentage_to_volume(obj.value(), cv);
-            pa_context
-                .borrow_mut()
-                .as_mut()
-                .unwrap()
-                .introspect()
-                .set_source_volume_by_name(&name, &cv, None);
-        });
+        let source = self.clone();
+        obj.connect_value_changed(move |obj| source.set_volume_percentage(obj.value()));
         obj
     }
+
+    /// Sets the volume of all channels.
+    pub fn set_volume_percentage(&self, percentage: f64) {
+        let cv = super::util::percentage_to_volume(percentage, self.volume);
+        self.backend.set_source_volume(&self.name, &cv);
+    }
 }
@@ -0,0 +1,336 @@
+// Copyright 2021 Masaya Suzuki
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for driving the modules without the daemons. Enabled with the "testing" feature.
+//!
+//! A bar is built from a fixture config in an offscreen window, and its widget tree is rendered as
+//! text to compare with the expected one. The i3 and PulseAudio states can be replaced with mocks
+//! that record the requests, and the events are sent by the test with the `push_*` methods of the
+//! states. The mocks must be installed before the bar is built.
+//!
+//! ```no_run
+//! use gtk::prelude::*;
+//!
+//! let (i3, commands) = jiji::testing::install_mock_i3();
+//! let bar = jiji::testing::offscreen_bar(serde_json::json!({
+//!     "plugins": [{ "name": "i3" }],
+//!     "default_monitor": { "left_modules": [{ "name": "i3-workspaces" }] },
+//! }))
+//! .unwrap();
+//! i3.push_workspaces(Default::default());
+//! jiji::testing::run_pending();
+//! println!("{}", jiji::testing::widget_tree(bar.upcast_ref()));
+//! ```
+
+use crate::bar::Bar;
+use crate::config::Config;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Builds the bar of the config for the first monitor in an offscreen window.
+///
+/// GTK is initialized on the first call. A display is still needed (e.g. Xvfb) for the monitor.
+pub fn offscreen_bar(config: serde_json::Value) -> Result<gtk::OffscreenWindow, String> {
+    gtk::init().map_err(|e| format!("Failed to initialize GTK: {}", e))?;
+    let config = Config::from_json(config)?;
+    let monitor = gtk::gdk::Display::default()
+        .and_then(|d| d.monitor(0))
+        .ok_or_else(|| "No monitor".to_owned())?;
+    let module_factories = crate::module::make_module_factories(config.plugins());
+    let bar = Bar::new(
+        "",
+        crate::config::find_monitor_config(&config, &monitor),
+        &module_factories,
+        &monitor,
+    );
+    let window = gtk::OffscreenWindow::new();
+    window.add(&bar.widget());
+    window.show_all();
+    run_pending();
+    Ok(window)
+}
+
+/// Runs the pending GTK events, e.g. the property notifications after a `push_*` call.
+pub fn run_pending() {
+    while gtk::events_pending() {
+        gtk::main_iteration();
+    }
+}
+
+/// Sends a press and a release of the mouse button (e.g. `gdk::BUTTON_PRIMARY`) to the widget, as
+/// if it's clicked. The widget must be realized, e.g. in a bar from [`offscreen_bar`].
+pub fn click(widget: &gtk::Widget, button: u32) {
+    use gtk::glib::translate::{ToGlibPtr, ToGlibPtrMut};
+    for event_type in &[
+        gtk::gdk::EventType::ButtonPress,
+        gtk::gdk::EventType::ButtonRelease,
+    ] {
+        let mut event = gtk::gdk::Event::new(*event_type);
+        // gdk::EventButton has no setters. The event owns the reference to the window.
+        unsafe {
+            let e = event.to_glib_none_mut().0 as *mut gtk::gdk::ffi::GdkEventButton;
+            (*e).window = widget.window().to_glib_full();
+            (*e).button = button;
+        }
+        widget.event(&event);
+    }
+    run_pending();
+}
+
+/// Renders the widget tree, one widget per line indented by the depth.
+///
+/// A line has the type name, the widget name if set, the style classes, and the text of a label
+/// or a button (e.g. `GtkLabel.workspace "1"`). Hidden widgets are marked with "(hidden)".
+pub fn widget_tree(widget: &gtk::Widget) -> String {
+    let mut out = String::new();
+    write_widget(&mut out, widget, 0);
+    out
+}
+
+fn write_widget(out: &mut String, widget: &gtk::Widget, depth: usize) {
+    let type_name = widget.type_().name();
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(type_name);
+    let name = widget.widget_name();
+    if name.as_str() != type_name {
+        out.push_str(&format!("#{}", name));
+    }
+    for class in widget.style_context().list_classes() {
+        out.push_str(&format!(".{}", class));
+    }
+    let text = if let Some(label) = widget.downcast_ref::<gtk::Label>() {
+        Some(label.text().to_string())
+    } else if let Some(button) = widget.downcast_ref::<gtk::Button>() {
+        button.label().map(|l| l.to_string())
+    } else {
+        None
+    };
+    if let Some(text) = text {
+        out.push_str(&format!(" {:?}", text));
+    }
+    if !widget.is_visible() {
+        out.push_str(" (hidden)");
+    }
+    out.push('\n');
+    if let Some(container) = widget.downcast_ref::<gtk::Container>() {
+        for child in container.children() {
+            write_widget(out, &child, depth + 1);
+        }
+    }
+}
+
+/// The requests recorded by a mock backend, in the order they were sent.
+pub type Requests = Rc<RefCell<Vec<String>>>;
+
+#[cfg(feature = "i3")]
+struct MockI3 {
+    requests: Requests,
+}
+
+#[cfg(feature = "i3")]
+impl crate::i3::I3Backend for MockI3 {
+    fn run_command(&mut self, command: &str) -> Result<Vec<(bool, Option<String>)>, String> {
+        self.requests.borrow_mut().push(command.to_owned());
+        Ok(vec![(true, None)])
+    }
+}
+
+/// Replaces the shared i3 state with one that has no workspaces and records the commands. All
+/// commands succeed.
+#[cfg(feature = "i3")]
+pub fn install_mock_i3() -> (crate::i3::I3State, Requests) {
+    let requests = Requests::default();
+    let state = crate::i3::I3State::with_backend(Box::new(MockI3 {
+        requests: requests.clone(),
+    }));
+    crate::i3::I3State::set_shared(state.clone());
+    (state, requests)
+}
+
+#[cfg(feature = "pulseaudio")]
+struct MockPulseAudio {
+    requests: Requests,
+}
+
+#[cfg(feature = "pulseaudio")]
+impl MockPulseAudio {
+    fn record(&self, request: String) {
+        self.requests.borrow_mut().push(request);
+    }
+}
+
+#[cfg(feature = "pulseaudio")]
+impl crate::pulseaudio::PulseAudioBackend for MockPulseAudio {
+    fn set_default_sink(&self, name: &str) {
+        self.record(format!("set-default-sink {}", name));
+    }
+
+    fn set_default_source(&self, name: &str) {
+        self.record(format!("set-default-source {}", name));
+    }
+
+    fn move_sink_inputs(&self, sink: &str) {
+        self.record(format!("move-sink-inputs {}", sink));
+    }
+
+    fn set_sink_mute(&self, name: &str, mute: bool) {
+        self.record(format!("set-sink-mute {} {}", name, mute));
+    }
+
+    fn set_sink_volume(&self, name: &str, volume: &pulse::volume::ChannelVolumes) {
+        self.record(format!("set-sink-volume {} {}", name, volume.max().0));
+    }
+
+    fn set_source_mute(&self, name: &str, mute: bool) {
+        self.record(format!("set-source-mute {} {}", name, mute));
+    }
+
+    fn set_source_volume(&self, name: &str, volume: &pulse::volume::ChannelVolumes) {
+        self.record(format!("set-source-volume {} {}", name, volume.max().0));
+    }
+}
+
+/// Replaces the shared PulseAudio state with one that has no devices and records the requests
+/// (e.g. "set-sink-mute NAME true"). Use [`crate::prelude::SinkState::with_name`] with the
+/// state's backend to push the sinks.
+#[cfg(feature = "pulseaudio")]
+pub fn install_mock_pulseaudio() -> (crate::pulseaudio::PulseAudioState, Requests) {
+    let requests = Requests::default();
+    let state = crate::pulseaudio::PulseAudioState::with_backend(Rc::new(MockPulseAudio {
+        requests: requests.clone(),
+    }));
+    crate::pulseaudio::PulseAudioState::set_shared(state.clone());
+    (state, requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    /// Returns the buttons that match the predicate in the widget tree.
+    fn find_buttons(widget: &gtk::Widget, pred: &dyn Fn(&gtk::Button) -> bool) -> Vec<gtk::Button> {
+        let mut found = vec![];
+        if let Some(button) = widget.downcast_ref::<gtk::Button>() {
+            if pred(button) {
+                found.push(button.clone());
+            }
+        }
+        if let Some(container) = widget.downcast_ref::<gtk::Container>() {
+            for child in container.children() {
+                found.extend(find_buttons(&child, pred));
+            }
+        }
+        found
+    }
+
+    fn has_class(class: &'static str) -> impl Fn(&gtk::Button) -> bool {
+        move |b| b.style_context().has_class(class)
+    }
+
+    #[cfg(feature = "i3")]
+    fn workspace_push() {
+        use crate::i3::WorkspaceState;
+
+        let (i3, commands) = install_mock_i3();
+        let bar = offscreen_bar(json!({
+            "plugins": [{ "name": "i3" }],
+            "default_monitor": {
+                "left_modules": [{ "name": "i3-workspaces", "output": "eDP-1" }],
+            },
+        }))
+        .unwrap();
+        let workspace = |num: i32, focused: bool| WorkspaceState {
+            num,
+            name: num.to_string(),
+            visible: focused,
+            focused,
+            urgent: false,
+        };
+        let mut workspaces = BTreeMap::new();
+        workspaces.insert(
+            "eDP-1".to_owned(),
+            vec![workspace(1, true), workspace(2, false)],
+        );
+        workspaces.insert("HDMI-1".to_owned(), vec![workspace(3, true)]);
+        i3.push_workspaces(workspaces);
+        run_pending();
+
+        let bar = bar.upcast_ref::<gtk::Widget>();
+        let tree = widget_tree(bar);
+        assert!(tree.contains("GtkLabel \"1\""), "{}", tree);
+        assert!(tree.contains("GtkLabel \"2\""), "{}", tree);
+        assert!(!tree.contains("GtkLabel \"3\""), "{}", tree);
+        let focused = find_buttons(bar, &has_class("workspace-focused"));
+        assert_eq!(focused.len(), 1, "{}", tree);
+        assert!(focused[0].style_context().has_class("workspace-num-1"));
+
+        find_buttons(bar, &has_class("workspace-num-2"))[0].clicked();
+        assert_eq!(*commands.borrow(), vec!["workspace number 2".to_owned()]);
+    }
+
+    #[cfg(feature = "pulseaudio")]
+    fn sink_mute_round_trip() {
+        use crate::pulseaudio::sink::SinkState;
+
+        let (pa, requests) = install_mock_pulseaudio();
+        let bar = offscreen_bar(json!({
+            "plugins": [{ "name": "pulseaudio" }],
+            "default_monitor": {
+                "left_modules": [{ "name": "pulseaudio-default-sink-volume-toggle" }],
+            },
+        }))
+        .unwrap();
+        let push_sink = |mute: bool| {
+            let mut sink = SinkState::with_name(pa.backend(), "speaker");
+            sink.mute = mute;
+            pa.push_sinks(std::iter::once((0, sink)).collect());
+            run_pending();
+        };
+        pa.push_defaults("speaker", "");
+        push_sink(false);
+
+        let bar = bar.upcast_ref::<gtk::Widget>();
+        let tree = widget_tree(bar);
+        assert!(!tree.contains("(muted)"), "{}", tree);
+        // The only button other than the overflow button of the bar.
+        let buttons = find_buttons(bar, &|b| !b.style_context().has_class("overflow"));
+        assert_eq!(buttons.len(), 1, "{}", tree);
+        click(buttons[0].upcast_ref(), gtk::gdk::BUTTON_PRIMARY);
+        assert_eq!(
+            *requests.borrow(),
+            vec!["set-sink-mute speaker true".to_owned()]
+        );
+
+        // The server notifies the change.
+        push_sink(true);
+        let tree = widget_tree(bar);
+        assert!(tree.contains("(muted)"), "{}", tree);
+    }
+
+    /// GTK can be used only from the thread that initialized it, so the cases run in one test.
+    #[test]
+    fn mock_backends() {
+        if gtk::init().is_err() {
+            eprintln!("No display. Skipped.");
+            return;
+        }
+        #[cfg(feature = "i3")]
+        workspace_push();
+        #[cfg(feature = "pulseaudio")]
+        sink_mute_round_trip();
+    }
+}